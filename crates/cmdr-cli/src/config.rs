@@ -0,0 +1,305 @@
+//! `cmdr config` subcommands
+
+use anyhow::Context;
+use clap::Subcommand;
+use cmdr_config::{
+    check_key, Config, ConfigFile, ConfigLoader, LayeredConfig, Origin, Severity, CONFIG_VERSION,
+};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use toml::Value;
+
+/// Subcommands that change a file act on the user file,
+/// `~/.config/cmdr/config.toml`, or on the file given with `--config`
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Write a configuration file with the default settings, commented
+    Init {
+        /// Replace the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Print the effective value of a key, or of every key in a table
+    Get {
+        /// Dotted key, such as `shell.timeout_secs`
+        key: String,
+    },
+
+    /// Set a key in the configuration file
+    Set {
+        /// Dotted key, such as `shell.timeout_secs`
+        key: String,
+        /// TOML value; anything that is not TOML is taken as a string
+        value: String,
+    },
+
+    /// Remove a key from the configuration file
+    Unset {
+        /// Dotted key, such as `shell.timeout_secs`
+        key: String,
+    },
+
+    /// Open the configuration file in `$VISUAL` or `$EDITOR`, and check it
+    /// once the editor exits
+    Edit,
+
+    /// Print the path of the configuration file
+    Path,
+
+    /// Print the effective configuration
+    Show {
+        /// Print each key with the file, environment variable or flag its
+        /// value came from
+        #[arg(long)]
+        origin: bool,
+    },
+
+    /// Report every problem in the configuration, with its location
+    Check,
+
+    /// Upgrade configuration files written for an older schema version,
+    /// keeping a copy of each original
+    Migrate,
+}
+
+/// Widest setting `config show --origin` aligns origins after
+const MAX_ALIGNED_WIDTH: usize = 48;
+
+/// Run a `cmdr config` subcommand
+pub fn run(command: ConfigCommand, loader: &ConfigLoader) -> anyhow::Result<()> {
+    match command {
+        ConfigCommand::Init { force } => init(loader, force)?,
+        ConfigCommand::Get { key } => get(loader, &key)?,
+        ConfigCommand::Set { key, value } => {
+            let mut file = ConfigFile::open(user_file(loader)?)?;
+            file.set(&key, &value)?;
+            save_checked(&file)?;
+            warn_if_overridden(loader, &key, file.path());
+        }
+        ConfigCommand::Unset { key } => {
+            let mut file = ConfigFile::open(user_file(loader)?)?;
+            if !file.unset(&key)? {
+                println!("{key} is not set in {}", file.path().display());
+                return Ok(());
+            }
+            save_checked(&file)?;
+            warn_if_overridden(loader, &key, file.path());
+        }
+        ConfigCommand::Edit => edit(loader)?,
+        ConfigCommand::Path => println!("{}", user_file(loader)?.display()),
+        ConfigCommand::Show { origin: false } => {
+            print!("{}", toml::to_string_pretty(load(loader)?.config())?);
+        }
+        ConfigCommand::Show { origin: true } => print_origins(&load(loader)?),
+        ConfigCommand::Check => check(loader)?,
+        ConfigCommand::Migrate => migrate(loader)?,
+    }
+    Ok(())
+}
+
+/// Load the configuration, warning about settings that were ignored
+pub fn load(loader: &ConfigLoader) -> anyhow::Result<LayeredConfig> {
+    let layered = loader.load()?;
+    for warning in layered.warnings() {
+        eprintln!("Warning: {warning}");
+    }
+    Ok(layered)
+}
+
+/// The file the subcommands that change the configuration write to
+fn user_file(loader: &ConfigLoader) -> anyhow::Result<PathBuf> {
+    loader
+        .user_file()
+        .map(Path::to_path_buf)
+        .context("cannot find the configuration directory; set HOME or pass --config")
+}
+
+/// Write the default configuration to the user file
+fn init(loader: &ConfigLoader, force: bool) -> anyhow::Result<()> {
+    let path = user_file(loader)?;
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; pass --force to replace it",
+            path.display()
+        );
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+    }
+    cmdr_config::save_config(&Config::default(), &path)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Print the value of `key`, a string as it is, or `key = value` for each
+/// key in the table `key`
+fn get(loader: &ConfigLoader, key: &str) -> anyhow::Result<()> {
+    check_key(key)?;
+    let layered = load(loader)?;
+    let table = format!("{key}.");
+    let mut found = false;
+    for (name, value, _) in layered.values() {
+        match value {
+            Value::String(value) if name == key => println!("{value}"),
+            value if name == key => println!("{value}"),
+            value if name.starts_with(&table) => println!("{name} = {value}"),
+            _ => continue,
+        }
+        found = true;
+    }
+    if !found {
+        anyhow::bail!("{key} is not set");
+    }
+    Ok(())
+}
+
+/// Save `file` unless checking it finds errors, which are printed instead
+fn save_checked(file: &ConfigFile) -> anyhow::Result<()> {
+    let errors: Vec<_> = file
+        .check()
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .collect();
+    if errors.is_empty() {
+        return file.save();
+    }
+    for error in &errors {
+        eprintln!("{error}");
+    }
+    anyhow::bail!(
+        "left {} unchanged, since the change would leave errors in it",
+        file.path().display()
+    );
+}
+
+/// Warn when another source decides the value of `key` after changing it in
+/// the file at `path`
+fn warn_if_overridden(loader: &ConfigLoader, key: &str, path: &Path) {
+    let Ok(layered) = loader.load() else {
+        return;
+    };
+    match layered.origin(key) {
+        None | Some(Origin::Default) => {}
+        Some(origin) if origin.path() == Some(path) => {}
+        Some(origin) => eprintln!("Warning: {key} is still set by {origin}"),
+    }
+}
+
+/// Open the user file in the user's editor, creating it if need be, until
+/// it has no errors or the user gives up and the old file is restored
+fn edit(loader: &ConfigLoader) -> anyhow::Result<()> {
+    let path = user_file(loader)?;
+    let original = match std::fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("cannot read {}", path.display())),
+    };
+    if original.is_none() {
+        init(loader, false)?;
+    }
+    loop {
+        run_editor(&path)?;
+        let diagnostics = ConfigLoader::new().with_config_file(&path).check();
+        for diagnostic in &diagnostics {
+            println!("{diagnostic}");
+        }
+        if diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity != Severity::Error)
+        {
+            return Ok(());
+        }
+        if !crate::confirm("Edit again? [y/N] ")? {
+            match &original {
+                Some(text) => std::fs::write(&path, text),
+                None => std::fs::remove_file(&path),
+            }
+            .with_context(|| format!("cannot restore {}", path.display()))?;
+            anyhow::bail!("discarded the changes, since they left errors in the file");
+        }
+    }
+}
+
+/// Run `$VISUAL`, `$EDITOR` or `vi` on `path`
+fn run_editor(path: &Path) -> anyhow::Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    // Through the shell, since the editor may come with arguments, as in
+    // `code --wait`
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("cannot run {editor}"))?;
+    if !status.success() {
+        anyhow::bail!("{editor} failed with {status}");
+    }
+    Ok(())
+}
+
+/// Print every key as `key = value`, followed by where its value came from
+fn print_origins(layered: &LayeredConfig) {
+    let settings: Vec<_> = layered
+        .values()
+        .map(|(key, value, origin)| (format!("{key} = {value}"), origin))
+        .collect();
+    // Align the origins, except after the odd long list
+    let width = settings
+        .iter()
+        .map(|(setting, _)| setting.len())
+        .filter(|&len| len <= MAX_ALIGNED_WIDTH)
+        .max()
+        .unwrap_or(0);
+    for (setting, origin) in settings {
+        println!("{setting:<width$}  # {origin}");
+    }
+}
+
+/// Print every problem in the configuration; fails if any is an error
+fn check(loader: &ConfigLoader) -> anyhow::Result<()> {
+    let diagnostics = loader.check();
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    match (errors, diagnostics.len()) {
+        (0, 0) => println!("The configuration is valid"),
+        (0, warnings) => println!("The configuration is usable, with {warnings} warning(s)"),
+        (errors, _) => anyhow::bail!("the configuration has {errors} error(s)"),
+    }
+    Ok(())
+}
+
+/// Rewrite each configuration file older than the current schema version
+fn migrate(loader: &ConfigLoader) -> anyhow::Result<()> {
+    let mut upgraded = 0;
+    for origin in loader.file_origins() {
+        let path = origin.path().expect("files have paths");
+        let Some(upgrade) = cmdr_config::upgrade_file(path)? else {
+            continue;
+        };
+        println!(
+            "Upgraded {} from version {} to {CONFIG_VERSION}; the original is saved as {}",
+            path.display(),
+            upgrade.from,
+            cmdr_config::backup_path(path, upgrade.from).display()
+        );
+        for change in &upgrade.changes {
+            println!("  - {change}");
+        }
+        upgraded += 1;
+    }
+    if upgraded == 0 {
+        println!("The configuration files are up to date");
+    }
+    Ok(())
+}
@@ -1,13 +1,37 @@
+mod config;
+mod repl;
+
 use clap::{Parser, Subcommand};
-use rustyline::error::ReadlineError;
-use rustyline::history::DefaultHistory;
-use rustyline::Editor;
+use std::io::{self, Write};
+use std::path::PathBuf;
 use std::process;
-
-use cmdr_core::InferenceEngine;
-use cmdr_core::{MockInferenceEngine, NaturalLanguageRequest, ShellExecutor, TranslationEngine};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use cmdr_config::{Config, ConfigLoader, InferenceConfig};
+use cmdr_core::inference::{InferenceError, DEFAULT_OLLAMA_MODEL};
+use cmdr_core::shell::{
+    ChangeKind, FileChange, OutputChunk, OutputStream, PolicyAction, Preview, ShellError,
+};
+use cmdr_core::translation::{CommandStream, TranslationError};
+use cmdr_core::{
+    CancellationToken, ContextCollector, GenerationOptions, InferenceEngine, ShellCommand,
+};
+use cmdr_core::{
+    NaturalLanguageRequest, OllamaInferenceEngine, PtyMode, ShellExecutor, ShellSession,
+    TranslationEngine,
+};
 use llm::LlmInstaller;
 
+/// How long a single translation may run before it is abandoned
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Most tokens the model may generate for one translation
+const MAX_COMMAND_TOKENS: usize = 256;
+
+/// Exit status reported when the user interrupts cmdr with Ctrl-C
+const EXIT_INTERRUPTED: i32 = 130;
+
 /// cmdr - A fast, REPL-based command-line interface that translates natural language to shell commands
 ///
 /// Phase 1 CLI Interface Contract:
@@ -18,7 +42,7 @@ use llm::LlmInstaller;
 ///
 /// Phase 1 operational flags:
 /// -c, --command <STRING>  Single-shot mode: translate and execute a single natural language command
-/// --config <FILE>         Configuration file path (placeholder, not yet used in Phase 1)
+/// --config <FILE>         Configuration file to use instead of the user file
 ///
 /// Phase 2+ reserved functionality:
 /// - Interactive REPL mode (default when no -c flag provided)
@@ -41,9 +65,26 @@ struct Args {
     #[arg(short = 'c', long = "command", value_name = "STRING")]
     command: Option<String>,
 
-    /// Configuration file path (placeholder, not yet used)
+    /// Run commands in a sandbox and report the files they would change
+    /// instead of changing them
+    #[arg(long)]
+    sandbox: bool,
+
+    /// Show what commands would change without changing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Kill commands that run longer than this many seconds
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// When to run commands on a pseudo-terminal: never, auto or always
+    #[arg(long, value_name = "WHEN")]
+    pty: Option<PtyMode>,
+
+    /// Configuration file to use instead of ~/.config/cmdr/config.toml
     #[arg(long = "config", value_name = "FILE")]
-    config: Option<String>,
+    config: Option<PathBuf>,
 
     #[command(subcommand)]
     command_type: Option<Commands>,
@@ -64,13 +105,19 @@ enum Commands {
 
     /// Check LLM installation status
     Status,
+
+    /// Inspect and change the configuration
+    Config {
+        #[command(subcommand)]
+        command: config::ConfigCommand,
+    },
 }
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     // Handle subcommands first
-    if let Some(command_type) = args.command_type {
+    if let Some(command_type) = args.command_type.take() {
         match command_type {
             Commands::Install { skip_checks, force } => {
                 match handle_install_command(skip_checks, force) {
@@ -88,99 +135,355 @@ fn main() {
                     process::exit(1);
                 }
             },
+            Commands::Config { command } => {
+                match config_loader(&args).and_then(|loader| config::run(command, &loader)) {
+                    Ok(_) => process::exit(0),
+                    Err(e) => {
+                        eprintln!("Error: {e:#}");
+                        process::exit(1);
+                    }
+                }
+            }
         }
     }
 
+    let config = match config_loader(&args).and_then(|loader| config::load(&loader)) {
+        Ok(layered) => layered.into_config(),
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            eprintln!("Run `cmdr config check` to list every problem");
+            process::exit(1);
+        }
+    };
+    let shell_executor = match shell_executor(&args, &config) {
+        Ok(shell_executor) => shell_executor,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            process::exit(1);
+        }
+    };
+
     // Fast-path execution logic for mature UNIX tool behavior
 
     // Handle -c/--command fast-path
-    if let Some(command) = args.command {
-        match execute_single_command(&command) {
+    if let Some(command) = &args.command {
+        match execute_single_command(command, &config, shell_executor) {
             Ok(_) => process::exit(0),
+            Err(e) if is_cancellation(&e) => {
+                eprintln!("Cancelled");
+                process::exit(EXIT_INTERRUPTED);
+            }
             Err(e) => {
-                eprintln!("Error: {e}");
+                report_error(&e);
                 process::exit(1);
             }
         }
     }
 
-    // Handle --config (placeholder for Phase 2)
-    if let Some(config_file) = args.config {
-        println!("Configuration file specified: {config_file}");
-        // TODO: Implement configuration file parsing in Phase 2
-        println!("Configuration loading not yet implemented");
+    // Interactive REPL mode
+    repl::run_repl(shell_executor, &config);
+}
+
+/// A loader for the configuration from every source, with the flags on top
+fn config_loader(args: &Args) -> anyhow::Result<ConfigLoader> {
+    let mut loader = ConfigLoader::default();
+    if let Some(path) = &args.config {
+        loader = loader.with_config_file(path);
+    }
+    if let Some(seconds) = args.timeout {
+        let seconds = i64::try_from(seconds).unwrap_or(i64::MAX);
+        loader = loader.with_override("shell.timeout_secs", seconds, "--timeout");
     }
+    if let Some(mode) = args.pty {
+        loader = loader.with_override("shell.pty", toml::Value::try_from(mode)?, "--pty");
+    }
+    Ok(loader)
+}
 
-    // Interactive REPL mode
-    run_repl();
+/// Connect to the inference backend and wrap it in a translation engine,
+/// with the configured prompt and examples, that writes commands for
+/// `shell_executor`'s shell
+fn connect_translation_engine(
+    shell_executor: &ShellExecutor,
+    config: &Config,
+) -> anyhow::Result<TranslationEngine<Box<dyn InferenceEngine>>> {
+    let inference_engine = connect_inference_engine(&config.inference)?;
+    let mut translation_engine = TranslationEngine::new(inference_engine)
+        .with_examples(config.prompt.load_examples()?)
+        .with_example_count(config.prompt.example_count);
+    if let Some(template) = config.prompt.load_template()? {
+        translation_engine = translation_engine.with_prompt_template(template);
+    }
+    if let Some(kind) = shell_executor.kind() {
+        translation_engine = translation_engine.with_shell(kind);
+    }
+    Ok(translation_engine)
 }
 
-fn execute_single_command(natural_language: &str) -> anyhow::Result<()> {
-    let mut inference_engine = MockInferenceEngine::new();
+/// Start the inference backend `config` selects, load its model and apply
+/// its generation settings
+///
+/// Built with the `native-llama` feature, cmdr runs the GGUF model at
+/// `model_path` in-process when one is set. Otherwise it uses Ollama, with
+/// `model` or the model `cmdr install` pulls.
+fn connect_inference_engine(config: &InferenceConfig) -> anyhow::Result<Box<dyn InferenceEngine>> {
+    let mut inference_engine = backend(config)?;
+    if let Some(temperature) = config.temperature {
+        inference_engine.set_parameters(temperature, MAX_COMMAND_TOKENS)?;
+    }
+    Ok(inference_engine)
+}
+
+/// The backend `config` selects, with its model loaded
+fn backend(config: &InferenceConfig) -> anyhow::Result<Box<dyn InferenceEngine>> {
+    #[cfg(feature = "native-llama")]
+    if let Some(path) = &config.model_path {
+        let defaults = llm::LlmParams::default();
+        let mut inference_engine = llm::LlamaInferenceEngine::with_params(llm::LlmParams {
+            n_ctx: config.context_size.unwrap_or(defaults.n_ctx),
+            n_threads: config
+                .threads
+                .map_or(defaults.n_threads, |threads| threads as i32),
+            ..defaults
+        });
+        inference_engine.initialize()?;
+        inference_engine.load_model(&path.to_string_lossy())?;
+        return Ok(Box::new(inference_engine));
+    }
+    #[cfg(not(feature = "native-llama"))]
+    if let Some(path) = &config.model_path {
+        eprintln!(
+            "Warning: ignoring inference.model_path ({}): this cmdr was built without the \
+             native-llama feature, so it uses Ollama",
+            path.display()
+        );
+    }
+
+    let mut inference_engine = OllamaInferenceEngine::from_env()?;
+    if let Some(threads) = config.threads {
+        inference_engine = inference_engine.with_threads(threads);
+    }
+    if let Some(context_size) = config.context_size {
+        inference_engine = inference_engine.with_context_size(context_size);
+    }
     inference_engine.initialize()?;
-    inference_engine.load_model("mock-model")?;
+    inference_engine.load_model(config.model.as_deref().unwrap_or(DEFAULT_OLLAMA_MODEL))?;
+    Ok(Box::new(inference_engine))
+}
+
+/// The executor for translated commands, as configured by the `[shell]`
+/// and `[safety]` sections and the flags
+fn shell_executor(args: &Args, config: &Config) -> anyhow::Result<ShellExecutor> {
+    let shell = &config.shell;
+    let shell_executor = ShellExecutor::new(shell.shell())
+        .with_sandbox(args.sandbox)
+        .with_dry_run(args.dry_run)
+        .with_pty(shell.pty)
+        .with_limits(shell.limits)
+        .with_policy(config.safety.load_policy()?);
+    Ok(match shell.timeout_secs {
+        Some(seconds) => shell_executor.with_timeout(Duration::from_secs(seconds)),
+        None => shell_executor,
+    })
+}
 
-    let mut translation_engine = TranslationEngine::new(inference_engine);
-    let shell_executor = ShellExecutor::default();
+fn execute_single_command(
+    natural_language: &str,
+    config: &Config,
+    shell_executor: ShellExecutor,
+) -> anyhow::Result<()> {
+    let mut translation_engine = connect_translation_engine(&shell_executor, config)?;
 
     let request = NaturalLanguageRequest {
         text: natural_language.to_string(),
-        context: None,
+        context: Some(ContextCollector::new(config.context.clone()).collect()),
     };
 
-    // TODO: Make this async when we have proper async runtime
-    let command =
-        tokio::runtime::Runtime::new()?.block_on(translation_engine.translate(request))?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let cancellation = CancellationToken::new();
+        cancel_on_ctrl_c(cancellation.clone());
+        let options = GenerationOptions::new()
+            .with_cancellation(cancellation.clone())
+            .with_timeout(GENERATION_TIMEOUT);
+
+        // Show only the command while the model streams its JSON answer
+        print!("Translated command: ");
+        io::stdout().flush()?;
+        let mut stream = CommandStream::new();
+        let mut streamed = false;
+        let command = translation_engine
+            .translate_stream(request, &options, &mut |token: &str| {
+                let text = stream.push(token);
+                if !text.is_empty() {
+                    streamed = true;
+                    print!("{text}");
+                    let _ = io::stdout().flush();
+                }
+            })
+            .await?;
+        if !streamed {
+            print!("{}", command.command);
+        }
+        println!();
+        if let Some(explanation) = &command.explanation {
+            println!("Explanation: {explanation}");
+        }
 
-    println!("Translated command: {command:?}");
+        let mut session = ShellSession::new(shell_executor)?;
+        run_command(&mut session, command, cancellation, false).await
+    })
+}
 
-    let result = shell_executor.execute(&command)?;
-    if result.success {
-        println!("{result:?}");
-    } else {
+/// Execute `command` in `session` and print its result
+///
+/// Commands the safety policy wants confirmed are confirmed on the terminal
+/// unless the user has already `confirmed` this one. Must be called from
+/// within a multi-threaded Tokio runtime.
+async fn run_command(
+    session: &mut ShellSession,
+    command: ShellCommand,
+    cancellation: CancellationToken,
+    confirmed: bool,
+) -> anyhow::Result<()> {
+    // Judge paths from where the session is, as it will when running it
+    let decision = session.check(&command.command);
+    let shell_executor = session.executor();
+    let confirmed = match decision.action {
+        PolicyAction::Allow => false,
+        PolicyAction::Confirm if confirmed => true,
+        PolicyAction::Confirm if shell_executor.is_sandboxed() || shell_executor.is_dry_run() => {
+            false
+        }
+        PolicyAction::Deny => {
+            return Err(ShellError::PolicyDenied(Box::new(decision)).into());
+        }
+        PolicyAction::Confirm => {
+            println!("Warning: {decision}");
+            if !confirm("Run it anyway? [y/N] ")? {
+                println!("Skipped.");
+                return Ok(());
+            }
+            true
+        }
+    };
+
+    // The session is borrowed, so run the command on this worker thread
+    // while the runtime moves other tasks, such as the Ctrl-C handler, away
+    let result = tokio::task::block_in_place(|| {
+        let mut on_output = |chunk: &OutputChunk| print_output(chunk);
+        if confirmed {
+            session.execute_confirmed_streaming(&command, &cancellation, &mut on_output)
+        } else {
+            session.execute_streaming(&command, &cancellation, &mut on_output)
+        }
+    })?;
+    if result.truncated {
         eprintln!(
-            "Error: {err}",
-            err = result.error.unwrap_or_else(|| "Unknown error".to_string())
+            "[output truncated after {} bytes]",
+            result.output.len() + result.error.as_ref().map_or(0, String::len)
         );
     }
-
+    if let Some(preview) = &result.preview {
+        print_preview(preview);
+    } else if let Some(changes) = &result.changes {
+        print_changes(changes);
+    }
+    if result.killed {
+        eprintln!(
+            "Error: killed after running for {:.1}s",
+            result.duration.as_secs_f64()
+        );
+    } else if let Some(signal) = result.signal {
+        eprintln!("Error: command terminated by signal {signal}");
+    } else if !result.success {
+        eprintln!("Error: command exited with status {}", result.exit_code);
+    }
     Ok(())
 }
 
-fn run_repl() {
-    let mut rl = Editor::<(), DefaultHistory>::new().expect("Failed to create line editor");
+/// Copy a chunk of command output to our own stdout or stderr
+fn print_output(chunk: &OutputChunk) {
+    let result = match chunk.stream {
+        OutputStream::Stdout => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&chunk.data).and_then(|_| stdout.flush())
+        }
+        OutputStream::Stderr => io::stderr().lock().write_all(&chunk.data),
+    };
+    // A closed terminal is no reason to stop the command
+    let _ = result;
+}
 
-    loop {
-        let readline = rl.readline("[cmdr] ");
+/// List the files a sandboxed command would have changed
+fn print_changes(changes: &[FileChange]) {
+    if changes.is_empty() {
+        println!("Sandbox: no files would change");
+        return;
+    }
+    println!("Sandbox: the command would change these files:");
+    for change in changes {
+        let kind = match change.kind {
+            ChangeKind::Created => "created",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+        };
+        println!("  {kind:>8}  {}", change.path.display());
+    }
+}
 
-        match readline {
-            Ok(line) => {
-                // Add line to history
-                let _ = rl.add_history_entry(line.as_str());
+/// List what a dry run found the command would change
+fn print_preview(preview: &Preview) {
+    if preview.changes.is_empty() {
+        println!("Dry run: no files would change");
+        return;
+    }
+    println!("Dry run: the command would");
+    for change in &preview.changes {
+        println!("  {}  {}", change.operation, change.path.display());
+    }
+}
 
-                // Handle non-empty lines
-                if !line.trim().is_empty() {
-                    println!("(REPL not yet implemented): {line}");
-                }
-            }
-            Err(ReadlineError::Interrupted) => {
-                // Ctrl-C: abort current line and continue
-                println!();
-                continue;
-            }
-            Err(ReadlineError::Eof) => {
-                // Ctrl-D: exit cleanly
-                break;
-            }
-            Err(err) => {
-                eprintln!("Error: {err}");
-                break;
-            }
+/// Ask a yes/no question on the terminal; anything but `y` means no
+fn confirm(question: &str) -> io::Result<bool> {
+    print!("{question}");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Cancel `token` when the user presses Ctrl-C
+///
+/// Must be called from within a Tokio runtime. Abort the returned task once
+/// the operation is over so later Ctrl-C presses are not swallowed.
+fn cancel_on_ctrl_c(token: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            token.cancel();
         }
+    })
+}
+
+/// Print an error, including the model output when it could not be parsed
+fn report_error(error: &anyhow::Error) {
+    eprintln!("Error: {error}");
+    if let Some(TranslationError::Generation { raw: Some(raw), .. }) =
+        error.downcast_ref::<TranslationError>()
+    {
+        eprintln!("Model response:\n{raw}");
     }
+}
 
-    // Clean exit
-    process::exit(0);
+/// Whether `error` means the user cancelled the operation
+fn is_cancellation(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<TranslationError>(),
+        Some(TranslationError::Inference(InferenceError::Cancelled))
+    ) || matches!(
+        error.downcast_ref::<ShellError>(),
+        Some(ShellError::Cancelled)
+    )
 }
 
 fn handle_install_command(skip_checks: bool, force: bool) -> anyhow::Result<()> {
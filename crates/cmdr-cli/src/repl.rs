@@ -0,0 +1,494 @@
+//! Interactive REPL
+//!
+//! Each line is translated into a few ranked candidate commands, the first
+//! shown as the model writes it. The chosen one is shown with its
+//! explanation and risk level, and the user then executes it, picks another
+//! candidate by number, edits it in place, asks for a fuller explanation,
+//! has new candidates generated or cancels. Commands run in one shell
+//! session, so a `cd` or `export` carries over to later lines.
+
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use std::io::{self, Write};
+use std::process;
+
+use cmdr_config::{Config, ShellConfig};
+use cmdr_core::shell::{DryRun, PolicyAction, PolicyDecision};
+use cmdr_core::translation::{normalize_command, CommandStream, TranslationError};
+use cmdr_core::{
+    CancellationToken, ContextCollector, GenerationOptions, InferenceEngine,
+    NaturalLanguageRequest, RiskLevel, ShellCommand, ShellExecutor, ShellSession,
+    TranslationEngine,
+};
+
+use crate::{
+    cancel_on_ctrl_c, connect_translation_engine, is_cancellation, print_preview, report_error,
+    run_command, GENERATION_TIMEOUT,
+};
+
+/// Number of candidate commands offered for each request
+const REPL_CANDIDATES: usize = 3;
+
+/// Seeds set aside for each round of candidates, so that regenerating
+/// samples with seeds no earlier round used
+const SEEDS_PER_ROUND: u64 = 1 << 16;
+
+/// How the REPL treats translated commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplOptions {
+    /// Run commands without offering the choices first; the safety policy
+    /// still asks before risky ones
+    pub auto_execute: bool,
+    /// Show commands before they are executed automatically
+    pub show_translation: bool,
+}
+
+impl Default for ReplOptions {
+    fn default() -> Self {
+        Self {
+            auto_execute: false,
+            show_translation: true,
+        }
+    }
+}
+
+impl From<&ShellConfig> for ReplOptions {
+    fn from(config: &ShellConfig) -> Self {
+        Self {
+            auto_execute: config.auto_execute,
+            show_translation: config.show_translation,
+        }
+    }
+}
+
+/// What to do with a proposed command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Execute,
+    /// Switch to the candidate at this index
+    Pick(usize),
+    Edit,
+    Explain,
+    Regenerate,
+    Cancel,
+}
+
+/// Run the REPL until the user exits with Ctrl-D
+pub fn run_repl(shell_executor: ShellExecutor, config: &Config) {
+    let options = ReplOptions::from(&config.shell);
+    let mut rl = Editor::<(), DefaultHistory>::new().expect("Failed to create line editor");
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime");
+    let mut session = match ShellSession::new(shell_executor) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }
+    };
+
+    // Connected on first use so the REPL starts even when the backend is down
+    let mut translation_engine: Option<TranslationEngine<Box<dyn InferenceEngine>>> = None;
+
+    loop {
+        let readline = rl.readline(&config.ui.prompt);
+
+        match readline {
+            Ok(line) => {
+                // Add line to history
+                let _ = rl.add_history_entry(line.as_str());
+
+                // Handle non-empty lines
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let engine = match translation_engine.as_mut() {
+                    Some(engine) => engine,
+                    None => match connect_translation_engine(session.executor(), config) {
+                        Ok(engine) => translation_engine.insert(engine),
+                        Err(e) => {
+                            report_error(&e);
+                            continue;
+                        }
+                    },
+                };
+                let mut repl = Repl {
+                    rl: &mut rl,
+                    runtime: &runtime,
+                    engine,
+                    session: &mut session,
+                    config,
+                    options,
+                };
+                match repl.handle_request(&line) {
+                    Ok(()) => {}
+                    Err(e) if is_cancellation(&e) => println!("Cancelled"),
+                    Err(e) => report_error(&e),
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                // Ctrl-C: abort current line and continue
+                println!();
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
+                // Ctrl-D: exit cleanly
+                break;
+            }
+            Err(err) => {
+                eprintln!("Error: {err}");
+                break;
+            }
+        }
+    }
+
+    // Clean exit; `exit` skips destructors, so end the shell first
+    drop(session);
+    process::exit(0);
+}
+
+/// Everything one request needs
+struct Repl<'a> {
+    rl: &'a mut Editor<(), DefaultHistory>,
+    runtime: &'a tokio::runtime::Runtime,
+    engine: &'a mut TranslationEngine<Box<dyn InferenceEngine>>,
+    session: &'a mut ShellSession,
+    config: &'a Config,
+    options: ReplOptions,
+}
+
+impl Repl<'_> {
+    /// Translate one line and let the user decide what to do with it
+    fn handle_request(&mut self, line: &str) -> anyhow::Result<()> {
+        // Describe where the session is, which earlier commands may have changed
+        let request = NaturalLanguageRequest {
+            text: line.to_string(),
+            context: Some(
+                ContextCollector::new(self.config.context.clone())
+                    .with_cwd(self.session.cwd())
+                    .collect(),
+            ),
+        };
+
+        if self.options.auto_execute {
+            let command = self.translate(&request, self.options.show_translation)?;
+            if self.options.show_translation {
+                let decision = self.session.check(&command.command);
+                print_proposal(&[], &command, &decision);
+            }
+            return self.execute(command, false);
+        }
+
+        // Show the first candidate as it is written, then look for others
+        let first = self.translate(&request, true)?;
+        let mut candidates: Vec<ShellCommand> = self
+            .candidates(&request, REPL_CANDIDATES - 1, 1)?
+            .into_iter()
+            .filter(|candidate| {
+                normalize_command(&candidate.command) != normalize_command(&first.command)
+            })
+            .collect();
+        candidates.insert(0, first);
+        let mut command = candidates[0].clone();
+        // Commands already offered, which regenerating must not offer again
+        let mut rejected: Vec<String> = Vec::new();
+        let mut round = 0;
+
+        let mut show = true;
+        loop {
+            let decision = self.session.check(&command.command);
+            if show {
+                print_proposal(&candidates, &command, &decision);
+            }
+            show = true;
+            let allowed = decision.action != PolicyAction::Deny;
+            match self.choose(allowed, candidates.len())? {
+                Choice::Execute => return self.execute(command, true),
+                Choice::Pick(index) => command = candidates[index].clone(),
+                Choice::Edit => {
+                    if let Some(edited) = self.edit(&command.command)? {
+                        command = ShellCommand {
+                            command: edited,
+                            explanation: None,
+                            confidence: 1.0,
+                        };
+                    } else {
+                        show = false;
+                    }
+                }
+                Choice::Explain => {
+                    explain(self.session, &command, &decision);
+                    show = false;
+                }
+                Choice::Regenerate => {
+                    rejected.extend(
+                        candidates
+                            .iter()
+                            .chain([&command])
+                            .map(|candidate| normalize_command(&candidate.command)),
+                    );
+                    round += 1;
+                    // Ask for enough to be left with new ones once those
+                    // already offered are dropped
+                    let regenerated: Vec<ShellCommand> = self
+                        .candidates(
+                            &request,
+                            REPL_CANDIDATES + rejected.len(),
+                            round * SEEDS_PER_ROUND,
+                        )?
+                        .into_iter()
+                        .filter(|candidate| {
+                            !rejected.contains(&normalize_command(&candidate.command))
+                        })
+                        .take(REPL_CANDIDATES)
+                        .collect();
+                    match regenerated.first() {
+                        Some(first) => {
+                            command = first.clone();
+                            candidates = regenerated;
+                        }
+                        None => {
+                            println!("The model suggested the same command again.");
+                            show = false;
+                        }
+                    }
+                }
+                Choice::Cancel => {
+                    println!("Skipped.");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Translate `request`, cancelling on Ctrl-C, and if `show` is set
+    /// print the command as the model writes it
+    fn translate(
+        &mut self,
+        request: &NaturalLanguageRequest,
+        show: bool,
+    ) -> anyhow::Result<ShellCommand> {
+        let engine = &mut *self.engine;
+        let mut stream = CommandStream::new();
+        let mut streamed = false;
+        let command = self.runtime.block_on(async {
+            let cancellation = CancellationToken::new();
+            let ctrl_c = cancel_on_ctrl_c(cancellation.clone());
+            let options = GenerationOptions::new()
+                .with_cancellation(cancellation)
+                .with_timeout(GENERATION_TIMEOUT)
+                .with_seed(0);
+            let command = engine
+                .translate_stream(request.clone(), &options, &mut |token: &str| {
+                    let text = stream.push(token);
+                    if !show || text.is_empty() {
+                        return;
+                    }
+                    if !streamed {
+                        streamed = true;
+                        print!("Translating: ");
+                    }
+                    print!("{text}");
+                    let _ = io::stdout().flush();
+                })
+                .await;
+            ctrl_c.abort();
+            command
+        });
+        if streamed {
+            println!();
+        }
+        Ok(command?)
+    }
+
+    /// Up to `n` ranked candidates for `request`, sampled with seeds from
+    /// `seed` on, cancelling on Ctrl-C
+    ///
+    /// Having none is not an error when the model's answers cannot be
+    /// parsed, since there is already a command to offer.
+    fn candidates(
+        &mut self,
+        request: &NaturalLanguageRequest,
+        n: usize,
+        seed: u64,
+    ) -> anyhow::Result<Vec<ShellCommand>> {
+        let engine = &mut *self.engine;
+        let candidates = self.runtime.block_on(async {
+            let cancellation = CancellationToken::new();
+            let ctrl_c = cancel_on_ctrl_c(cancellation.clone());
+            let options = GenerationOptions::new()
+                .with_cancellation(cancellation)
+                .with_timeout(GENERATION_TIMEOUT)
+                .with_seed(seed);
+            let candidates = engine.translate_n(request.clone(), n, &options).await;
+            ctrl_c.abort();
+            candidates
+        });
+        match candidates {
+            Ok(candidates) => Ok(candidates),
+            Err(TranslationError::Generation { .. }) => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Run `command` in the session with live output, cancelling on Ctrl-C
+    fn execute(&mut self, command: ShellCommand, confirmed: bool) -> anyhow::Result<()> {
+        let session = &mut *self.session;
+        self.runtime.block_on(async {
+            let cancellation = CancellationToken::new();
+            let ctrl_c = cancel_on_ctrl_c(cancellation.clone());
+            let result = run_command(session, command, cancellation, confirmed).await;
+            ctrl_c.abort();
+            result
+        })
+    }
+
+    /// Ask what to do with the proposed command, one of `count` candidates
+    ///
+    /// Executing is only offered if the safety policy does not deny it, and
+    /// picking another candidate only if there is more than one.
+    fn choose(&mut self, allowed: bool, count: usize) -> anyhow::Result<Choice> {
+        let pick = match count {
+            0 | 1 => String::new(),
+            count => format!("[1-{count}] pick, "),
+        };
+        let prompt = if allowed {
+            format!("Execute? [y]es, {pick}[e]dit, e[x]plain, [r]egenerate, [N]o: ")
+        } else {
+            format!("{pick}[e]dit, e[x]plain, [r]egenerate, [N]o: ")
+        };
+        loop {
+            let input = match self.rl.readline(&prompt) {
+                Ok(input) => input,
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(Choice::Cancel),
+                Err(e) => return Err(e.into()),
+            };
+            match parse_choice(&input, count) {
+                Some(Choice::Execute) if !allowed => {
+                    println!("The safety policy does not allow this command.");
+                }
+                Some(choice) => return Ok(choice),
+                None if count > 1 => println!("Enter y, a number from 1 to {count}, e, x, r or n"),
+                None => println!("Enter y, e, x, r or n"),
+            }
+        }
+    }
+
+    /// Let the user edit `command` in place; `None` keeps it unchanged
+    fn edit(&mut self, command: &str) -> anyhow::Result<Option<String>> {
+        match self.rl.readline_with_initial("Edit: ", (command, "")) {
+            Ok(edited) if edited.trim().is_empty() || edited == command => Ok(None),
+            Ok(edited) => Ok(Some(edited.trim().to_string())),
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Turn an answer to the choice prompt, with `count` candidates to pick
+/// from by 1-based number, into a choice; empty means cancel
+fn parse_choice(input: &str, count: usize) -> Option<Choice> {
+    if let Ok(number) = input.trim().parse::<usize>() {
+        return (1..=count)
+            .contains(&number)
+            .then(|| Choice::Pick(number - 1));
+    }
+    match input.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Some(Choice::Execute),
+        "e" | "edit" => Some(Choice::Edit),
+        "x" | "explain" => Some(Choice::Explain),
+        "r" | "regenerate" => Some(Choice::Regenerate),
+        "" | "n" | "no" | "c" | "cancel" => Some(Choice::Cancel),
+        _ => None,
+    }
+}
+
+/// Show a proposed command with its explanation, risk and policy verdict
+///
+/// When it is one of several `candidates`, they are all listed by number
+/// with the proposed one marked.
+fn print_proposal(candidates: &[ShellCommand], command: &ShellCommand, decision: &PolicyDecision) {
+    match candidates.iter().position(|c| c.command == command.command) {
+        Some(picked) if candidates.len() > 1 => {
+            for (i, candidate) in candidates.iter().enumerate() {
+                let marker = if i == picked { '>' } else { ' ' };
+                println!(
+                    "{marker} {}) {}  ({:.0}%)",
+                    i + 1,
+                    candidate.command,
+                    candidate.confidence * 100.0
+                );
+                if let Some(explanation) = &candidate.explanation {
+                    println!("     {explanation}");
+                }
+            }
+        }
+        _ => {
+            println!("  {}", command.command);
+            if let Some(explanation) = &command.explanation {
+                println!("  {explanation}");
+            }
+        }
+    }
+    let risk = &decision.risk;
+    match risk.reasons().first() {
+        Some(reason) if risk.level > RiskLevel::Safe => {
+            println!("  Risk: {} ({reason})", risk.level)
+        }
+        _ => println!("  Risk: {}", risk.level),
+    }
+    match decision.action {
+        PolicyAction::Allow => {}
+        PolicyAction::Confirm => println!("  Warning: {decision}"),
+        PolicyAction::Deny => println!("  Blocked: {decision}"),
+    }
+}
+
+/// Describe everything cmdr can tell about `command` without running it
+fn explain(session: &ShellSession, command: &ShellCommand, decision: &PolicyDecision) {
+    if let Some(explanation) = &command.explanation {
+        println!("{explanation}");
+    }
+    let risk = &decision.risk;
+    println!("Risk: {}", risk.level);
+    for reason in risk.reasons() {
+        println!("  - {reason}");
+    }
+    if let Some(error) = &risk.parse_error {
+        println!("  - cmdr could not parse it, so its effects are unknown: {error}");
+    }
+    if let Some(program) = &risk.interactive {
+        println!("  - {program} is interactive and needs a terminal");
+    }
+    println!("Policy: {decision}");
+    match DryRun::new(session.cwd()).preview(&command.command) {
+        Some(preview) => print_preview(&preview),
+        None => println!("Dry run: cmdr cannot tell in advance which files it would change"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("y", 1), Some(Choice::Execute));
+        assert_eq!(parse_choice(" Yes\n", 1), Some(Choice::Execute));
+        assert_eq!(parse_choice("e", 1), Some(Choice::Edit));
+        assert_eq!(parse_choice("x", 1), Some(Choice::Explain));
+        assert_eq!(parse_choice("r", 1), Some(Choice::Regenerate));
+        assert_eq!(parse_choice("", 1), Some(Choice::Cancel));
+        assert_eq!(parse_choice("n", 1), Some(Choice::Cancel));
+        assert_eq!(parse_choice("ls", 1), None);
+    }
+
+    #[test]
+    fn test_parse_choice_picks_candidates() {
+        assert_eq!(parse_choice("1", 3), Some(Choice::Pick(0)));
+        assert_eq!(parse_choice(" 3 ", 3), Some(Choice::Pick(2)));
+        assert_eq!(parse_choice("0", 3), None);
+        assert_eq!(parse_choice("4", 3), None);
+        assert_eq!(parse_choice("2", 1), None);
+    }
+}
@@ -0,0 +1,247 @@
+//! `cmdr config`, driven through the `cmdr` binary
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// `cmdr` in `dir`, with its user configuration under `dir/.config`
+///
+/// Cargo passes the build's `CMDR_VERSION` on to tests, where it would be
+/// reported as naming no configuration key.
+fn cmdr(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cmdr"));
+    command
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join(".config"))
+        .env_remove("CMDR_VERSION")
+        .env_remove("CMDR_SHELL_TIMEOUT_SECS")
+        .env_remove("CMDR_SHELL_PTY");
+    command
+}
+
+/// The line `config show --origin` prints for `key`
+fn origin_line<'a>(output: &'a Output, key: &str) -> &'a str {
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    stdout
+        .lines()
+        .find(|line| line.starts_with(&format!("{key} = ")))
+        .unwrap_or_else(|| panic!("no {key} in:\n{stdout}"))
+}
+
+#[test]
+fn test_show_origins() {
+    let dir = tempfile::tempdir().unwrap();
+    let user_dir = dir.path().join(".config/cmdr");
+    std::fs::create_dir_all(&user_dir).unwrap();
+    std::fs::write(
+        user_dir.join("config.toml"),
+        "[shell]\ntimeout_secs = 10\npty = \"never\"\n\n[ui]\nprompt = \"> \"\n",
+    )
+    .unwrap();
+    let project = dir.path().join("project");
+    std::fs::create_dir_all(project.join("src")).unwrap();
+    std::fs::write(
+        project.join(".cmdr.toml"),
+        "[shell]\ntimeout_secs = 20\nshow_translation = false\n",
+    )
+    .unwrap();
+
+    let output = cmdr(&project.join("src"))
+        .env("XDG_CONFIG_HOME", dir.path().join(".config"))
+        .env("CMDR_SHELL_PTY", "always")
+        .args(["--timeout", "30", "config", "show", "--origin"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    let line = origin_line(&output, "ui.prompt");
+    assert!(line.starts_with("ui.prompt = \"> \""), "{line}");
+    assert!(line.contains("# user file "), "{line}");
+    assert!(origin_line(&output, "shell.show_translation").contains("project file"));
+    let line = origin_line(&output, "shell.pty");
+    assert!(line.starts_with("shell.pty = \"always\""), "{line}");
+    assert!(
+        line.ends_with("# environment variable CMDR_SHELL_PTY"),
+        "{line}"
+    );
+    let line = origin_line(&output, "shell.timeout_secs");
+    assert!(line.starts_with("shell.timeout_secs = 30"), "{line}");
+    assert!(line.ends_with("# command-line flag --timeout"), "{line}");
+    assert!(origin_line(&output, "prompt.example_count").ends_with("# default"));
+}
+
+#[test]
+fn test_invalid_configuration_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = cmdr(dir.path())
+        .env("CMDR_SHELL_AUTO_EXECUTE", "maybe")
+        .args(["config", "show"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("shell.auto_execute"), "{stderr}");
+
+    let output = cmdr(dir.path())
+        .args(["--config", "missing.toml", "config", "show"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_check_reports_located_problems() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = cmdr(dir.path()).args(["config", "check"]).output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("The configuration is valid"), "{stdout}");
+
+    std::fs::write(
+        dir.path().join(".cmdr.toml"),
+        "[inference]\ntemperature = 3.0\n\n[shell]\ntimout = 5\n",
+    )
+    .unwrap();
+    let output = cmdr(dir.path()).args(["config", "check"]).output().unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(".cmdr.toml:2:15: error: inference.temperature:"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(
+            ".cmdr.toml:5:1: warning: shell.timout: unknown key; did you mean `shell.timeout_secs`?"
+        ),
+        "{stdout}"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 error(s)"), "{stderr}");
+}
+
+#[test]
+fn test_migrate_upgrades_old_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let user_dir = dir.path().join(".config/cmdr");
+    std::fs::create_dir_all(&user_dir).unwrap();
+    let original = "# Mine\n[shell]\npty = \"never\"\n";
+    std::fs::write(user_dir.join("config.toml"), original).unwrap();
+
+    let output = cmdr(dir.path())
+        .args(["config", "migrate"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("from version 0 to 1"), "{stdout}");
+    let upgraded = std::fs::read_to_string(user_dir.join("config.toml")).unwrap();
+    assert_eq!(upgraded, format!("version = 1\n{original}"));
+    let backup = std::fs::read_to_string(user_dir.join("config.toml.v0.bak")).unwrap();
+    assert_eq!(backup, original);
+
+    let output = cmdr(dir.path())
+        .args(["config", "migrate"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("up to date"), "{stdout}");
+}
+
+/// Run `cmdr config <args>` in `dir`, returning its standard output
+fn config(dir: &Path, args: &[&str]) -> String {
+    let output = cmdr(dir).arg("config").args(args).output().unwrap();
+    assert!(output.status.success(), "{args:?}: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_init_get_set_unset() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".config/cmdr/config.toml");
+    assert_eq!(config(dir.path(), &["path"]).trim(), path.to_str().unwrap());
+
+    config(dir.path(), &["init"]);
+    let initial = std::fs::read_to_string(&path).unwrap();
+    assert!(initial.contains("# How translated commands run\n[shell]\n"));
+    let output = cmdr(dir.path()).args(["config", "init"]).output().unwrap();
+    assert!(!output.status.success());
+
+    config(dir.path(), &["set", "shell.timeout_secs", "30"]);
+    config(dir.path(), &["set", "ui.prompt", "> "]);
+    assert_eq!(config(dir.path(), &["get", "shell.timeout_secs"]), "30\n");
+    assert_eq!(config(dir.path(), &["get", "ui.prompt"]), "> \n");
+    let context = config(dir.path(), &["get", "context"]);
+    assert!(context.contains("context.git = true\n"), "{context}");
+
+    // Edits leave the comments alone
+    let edited = std::fs::read_to_string(&path).unwrap();
+    assert!(edited.contains("# timeout_secs = 60\n"), "{edited}");
+    assert!(edited.contains("\ntimeout_secs = 30\n"), "{edited}");
+    config(dir.path(), &["unset", "shell.timeout_secs"]);
+    config(dir.path(), &["set", "ui.prompt", "[cmdr] "]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), initial);
+
+    let failing: [&[&str]; 5] = [
+        &["set", "shell.auto_execute", "maybe"],
+        &["set", "shell.timout", "5"],
+        &["set", "inference.temperature", "5"],
+        &["set", "shell.timeout_secs", "0"],
+        &["get", "shell.timeout_secs"],
+    ];
+    for args in failing {
+        let output = cmdr(dir.path()).arg("config").args(args).output().unwrap();
+        assert!(!output.status.success(), "{args:?}");
+    }
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), initial);
+
+    // Values of the right type are still checked before saving
+    let output = cmdr(dir.path())
+        .args(["config", "set", "inference.temperature", "5"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error: inference.temperature: must be between 0 and 2, got 5"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edit_checks_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".config/cmdr/config.toml");
+    let edit = |editor: &str, answer: &str| {
+        let mut child = cmdr(dir.path())
+            .env_remove("VISUAL")
+            .env("EDITOR", editor)
+            .args(["config", "edit"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(answer.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = edit("sed -i 's/^temperature = .*/temperature = 0.3/'", "");
+    assert!(output.status.success(), "{output:?}");
+    let edited = std::fs::read_to_string(&path).unwrap();
+    assert!(edited.contains("\ntemperature = 0.3\n"), "{edited}");
+
+    // Errors are reported, and the old file is kept unless editing again
+    let output = edit("sed -i 's/^temperature = .*/temperature = \"hot\"/'", "n\n");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("config.toml:14:15: error: inference.temperature:"),
+        "{stdout}"
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), edited);
+}
@@ -0,0 +1,396 @@
+//! REPL behaviour, driven through the `cmdr` binary with a fake Ollama
+//! server that answers each request with a canned command
+
+use cmdr_core::inference::DEFAULT_OLLAMA_MODEL;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a REPL run may take before the test gives up on it
+const RUN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An Ollama server that translates requests through a fixed table
+struct FakeOllama {
+    port: u16,
+    generations: Arc<AtomicUsize>,
+}
+
+impl FakeOllama {
+    /// Serve `replies`, pairs of a request and the command it translates
+    /// to; a command of several lines is one alternative per line, picked
+    /// by the sampling seed
+    fn start(replies: &[(&str, &str)]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let generations = Arc::new(AtomicUsize::new(0));
+        let replies: Vec<(String, String)> = replies
+            .iter()
+            .map(|(request, command)| (request.to_string(), command.to_string()))
+            .collect();
+        let counter = generations.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                serve(stream, &replies, &counter);
+            }
+        });
+        Self { port, generations }
+    }
+
+    /// How many translations were requested
+    fn generations(&self) -> usize {
+        self.generations.load(Ordering::SeqCst)
+    }
+
+    /// The `cmdr` REPL in `dir`, talking to this server; unless the test
+    /// writes its own configuration, it offers the choices before running
+    /// a command
+    fn cmdr(&self, dir: &Path) -> Command {
+        let config = dir.join(".config/cmdr/config.toml");
+        if !config.exists() {
+            std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+            std::fs::write(&config, "[shell]\nauto_execute = false\n").unwrap();
+        }
+        let mut command = Command::new(env!("CARGO_BIN_EXE_cmdr"));
+        command
+            .current_dir(dir)
+            .env("OLLAMA_HOST", format!("127.0.0.1:{}", self.port))
+            .env("XDG_CONFIG_HOME", dir.join(".config"))
+            .env("SHELL", "/bin/sh")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        command
+    }
+
+    /// Run the REPL in `dir` on `input` until it exits
+    fn run(&self, dir: &Path, input: &str) -> Output {
+        let mut child = self.cmdr(dir).spawn().unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        wait(child)
+    }
+}
+
+/// Answer one HTTP request
+fn serve(mut stream: TcpStream, replies: &[(String, String)], generations: &AtomicUsize) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let response = match request_line.split_whitespace().nth(1).unwrap_or_default() {
+        "/api/version" => json!({ "version": "0.5.0" }).to_string(),
+        "/api/tags" => json!({ "models": [{ "name": DEFAULT_OLLAMA_MODEL }] }).to_string(),
+        "/api/generate" => {
+            generations.fetch_add(1, Ordering::SeqCst);
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            let prompt = body["prompt"].as_str().unwrap();
+            let request = prompt
+                .rsplit("User request: ")
+                .next()
+                .and_then(|rest| rest.lines().next())
+                .unwrap_or_default();
+            let seed = body["options"]["seed"].as_u64().unwrap_or(0);
+            let command = replies
+                .iter()
+                .find(|(text, _)| text == request)
+                .map_or_else(
+                    || format!("echo unknown request: {request}"),
+                    |(_, commands)| {
+                        let alternatives: Vec<&str> = commands.lines().collect();
+                        alternatives[seed as usize % alternatives.len()].to_string()
+                    },
+                );
+            let answer = json!({
+                "command": command,
+                "explanation": format!("Runs {command}"),
+                "confidence": 0.9,
+            });
+            format!(
+                "{}\n",
+                json!({ "response": answer.to_string(), "done": true })
+            )
+        }
+        _ => String::new(),
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+        response.len()
+    );
+}
+
+/// Wait for `child` to exit, killing it after [`RUN_TIMEOUT`]
+fn wait(mut child: Child) -> Output {
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > RUN_TIMEOUT {
+            child.kill().unwrap();
+            panic!("cmdr did not exit within {RUN_TIMEOUT:?}");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn test_executes_confirmed_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[
+        ("say hello", "echo hello from the shell"),
+        ("say goodbye", "echo goodbye"),
+    ]);
+    let output = server.run(dir.path(), "say hello\ny\nsay goodbye\nn\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("echo hello from the shell"), "{stdout}");
+    assert!(
+        stdout.contains("Runs echo hello from the shell"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Risk: safe"), "{stdout}");
+    assert!(stdout.contains("\nhello from the shell\n"), "{stdout}");
+    assert!(!stdout.contains("\ngoodbye\n"), "{stdout}");
+    assert!(stdout.contains("Skipped."), "{stdout}");
+}
+
+#[test]
+fn test_end_of_input_exits_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[("say hello", "echo hello")]);
+
+    // End of input at the choice prompt cancels, then ends the REPL
+    let output = server.run(dir.path(), "say hello\n");
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Skipped."));
+
+    let output = server.run(dir.path(), "");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_empty_lines_are_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[("say hello", "echo hello")]);
+    server.run(dir.path(), "say hello\ny\n");
+    let per_request = server.generations();
+    let output = server.run(dir.path(), "\n   \nsay hello\ny\n\n");
+
+    assert!(output.status.success());
+    assert_eq!(server.generations(), 2 * per_request);
+    assert!(stdout(&output).contains("\nhello\n"));
+}
+
+#[test]
+fn test_session_state_carries_across_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("projects")).unwrap();
+    let server = FakeOllama::start(&[
+        ("go to my projects folder", "cd projects"),
+        ("where am I", "pwd"),
+    ]);
+    let output = server.run(dir.path(), "go to my projects folder\ny\nwhere am I\ny\n");
+
+    assert!(output.status.success());
+    let projects = dir.path().join("projects").canonicalize().unwrap();
+    assert!(
+        stdout(&output).contains(&format!("\n{}\n", projects.display())),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn test_edit_replaces_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[("say hello", "echo hello")]);
+    let output = server.run(dir.path(), "say hello\ne\necho edited\ny\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("\nedited\n"), "{stdout}");
+    assert!(!stdout.contains("\nhello\n"), "{stdout}");
+}
+
+#[test]
+fn test_explain_shows_risk_and_effects() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("build")).unwrap();
+    let server = FakeOllama::start(&[("clean up", "rm -r build")]);
+    let output = server.run(dir.path(), "clean up\nx\nn\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Risk: destructive"), "{stdout}");
+    assert!(stdout.contains("Dry run: the command would"), "{stdout}");
+    assert!(dir.path().join("build").exists());
+}
+
+#[test]
+fn test_pick_candidate_by_number() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[("count", "echo one\necho two\necho three")]);
+    let output = server.run(dir.path(), "count\n3\ny\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Translating: echo one\n"), "{stdout}");
+    assert!(stdout.contains("> 1) echo one  (90%)"), "{stdout}");
+    assert!(stdout.contains("  2) echo two  (90%)"), "{stdout}");
+    assert!(stdout.contains("> 3) echo three  (90%)"), "{stdout}");
+    assert!(stdout.contains("\nthree\n"), "{stdout}");
+    assert!(!stdout.contains("\none\n"), "{stdout}");
+}
+
+#[test]
+fn test_regenerate_offers_new_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[("say hello", "echo hello")]);
+    let output = server.run(dir.path(), "say hello\nr\nn\n");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("The model suggested the same command again."));
+
+    // Regenerating samples new seeds and skips the commands already offered
+    let server = FakeOllama::start(&[("count", "echo one\necho two\necho three\necho four")]);
+    let output = server.run(dir.path(), "count\nr\ny\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("  echo four\n"), "{stdout}");
+    assert!(stdout.contains("\nfour\n"), "{stdout}");
+}
+
+#[test]
+fn test_denied_commands_cannot_execute() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[("wipe the disk", "dd if=/dev/zero of=/dev/sda")]);
+    let output = server.run(dir.path(), "wipe the disk\ny\nn\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Blocked:"), "{stdout}");
+    assert!(
+        stdout.contains("The safety policy does not allow this command."),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_policy_follows_the_session_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    std::fs::create_dir(root.join("guarded")).unwrap();
+    let config_dir = root.join(".config/cmdr");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("policy.toml"),
+        format!(
+            "default = \"allow\"\n\n[[rules]]\naction = \"confirm\"\npaths = [\"{}\"]\n",
+            root.join("guarded/b").display()
+        ),
+    )
+    .unwrap();
+    let server = FakeOllama::start(&[("go to guarded", "cd guarded"), ("make b", "touch b")]);
+
+    // Accepting the proposal confirms the command the session rates Confirm
+    let output = server.run(&root, "go to guarded\ny\nmake b\ny\n");
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Warning:"), "{stdout}");
+    assert!(!stdout.contains("Run it anyway?"), "{stdout}");
+    assert!(root.join("guarded/b").exists(), "{stdout}");
+    assert!(!root.join("b").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctrl_c_cancels_running_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = FakeOllama::start(&[
+        ("wait a while", "echo started; sleep 30"),
+        ("say hello", "echo hello"),
+    ]);
+    let mut child = server.cmdr(dir.path()).spawn().unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"wait a while\ny\n").unwrap();
+
+    // Interrupt once the command is running. Read raw output: the session
+    // holds back a trailing newline until it knows the command is not done
+    let (chunks, received) = mpsc::channel();
+    let mut reader = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0; 1024];
+        while let Ok(len @ 1..) = reader.read(&mut buf) {
+            let _ = chunks.send(String::from_utf8_lossy(&buf[..len]).to_string());
+        }
+    });
+    let mut stdout = String::new();
+    while !stdout.contains("\nstarted") {
+        stdout.push_str(&received.recv_timeout(RUN_TIMEOUT).unwrap());
+    }
+    let interrupted = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(interrupted.success());
+
+    // The REPL carries on with the next line
+    stdin.write_all(b"say hello\ny\n").unwrap();
+    drop(stdin);
+    let status = wait(child).status;
+    stdout.extend(received.try_iter());
+
+    assert!(status.success());
+    assert!(stdout.contains("Cancelled"), "{stdout}");
+    assert!(stdout.contains("\nhello\n"), "{stdout}");
+}
+
+#[test]
+fn test_auto_execute_from_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_dir = dir.path().join(".config/cmdr");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let mut config = cmdr_config::Config::default();
+    config.shell.auto_execute = true;
+    config.shell.show_translation = false;
+    cmdr_config::save_config(&config, &config_dir.join("config.toml")).unwrap();
+
+    let server = FakeOllama::start(&[("say hello", "echo hello")]);
+    let output = server.run(dir.path(), "say hello\n");
+
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("hello\n"), "{stdout}");
+    assert!(!stdout.contains("echo hello"), "{stdout}");
+    assert!(!stdout.contains("Skipped."), "{stdout}");
+}
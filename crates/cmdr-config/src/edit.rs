@@ -0,0 +1,288 @@
+//! Writing and editing configuration files
+//!
+//! [`save_config`](crate::save_config) writes a whole file, commented so it
+//! can be read without the documentation. [`ConfigFile`] changes single
+//! keys of an existing file through `toml_edit`, so everything else in it,
+//! comments and formatting included, stays as it was.
+
+use crate::layers::env_value;
+use crate::migrate::{self, VERSION_KEY};
+use crate::validate::{check_key, check_value, OPTIONAL_KEYS};
+use crate::{Config, CONFIG_VERSION};
+use anyhow::{bail, Context};
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use toml::Value;
+use toml_edit::{value, DocumentMut, Item, Table, TableLike};
+
+/// The comment at the top of a file written by `save_config`
+const HEADER: &str = "\
+# cmdr configuration
+#
+# Keys left out take their default values, and commented-out ones are unset
+# by default. `cmdr config check` reports any problem in this file.
+
+";
+
+/// What each table holds, written above it by `save_config`
+const TABLE_COMMENTS: &[(&str, &str)] = &[
+    ("inference", "The model and how it generates commands"),
+    ("shell", "How translated commands run"),
+    (
+        "shell.limits",
+        "Resource limits for each command; unset means unlimited",
+    ),
+    ("ui", "The interactive prompt"),
+    (
+        "context",
+        "What cmdr tells the model about your environment",
+    ),
+    ("prompt", "The prompt template and few-shot examples"),
+    ("safety", "The policy commands are checked against"),
+];
+
+/// `config` as a configuration file, with a comment above each table and
+/// the keys it leaves unset listed, commented out, with example values
+pub(crate) fn commented(config: &Config) -> anyhow::Result<String> {
+    let values = Value::try_from(config)?;
+    let mut text = String::from(HEADER);
+    for line in toml::to_string_pretty(config)?.lines() {
+        let Some(table) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        else {
+            writeln!(text, "{line}")?;
+            continue;
+        };
+        if let Some((_, comment)) = TABLE_COMMENTS.iter().find(|(name, _)| *name == table) {
+            writeln!(text, "# {comment}")?;
+        }
+        writeln!(text, "{line}")?;
+        for (key, example) in OPTIONAL_KEYS {
+            let unset = key
+                .split('.')
+                .try_fold(&values, |value, name| value.get(name))
+                .is_none();
+            match key
+                .strip_prefix(table)
+                .and_then(|key| key.strip_prefix('.'))
+            {
+                Some(name) if unset && !name.contains('.') => {
+                    writeln!(text, "# {name} = {example}")?
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// A configuration file being edited
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    document: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Open the file at `path`; a missing file starts out empty, for
+    /// [`CONFIG_VERSION`]
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let document = match std::fs::read_to_string(&path) {
+            Ok(text) => text
+                .parse()
+                .with_context(|| format!("invalid configuration in {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut document = DocumentMut::new();
+                document[VERSION_KEY] = value(i64::from(CONFIG_VERSION));
+                document
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("cannot read {}", path.display()));
+            }
+        };
+        Ok(Self { path, document })
+    }
+
+    /// The file's path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Set the dotted `key` to `raw`, which is read as TOML, or as a string
+    /// if it is not TOML, and must have the type the key takes
+    pub fn set(&mut self, key: &str, raw: &str) -> anyhow::Result<()> {
+        if key == VERSION_KEY {
+            bail!("{key}: set by `cmdr config migrate`");
+        }
+        let parsed = env_value(raw);
+        check_value(key, parsed.clone()).map_err(|message| anyhow::anyhow!("{key}: {message}"))?;
+        let mut new: toml_edit::Value = parsed.to_string().parse()?;
+        new.decor_mut().clear();
+
+        let (tables, name) = key.rsplit_once('.').unwrap_or(("", key));
+        let mut table: &mut dyn TableLike = self.document.as_table_mut();
+        for part in tables.split('.').filter(|part| !part.is_empty()) {
+            table = table
+                .entry(part)
+                .or_insert_with(|| {
+                    let mut table = Table::new();
+                    table.set_implicit(true);
+                    Item::Table(table)
+                })
+                .as_table_like_mut()
+                .with_context(|| format!("{key}: {part} is not a table"))?;
+        }
+        match table.get_mut(name).and_then(Item::as_value_mut) {
+            // Keep the comment after the old value
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = new;
+                *old.decor_mut() = decor;
+            }
+            None => {
+                table.insert(name, Item::Value(new));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the dotted `key`, and any table left empty; returns whether
+    /// the file set it
+    pub fn unset(&mut self, key: &str) -> anyhow::Result<bool> {
+        if key == VERSION_KEY {
+            bail!("{key}: set by `cmdr config migrate`");
+        }
+        check_key(key)?;
+        let parts: Vec<&str> = key.split('.').collect();
+        Ok(remove(self.document.as_table_mut(), &parts))
+    }
+
+    /// Write the file, provided cmdr can still read it
+    pub fn save(&self) -> anyhow::Result<()> {
+        let text = self.to_string();
+        toml::from_str::<Config>(&migrate::parse(&text)?.to_string())
+            .with_context(|| format!("invalid configuration for {}", self.path.display()))?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("cannot create {}", dir.display()))?;
+        }
+        std::fs::write(&self.path, text)
+            .with_context(|| format!("cannot write {}", self.path.display()))
+    }
+}
+
+impl fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.document)
+    }
+}
+
+/// Remove the key at `path` under `table`, then the tables it leaves empty
+fn remove(table: &mut dyn TableLike, path: &[&str]) -> bool {
+    match path {
+        [] => false,
+        [name] => table.remove(name).is_some(),
+        [name, rest @ ..] => {
+            let Some(nested) = table.get_mut(name).and_then(Item::as_table_like_mut) else {
+                return false;
+            };
+            let removed = remove(nested, rest);
+            if removed && nested.is_empty() {
+                table.remove(name);
+            }
+            removed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(text: &str) -> ConfigFile {
+        ConfigFile {
+            path: PathBuf::from("config.toml"),
+            document: text.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_commented_defaults() {
+        let text = commented(&Config::default()).unwrap();
+        let config: Config = toml::from_str(&text).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(text.starts_with("# cmdr configuration\n"), "{text}");
+        assert!(
+            text.contains("# How translated commands run\n[shell]\n# default_shell = \"zsh\"\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("[shell.limits]\n# cpu_seconds = 30\n"),
+            "{text}"
+        );
+
+        let mut config = Config::default();
+        config.shell.timeout_secs = Some(5);
+        let text = commented(&config).unwrap();
+        assert!(!text.contains("# timeout_secs"), "{text}");
+        assert!(text.contains("\ntimeout_secs = 5\n"), "{text}");
+    }
+
+    #[test]
+    fn test_set_keeps_comments() {
+        let mut file = file("# Mine\n[shell]\n# Careful\nauto_execute = false # for now\n");
+        file.set("shell.auto_execute", "true").unwrap();
+        file.set("shell.timeout_secs", "30").unwrap();
+        file.set("ui.prompt", "> ").unwrap();
+        assert_eq!(
+            file.to_string(),
+            "# Mine\n[shell]\n# Careful\nauto_execute = true # for now\ntimeout_secs = 30\n\n\
+             [ui]\nprompt = \"> \"\n"
+        );
+    }
+
+    #[test]
+    fn test_set_checks_types() {
+        let mut file = file("");
+        let error = file.set("shell.auto_execute", "maybe").unwrap_err();
+        assert!(
+            error.to_string().starts_with("shell.auto_execute: "),
+            "{error}"
+        );
+        let error = file.set("shell.timout", "5").unwrap_err();
+        assert!(error.to_string().contains("shell.timeout_secs"), "{error}");
+        assert!(file.set("version", "2").is_err());
+        assert_eq!(file.to_string(), "");
+
+        file.set("shell.limits.processes", "10").unwrap();
+        assert_eq!(file.to_string(), "[shell.limits]\nprocesses = 10\n");
+    }
+
+    #[test]
+    fn test_unset_removes_empty_tables() {
+        let mut file = file("[shell]\npty = \"never\"\n\n[shell.limits]\nprocesses = 10\n");
+        assert!(file.unset("shell.limits.processes").unwrap());
+        assert_eq!(file.to_string(), "[shell]\npty = \"never\"\n");
+        assert!(!file.unset("shell.timeout_secs").unwrap());
+        assert!(file.unset("shell.tiemout_secs").is_err());
+    }
+
+    #[test]
+    fn test_open_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cmdr/config.toml");
+        let mut file = ConfigFile::open(&path).unwrap();
+        file.set("inference.temperature", "0.2").unwrap();
+        file.save().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("version = {CONFIG_VERSION}\n\n[inference]\ntemperature = 0.2\n")
+        );
+        assert_eq!(
+            ConfigFile::open(&path).unwrap().to_string(),
+            file.to_string()
+        );
+    }
+}
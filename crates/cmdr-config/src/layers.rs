@@ -0,0 +1,600 @@
+//! Layered configuration
+//!
+//! The effective configuration is assembled from several sources, each
+//! overriding the ones before it:
+//!
+//! 1. built-in defaults
+//! 2. the system file, [`SYSTEM_CONFIG_FILE`]
+//! 3. the user file, `config.toml` in [`config_dir`]
+//! 4. the project file, the nearest [`PROJECT_CONFIG_FILE`] in the working
+//!    directory or one of its parents
+//! 5. `CMDR_*` environment variables, such as `CMDR_SHELL_TIMEOUT_SECS` for
+//!    `shell.timeout_secs`
+//! 6. command-line flags
+//!
+//! Files only need to set the keys they change. Every key remembers the
+//! source its value came from.
+
+use crate::migrate::{self, VERSION_KEY};
+use crate::{config_dir, Config};
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// The system-wide configuration file
+pub const SYSTEM_CONFIG_FILE: &str = "/etc/cmdr/config.toml";
+
+/// The per-project configuration file
+pub const PROJECT_CONFIG_FILE: &str = ".cmdr.toml";
+
+/// Prefix of environment variables that set configuration keys
+const ENV_PREFIX: &str = "CMDR_";
+
+/// Keys holding paths; relative ones are resolved against the directory of
+/// the file that sets them
+const PATH_KEYS: &[&str] = &[
+    "inference.model_path",
+    "ui.history_file",
+    "prompt.template",
+    "prompt.examples_dir",
+    "safety.policy",
+];
+
+/// Keys a project file may not set, so that a repository cannot loosen the
+/// safety checks of whoever runs cmdr in it
+pub(crate) const PROJECT_RESTRICTED_KEYS: &[&str] = &["shell.auto_execute", "safety.policy"];
+
+/// Where a configuration value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// Built into cmdr
+    Default,
+    /// The system file
+    System(PathBuf),
+    /// The user file, or the file given with `--config`
+    User(PathBuf),
+    /// A project's `.cmdr.toml`
+    Project(PathBuf),
+    /// An environment variable
+    Environment(String),
+    /// A command-line flag
+    CommandLine(String),
+}
+
+impl Origin {
+    /// The file the value came from, if it came from one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::System(path) | Self::User(path) | Self::Project(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::System(path) => write!(f, "system file {}", path.display()),
+            Self::User(path) => write!(f, "user file {}", path.display()),
+            Self::Project(path) => write!(f, "project file {}", path.display()),
+            Self::Environment(name) => write!(f, "environment variable {name}"),
+            Self::CommandLine(flag) => write!(f, "command-line flag {flag}"),
+        }
+    }
+}
+
+/// The effective configuration, with the origin of each value
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    config: Config,
+    values: BTreeMap<String, (Value, Origin)>,
+    warnings: Vec<String>,
+}
+
+impl LayeredConfig {
+    /// The effective configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Take the effective configuration
+    pub fn into_config(self) -> Config {
+        self.config
+    }
+
+    /// Where the value of the dotted `key`, such as `shell.pty`, came from
+    pub fn origin(&self, key: &str) -> Option<&Origin> {
+        self.values.get(key).map(|(_, origin)| origin)
+    }
+
+    /// Every set key with its value and origin, sorted by key
+    pub fn values(&self) -> impl Iterator<Item = (&str, &Value, &Origin)> {
+        self.values
+            .iter()
+            .map(|(key, (value, origin))| (key.as_str(), value, origin))
+    }
+
+    /// Settings that were ignored, such as restricted keys in a project file
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+/// Finds and merges the configuration sources
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    system_file: Option<PathBuf>,
+    user_file: Option<PathBuf>,
+    /// Whether a missing user file is an error, as it is when named
+    /// explicitly
+    user_file_required: bool,
+    project_dir: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    overrides: Vec<(String, Value, String)>,
+    /// A file being edited, read as this text rather than from disk
+    edited: Option<(PathBuf, String)>,
+}
+
+impl Default for ConfigLoader {
+    /// A loader for the standard files, the working directory and the
+    /// process's environment
+    fn default() -> Self {
+        Self {
+            system_file: Some(PathBuf::from(SYSTEM_CONFIG_FILE)),
+            user_file: config_dir().map(|dir| dir.join("config.toml")),
+            user_file_required: false,
+            project_dir: std::env::current_dir().ok(),
+            env: std::env::vars()
+                .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+                .collect(),
+            overrides: Vec::new(),
+            edited: None,
+        }
+    }
+}
+
+impl ConfigLoader {
+    /// A loader that reads no files or environment variables
+    pub fn new() -> Self {
+        Self {
+            system_file: None,
+            user_file: None,
+            user_file_required: false,
+            project_dir: None,
+            env: Vec::new(),
+            overrides: Vec::new(),
+            edited: None,
+        }
+    }
+
+    /// Set the system file; it is skipped if missing
+    pub fn with_system_file(mut self, path: Option<PathBuf>) -> Self {
+        self.system_file = path;
+        self
+    }
+
+    /// Set the user file; it is skipped if missing
+    pub fn with_user_file(mut self, path: Option<PathBuf>) -> Self {
+        self.user_file = path;
+        self.user_file_required = false;
+        self
+    }
+
+    /// Use `path` instead of the user file; it must exist
+    pub fn with_config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.user_file = Some(path.into());
+        self.user_file_required = true;
+        self
+    }
+
+    /// Set the directory the search for a project file starts in; `None`
+    /// skips the search
+    pub fn with_project_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.project_dir = dir;
+        self
+    }
+
+    /// Set the environment variables; those without the `CMDR_` prefix are
+    /// ignored
+    pub fn with_env(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.env = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        self
+    }
+
+    /// Set the dotted `key` to `value`, as requested by the command-line
+    /// `flag`
+    pub fn with_override(mut self, key: &str, value: impl Into<Value>, flag: &str) -> Self {
+        self.overrides
+            .push((key.to_string(), value.into(), flag.to_string()));
+        self
+    }
+
+    /// Read the file at `path` as `text`, which is not saved yet
+    pub(crate) fn with_edited_file(mut self, path: &Path, text: String) -> Self {
+        self.edited = Some((path.to_path_buf(), text));
+        self
+    }
+
+    /// Read every source and merge them
+    pub fn load(&self) -> anyhow::Result<LayeredConfig> {
+        let defaults = default_table()?;
+        let mut layers = Layers::default();
+        layers.apply(defaults.clone(), &Origin::Default);
+
+        for origin in self.file_origins() {
+            let path = origin.path().expect("files have paths");
+            let mut table = read_file(path, &self.read_text(path)?)?;
+            if matches!(origin, Origin::Project(_)) {
+                for key in PROJECT_RESTRICTED_KEYS {
+                    if remove_key(&mut table, key).is_some() {
+                        layers.warnings.push(format!(
+                            "{}: ignoring {key}, which only user and system files may set",
+                            path.display()
+                        ));
+                    }
+                }
+            }
+            layers.apply(table, &origin);
+        }
+        for (name, raw) in &self.env {
+            match env_key(name, &defaults) {
+                Some(key) => layers.set(&key, env_value(raw), &Origin::Environment(name.clone())),
+                None => layers
+                    .warnings
+                    .push(format!("ignoring {name}, which names no configuration key")),
+            }
+        }
+        for (key, value, flag) in &self.overrides {
+            layers.set(key, value.clone(), &Origin::CommandLine(flag.clone()));
+        }
+
+        let config = Value::Table(layers.table)
+            .try_into()
+            .context("invalid configuration")?;
+        Ok(LayeredConfig {
+            config,
+            values: layers.values,
+            warnings: layers.warnings,
+        })
+    }
+}
+
+/// The built-in defaults as a table
+pub(crate) fn default_table() -> anyhow::Result<Table> {
+    match Value::try_from(Config::default())? {
+        Value::Table(table) => Ok(table),
+        _ => unreachable!("the configuration serializes to a table"),
+    }
+}
+
+impl ConfigLoader {
+    /// The user file, or the file given with `--config`, which is the one
+    /// `cmdr config` edits
+    pub fn user_file(&self) -> Option<&Path> {
+        self.user_file.as_deref()
+    }
+
+    /// The configuration files that exist, lowest precedence first
+    pub fn file_origins(&self) -> Vec<Origin> {
+        let mut origins = Vec::new();
+        if let Some(path) = self.system_file.as_ref().filter(|path| path.exists()) {
+            origins.push(Origin::System(path.clone()));
+        }
+        if let Some(path) = &self.user_file {
+            if self.user_file_required || path.exists() {
+                origins.push(Origin::User(path.clone()));
+            }
+        }
+        if let Some(path) = self.project_dir.as_deref().and_then(find_project_file) {
+            origins.push(Origin::Project(path));
+        }
+        origins
+    }
+
+    /// The text of the file at `path`
+    pub(crate) fn read_text(&self, path: &Path) -> anyhow::Result<String> {
+        match &self.edited {
+            Some((edited, text)) if edited == path => Ok(text.clone()),
+            _ => std::fs::read_to_string(path)
+                .with_context(|| format!("cannot read {}", path.display())),
+        }
+    }
+
+    /// The `CMDR_*` environment variables
+    pub(crate) fn env_vars(&self) -> &[(String, String)] {
+        &self.env
+    }
+
+    /// The keys set by flags, with their values and flags
+    pub(crate) fn overrides(&self) -> impl Iterator<Item = (&str, &Value, &str)> {
+        self.overrides
+            .iter()
+            .map(|(key, value, flag)| (key.as_str(), value, flag.as_str()))
+    }
+}
+
+/// The merged sources read so far
+#[derive(Debug, Default)]
+struct Layers {
+    table: Table,
+    values: BTreeMap<String, (Value, Origin)>,
+    warnings: Vec<String>,
+}
+
+impl Layers {
+    /// Merge `layer` over the sources read so far
+    fn apply(&mut self, layer: Table, origin: &Origin) {
+        merge(&mut self.table, layer, "", origin, &mut self.values);
+    }
+
+    /// Set the dotted `key` to `value`
+    fn set(&mut self, key: &str, value: Value, origin: &Origin) {
+        self.apply(nested(key, value), origin);
+    }
+}
+
+/// Merge `layer` into `base`, which is at the dotted `prefix`, recording the
+/// origin of each value set
+fn merge(
+    base: &mut Table,
+    layer: Table,
+    prefix: &str,
+    origin: &Origin,
+    values: &mut BTreeMap<String, (Value, Origin)>,
+) {
+    for (name, value) in layer {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{prefix}.{name}")
+        };
+        match value {
+            Value::Table(table) => {
+                let entry = base
+                    .entry(name)
+                    .or_insert_with(|| Value::Table(Table::new()));
+                if !entry.is_table() {
+                    values.remove(&key);
+                    *entry = Value::Table(Table::new());
+                }
+                if let Value::Table(existing) = entry {
+                    merge(existing, table, &key, origin, values);
+                }
+            }
+            value => {
+                let nested = format!("{key}.");
+                values.retain(|other, _| !other.starts_with(&nested));
+                values.insert(key, (value.clone(), origin.clone()));
+                base.insert(name, value);
+            }
+        }
+    }
+}
+
+/// A table holding only `value` at the dotted `key`
+pub(crate) fn nested(key: &str, value: Value) -> Table {
+    let mut parts = key.rsplit('.');
+    let mut table = Table::new();
+    table.insert(parts.next().unwrap_or(key).to_string(), value);
+    for parent in parts {
+        table = Table::from_iter([(parent.to_string(), Value::Table(table))]);
+    }
+    table
+}
+
+/// Read a configuration file, resolving relative paths in it against its
+/// directory
+fn read_file(path: &Path, content: &str) -> anyhow::Result<Table> {
+    let content = migrate::parse(content)
+        .with_context(|| format!("invalid configuration in {}", path.display()))?
+        .to_string();
+    // Check the types here, where errors can point into the file
+    toml::from_str::<Config>(&content)
+        .with_context(|| format!("invalid configuration in {}", path.display()))?;
+    let mut table: Table = toml::from_str(&content)?;
+    // The version only says how to read this file
+    table.remove(VERSION_KEY);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for key in PATH_KEYS {
+        if let Some(Value::String(value)) = get_key_mut(&mut table, key) {
+            let relative = Path::new(value.as_str());
+            if relative.is_relative() && !value.starts_with('~') {
+                *value = dir.join(relative).to_string_lossy().into_owned();
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// The nearest project file in `dir` or one of its parents
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// The key an environment variable such as `CMDR_SHELL_LIMITS_CPU_SECONDS`
+/// sets, found by matching its name against the tables of `defaults`
+pub(crate) fn env_key(name: &str, defaults: &Table) -> Option<String> {
+    let mut rest = name.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase();
+    let mut table = defaults;
+    let mut key = String::new();
+    // Descend into the tables the name starts with, longest name first
+    while let Some((child, nested)) = table
+        .iter()
+        .filter_map(|(child, value)| match value {
+            Value::Table(nested) if rest.starts_with(&format!("{child}_")) => Some((child, nested)),
+            _ => None,
+        })
+        .max_by_key(|(child, _)| child.len())
+    {
+        key.push_str(child);
+        key.push('.');
+        rest.drain(..=child.len());
+        table = nested;
+    }
+    if key.is_empty() || rest.is_empty() {
+        return None;
+    }
+    key.push_str(&rest);
+    Some(key)
+}
+
+/// An environment variable's value as TOML, or as a plain string if it is
+/// not a TOML value
+pub(crate) fn env_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// The value at the dotted `key`
+fn get_key_mut<'a>(table: &'a mut Table, key: &str) -> Option<&'a mut Value> {
+    let (parent, name) = match key.rsplit_once('.') {
+        Some((parent, name)) => (get_key_mut(table, parent)?.as_table_mut()?, name),
+        None => (table, key),
+    };
+    parent.get_mut(name)
+}
+
+/// Remove the value at the dotted `key`
+fn remove_key(table: &mut Table, key: &str) -> Option<Value> {
+    match key.rsplit_once('.') {
+        Some((parent, name)) => get_key_mut(table, parent)?.as_table_mut()?.remove(name),
+        None => table.remove(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(&system, "[shell]\ntimeout_secs = 10\npty = \"never\"\n").unwrap();
+        std::fs::write(&user, "[shell]\ntimeout_secs = 20\n").unwrap();
+        std::fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            "[shell]\ntimeout_secs = 30\nshow_translation = false\n",
+        )
+        .unwrap();
+
+        let loader = ConfigLoader::new()
+            .with_system_file(Some(system.clone()))
+            .with_user_file(Some(user.clone()))
+            .with_project_dir(Some(project.join("src")));
+        let layered = loader.load().unwrap();
+        let shell = &layered.config().shell;
+        assert_eq!(shell.timeout_secs, Some(30));
+        assert!(!shell.show_translation);
+        assert_eq!(shell.pty, cmdr_core::PtyMode::Never);
+        assert_eq!(layered.origin("shell.pty"), Some(&Origin::System(system)));
+        assert_eq!(
+            layered.origin("shell.timeout_secs"),
+            Some(&Origin::Project(project.join(PROJECT_CONFIG_FILE)))
+        );
+        assert_eq!(layered.origin("ui.prompt"), Some(&Origin::Default));
+
+        let layered = loader
+            .clone()
+            .with_env(env(&[("CMDR_SHELL_TIMEOUT_SECS", "40")]))
+            .load()
+            .unwrap();
+        assert_eq!(layered.config().shell.timeout_secs, Some(40));
+
+        let layered = loader
+            .with_env(env(&[("CMDR_SHELL_TIMEOUT_SECS", "40")]))
+            .with_override("shell.timeout_secs", 50, "--timeout")
+            .load()
+            .unwrap();
+        assert_eq!(layered.config().shell.timeout_secs, Some(50));
+        assert_eq!(
+            layered.origin("shell.timeout_secs"),
+            Some(&Origin::CommandLine("--timeout".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_env_keys() {
+        let defaults = default_table().unwrap();
+        let key = |name| env_key(name, &defaults);
+        assert_eq!(
+            key("CMDR_SHELL_TIMEOUT_SECS").as_deref(),
+            Some("shell.timeout_secs")
+        );
+        assert_eq!(
+            key("CMDR_SHELL_LIMITS_CPU_SECONDS").as_deref(),
+            Some("shell.limits.cpu_seconds")
+        );
+        assert_eq!(
+            key("CMDR_CONTEXT_MAX_TOTAL_BYTES").as_deref(),
+            Some("context.max_total_bytes")
+        );
+        assert_eq!(key("CMDR_SHELL"), None);
+        assert_eq!(key("CMDR_UNKNOWN_KEY"), None);
+
+        assert_eq!(env_value("true"), Value::Boolean(true));
+        assert_eq!(env_value("0.2"), Value::Float(0.2));
+        assert_eq!(env_value("/bin/zsh"), Value::String("/bin/zsh".to_string()));
+    }
+
+    #[test]
+    fn test_project_file_restrictions_and_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(PROJECT_CONFIG_FILE),
+            "[shell]\nauto_execute = false\n\n[prompt]\ntemplate = \"prompt.txt\"\n",
+        )
+        .unwrap();
+
+        let layered = ConfigLoader::new()
+            .with_project_dir(Some(dir.path().to_path_buf()))
+            .load()
+            .unwrap();
+        assert_eq!(layered.origin("shell.auto_execute"), Some(&Origin::Default));
+        assert_eq!(layered.warnings().len(), 1);
+        assert_eq!(
+            layered.config().prompt.template,
+            Some(dir.path().join("prompt.txt"))
+        );
+    }
+
+    #[test]
+    fn test_missing_and_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        assert!(ConfigLoader::new()
+            .with_user_file(Some(missing.clone()))
+            .load()
+            .is_ok());
+        assert!(ConfigLoader::new()
+            .with_config_file(&missing)
+            .load()
+            .is_err());
+
+        let invalid = dir.path().join("invalid.toml");
+        std::fs::write(&invalid, "[shell]\nauto_execute = \"yes\"\n").unwrap();
+        assert!(ConfigLoader::new()
+            .with_config_file(&invalid)
+            .load()
+            .is_err());
+    }
+}
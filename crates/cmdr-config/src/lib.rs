@@ -3,46 +3,106 @@
 //! This crate handles loading, parsing, and managing configuration
 //! for the cmdr application.
 
-use serde::{Deserialize, Serialize};
+use cmdr_core::{
+    ContextOptions, ExampleLibrary, PromptTemplate, PtyMode, ResourceLimits, SafetyPolicy,
+    ShellKind,
+};
+use serde::{Deserialize, Serialize, Serializer};
 use std::path::PathBuf;
 
+mod edit;
+mod layers;
+mod migrate;
+mod validate;
+
+pub use edit::ConfigFile;
+pub use layers::{ConfigLoader, LayeredConfig, Origin, PROJECT_CONFIG_FILE, SYSTEM_CONFIG_FILE};
+pub use migrate::{
+    backup_path, migrate, upgrade, upgrade_file, version_of, Upgrade, CONFIG_VERSION,
+};
+pub use validate::{check_key, Diagnostic, Severity};
+
 /// Main configuration structure for cmdr
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+///
+/// Missing sections and keys take their default values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    /// Schema version the configuration was written for, [`CONFIG_VERSION`]
+    /// once loaded
+    pub version: u32,
     /// Inference engine configuration
     pub inference: InferenceConfig,
     /// Shell execution configuration
     pub shell: ShellConfig,
     /// UI/REPL configuration
     pub ui: UiConfig,
+    /// Environment context included in prompts
+    pub context: ContextOptions,
+    /// Prompt template configuration
+    pub prompt: PromptConfig,
+    /// Safety policy configuration
+    pub safety: SafetyConfig,
 }
 
 /// Inference engine configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct InferenceConfig {
-    /// Path to the model file
+    /// Ollama model to translate with; the one `cmdr install` pulls if unset
+    pub model: Option<String>,
+    /// GGUF model file run in-process, in builds with the `native-llama`
+    /// feature
     pub model_path: Option<PathBuf>,
     /// Number of threads to use for inference
     pub threads: Option<u32>,
     /// Context size for the model
     pub context_size: Option<u32>,
     /// Temperature for generation
+    #[serde(serialize_with = "serialize_f32")]
     pub temperature: Option<f32>,
 }
 
 /// Shell execution configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ShellConfig {
-    /// Whether to execute commands automatically
+    /// Whether to execute commands without offering to edit, explain or
+    /// regenerate them first
     pub auto_execute: bool,
     /// Whether to show the translated command before execution
     pub show_translation: bool,
-    /// Default shell to use
-    pub default_shell: String,
+    /// Shell commands run in; `$SHELL` if unset, falling back to bash
+    pub default_shell: Option<String>,
+    /// Seconds a command may run before it is killed; unlimited if unset
+    pub timeout_secs: Option<u64>,
+    /// Resource limits applied to each command
+    pub limits: ResourceLimits,
+    /// When commands run on a pseudo-terminal
+    pub pty: PtyMode,
+}
+
+impl ShellConfig {
+    /// The shell program commands run in
+    pub fn shell(&self) -> String {
+        self.default_shell
+            .clone()
+            .or_else(|| ShellKind::detect().map(|kind| kind.program().to_string()))
+            .unwrap_or_else(|| ShellKind::default().program().to_string())
+    }
+}
+
+/// Write an `f32` as the `f64` with the same shortest decimal form, so
+/// `0.7` is saved as `0.7` rather than `0.699999988079071`
+fn serialize_f32<S: Serializer>(value: &Option<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+    value
+        .map(|value| value.to_string().parse().unwrap_or(f64::from(value)))
+        .serialize(serializer)
 }
 
 /// UI/REPL configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UiConfig {
     /// Prompt string for the REPL
     pub prompt: String,
@@ -52,9 +112,102 @@ pub struct UiConfig {
     pub history_file: Option<PathBuf>,
 }
 
+/// Prompt template configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptConfig {
+    /// File holding a custom prompt template; the built-in one is used if unset
+    pub template: Option<PathBuf>,
+    /// Directory of extra few-shot example files; defaults to `examples`
+    /// in the cmdr configuration directory
+    pub examples_dir: Option<PathBuf>,
+    /// Number of few-shot examples included in each prompt
+    pub example_count: usize,
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            template: None,
+            examples_dir: None,
+            example_count: 3,
+        }
+    }
+}
+
+impl PromptConfig {
+    /// Load the configured prompt template, if any
+    pub fn load_template(&self) -> anyhow::Result<Option<PromptTemplate>> {
+        match &self.template {
+            Some(path) => Ok(Some(PromptTemplate::from_file(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the shipped few-shot examples plus those in the examples directory
+    pub fn load_examples(&self) -> anyhow::Result<ExampleLibrary> {
+        let mut library = ExampleLibrary::builtin();
+        let dir = self
+            .examples_dir
+            .clone()
+            .or_else(|| config_dir().map(|dir| dir.join("examples")));
+        if let Some(dir) = dir {
+            library.load_dir(dir)?;
+        }
+        Ok(library)
+    }
+}
+
+/// Safety policy configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafetyConfig {
+    /// Policy file; defaults to `policy.toml` in the cmdr configuration
+    /// directory, falling back to the built-in policy if that is missing
+    pub policy: Option<PathBuf>,
+}
+
+impl SafetyConfig {
+    /// Load the configured safety policy
+    pub fn load_policy(&self) -> anyhow::Result<SafetyPolicy> {
+        if let Some(path) = &self.policy {
+            return Ok(SafetyPolicy::from_file(path)?);
+        }
+        match config_dir().map(|dir| dir.join("policy.toml")) {
+            Some(path) if path.exists() => Ok(SafetyPolicy::from_file(path)?),
+            _ => Ok(SafetyPolicy::default()),
+        }
+    }
+}
+
+/// The cmdr configuration directory, `$XDG_CONFIG_HOME/cmdr` or
+/// `~/.config/cmdr`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("cmdr"))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            inference: InferenceConfig::default(),
+            shell: ShellConfig::default(),
+            ui: UiConfig::default(),
+            context: ContextOptions::default(),
+            prompt: PromptConfig::default(),
+            safety: SafetyConfig::default(),
+        }
+    }
+}
+
 impl Default for InferenceConfig {
     fn default() -> Self {
         Self {
+            model: None,
             model_path: None,
             threads: Some(num_cpus::get() as u32),
             context_size: Some(2048),
@@ -68,7 +221,10 @@ impl Default for ShellConfig {
         Self {
             auto_execute: true,
             show_translation: true,
-            default_shell: "bash".to_string(),
+            default_shell: None,
+            timeout_secs: None,
+            limits: ResourceLimits::default(),
+            pty: PtyMode::default(),
         }
     }
 }
@@ -83,16 +239,19 @@ impl Default for UiConfig {
     }
 }
 
-/// Load configuration from a file
+/// Load configuration from a file, upgrading it in memory if it was written
+/// for an older schema version
 pub fn load_config(path: &PathBuf) -> anyhow::Result<Config> {
     let content = std::fs::read_to_string(path)?;
-    let config: Config = toml::from_str(&content)?;
+    let document = migrate::parse(&content)?;
+    let config: Config = toml::from_str(&document.to_string())?;
     Ok(config)
 }
 
-/// Save configuration to a file
+/// Save configuration to a file, with comments describing its tables and
+/// the keys left unset
 pub fn save_config(config: &Config, path: &PathBuf) -> anyhow::Result<()> {
-    let content = toml::to_string_pretty(config)?;
+    let content = edit::commented(config)?;
     std::fs::write(path, content)?;
     Ok(())
 }
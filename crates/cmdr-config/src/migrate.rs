@@ -0,0 +1,220 @@
+//! Configuration schema versions
+//!
+//! A configuration file records the schema version it was written for in
+//! its top-level `version` key; files without one predate versioning and
+//! are version 0. Whenever a key is renamed or changes meaning,
+//! [`CONFIG_VERSION`] goes up and a step joins the migration chain. Older files
+//! are upgraded in memory each time they are read, and [`upgrade_file`]
+//! rewrites one for good, keeping a backup. Files are edited with
+//! `toml_edit`, so their comments and formatting survive.
+
+use anyhow::{bail, Context};
+use std::path::{Path, PathBuf};
+use toml_edit::{value, DocumentMut};
+
+/// The schema version this build of cmdr reads and writes
+pub const CONFIG_VERSION: u32 = 1;
+
+/// The key holding a file's schema version
+pub(crate) const VERSION_KEY: &str = "version";
+
+/// A step of the migration chain
+struct Migration {
+    /// The version this step upgrades from to the next
+    from: u32,
+    /// What the step changes, in a few words
+    summary: &'static str,
+    apply: fn(&mut DocumentMut),
+}
+
+/// Every migration, oldest first
+const MIGRATIONS: &[Migration] = &[
+    // Files from before versioning use the keys of version 1 as they are
+    Migration {
+        from: 0,
+        summary: "record the schema version",
+        apply: |_| {},
+    },
+];
+
+/// A configuration file upgraded to [`CONFIG_VERSION`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    /// The version the file was written for
+    pub from: u32,
+    /// What each migration applied changed
+    pub changes: Vec<&'static str>,
+    /// The upgraded file
+    pub text: String,
+}
+
+/// The schema version `document` was written for
+pub fn version_of(document: &DocumentMut) -> anyhow::Result<u32> {
+    match document.get(VERSION_KEY) {
+        None => Ok(0),
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("`version` must be a non-negative integer"),
+    }
+}
+
+/// Upgrade `document` to [`CONFIG_VERSION`] in place, returning what each
+/// migration changed
+pub fn migrate(document: &mut DocumentMut) -> anyhow::Result<Vec<&'static str>> {
+    let (from, changes) = run(document, MIGRATIONS, CONFIG_VERSION)?;
+    if from < CONFIG_VERSION {
+        document[VERSION_KEY] = value(i64::from(CONFIG_VERSION));
+    }
+    Ok(changes)
+}
+
+/// Upgrade the configuration file `text`; `None` if it is up to date
+pub fn upgrade(text: &str) -> anyhow::Result<Option<Upgrade>> {
+    let mut document: DocumentMut = text.parse()?;
+    let from = version_of(&document)?;
+    let changes = migrate(&mut document)?;
+    Ok((from < CONFIG_VERSION).then(|| Upgrade {
+        from,
+        changes,
+        text: document.to_string(),
+    }))
+}
+
+/// Upgrade the configuration file at `path` if it is older than
+/// [`CONFIG_VERSION`], first copying it to [`backup_path`]
+pub fn upgrade_file(path: &Path) -> anyhow::Result<Option<Upgrade>> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+    let Some(upgrade) =
+        upgrade(&text).with_context(|| format!("cannot upgrade {}", path.display()))?
+    else {
+        return Ok(None);
+    };
+    let backup = backup_path(path, upgrade.from);
+    std::fs::copy(path, &backup)
+        .with_context(|| format!("cannot back up {} to {}", path.display(), backup.display()))?;
+    std::fs::write(path, &upgrade.text)
+        .with_context(|| format!("cannot write {}", path.display()))?;
+    Ok(Some(upgrade))
+}
+
+/// Where [`upgrade_file`] keeps the version `from` copy of `path`, such as
+/// `config.toml.v0.bak`
+pub fn backup_path(path: &Path, from: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{from}.bak"));
+    path.with_file_name(name)
+}
+
+/// Apply the migrations `document` needs, without touching its version
+/// key, so that the positions of its other keys still match the file;
+/// returns the version it was written for
+pub(crate) fn apply_migrations(document: &mut DocumentMut) -> anyhow::Result<u32> {
+    Ok(run(document, MIGRATIONS, CONFIG_VERSION)?.0)
+}
+
+/// Apply those of `migrations` that `document` needs to reach `target`,
+/// returning the version it was written for and what each changed
+fn run(
+    document: &mut DocumentMut,
+    migrations: &[Migration],
+    target: u32,
+) -> anyhow::Result<(u32, Vec<&'static str>)> {
+    let from = version_of(document)?;
+    if from > target {
+        bail!("written for configuration version {from}, but this cmdr reads version {target} at most; upgrade cmdr to use it");
+    }
+    let mut changes = Vec::new();
+    for migration in migrations
+        .iter()
+        .filter(|migration| (from..target).contains(&migration.from))
+    {
+        (migration.apply)(document);
+        changes.push(migration.summary);
+    }
+    Ok((from, changes))
+}
+
+/// Parse a configuration file, upgrading it in memory if it is older
+pub(crate) fn parse(text: &str) -> anyhow::Result<DocumentMut> {
+    let mut document: DocumentMut = text.parse()?;
+    migrate(&mut document)?;
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A later migration, renaming `[shell] timeout` to `timeout_secs`
+    fn rename_timeout(document: &mut DocumentMut) {
+        let shell = document["shell"].as_table_mut().unwrap();
+        if let Some(item) = shell.remove("timeout") {
+            shell.insert("timeout_secs", item);
+        }
+    }
+
+    const CHAIN: &[Migration] = &[
+        Migration {
+            from: 0,
+            summary: "record the schema version",
+            apply: |_| {},
+        },
+        Migration {
+            from: 1,
+            summary: "rename shell.timeout to shell.timeout_secs",
+            apply: rename_timeout,
+        },
+    ];
+
+    #[test]
+    fn test_chain_applies_needed_steps() {
+        for (text, changes) in [
+            ("[shell]\ntimeout = 5\n", 2),
+            ("version = 1\n\n[shell]\ntimeout = 5\n", 1),
+        ] {
+            let mut document: DocumentMut = text.parse().unwrap();
+            let (_, applied) = run(&mut document, CHAIN, 2).unwrap();
+            assert_eq!(
+                applied,
+                CHAIN[2 - changes..]
+                    .iter()
+                    .map(|m| m.summary)
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(document["shell"]["timeout_secs"].as_integer(), Some(5));
+        }
+
+        let mut document: DocumentMut = "version = 2\n\n[shell]\ntimeout = 5\n".parse().unwrap();
+        let (from, applied) = run(&mut document, CHAIN, 2).unwrap();
+        assert_eq!((from, applied.len()), (2, 0));
+        assert!(document["shell"].get("timeout_secs").is_none());
+    }
+
+    #[test]
+    fn test_newer_versions_are_refused() {
+        let error = upgrade(&format!("version = {}\n", CONFIG_VERSION + 1)).unwrap_err();
+        assert!(error.to_string().contains("upgrade cmdr"), "{error}");
+        assert!(upgrade("version = -1\n").is_err());
+        assert!(upgrade("version = \"1\"\n").is_err());
+    }
+
+    #[test]
+    fn test_upgrade_keeps_comments() {
+        let text = "# Mine\n[shell]\n# Quiet\nshow_translation = false # for now\n";
+        let upgrade = upgrade(text).unwrap().unwrap();
+        assert_eq!(upgrade.from, 0);
+        assert_eq!(upgrade.changes, ["record the schema version"]);
+        assert_eq!(upgrade.text, format!("version = {CONFIG_VERSION}\n{text}"));
+        assert_eq!(super::upgrade(&upgrade.text).unwrap(), None);
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(
+            backup_path(Path::new("/etc/cmdr/config.toml"), 0),
+            Path::new("/etc/cmdr/config.toml.v0.bak")
+        );
+    }
+}
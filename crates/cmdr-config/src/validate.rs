@@ -0,0 +1,739 @@
+//! Configuration checks
+//!
+//! [`ConfigLoader::check`] reports every problem it finds in the
+//! configuration sources rather than stopping at the first: syntax and type
+//! errors, unknown keys, and values cmdr cannot use, such as an
+//! out-of-range temperature or a shell that is not installed. Each problem
+//! points at the line and column that causes it, or names the environment
+//! variable or flag.
+
+use crate::layers::{default_table, env_key, env_value, nested, PROJECT_RESTRICTED_KEYS};
+use crate::migrate::{apply_migrations, VERSION_KEY};
+use crate::{Config, ConfigFile, ConfigLoader, LayeredConfig, Origin, CONFIG_VERSION};
+use cmdr_core::ShellKind;
+use serde::Deserialize;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+use toml_edit::{ImDocument, Item, TableLike};
+
+/// Keys that are unset by default, and so missing from the default table,
+/// each with an example value
+pub(crate) const OPTIONAL_KEYS: &[(&str, &str)] = &[
+    ("inference.model", "\"llama3.2:3b\""),
+    ("inference.model_path", "\"~/models/model.gguf\""),
+    ("shell.default_shell", "\"zsh\""),
+    ("shell.timeout_secs", "60"),
+    ("shell.limits.cpu_seconds", "30"),
+    ("shell.limits.memory_bytes", "1073741824"),
+    ("shell.limits.file_size_bytes", "104857600"),
+    ("shell.limits.processes", "256"),
+    ("prompt.template", "\"prompt.txt\""),
+    ("prompt.examples_dir", "\"examples\""),
+    ("safety.policy", "\"policy.toml\""),
+];
+
+/// Highest temperature worth sampling at; above it output is mostly noise
+const MAX_TEMPERATURE: f32 = 2.0;
+
+/// How serious a problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// cmdr works, but probably not as intended
+    Warning,
+    /// cmdr cannot use the configuration
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found in the configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The dotted key concerned, if known
+    pub key: Option<String>,
+    /// The source that sets the offending value
+    pub origin: Option<Origin>,
+    /// Line and column in the origin's file, counted from 1
+    pub position: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, key: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            key: key.map(str::to_string),
+            origin: None,
+            position: None,
+        }
+    }
+
+    fn error(key: Option<&str>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, key, message)
+    }
+
+    fn warning(key: Option<&str>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, key, message)
+    }
+
+    fn with_origin(mut self, origin: &Origin) -> Self {
+        self.origin = Some(origin.clone());
+        self
+    }
+
+    /// Point at byte `offset` of `file`
+    fn at(mut self, file: &SourceFile, offset: Option<usize>) -> Self {
+        self.origin = Some(file.origin.clone());
+        self.position = offset.map(|offset| line_column(&file.text, offset));
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.origin.as_ref().and_then(Origin::path);
+        match (path, self.position) {
+            (Some(path), Some((line, column))) => {
+                write!(f, "{}:{line}:{column}: ", path.display())?
+            }
+            (Some(path), None) => write!(f, "{}: ", path.display())?,
+            _ => {}
+        }
+        write!(f, "{}: ", self.severity)?;
+        if let Some(key) = &self.key {
+            write!(f, "{key}: ")?;
+        }
+        f.write_str(&self.message)?;
+        match &self.origin {
+            Some(origin @ (Origin::Environment(_) | Origin::CommandLine(_))) => {
+                write!(f, " (set by {origin})")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ConfigLoader {
+    /// Check every source, reporting all the problems found
+    ///
+    /// Values are only checked for usability once every source parses, since
+    /// until then the effective configuration is unknown.
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut files = Vec::new();
+        for origin in self.file_origins() {
+            match SourceFile::read(origin, self) {
+                Ok(file) => {
+                    file.check(&mut diagnostics);
+                    files.push(file);
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+        let defaults = default_table().expect("the defaults serialize");
+        for (name, raw) in self.env_vars() {
+            let origin = Origin::Environment(name.clone());
+            let diagnostic = match env_key(name, &defaults) {
+                // Unknown keys are ignored, as they are in files
+                Some(key) if !is_known_key(&key) => {
+                    Some(Diagnostic::warning(Some(&key), unknown_key_message(&key)))
+                }
+                Some(key) => check_value(&key, env_value(raw))
+                    .err()
+                    .map(|message| Diagnostic::error(Some(&key), message)),
+                None => Some(Diagnostic::warning(
+                    None,
+                    "ignored, since it names no configuration key",
+                )),
+            };
+            diagnostics.extend(diagnostic.map(|diagnostic| diagnostic.with_origin(&origin)));
+        }
+        for (key, value, flag) in self.overrides() {
+            if let Err(message) = check_value(key, value.clone()) {
+                let origin = Origin::CommandLine(flag.to_string());
+                diagnostics.push(Diagnostic::error(Some(key), message).with_origin(&origin));
+            }
+        }
+
+        if !diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            match self.load() {
+                Ok(layered) => diagnostics.extend(check_config(&layered, &files)),
+                Err(e) => diagnostics.push(Diagnostic::error(None, format!("{e:#}"))),
+            }
+        }
+        diagnostics
+    }
+}
+
+impl ConfigFile {
+    /// Check the file as edited, on its own as `cmdr config edit` checks
+    /// it, before it is saved
+    pub fn check(&self) -> Vec<Diagnostic> {
+        ConfigLoader::new()
+            .with_config_file(self.path())
+            .with_edited_file(self.path(), self.to_string())
+            .check()
+    }
+}
+
+/// Whether the dotted `key` is a configuration key
+pub(crate) fn is_known_key(key: &str) -> bool {
+    known_keys().iter().any(|known| known == key)
+}
+
+/// Check that the dotted `key` names a configuration key or table,
+/// suggesting one it may be a misspelling of if not
+pub fn check_key(key: &str) -> anyhow::Result<()> {
+    let table = format!("{key}.");
+    if known_keys()
+        .iter()
+        .any(|known| known == key || known.starts_with(&table))
+    {
+        Ok(())
+    } else {
+        anyhow::bail!("{key}: {}", unknown_key_message(key))
+    }
+}
+
+/// Check that `value` is of the right type for the dotted `key`
+pub(crate) fn check_value(key: &str, value: Value) -> Result<(), String> {
+    if !is_known_key(key) {
+        return Err(unknown_key_message(key));
+    }
+    Config::deserialize(Value::Table(nested(key, value)))
+        .map(drop)
+        .map_err(|e| e.message().to_string())
+}
+
+/// Every key in `table`, which is at the dotted `prefix`, that holds a
+/// value rather than a table
+fn leaf_keys(table: &Table, prefix: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    for (name, value) in table {
+        let key = join(prefix, name);
+        match value {
+            Value::Table(table) => keys.extend(leaf_keys(table, &key)),
+            _ => keys.push(key),
+        }
+    }
+    keys
+}
+
+/// Every configuration key
+fn known_keys() -> Vec<String> {
+    let mut keys = leaf_keys(&default_table().expect("the defaults serialize"), "");
+    keys.extend(OPTIONAL_KEYS.iter().map(|(key, _)| key.to_string()));
+    keys
+}
+
+/// Say that `key` is unknown, suggesting a key or table it may be a
+/// misspelling of
+fn unknown_key_message(key: &str) -> String {
+    let mut candidates = known_keys();
+    let tables: Vec<String> = candidates
+        .iter()
+        .flat_map(|key| {
+            key.match_indices('.')
+                .map(|(end, _)| key[..end].to_string())
+        })
+        .collect();
+    candidates.extend(tables);
+    candidates.sort();
+    candidates.dedup();
+    match suggest(key, &candidates) {
+        Some(known) => format!("unknown key; did you mean `{known}`?"),
+        None => "unknown key".to_string(),
+    }
+}
+
+/// The known key closest to `key`, if any is close enough to be a likely
+/// misspelling
+///
+/// Keys in the same table are preferred, including those `key` misspells
+/// the start of, so `[shell] timout` suggests `timeout_secs`; otherwise a
+/// key of the same name in another table is suggested, as for
+/// `[shell] temperature`.
+fn suggest<'a>(key: &str, known: &'a [String]) -> Option<&'a str> {
+    let (table, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let siblings = known.iter().filter_map(|known| {
+        let (known_table, known_name) = known.rsplit_once('.').unwrap_or(("", known));
+        (known_table == table).then_some((known, known_name))
+    });
+    let close = siblings
+        .filter(|(_, known_name)| {
+            edit_distance(name, known_name) <= (name.len() / 3).max(1)
+                || (name.len() >= 4 && prefix_distance(name, known_name) <= 1)
+        })
+        .min_by_key(|(_, known_name)| edit_distance(name, known_name));
+    if let Some((known, _)) = close {
+        return Some(known);
+    }
+    known
+        .iter()
+        .find(|known| {
+            known
+                .rsplit_once('.')
+                .is_some_and(|(_, known_name)| known_name == name)
+        })
+        .map(String::as_str)
+}
+
+/// The smallest edit distance between `name` and a start of `known` about
+/// as long as it
+fn prefix_distance(name: &str, known: &str) -> usize {
+    let len = name.chars().count();
+    (len.saturating_sub(1)..=len + 1)
+        .map(|len| edit_distance(name, &known.chars().take(len).collect::<String>()))
+        .min()
+        .unwrap_or(usize::MAX)
+}
+
+/// The Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// A dotted key with the byte ranges of its name and value
+type KeySpans = (String, Option<Range<usize>>, Option<Range<usize>>);
+
+/// A configuration file with the positions of its keys
+struct SourceFile {
+    origin: Origin,
+    /// The file as cmdr reads it, upgraded to the current schema version
+    text: String,
+    keys: Vec<KeySpans>,
+    /// The version the file was written for, if upgrading it changed it
+    outdated: Option<u32>,
+}
+
+impl SourceFile {
+    fn read(origin: Origin, loader: &ConfigLoader) -> Result<Self, Diagnostic> {
+        let path = origin.path().expect("files have paths");
+        let text = loader.read_text(path).map_err(|e| {
+            Diagnostic::error(None, format!("cannot read: {}", e.root_cause())).with_origin(&origin)
+        })?;
+        let mut file = Self {
+            origin,
+            text,
+            keys: Vec::new(),
+            outdated: None,
+        };
+        let mut document = match ImDocument::parse(file.text.as_str()) {
+            Ok(document) => {
+                collect_keys(document.as_table(), "", &mut file.keys);
+                document.into_mut()
+            }
+            Err(e) => {
+                let offset = e.span().map(|span| span.start);
+                return Err(Diagnostic::error(None, e.message().trim()).at(&file, offset));
+            }
+        };
+        let version = apply_migrations(&mut document).map_err(|e| {
+            Diagnostic::error(Some(VERSION_KEY), e.to_string())
+                .at(&file, file.offset_of(VERSION_KEY))
+        })?;
+        let migrated = document.to_string();
+        if migrated != file.text {
+            file.text = migrated;
+            file.keys.clear();
+            let document =
+                ImDocument::parse(file.text.as_str()).expect("migrations keep files valid");
+            collect_keys(document.as_table(), "", &mut file.keys);
+            file.outdated = Some(version);
+        }
+        Ok(file)
+    }
+
+    /// Report unknown keys, keys the file may not set and values of the
+    /// wrong type
+    fn check(&self, diagnostics: &mut Vec<Diagnostic>) {
+        if let Some(version) = self.outdated {
+            let diagnostic = Diagnostic::warning(
+                None,
+                format!(
+                    "written for configuration version {version}, and checked as upgraded to \
+                     version {CONFIG_VERSION}; `cmdr config migrate` upgrades the file"
+                ),
+            );
+            diagnostics.push(diagnostic.at(self, None));
+        }
+        let known = known_keys();
+        let mut unknown: Vec<&str> = Vec::new();
+        for (key, key_span, _) in &self.keys {
+            // Report a whole unknown table once
+            if unknown
+                .iter()
+                .any(|table| key.starts_with(&format!("{table}.")))
+            {
+                continue;
+            }
+            let nested = format!("{key}.");
+            if !known
+                .iter()
+                .any(|known| known == key || known.starts_with(&nested))
+            {
+                unknown.push(key);
+                let diagnostic = Diagnostic::warning(Some(key), unknown_key_message(key));
+                diagnostics.push(diagnostic.at(self, key_span.as_ref().map(|span| span.start)));
+            } else if matches!(self.origin, Origin::Project(_))
+                && PROJECT_RESTRICTED_KEYS.contains(&key.as_str())
+            {
+                let diagnostic = Diagnostic::warning(
+                    Some(key),
+                    "ignored, since only user and system files may set it",
+                );
+                diagnostics.push(diagnostic.at(self, key_span.as_ref().map(|span| span.start)));
+            }
+        }
+
+        if let Err(e) = toml::from_str::<Config>(&self.text) {
+            let offset = e.span().map(|span| span.start);
+            let key = offset.and_then(|offset| {
+                self.keys
+                    .iter()
+                    .filter(|(_, _, value)| {
+                        value.as_ref().is_some_and(|span| span.contains(&offset))
+                    })
+                    .map(|(key, _, _)| key.as_str())
+                    .next_back()
+            });
+            diagnostics.push(Diagnostic::error(key, e.message()).at(self, offset));
+        }
+    }
+
+    /// Byte offset of the value of `key`, or of the key itself
+    fn offset_of(&self, key: &str) -> Option<usize> {
+        let (_, key_span, value_span) = self.keys.iter().find(|(known, _, _)| known == key)?;
+        value_span
+            .as_ref()
+            .or(key_span.as_ref())
+            .map(|span| span.start)
+    }
+}
+
+/// Record every key in `table`, which is at the dotted `prefix`, with the
+/// positions of its name and value
+fn collect_keys(table: &dyn TableLike, prefix: &str, keys: &mut Vec<KeySpans>) {
+    for (name, item) in table.iter() {
+        let key = join(prefix, name);
+        let key_span = table.key(name).and_then(|key| key.span());
+        let value_span = match item {
+            Item::Value(value) => value.span(),
+            _ => None,
+        };
+        keys.push((key.clone(), key_span, value_span));
+        if let Some(nested) = item.as_table_like() {
+            collect_keys(nested, &key, keys);
+        }
+    }
+}
+
+/// Check that cmdr can use the effective configuration, pointing each
+/// problem at the file, variable or flag that set the value
+fn check_config(layered: &LayeredConfig, files: &[SourceFile]) -> Vec<Diagnostic> {
+    let config = layered.config();
+    let mut problems: Vec<(Severity, &str, String)> = Vec::new();
+    let error = |key, message| (Severity::Error, key, message);
+
+    let inference = &config.inference;
+    if let Some(temperature) = inference.temperature {
+        if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+            problems.push(error(
+                "inference.temperature",
+                format!("must be between 0 and {MAX_TEMPERATURE}, got {temperature}"),
+            ));
+        }
+    }
+    if inference
+        .model
+        .as_deref()
+        .is_some_and(|model| model.trim().is_empty())
+    {
+        problems.push(error("inference.model", "must not be empty".to_string()));
+    }
+    if inference.threads == Some(0) {
+        problems.push(error("inference.threads", "must be at least 1".to_string()));
+    }
+    if inference.context_size == Some(0) {
+        problems.push(error(
+            "inference.context_size",
+            "must be at least 1".to_string(),
+        ));
+    }
+    if let Some(path) = &inference.model_path {
+        if !path.exists() {
+            problems.push(error(
+                "inference.model_path",
+                format!("{} does not exist", path.display()),
+            ));
+        }
+    }
+
+    let shell = &config.shell;
+    if let Some(program) = &shell.default_shell {
+        if find_program(program).is_none() {
+            problems.push(error(
+                "shell.default_shell",
+                format!("{program} is not on PATH"),
+            ));
+        } else if ShellKind::from_program(program).is_none() {
+            problems.push((
+                Severity::Warning,
+                "shell.default_shell",
+                format!(
+                    "cmdr knows the syntax of sh, bash, zsh and fish, so commands for \
+                     {program} will get no dialect hints"
+                ),
+            ));
+        }
+    }
+    if shell.timeout_secs == Some(0) {
+        problems.push(error(
+            "shell.timeout_secs",
+            "must be at least 1".to_string(),
+        ));
+    }
+
+    if let Err(e) = config.prompt.load_template() {
+        problems.push(error("prompt.template", format!("{e:#}")));
+    }
+    if let Err(e) = config.prompt.load_examples() {
+        problems.push(error("prompt.examples_dir", format!("{e:#}")));
+    }
+    if let Err(e) = config.safety.load_policy() {
+        problems.push(error("safety.policy", format!("{e:#}")));
+    }
+
+    problems
+        .into_iter()
+        .map(|(severity, key, message)| {
+            let diagnostic = Diagnostic::new(severity, Some(key), message);
+            let Some(origin) = layered.origin(key) else {
+                return diagnostic;
+            };
+            match files.iter().find(|file| &file.origin == origin) {
+                Some(file) => diagnostic.at(file, file.offset_of(key)),
+                None => diagnostic.with_origin(origin),
+            }
+        })
+        .collect()
+}
+
+/// Where `program`, a name or a path, is found
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return is_executable(Path::new(program)).then(|| PathBuf::from(program));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
+
+/// The 1-based line and column of byte `offset` in `text`
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |line| line.chars().count())
+        + 1;
+    (line, column)
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}.{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check `config` as the user file
+    fn check(config: &str) -> Vec<Diagnostic> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, config).unwrap();
+        ConfigLoader::new().with_config_file(path).check()
+    }
+
+    #[test]
+    fn test_partial_files_deserialize() {
+        let config: Config = toml::from_str("[shell]\ntimeout_secs = 5\n").unwrap();
+        assert_eq!(config.shell.timeout_secs, Some(5));
+        assert!(config.shell.show_translation);
+        assert_eq!(config.ui.prompt, "[cmdr] ");
+        assert!(check("").is_empty());
+    }
+
+    #[test]
+    fn test_optional_keys_exist() {
+        for (key, example) in OPTIONAL_KEYS {
+            let value = env_value(example);
+            assert!(check_value(key, value.clone()).is_ok(), "{key} = {example}");
+            let config = Config::deserialize(Value::Table(nested(key, value))).unwrap();
+            let table = Value::try_from(config).unwrap();
+            let set = key
+                .split('.')
+                .try_fold(&table, |value, name| value.get(name));
+            assert!(set.is_some(), "{key} is not a configuration key");
+        }
+    }
+
+    #[test]
+    fn test_reports_positions() {
+        let diagnostics = check("[inference]\ntemperature = 3.5\n\n[shell]\ntimeout_secs = 0\n");
+        assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].key.as_deref(), Some("inference.temperature"));
+        assert_eq!(diagnostics[0].position, Some((2, 15)));
+        assert_eq!(diagnostics[1].key.as_deref(), Some("shell.timeout_secs"));
+        assert_eq!(diagnostics[1].position, Some((5, 16)));
+        let line = diagnostics[0].to_string();
+        assert!(
+            line.ends_with(
+                "config.toml:2:15: error: inference.temperature: must be between 0 and 2, got 3.5"
+            ),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn test_checks_edits_before_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[inference]\ntemperature = 0.5\n").unwrap();
+
+        let mut file = ConfigFile::open(&path).unwrap();
+        file.set("inference.temperature", "5").unwrap();
+        let diagnostics = file.check();
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].key.as_deref(), Some("inference.temperature"));
+        assert_eq!(diagnostics[0].position, Some((2, 15)));
+
+        file.set("inference.temperature", "1.5").unwrap();
+        assert!(file.check().is_empty());
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(saved, "[inference]\ntemperature = 0.5\n");
+    }
+
+    #[test]
+    fn test_reports_type_and_syntax_errors() {
+        let diagnostics = check("[shell]\nauto_execute = \"yes\"\n");
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].key.as_deref(), Some("shell.auto_execute"));
+        assert_eq!(diagnostics[0].position, Some((2, 16)));
+
+        let diagnostics = check("[shell\n");
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].position.map(|(line, _)| line), Some(1));
+    }
+
+    #[test]
+    fn test_reports_newer_versions() {
+        assert!(check(&format!("version = {CONFIG_VERSION}\n")).is_empty());
+
+        let diagnostics = check("\nversion = 99\n");
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].key.as_deref(), Some("version"));
+        assert_eq!(diagnostics[0].position, Some((2, 11)));
+    }
+
+    #[test]
+    fn test_suggests_known_keys() {
+        let diagnostics =
+            check("[shell]\ntimout = 5\ntemperature = 0.2\n\n[shel]\npty = \"never\"\n");
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.key.clone().unwrap(), d.message.clone(), d.position))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    "shell.timout".to_string(),
+                    "unknown key; did you mean `shell.timeout_secs`?".to_string(),
+                    Some((2, 1))
+                ),
+                (
+                    "shell.temperature".to_string(),
+                    "unknown key; did you mean `inference.temperature`?".to_string(),
+                    Some((3, 1))
+                ),
+                (
+                    "shel".to_string(),
+                    "unknown key; did you mean `shell`?".to_string(),
+                    Some((5, 2))
+                ),
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn test_checks_paths_and_programs() {
+        let diagnostics = check(
+            "[inference]\nmodel_path = \"missing.gguf\"\n\n[shell]\ndefault_shell = \"no-such-shell\"\n",
+        );
+        let keys: Vec<_> = diagnostics.iter().map(|d| d.key.as_deref()).collect();
+        assert_eq!(
+            keys,
+            [Some("inference.model_path"), Some("shell.default_shell")]
+        );
+
+        let diagnostics = ConfigLoader::new()
+            .with_env([("CMDR_INFERENCE_THREADS".to_string(), "many".to_string())])
+            .check();
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(
+            diagnostics[0].origin,
+            Some(Origin::Environment("CMDR_INFERENCE_THREADS".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("timeout", "timeout"), 0);
+        assert_eq!(edit_distance("prmopt", "prompt"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,92 @@
+//! Configuration files as written for each schema version, read and
+//! upgraded by the current one
+
+use cmdr_config::{
+    backup_path, load_config, upgrade, upgrade_file, ConfigLoader, Origin, CONFIG_VERSION,
+};
+use cmdr_core::PtyMode;
+use std::path::PathBuf;
+
+/// Each fixture with the version it was written for
+const FIXTURES: &[(&str, u32)] = &[
+    // As `save_config` wrote the defaults before files were layered
+    ("v0-initial.toml", 0),
+    // A hand-written partial file from before versioning
+    ("v0-layered.toml", 0),
+    ("v1.toml", 1),
+];
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn test_every_version_loads() {
+    for (name, _) in FIXTURES {
+        let config = load_config(&fixture(name)).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION, "{name}");
+    }
+
+    let config = load_config(&fixture("v0-initial.toml")).unwrap();
+    assert_eq!(config.inference.threads, Some(8));
+    assert_eq!(config.shell.default_shell.as_deref(), Some("bash"));
+    assert!(config.shell.auto_execute);
+
+    let config = load_config(&fixture("v0-layered.toml")).unwrap();
+    assert_eq!(config.inference.temperature, Some(0.2));
+    assert_eq!(config.shell.limits.memory_bytes, Some(1 << 30));
+    assert_eq!(config.prompt.example_count, 5);
+
+    let config = load_config(&fixture("v1.toml")).unwrap();
+    assert_eq!(config.shell.timeout_secs, Some(30));
+    assert_eq!(config.shell.pty, PtyMode::Never);
+}
+
+#[test]
+fn test_loader_reads_every_version() {
+    for (name, _) in FIXTURES {
+        let layered = ConfigLoader::new()
+            .with_user_file(Some(fixture(name)))
+            .load()
+            .unwrap();
+        assert_eq!(layered.config().version, CONFIG_VERSION, "{name}");
+        // A file's version is not a setting
+        assert_eq!(layered.origin("version"), Some(&Origin::Default), "{name}");
+    }
+}
+
+#[test]
+fn test_upgrade_keeps_comments() {
+    for (name, version) in FIXTURES {
+        let text = std::fs::read_to_string(fixture(name)).unwrap();
+        let upgraded = upgrade(&text).unwrap();
+        if *version == CONFIG_VERSION {
+            assert_eq!(upgraded, None, "{name}");
+            continue;
+        }
+        let upgraded = upgraded.unwrap();
+        assert_eq!(upgraded.from, *version, "{name}");
+        assert_eq!(
+            upgraded.text,
+            format!("version = {CONFIG_VERSION}\n{text}"),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn test_upgrade_file_keeps_a_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    let original = std::fs::read_to_string(fixture("v0-layered.toml")).unwrap();
+    std::fs::write(&path, &original).unwrap();
+
+    let upgraded = upgrade_file(&path).unwrap().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), upgraded.text);
+    let backup = backup_path(&path, 0);
+    assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+
+    assert_eq!(upgrade_file(&path).unwrap(), None);
+}
@@ -0,0 +1,461 @@
+//! Environment context collection
+//!
+//! Every source is best effort: anything that cannot be determined quickly
+//! is left out rather than reported as an error.
+
+use super::{ContextOptions, EnvironmentContext, GitStatus};
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a helper process (`git`, `$SHELL --version`) may run
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Collects an [`EnvironmentContext`] according to [`ContextOptions`]
+#[derive(Debug, Clone, Default)]
+pub struct ContextCollector {
+    options: ContextOptions,
+    cwd: Option<PathBuf>,
+}
+
+impl ContextCollector {
+    /// Create a collector with the given options
+    pub fn new(options: ContextOptions) -> Self {
+        Self { options, cwd: None }
+    }
+
+    /// Describe `cwd` instead of the process's working directory
+    pub fn with_cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Get the collector's options
+    pub fn options(&self) -> &ContextOptions {
+        &self.options
+    }
+
+    /// Gather every enabled source
+    pub fn collect(&self) -> EnvironmentContext {
+        let options = &self.options;
+        let cwd = self.cwd.clone().or_else(|| env::current_dir().ok());
+        let mut context = EnvironmentContext::default();
+
+        if options.os {
+            context.os = Some(os_description());
+        }
+        if options.shell {
+            context.shell = shell_description();
+        }
+        if options.cwd {
+            context.cwd = cwd.clone();
+        }
+        if let Some(dir) = cwd.as_deref() {
+            if options.directory_listing {
+                if let Some((entries, omitted)) =
+                    directory_listing(dir, options.max_directory_entries)
+                {
+                    context.directory_listing = Some(entries);
+                    context.omitted_entries = omitted;
+                }
+            }
+            if options.git {
+                match git_status(dir, options.max_git_status_lines) {
+                    GitProbe::Repository(status) => context.git = Some(status),
+                    GitProbe::NotARepository => context.not_a_repository = true,
+                    GitProbe::Unavailable => {}
+                }
+            }
+        }
+        if options.tools {
+            context.tools = Some(tools_on_path(&options.tool_names));
+        }
+
+        enforce_caps(&mut context, options);
+        context
+    }
+}
+
+/// Shrink `context` to the byte limits in `options`
+///
+/// Lists lose their last items and text is cut until each source fits
+/// `max_source_bytes`. Then whole sources are dropped, least useful first,
+/// until the rendered context fits `max_total_bytes`.
+fn enforce_caps(context: &mut EnvironmentContext, options: &ContextOptions) {
+    let max = options.max_source_bytes;
+    for text in [&mut context.os, &mut context.shell].into_iter().flatten() {
+        truncate(text, max);
+    }
+    if let Some(entries) = &mut context.directory_listing {
+        while entries.join(", ").len() > max && entries.pop().is_some() {
+            context.omitted_entries += 1;
+        }
+    }
+    if let Some(git) = &mut context.git {
+        while git.changes.iter().map(|c| c.len() + 3).sum::<usize>() > max
+            && git.changes.pop().is_some()
+        {
+            git.omitted_changes += 1;
+        }
+    }
+    if let Some(tools) = &mut context.tools {
+        while tools.join(", ").len() > max && tools.pop().is_some() {}
+    }
+
+    let droppers: [fn(&mut EnvironmentContext); 6] = [
+        |c| c.tools = None,
+        |c| {
+            c.directory_listing = None;
+            c.omitted_entries = 0;
+        },
+        |c| {
+            c.git = None;
+            c.not_a_repository = false;
+        },
+        |c| c.shell = None,
+        |c| c.os = None,
+        |c| c.cwd = None,
+    ];
+    for drop_source in droppers {
+        if context.render().len() <= options.max_total_bytes {
+            break;
+        }
+        drop_source(context);
+    }
+}
+
+/// Cut `text` to at most `max_bytes`, on a character boundary, marking the cut
+fn truncate(text: &mut String, max_bytes: usize) {
+    const MARKER: &str = "...";
+    if text.len() <= max_bytes {
+        return;
+    }
+    let mut end = max_bytes.saturating_sub(MARKER.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    text.push_str(MARKER);
+}
+
+/// Operating system, with the distribution or release where known
+fn os_description() -> String {
+    let os = match env::consts::OS {
+        "linux" => "Linux",
+        "macos" => "macOS",
+        "freebsd" => "FreeBSD",
+        "openbsd" => "OpenBSD",
+        "windows" => "Windows",
+        other => other,
+    };
+    let release = match env::consts::OS {
+        "linux" => fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|text| os_release_name(&text)),
+        "macos" => probe(Command::new("sw_vers").arg("-productVersion"))
+            .map(|version| version.trim().to_string()),
+        _ => None,
+    };
+    match release {
+        Some(release) if !release.is_empty() => {
+            format!("{os} ({release}, {})", env::consts::ARCH)
+        }
+        _ => format!("{os} ({})", env::consts::ARCH),
+    }
+}
+
+/// Extract `PRETTY_NAME` (or `NAME`) from `/etc/os-release`
+fn os_release_name(text: &str) -> Option<String> {
+    let value = |key: &str| {
+        text.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string())
+        })
+    };
+    value("PRETTY_NAME").or_else(|| value("NAME"))
+}
+
+/// Name and version of the user's shell, from `$SHELL`
+fn shell_description() -> Option<String> {
+    let shell = env::var("SHELL").ok().filter(|shell| !shell.is_empty())?;
+    let name = Path::new(&shell).file_name()?.to_string_lossy().to_string();
+    let version = probe(Command::new(&shell).arg("--version"))
+        .and_then(|output| version_number(output.lines().next()?));
+    Some(match version {
+        Some(version) => format!("{name} {version}"),
+        None => name,
+    })
+}
+
+/// Find the first dotted version number in a `--version` line
+fn version_number(line: &str) -> Option<String> {
+    line.split(|c: char| c.is_whitespace() || c == ',' || c == '(')
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| word.contains('.') && word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|word| {
+            word.trim_end_matches(|c: char| !c.is_ascii_alphanumeric())
+                .to_string()
+        })
+}
+
+/// Sorted directory entries, directories marked with `/`, and how many were
+/// left out beyond `max_entries`
+fn directory_listing(dir: &Path, max_entries: usize) -> Option<(Vec<String>, usize)> {
+    let mut entries: Vec<String> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().is_ok_and(|ty| ty.is_dir()) {
+                name.push('/');
+            }
+            name
+        })
+        .collect();
+    // Visible entries first; dotfiles are rarely what a request is about
+    entries.sort_by(|a, b| (a.starts_with('.'), a).cmp(&(b.starts_with('.'), b)));
+
+    let omitted = entries.len().saturating_sub(max_entries);
+    entries.truncate(max_entries);
+    Some((entries, omitted))
+}
+
+enum GitProbe {
+    Repository(GitStatus),
+    NotARepository,
+    /// git is missing or did not answer in time
+    Unavailable,
+}
+
+/// Branch and changed files of the repository containing `dir`
+fn git_status(dir: &Path, max_lines: usize) -> GitProbe {
+    let output = probe_status(Command::new("git").arg("-C").arg(dir).args([
+        "status",
+        "--porcelain=v1",
+        "--branch",
+    ]));
+    let Some((success, output)) = output else {
+        return GitProbe::Unavailable;
+    };
+    if !success {
+        return GitProbe::NotARepository;
+    }
+
+    let mut lines = output.lines();
+    let branch = lines
+        .next()
+        .and_then(|header| header.strip_prefix("## "))
+        .and_then(|header| {
+            let branch = header.split("...").next()?.trim();
+            let branch = branch.strip_prefix("No commits yet on ").unwrap_or(branch);
+            (!branch.starts_with("HEAD")).then(|| branch.to_string())
+        });
+    let changes: Vec<String> = lines.map(str::to_string).collect();
+    let omitted_changes = changes.len().saturating_sub(max_lines);
+
+    GitProbe::Repository(GitStatus {
+        branch,
+        changes: changes.into_iter().take(max_lines).collect(),
+        omitted_changes,
+    })
+}
+
+/// Which of `names` are executables on `PATH`, in the order given
+fn tools_on_path(names: &[String]) -> Vec<String> {
+    let dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+    names
+        .iter()
+        .filter(|name| dirs.iter().any(|dir| is_executable(&dir.join(name))))
+        .cloned()
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
+
+/// Run a helper and return its stdout if it succeeds within the timeout
+fn probe(command: &mut Command) -> Option<String> {
+    match probe_status(command)? {
+        (true, output) => Some(output),
+        (false, _) => None,
+    }
+}
+
+/// Run a helper and return whether it succeeded and its stdout
+///
+/// Returns `None` if it cannot be started or does not finish within
+/// [`PROBE_TIMEOUT`], in which case it is killed.
+fn probe_status(command: &mut Command) -> Option<(bool, String)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < PROBE_TIMEOUT => {
+                thread::sleep(Duration::from_millis(5))
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    Some((status.success(), reader.join().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn only(options: ContextOptions) -> ContextOptions {
+        ContextOptions {
+            os: false,
+            shell: false,
+            cwd: false,
+            directory_listing: false,
+            git: false,
+            tools: false,
+            ..options
+        }
+    }
+
+    #[test]
+    fn test_disabled_sources_are_not_collected() {
+        let collector = ContextCollector::new(only(ContextOptions::default()));
+        assert_eq!(collector.collect(), EnvironmentContext::default());
+    }
+
+    #[test]
+    fn test_directory_listing_is_sorted_and_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.txt", "a.txt", ".hidden", "c.txt"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::create_dir(dir.path().join("src")).unwrap();
+
+        let options = ContextOptions {
+            cwd: true,
+            directory_listing: true,
+            max_directory_entries: 3,
+            ..only(ContextOptions::default())
+        };
+        let context = ContextCollector::new(options)
+            .with_cwd(dir.path())
+            .collect();
+
+        assert_eq!(context.cwd.as_deref(), Some(dir.path()));
+        assert_eq!(
+            context.directory_listing,
+            Some(vec![
+                "a.txt".to_string(),
+                "b.txt".to_string(),
+                "c.txt".to_string()
+            ])
+        );
+        assert_eq!(context.omitted_entries, 2);
+    }
+
+    #[test]
+    fn test_git_status_outside_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ContextOptions {
+            git: true,
+            ..only(ContextOptions::default())
+        };
+        let context = ContextCollector::new(options)
+            .with_cwd(dir.path())
+            .collect();
+
+        // Outside a repository, or without git installed, there is no status
+        assert!(context.git.is_none());
+    }
+
+    #[test]
+    fn test_caps_shrink_sources_then_drop_them() {
+        let mut context = EnvironmentContext {
+            os: Some("Linux (Debian GNU/Linux 12, x86_64)".to_string()),
+            shell: Some("bash 5.2.15".to_string()),
+            directory_listing: Some((0..10).map(|i| format!("file{i}.txt")).collect()),
+            tools: Some(vec!["git".to_string(), "cargo".to_string()]),
+            ..EnvironmentContext::default()
+        };
+        let options = ContextOptions {
+            max_source_bytes: 24,
+            max_total_bytes: 110,
+            ..ContextOptions::default()
+        };
+        enforce_caps(&mut context, &options);
+
+        assert_eq!(context.os.as_deref(), Some("Linux (Debian GNU/Lin..."));
+        assert_eq!(context.tools, None);
+        assert_eq!(
+            context.directory_listing,
+            Some(vec!["file0.txt".to_string(), "file1.txt".to_string()])
+        );
+        assert_eq!(context.omitted_entries, 8);
+        assert!(context.render().len() <= 110);
+    }
+
+    #[test]
+    fn test_truncate_respects_char_boundaries() {
+        let mut text = "ééééé".to_string();
+        truncate(&mut text, 6);
+        assert_eq!(text, "é...");
+    }
+
+    #[test]
+    fn test_parses_os_release_and_versions() {
+        let os_release = "NAME=\"Ubuntu\"\nPRETTY_NAME=\"Ubuntu 24.04 LTS\"\n";
+        assert_eq!(
+            os_release_name(os_release).as_deref(),
+            Some("Ubuntu 24.04 LTS")
+        );
+        assert_eq!(os_release_name("NAME=Arch\n").as_deref(), Some("Arch"));
+
+        assert_eq!(
+            version_number("GNU bash, version 5.2.21(1)-release (x86_64-pc-linux-gnu)").as_deref(),
+            Some("5.2.21")
+        );
+        assert_eq!(
+            version_number("zsh 5.9 (x86_64-apple-darwin23.0)").as_deref(),
+            Some("5.9")
+        );
+        assert_eq!(
+            version_number("fish, version 3.7.1").as_deref(),
+            Some("3.7.1")
+        );
+    }
+
+    #[test]
+    fn test_tools_on_path_keeps_requested_order() {
+        let found = tools_on_path(&["definitely-not-a-tool".to_string(), "sh".to_string()]);
+        assert_eq!(found, vec!["sh".to_string()]);
+    }
+}
@@ -0,0 +1,230 @@
+//! Environment context for translation
+//!
+//! This module gathers facts about the user's environment (operating system,
+//! shell, working directory, git state, installed tools) so the model can
+//! tailor commands to it.
+
+mod collector;
+
+pub use collector::ContextCollector;
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Which context sources to collect and how much of each to keep
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextOptions {
+    /// Collect the operating system and distribution
+    pub os: bool,
+    /// Collect the current shell and its version
+    pub shell: bool,
+    /// Collect the working directory
+    pub cwd: bool,
+    /// Collect a listing of the working directory
+    pub directory_listing: bool,
+    /// Collect git repository status
+    pub git: bool,
+    /// Collect which common tools are on `PATH`
+    pub tools: bool,
+    /// Maximum number of directory entries listed
+    pub max_directory_entries: usize,
+    /// Maximum number of changed files reported from `git status`
+    pub max_git_status_lines: usize,
+    /// Maximum size in bytes of any single rendered source
+    pub max_source_bytes: usize,
+    /// Maximum size in bytes of the whole rendered context
+    pub max_total_bytes: usize,
+    /// Tools looked up on `PATH`
+    pub tool_names: Vec<String>,
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            os: true,
+            shell: true,
+            cwd: true,
+            directory_listing: true,
+            git: true,
+            tools: true,
+            max_directory_entries: 40,
+            max_git_status_lines: 20,
+            max_source_bytes: 512,
+            max_total_bytes: 2048,
+            tool_names: [
+                "git",
+                "docker",
+                "kubectl",
+                "python3",
+                "node",
+                "npm",
+                "cargo",
+                "go",
+                "make",
+                "curl",
+                "wget",
+                "jq",
+                "rg",
+                "fd",
+                "fzf",
+                "tar",
+                "zip",
+                "unzip",
+                "rsync",
+                "ssh",
+                "systemctl",
+                "brew",
+                "apt",
+                "dnf",
+                "pacman",
+                "ffmpeg",
+            ]
+            .iter()
+            .map(|tool| tool.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// Git state of the working directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GitStatus {
+    /// Current branch, if not detached
+    pub branch: Option<String>,
+    /// `git status --porcelain` lines, capped
+    pub changes: Vec<String>,
+    /// Number of changed files left out of `changes`
+    pub omitted_changes: usize,
+}
+
+/// Facts about the user's environment, each present only when collected
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentContext {
+    /// Operating system and distribution, e.g. `Linux (Ubuntu 24.04 LTS)`
+    pub os: Option<String>,
+    /// Shell name and version, e.g. `bash 5.2.21`
+    pub shell: Option<String>,
+    /// Working directory
+    pub cwd: Option<PathBuf>,
+    /// Directory entries, directories suffixed with `/`
+    pub directory_listing: Option<Vec<String>>,
+    /// Number of directory entries left out of `directory_listing`
+    pub omitted_entries: usize,
+    /// Git status; `Some` with no changes means a clean repository
+    pub git: Option<GitStatus>,
+    /// Whether the working directory was checked and is not a git repository
+    pub not_a_repository: bool,
+    /// Common tools found on `PATH`
+    pub tools: Option<Vec<String>>,
+}
+
+impl EnvironmentContext {
+    /// Render each collected source as a `(name, text)` pair
+    ///
+    /// The names double as prompt template variables.
+    pub fn sources(&self) -> Vec<(&'static str, String)> {
+        let mut sources = Vec::new();
+        if let Some(os) = &self.os {
+            sources.push(("os", os.clone()));
+        }
+        if let Some(shell) = &self.shell {
+            sources.push(("shell", shell.clone()));
+        }
+        if let Some(cwd) = &self.cwd {
+            sources.push(("cwd", cwd.display().to_string()));
+        }
+        if let Some(entries) = &self.directory_listing {
+            let mut listing = if entries.is_empty() {
+                "(empty)".to_string()
+            } else {
+                entries.join(", ")
+            };
+            if self.omitted_entries > 0 {
+                listing.push_str(&format!(" and {} more", self.omitted_entries));
+            }
+            sources.push(("directory_listing", listing));
+        }
+        if let Some(git) = &self.git {
+            let mut status = match &git.branch {
+                Some(branch) => format!("on branch {branch}"),
+                None => "detached HEAD".to_string(),
+            };
+            if git.changes.is_empty() {
+                status.push_str(", clean");
+            } else {
+                for change in &git.changes {
+                    status.push_str("\n  ");
+                    status.push_str(change);
+                }
+                if git.omitted_changes > 0 {
+                    status.push_str(&format!("\n  ... and {} more", git.omitted_changes));
+                }
+            }
+            sources.push(("git_status", status));
+        } else if self.not_a_repository {
+            sources.push(("git_status", "not a git repository".to_string()));
+        }
+        if let Some(tools) = &self.tools {
+            sources.push(("tools", tools.join(", ")));
+        }
+        sources
+    }
+
+    /// Render the context as labelled lines for a prompt
+    pub fn render(&self) -> String {
+        self.sources()
+            .into_iter()
+            .map(|(name, text)| format!("{}: {text}\n", label(name)))
+            .collect()
+    }
+}
+
+/// Human-readable label for a source name
+fn label(name: &str) -> &'static str {
+    match name {
+        "os" => "OS",
+        "shell" => "Shell",
+        "cwd" => "Working directory",
+        "directory_listing" => "Directory contents",
+        "git_status" => "Git",
+        "tools" => "Available tools",
+        _ => "Other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> EnvironmentContext {
+        EnvironmentContext {
+            os: Some("Linux (Debian GNU/Linux 12)".to_string()),
+            shell: Some("bash 5.2.15".to_string()),
+            cwd: Some(PathBuf::from("/home/user/project")),
+            directory_listing: Some(vec!["Cargo.toml".to_string(), "src/".to_string()]),
+            omitted_entries: 3,
+            git: Some(GitStatus {
+                branch: Some("main".to_string()),
+                changes: vec![" M src/lib.rs".to_string()],
+                omitted_changes: 0,
+            }),
+            not_a_repository: false,
+            tools: Some(vec!["git".to_string(), "cargo".to_string()]),
+        }
+    }
+
+    #[test]
+    fn test_render_labels_each_source() {
+        assert_eq!(
+            context().render(),
+            "OS: Linux (Debian GNU/Linux 12)\n\
+             Shell: bash 5.2.15\n\
+             Working directory: /home/user/project\n\
+             Directory contents: Cargo.toml, src/ and 3 more\n\
+             Git: on branch main\n   M src/lib.rs\n\
+             Available tools: git, cargo\n"
+        );
+        assert_eq!(EnvironmentContext::default().render(), "");
+    }
+}
@@ -2,9 +2,12 @@
 //!
 //! This module defines the trait that all inference engines must implement.
 
-use super::{InferenceError, InferenceResult};
+use super::{GenerationOptions, InferenceError, InferenceResult};
 use async_trait::async_trait;
 
+/// Callback receiving generated text as it is streamed
+pub type TokenCallback<'a> = dyn FnMut(&str) + Send + 'a;
+
 /// Trait for LLM inference engines
 #[async_trait]
 pub trait InferenceEngine: Send + Sync {
@@ -17,6 +20,24 @@ pub trait InferenceEngine: Send + Sync {
     /// Generate a response for the given prompt
     async fn generate(&mut self, prompt: &str) -> InferenceResult<String>;
 
+    /// Generate a response, passing each chunk of text to `on_token` as soon
+    /// as it is produced
+    ///
+    /// Returns the complete response, or [`InferenceError::Cancelled`] /
+    /// [`InferenceError::Timeout`] if `options` stops generation early. The
+    /// default implementation waits for [`generate`](Self::generate) and
+    /// delivers the result as a single chunk.
+    async fn generate_stream(
+        &mut self,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> InferenceResult<String> {
+        let response = options.run(self.generate(prompt)).await?;
+        on_token(&response);
+        Ok(response)
+    }
+
     /// Set generation parameters
     fn set_parameters(&mut self, temperature: f32, max_tokens: usize) -> InferenceResult<()>;
 
@@ -24,9 +45,45 @@ pub trait InferenceEngine: Send + Sync {
     fn is_model_loaded(&self) -> bool;
 }
 
+/// A boxed engine, so the backend can be chosen at run time
+#[async_trait]
+impl<E: InferenceEngine + ?Sized> InferenceEngine for Box<E> {
+    fn initialize(&mut self) -> InferenceResult<()> {
+        (**self).initialize()
+    }
+
+    fn load_model(&mut self, model_path: &str) -> InferenceResult<()> {
+        (**self).load_model(model_path)
+    }
+
+    async fn generate(&mut self, prompt: &str) -> InferenceResult<String> {
+        (**self).generate(prompt).await
+    }
+
+    async fn generate_stream(
+        &mut self,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> InferenceResult<String> {
+        (**self).generate_stream(prompt, options, on_token).await
+    }
+
+    fn set_parameters(&mut self, temperature: f32, max_tokens: usize) -> InferenceResult<()> {
+        (**self).set_parameters(temperature, max_tokens)
+    }
+
+    fn is_model_loaded(&self) -> bool {
+        (**self).is_model_loaded()
+    }
+}
+
 /// Mock inference engine for testing
 pub struct MockInferenceEngine {
     pub model_loaded: bool,
+    /// Canned responses returned in turn instead of echoing the prompt
+    pub responses: Vec<String>,
+    next_response: usize,
 }
 
 impl MockInferenceEngine {
@@ -34,8 +91,22 @@ impl MockInferenceEngine {
     pub fn new() -> Self {
         Self {
             model_loaded: false,
+            responses: Vec::new(),
+            next_response: 0,
         }
     }
+
+    /// Always respond with `response`
+    pub fn with_response(self, response: impl Into<String>) -> Self {
+        self.with_responses(vec![response.into()])
+    }
+
+    /// Respond with each of `responses` in turn, starting over after the last
+    pub fn with_responses(mut self, responses: Vec<String>) -> Self {
+        self.responses = responses;
+        self.next_response = 0;
+        self
+    }
 }
 
 impl Default for MockInferenceEngine {
@@ -62,8 +133,27 @@ impl InferenceEngine for MockInferenceEngine {
             ));
         }
 
-        // Return a mock response based on the prompt
-        Ok(format!("Mock response to: {prompt}"))
+        // Return the next canned response, or a mock response based on the prompt
+        if self.responses.is_empty() {
+            return Ok(format!("Mock response to: {prompt}"));
+        }
+        let response = self.responses[self.next_response % self.responses.len()].clone();
+        self.next_response += 1;
+        Ok(response)
+    }
+
+    async fn generate_stream(
+        &mut self,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> InferenceResult<String> {
+        let response = self.generate(prompt).await?;
+        for token in response.split_inclusive(' ') {
+            options.check()?;
+            on_token(token);
+        }
+        Ok(response)
     }
 
     fn set_parameters(&mut self, _temperature: f32, _max_tokens: usize) -> InferenceResult<()> {
@@ -74,3 +164,59 @@ impl InferenceEngine for MockInferenceEngine {
         self.model_loaded
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::CancellationToken;
+
+    #[tokio::test]
+    async fn test_mock_streams_word_by_word() {
+        let mut engine = MockInferenceEngine::new();
+        engine.load_model("mock-model").unwrap();
+
+        let mut tokens = Vec::new();
+        let response = engine
+            .generate_stream("list files", &GenerationOptions::new(), &mut |t: &str| {
+                tokens.push(t.to_string())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens.concat(), response);
+    }
+
+    #[tokio::test]
+    async fn test_mock_stops_when_cancelled() {
+        let mut engine = MockInferenceEngine::new();
+        engine.load_model("mock-model").unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = GenerationOptions::new().with_cancellation(token);
+        let result = engine
+            .generate_stream("list files", &options, &mut |_: &str| {})
+            .await;
+        assert!(matches!(result, Err(InferenceError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_boxed_engine_forwards_calls() {
+        let mut engine: Box<dyn InferenceEngine> =
+            Box::new(MockInferenceEngine::new().with_response("ls -la"));
+        assert!(!engine.is_model_loaded());
+        engine.load_model("mock-model").unwrap();
+        assert!(engine.is_model_loaded());
+
+        let mut streamed = String::new();
+        let response = engine
+            .generate_stream("list files", &GenerationOptions::new(), &mut |t: &str| {
+                streamed.push_str(t)
+            })
+            .await
+            .unwrap();
+        assert_eq!(response, "ls -la");
+        assert_eq!(streamed, response);
+    }
+}
@@ -0,0 +1,487 @@
+//! Minimal HTTP/1.1 client for local inference servers
+//!
+//! Inference backends such as Ollama listen on plain HTTP on the loopback
+//! interface, so this module only implements what those servers need:
+//! `http://` URLs, `Connection: close` requests, and `Content-Length` or
+//! chunked response bodies.
+
+use super::{InferenceError, InferenceResult};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// Host and port of an HTTP server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HttpEndpoint {
+    host: String,
+    port: u16,
+}
+
+/// A fully buffered HTTP response
+#[derive(Debug)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpEndpoint {
+    /// Parse an endpoint from a URL such as `http://127.0.0.1:11434`
+    ///
+    /// The scheme may be omitted; a missing port defaults to `default_port`.
+    pub fn parse(url: &str, default_port: u16) -> InferenceResult<Self> {
+        let rest = match url.split_once("://") {
+            Some(("http", rest)) => rest,
+            Some((scheme, _)) => {
+                return Err(InferenceError::InvalidParams(format!(
+                    "Unsupported URL scheme '{scheme}', only http is supported"
+                )))
+            }
+            None => url,
+        };
+        let authority = rest.split('/').next().unwrap_or_default();
+        if authority.is_empty() {
+            return Err(InferenceError::InvalidParams(format!(
+                "Missing host in URL '{url}'"
+            )));
+        }
+
+        let invalid = || InferenceError::InvalidParams(format!("Invalid host or port in '{url}'"));
+        let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+            let (host, after) = bracketed.split_once(']').ok_or_else(invalid)?;
+            match after.strip_prefix(':') {
+                Some(port) => (host, port.parse::<u16>().map_err(|_| invalid())?),
+                None if after.is_empty() => (host, default_port),
+                None => return Err(invalid()),
+            }
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse::<u16>().map_err(|_| invalid())?),
+                None => (authority, default_port),
+            }
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Reach a server bound to the wildcard address (`0.0.0.0` or `::`)
+    /// through the matching loopback address
+    pub fn connectable(mut self) -> Self {
+        match self.host.as_str() {
+            "0.0.0.0" => self.host = "127.0.0.1".to_string(),
+            "::" => self.host = "::1".to_string(),
+            _ => {}
+        }
+        self
+    }
+
+    /// Get the `host:port` form used in error messages and the Host header
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    fn request_bytes(&self, method: &str, path: &str, body: Option<&[u8]>) -> Vec<u8> {
+        let mut request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n",
+            self.authority()
+        );
+        if let Some(body) = body {
+            request.push_str("Content-Type: application/json\r\n");
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+
+        let mut bytes = request.into_bytes();
+        if let Some(body) = body {
+            bytes.extend_from_slice(body);
+        }
+        bytes
+    }
+
+    fn connection_error(&self, e: std::io::Error) -> InferenceError {
+        InferenceError::Connection(format!("{}: {e}", self.authority()))
+    }
+}
+
+/// Perform a blocking GET request
+///
+/// Used by the synchronous parts of [`super::InferenceEngine`] such as
+/// `initialize` and `load_model`, which run before any generation starts.
+pub(crate) fn get_blocking(
+    endpoint: &HttpEndpoint,
+    path: &str,
+    timeout: Duration,
+) -> InferenceResult<HttpResponse> {
+    let addr = (endpoint.host.as_str(), endpoint.port)
+        .to_socket_addrs()
+        .map_err(|e| endpoint.connection_error(e))?
+        .next()
+        .ok_or_else(|| {
+            InferenceError::Connection(format!("{}: no address found", endpoint.authority()))
+        })?;
+
+    let mut stream =
+        TcpStream::connect_timeout(&addr, timeout).map_err(|e| endpoint.connection_error(e))?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| endpoint.connection_error(e))?;
+
+    stream
+        .write_all(&endpoint.request_bytes("GET", path, None))
+        .map_err(|e| endpoint.connection_error(e))?;
+
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .map_err(|e| endpoint.connection_error(e))?;
+
+    parse_response(&raw)
+}
+
+/// Perform a GET request and buffer the whole response, giving up after
+/// `timeout`
+pub(crate) async fn get(
+    endpoint: &HttpEndpoint,
+    path: &str,
+    timeout: Duration,
+) -> InferenceResult<HttpResponse> {
+    let request = async {
+        let stream = send(endpoint, &endpoint.request_bytes("GET", path, None)).await?;
+        let status = stream.status();
+        let body = stream.read_to_end().await?;
+        Ok(HttpResponse { status, body })
+    };
+    tokio::time::timeout(timeout, request)
+        .await
+        .map_err(|_| InferenceError::Connection(format!("{}: timed out", endpoint.authority())))?
+}
+
+/// Perform a POST request with a JSON body and buffer the whole response
+pub(crate) async fn post_json(
+    endpoint: &HttpEndpoint,
+    path: &str,
+    body: &serde_json::Value,
+) -> InferenceResult<HttpResponse> {
+    let stream = post_json_streaming(endpoint, path, body).await?;
+    let status = stream.status();
+    let body = stream.read_to_end().await?;
+    Ok(HttpResponse { status, body })
+}
+
+/// Perform a POST request with a JSON body and return once the response
+/// headers have arrived, so the body can be consumed as it is produced
+pub(crate) async fn post_json_streaming(
+    endpoint: &HttpEndpoint,
+    path: &str,
+    body: &serde_json::Value,
+) -> InferenceResult<HttpStream> {
+    let body = serde_json::to_vec(body)
+        .map_err(|e| InferenceError::InvalidParams(format!("Failed to encode request: {e}")))?;
+    send(endpoint, &endpoint.request_bytes("POST", path, Some(&body))).await
+}
+
+/// Send a request and return once the response headers have arrived
+async fn send(endpoint: &HttpEndpoint, request: &[u8]) -> InferenceResult<HttpStream> {
+    let mut stream = tokio::net::TcpStream::connect((endpoint.host.as_str(), endpoint.port))
+        .await
+        .map_err(|e| endpoint.connection_error(e))?;
+
+    stream
+        .write_all(request)
+        .await
+        .map_err(|e| endpoint.connection_error(e))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        let n = reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| endpoint.connection_error(e))?;
+        if n == 0 {
+            return Err(malformed("connection closed before headers"));
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        head.push_str(&line);
+    }
+
+    let head = parse_head(head.trim_end())?;
+    Ok(HttpStream {
+        status: head.status,
+        reader,
+        body: if head.chunked {
+            BodyState::Chunked { remaining: 0 }
+        } else if let Some(len) = head.content_length {
+            BodyState::Length { remaining: len }
+        } else {
+            BodyState::UntilClose
+        },
+        pending: Vec::new(),
+    })
+}
+
+/// Framing state of a response body being read incrementally
+enum BodyState {
+    /// `Content-Length` body with this many bytes left
+    Length { remaining: usize },
+    /// Chunked body with this many bytes left in the current chunk
+    Chunked { remaining: usize },
+    /// Body delimited by the server closing the connection
+    UntilClose,
+    /// Body fully read
+    Done,
+}
+
+/// An HTTP response whose body is read as it arrives
+pub(crate) struct HttpStream {
+    status: u16,
+    reader: BufReader<tokio::net::TcpStream>,
+    body: BodyState,
+    pending: Vec<u8>,
+}
+
+impl HttpStream {
+    /// Get the response status code
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Read the next newline-delimited line of the body (for NDJSON streams)
+    ///
+    /// Returns `None` once the body is exhausted. The trailing newline is
+    /// stripped, and a final unterminated line is still returned.
+    pub async fn next_line(&mut self) -> InferenceResult<Option<Vec<u8>>> {
+        loop {
+            if let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+                let mut line: Vec<u8> = self.pending.drain(..=pos).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(Some(line));
+            }
+
+            let mut data = Vec::new();
+            if self.read_data(&mut data).await? == 0 {
+                return Ok(if self.pending.is_empty() {
+                    None
+                } else {
+                    Some(std::mem::take(&mut self.pending))
+                });
+            }
+            self.pending.extend(data);
+        }
+    }
+
+    /// Read the remainder of the body
+    pub async fn read_to_end(mut self) -> InferenceResult<Vec<u8>> {
+        let mut body = std::mem::take(&mut self.pending);
+        while self.read_data(&mut body).await? > 0 {}
+        Ok(body)
+    }
+
+    /// Append the next piece of decoded body data to `buf`
+    ///
+    /// Returns the number of bytes appended, or 0 at the end of the body.
+    async fn read_data(&mut self, buf: &mut Vec<u8>) -> InferenceResult<usize> {
+        let io_error = |e: std::io::Error| InferenceError::Connection(e.to_string());
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            let limit = match self.body {
+                BodyState::Done => return Ok(0),
+                BodyState::Length { remaining: 0 } => {
+                    self.body = BodyState::Done;
+                    return Ok(0);
+                }
+                BodyState::Chunked { remaining: 0 } => {
+                    let mut size_line = String::new();
+                    self.reader
+                        .read_line(&mut size_line)
+                        .await
+                        .map_err(io_error)?;
+                    let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+                    let size = usize::from_str_radix(size_hex, 16)
+                        .map_err(|_| malformed("bad chunk encoding"))?;
+                    if size == 0 {
+                        // Skip optional trailers up to the final empty line
+                        loop {
+                            let mut line = String::new();
+                            let n = self.reader.read_line(&mut line).await.map_err(io_error)?;
+                            if n == 0 || line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        self.body = BodyState::Done;
+                        return Ok(0);
+                    }
+                    self.body = BodyState::Chunked { remaining: size };
+                    continue;
+                }
+                BodyState::Length { remaining } | BodyState::Chunked { remaining } => {
+                    remaining.min(chunk.len())
+                }
+                BodyState::UntilClose => chunk.len(),
+            };
+
+            let n = self
+                .reader
+                .read(&mut chunk[..limit])
+                .await
+                .map_err(io_error)?;
+            buf.extend_from_slice(&chunk[..n]);
+
+            match &mut self.body {
+                BodyState::UntilClose if n == 0 => self.body = BodyState::Done,
+                BodyState::Length { .. } | BodyState::Chunked { .. } if n == 0 => {
+                    return Err(malformed("connection closed mid-body"))
+                }
+                BodyState::Length { remaining } => *remaining -= n,
+                BodyState::Chunked { remaining } => {
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        let mut crlf = String::new();
+                        self.reader.read_line(&mut crlf).await.map_err(io_error)?;
+                    }
+                }
+                _ => {}
+            }
+            return Ok(n);
+        }
+    }
+}
+
+/// Status and body framing of a response, from its header block
+struct ResponseHead {
+    status: u16,
+    chunked: bool,
+    content_length: Option<usize>,
+}
+
+fn parse_head(head: &str) -> InferenceResult<ResponseHead> {
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| malformed("bad status line"))?;
+
+    let mut chunked = false;
+    let mut content_length = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            }
+        }
+    }
+
+    Ok(ResponseHead {
+        status,
+        chunked,
+        content_length,
+    })
+}
+
+/// Parse a complete raw HTTP response
+fn parse_response(raw: &[u8]) -> InferenceResult<HttpResponse> {
+    let header_end =
+        find_subslice(raw, b"\r\n\r\n").ok_or_else(|| malformed("missing header terminator"))?;
+    let head = parse_head(&String::from_utf8_lossy(&raw[..header_end]))?;
+    let body = &raw[header_end + 4..];
+
+    let body = if head.chunked {
+        decode_chunked(body)?
+    } else if let Some(len) = head.content_length {
+        body[..len.min(body.len())].to_vec()
+    } else {
+        body.to_vec()
+    };
+
+    Ok(HttpResponse {
+        status: head.status,
+        body,
+    })
+}
+
+/// Decode a `Transfer-Encoding: chunked` body
+fn decode_chunked(mut data: &[u8]) -> InferenceResult<Vec<u8>> {
+    let malformed = || malformed("bad chunk encoding");
+    let mut body = Vec::new();
+
+    loop {
+        let line_end = find_subslice(data, b"\r\n").ok_or_else(malformed)?;
+        let size_line = String::from_utf8_lossy(&data[..line_end]);
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| malformed())?;
+        data = &data[line_end + 2..];
+
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size {
+            return Err(malformed());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = data.get(size + 2..).unwrap_or_default();
+    }
+}
+
+fn malformed(reason: &str) -> InferenceError {
+    InferenceError::Generation(format!("Malformed HTTP response: {reason}"))
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_parsing() {
+        let endpoint = HttpEndpoint::parse("http://localhost:8080/api", 11434).unwrap();
+        assert_eq!(endpoint.authority(), "localhost:8080");
+
+        let endpoint = HttpEndpoint::parse("127.0.0.1", 11434).unwrap();
+        assert_eq!(endpoint.authority(), "127.0.0.1:11434");
+
+        let endpoint = HttpEndpoint::parse("http://[::1]:9000", 11434).unwrap();
+        assert_eq!(endpoint.authority(), "[::1]:9000");
+
+        assert!(HttpEndpoint::parse("https://localhost", 11434).is_err());
+    }
+
+    #[test]
+    fn test_parse_content_length_response() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"{}");
+    }
+
+    #[test]
+    fn test_parse_chunked_response() {
+        let raw = b"HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nabcd\r\n3\r\nefg\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, b"abcdefg");
+    }
+}
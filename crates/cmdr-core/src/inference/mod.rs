@@ -3,8 +3,14 @@
 //! This module provides the interface for different LLM inference engines.
 
 mod engine;
+mod http;
+mod ollama;
+mod options;
 
-pub use engine::{InferenceEngine, MockInferenceEngine};
+pub use engine::{InferenceEngine, MockInferenceEngine, TokenCallback};
+pub use ollama::{OllamaApi, OllamaInferenceEngine, DEFAULT_OLLAMA_MODEL, DEFAULT_OLLAMA_URL};
+pub use options::{GenerationOptions, OutputConstraint};
+pub use tokio_util::sync::CancellationToken;
 
 /// Error type for inference operations
 #[derive(Debug, thiserror::Error)]
@@ -17,6 +23,14 @@ pub enum InferenceError {
     ModelNotLoaded(String),
     #[error("Invalid parameters: {0}")]
     InvalidParams(String),
+    #[error("Failed to connect to inference server: {0}")]
+    Connection(String),
+    #[error("Inference server returned HTTP {0}: {1}")]
+    Http(u16, String),
+    #[error("Generation was cancelled")]
+    Cancelled,
+    #[error("Generation timed out")]
+    Timeout,
 }
 
 /// Result type for inference operations
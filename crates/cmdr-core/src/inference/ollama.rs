@@ -0,0 +1,750 @@
+//! Ollama inference engine implementation
+//!
+//! This module implements [`InferenceEngine`] on top of the REST API exposed
+//! by a local Ollama server (`ollama serve`), which is what `cmdr install`
+//! sets up.
+
+use super::http::{self, HttpEndpoint, HttpResponse};
+use super::{
+    GenerationOptions, InferenceEngine, InferenceError, InferenceResult, OutputConstraint,
+    TokenCallback,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Default address of a local Ollama server
+pub const DEFAULT_OLLAMA_URL: &str = "http://127.0.0.1:11434";
+
+/// Default model pulled by `cmdr install`
+pub const DEFAULT_OLLAMA_MODEL: &str = "llama3.2:3b";
+
+const DEFAULT_OLLAMA_PORT: u16 = 11434;
+
+/// Timeout for the short metadata requests (`/api/version`, `/api/tags`)
+const METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which Ollama endpoint is used for generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OllamaApi {
+    /// Raw completion through `/api/generate`
+    #[default]
+    Generate,
+    /// Single-turn conversation through `/api/chat`
+    Chat,
+}
+
+/// Inference engine backed by a local Ollama server
+pub struct OllamaInferenceEngine {
+    endpoint: HttpEndpoint,
+    model: String,
+    api: OllamaApi,
+    temperature: f32,
+    max_tokens: usize,
+    /// CPU threads Ollama runs the model with; its own choice if unset
+    threads: Option<u32>,
+    /// Context window in tokens; the model's default if unset
+    context_size: Option<u32>,
+    /// Whether the server has the model, as found by `load_model` and
+    /// checked again after a generation fails
+    model_found: bool,
+}
+
+impl OllamaInferenceEngine {
+    /// Create an engine talking to the default local Ollama server
+    pub fn new() -> Self {
+        Self::with_url(DEFAULT_OLLAMA_URL).expect("default Ollama URL is valid")
+    }
+
+    /// Create an engine talking to the Ollama server at `url`
+    pub fn with_url(url: &str) -> InferenceResult<Self> {
+        Ok(Self {
+            endpoint: HttpEndpoint::parse(url, DEFAULT_OLLAMA_PORT)?,
+            model: DEFAULT_OLLAMA_MODEL.to_string(),
+            api: OllamaApi::default(),
+            temperature: 0.7,
+            max_tokens: 256,
+            threads: None,
+            context_size: None,
+            model_found: false,
+        })
+    }
+
+    /// Create an engine using the server address from `OLLAMA_HOST`, if set
+    ///
+    /// Mirrors the Ollama CLI: `OLLAMA_HOST` may omit the scheme and port,
+    /// and the wildcard bind address `0.0.0.0` is reached via loopback.
+    pub fn from_env() -> InferenceResult<Self> {
+        match std::env::var("OLLAMA_HOST") {
+            Ok(host) if !host.trim().is_empty() => Self::with_host(host.trim()),
+            _ => Ok(Self::new()),
+        }
+    }
+
+    /// Create an engine for an `OLLAMA_HOST` value
+    fn with_host(host: &str) -> InferenceResult<Self> {
+        let mut engine = Self::with_url(host)?;
+        engine.endpoint = engine.endpoint.connectable();
+        Ok(engine)
+    }
+
+    /// Select the endpoint used for generation
+    pub fn with_api(mut self, api: OllamaApi) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// Set the model used for generation without checking that it exists
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self.model_found = false;
+        self
+    }
+
+    /// Get the model used for generation
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Run the model with `threads` CPU threads
+    pub fn with_threads(mut self, threads: u32) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Give the model a context window of `tokens`
+    pub fn with_context_size(mut self, tokens: u32) -> Self {
+        self.context_size = Some(tokens);
+        self
+    }
+
+    /// List the models available on the server via `/api/tags`
+    pub fn list_models(&self) -> InferenceResult<Vec<String>> {
+        let response = http::get_blocking(&self.endpoint, "/api/tags", METADATA_TIMEOUT)?;
+        let body = self.check_response(response)?;
+        Ok(model_names(&body).map(str::to_string).collect())
+    }
+
+    /// Check whether the configured model is present on the server
+    fn model_available(&self) -> InferenceResult<bool> {
+        let response = http::get_blocking(&self.endpoint, "/api/tags", METADATA_TIMEOUT)?;
+        Ok(self.has_model(&self.check_response(response)?))
+    }
+
+    /// Whether a `/api/tags` response lists the configured model
+    fn has_model(&self, tags: &Value) -> bool {
+        let wanted = normalize_model_name(&self.model);
+        model_names(tags).any(|name| normalize_model_name(name) == wanted)
+    }
+
+    /// Ask the server again whether it has the model once a generation fails
+    /// for a reason other than the caller giving up
+    async fn note_failure<T>(&mut self, result: InferenceResult<T>) -> InferenceResult<T> {
+        if let Err(e) = &result {
+            if !matches!(e, InferenceError::Cancelled | InferenceError::Timeout) {
+                let tags = http::get(&self.endpoint, "/api/tags", METADATA_TIMEOUT).await;
+                self.model_found = tags
+                    .and_then(|response| self.check_response(response))
+                    .is_ok_and(|tags| self.has_model(&tags));
+            }
+        }
+        result
+    }
+
+    /// Build the request body for the configured endpoint
+    ///
+    /// A JSON schema constraint is sent as Ollama's `format` parameter.
+    /// Ollama cannot enforce GBNF grammars, so those are rejected.
+    fn request_body(
+        &self,
+        prompt: &str,
+        stream: bool,
+        generation: &GenerationOptions,
+    ) -> InferenceResult<Value> {
+        let mut options = json!({
+            "temperature": self.temperature,
+            "num_predict": self.max_tokens,
+        });
+        if let Some(seed) = generation.seed {
+            options["seed"] = json!(seed);
+        }
+        if let Some(threads) = self.threads {
+            options["num_thread"] = json!(threads);
+        }
+        if let Some(context_size) = self.context_size {
+            options["num_ctx"] = json!(context_size);
+        }
+
+        let mut body = match self.api {
+            OllamaApi::Generate => json!({
+                "model": self.model,
+                "prompt": prompt,
+                "stream": stream,
+                "options": options,
+            }),
+            OllamaApi::Chat => json!({
+                "model": self.model,
+                "messages": [{ "role": "user", "content": prompt }],
+                "stream": stream,
+                "options": options,
+            }),
+        };
+        match &generation.constraint {
+            Some(OutputConstraint::JsonSchema(schema)) => body["format"] = schema.clone(),
+            Some(OutputConstraint::Grammar(_)) => {
+                return Err(InferenceError::InvalidParams(
+                    "Ollama does not support GBNF grammars; use a JSON schema".to_string(),
+                ))
+            }
+            None => {}
+        }
+        Ok(body)
+    }
+
+    /// Get the path of the configured endpoint
+    fn api_path(&self) -> &'static str {
+        match self.api {
+            OllamaApi::Generate => "/api/generate",
+            OllamaApi::Chat => "/api/chat",
+        }
+    }
+
+    /// Extract the generated text from a response object or stream chunk
+    fn response_text<'a>(&self, body: &'a Value) -> Option<&'a str> {
+        match self.api {
+            OllamaApi::Generate => body["response"].as_str(),
+            OllamaApi::Chat => body["message"]["content"].as_str(),
+        }
+    }
+
+    /// Generate a whole response in one request
+    async fn complete(&self, prompt: &str) -> InferenceResult<String> {
+        let body = self.request_body(prompt, false, &GenerationOptions::new())?;
+        let response = http::post_json(&self.endpoint, self.api_path(), &body).await?;
+        let body = self.check_response(response)?;
+
+        self.response_text(&body)
+            .map(str::to_string)
+            .ok_or_else(|| {
+                InferenceError::Generation(format!("Unexpected response from Ollama: {body}"))
+            })
+    }
+
+    /// Stream a response from the server, passing each chunk to `on_token`
+    async fn stream_response(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> InferenceResult<String> {
+        let body = self.request_body(prompt, true, options)?;
+        let mut stream = http::post_json_streaming(&self.endpoint, self.api_path(), &body).await?;
+        let status = stream.status();
+        if !(200..300).contains(&status) {
+            let body = stream.read_to_end().await?;
+            return Err(self.error_response(status, &body));
+        }
+
+        // Ollama streams one JSON object per line until one has `"done": true`
+        let mut response = String::new();
+        while let Some(line) = stream.next_line().await? {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let chunk: Value = serde_json::from_slice(&line).map_err(|e| {
+                InferenceError::Generation(format!("Invalid stream chunk from Ollama: {e}"))
+            })?;
+            if let Some(error) = chunk["error"].as_str() {
+                return Err(InferenceError::Generation(error.to_string()));
+            }
+            if let Some(text) = self.response_text(&chunk).filter(|t| !t.is_empty()) {
+                on_token(text);
+                response.push_str(text);
+            }
+            if chunk["done"].as_bool().unwrap_or(false) {
+                break;
+            }
+        }
+        Ok(response)
+    }
+
+    /// Decode a JSON response body, mapping HTTP failures onto errors
+    fn check_response(&self, response: HttpResponse) -> InferenceResult<Value> {
+        if !(200..300).contains(&response.status) {
+            return Err(self.error_response(response.status, &response.body));
+        }
+        serde_json::from_slice(&response.body)
+            .map_err(|e| InferenceError::Generation(format!("Invalid JSON from Ollama: {e}")))
+    }
+
+    /// Map a non-2xx response onto the matching error
+    fn error_response(&self, status: u16, body: &[u8]) -> InferenceError {
+        let message = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|b| b["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+
+        match status {
+            404 => InferenceError::ModelNotLoaded(format!(
+                "{message} (try `ollama pull {}`)",
+                self.model
+            )),
+            400 => InferenceError::InvalidParams(message),
+            status => InferenceError::Http(status, message),
+        }
+    }
+}
+
+impl Default for OllamaInferenceEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl InferenceEngine for OllamaInferenceEngine {
+    fn initialize(&mut self) -> InferenceResult<()> {
+        let response = http::get_blocking(&self.endpoint, "/api/version", METADATA_TIMEOUT)
+            .map_err(|e| match e {
+                InferenceError::Connection(reason) => InferenceError::Initialization(format!(
+                    "Ollama is not reachable at {} ({reason}); is `ollama serve` running?",
+                    self.endpoint.authority()
+                )),
+                other => other,
+            })?;
+        self.check_response(response)?;
+        Ok(())
+    }
+
+    fn load_model(&mut self, model_path: &str) -> InferenceResult<()> {
+        if !model_path.trim().is_empty() {
+            self.model = model_path.trim().to_string();
+        }
+
+        self.model_found = self.model_available()?;
+        if !self.model_found {
+            return Err(InferenceError::ModelNotLoaded(format!(
+                "model '{}' is not available in Ollama; run `cmdr install` or `ollama pull {}`",
+                self.model, self.model
+            )));
+        }
+        Ok(())
+    }
+
+    async fn generate(&mut self, prompt: &str) -> InferenceResult<String> {
+        let result = self.complete(prompt).await;
+        self.note_failure(result).await
+    }
+
+    async fn generate_stream(
+        &mut self,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> InferenceResult<String> {
+        // Dropping the request on cancellation closes the connection, which
+        // makes Ollama stop generating.
+        let result = options
+            .run(self.stream_response(prompt, options, on_token))
+            .await;
+        self.note_failure(result).await
+    }
+
+    fn set_parameters(&mut self, temperature: f32, max_tokens: usize) -> InferenceResult<()> {
+        if !temperature.is_finite() || temperature < 0.0 {
+            return Err(InferenceError::InvalidParams(format!(
+                "temperature must be a non-negative number, got {temperature}"
+            )));
+        }
+        if max_tokens == 0 {
+            return Err(InferenceError::InvalidParams(
+                "max_tokens must be greater than zero".to_string(),
+            ));
+        }
+
+        self.temperature = temperature;
+        self.max_tokens = max_tokens;
+        Ok(())
+    }
+
+    fn is_model_loaded(&self) -> bool {
+        self.model_found
+    }
+}
+
+/// The model names listed in a `/api/tags` response
+fn model_names(tags: &Value) -> impl Iterator<Item = &str> {
+    tags["models"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["name"].as_str().or_else(|| m["model"].as_str()))
+}
+
+/// Normalize a model reference so that `llama3` and `llama3:latest` compare equal
+fn normalize_model_name(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{name}:latest")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Serve one canned `(status, body)` response per connection and
+    /// return the raw requests that were received.
+    fn stub_server(responses: Vec<(u16, String)>) -> (String, JoinHandle<Vec<String>>) {
+        stub_server_raw(
+            responses
+                .into_iter()
+                .map(|(status, body)| {
+                    format!(
+                        "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Serve one raw HTTP response per connection
+    fn stub_server_raw(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body_bytes = vec![0; content_length];
+                reader.read_exact(&mut body_bytes).unwrap();
+                request.push_str(&String::from_utf8(body_bytes).unwrap());
+                requests.push(request);
+
+                let mut stream = reader.into_inner();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        (url, handle)
+    }
+
+    fn request_json(request: &str) -> Value {
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_generate_uses_generate_endpoint_and_parameters() {
+        let (url, server) = stub_server(vec![(
+            200,
+            r#"{"model":"llama3.2:3b","response":"ls -la","done":true}"#.to_string(),
+        )]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+        engine.set_parameters(0.2, 64).unwrap();
+
+        let output = engine.generate("list files").await.unwrap();
+        assert_eq!(output, "ls -la");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("POST /api/generate HTTP/1.1"));
+        let body = request_json(&requests[0]);
+        assert_eq!(body["model"], "llama3.2:3b");
+        assert_eq!(body["prompt"], "list files");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["options"]["num_predict"], 64);
+        assert!((body["options"]["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_chat_endpoint() {
+        let (url, server) = stub_server(vec![(
+            200,
+            r#"{"message":{"role":"assistant","content":"pwd"},"done":true}"#.to_string(),
+        )]);
+        let mut engine = OllamaInferenceEngine::with_url(&url)
+            .unwrap()
+            .with_api(OllamaApi::Chat);
+
+        assert_eq!(engine.generate("where am I").await.unwrap(), "pwd");
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("POST /api/chat HTTP/1.1"));
+        let body = request_json(&requests[0]);
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"], "where am I");
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_reads_ndjson_chunks() {
+        let lines = [
+            r#"{"response":"ls","done":false}"#,
+            r#"{"response":" -la","done":false}"#,
+            r#"{"response":"","done":true}"#,
+        ];
+        let body: String = lines
+            .iter()
+            .map(|line| format!("{:x}\r\n{line}\n\r\n", line.len() + 1))
+            .collect();
+        let (url, server) = stub_server_raw(vec![format!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{body}0\r\n\r\n"
+        )]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+
+        let mut tokens = Vec::new();
+        let response = engine
+            .generate_stream("list files", &GenerationOptions::new(), &mut |t: &str| {
+                tokens.push(t.to_string())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response, "ls -la");
+        assert_eq!(tokens, vec!["ls", " -la"]);
+        let requests = server.join().unwrap();
+        assert_eq!(request_json(&requests[0])["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_sends_schema_and_seed() {
+        let (url, server) = stub_server(vec![(
+            200,
+            r#"{"response":"{\"command\":\"ls\"}","done":true}"#.to_string(),
+        )]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+        let schema = json!({
+            "type": "object",
+            "properties": { "command": { "type": "string" } },
+            "required": ["command"],
+        });
+
+        let options = GenerationOptions::new()
+            .with_constraint(OutputConstraint::JsonSchema(schema.clone()))
+            .with_seed(7);
+        let response = engine
+            .generate_stream("list files", &options, &mut |_: &str| {})
+            .await
+            .unwrap();
+
+        assert_eq!(response, r#"{"command":"ls"}"#);
+        let requests = server.join().unwrap();
+        let request = request_json(&requests[0]);
+        assert_eq!(request["format"], schema);
+        assert_eq!(request["options"]["seed"], 7);
+        assert!(request["options"].get("num_thread").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_sends_parameters() {
+        let (url, server) =
+            stub_server(vec![(200, r#"{"response":"ls","done":true}"#.to_string())]);
+        let mut engine = OllamaInferenceEngine::with_url(&url)
+            .unwrap()
+            .with_model("qwen2.5-coder:1.5b")
+            .with_threads(4)
+            .with_context_size(4096);
+        engine.set_parameters(0.2, 128).unwrap();
+
+        engine
+            .generate_stream("list files", &GenerationOptions::new(), &mut |_: &str| {})
+            .await
+            .unwrap();
+
+        let requests = server.join().unwrap();
+        let request = request_json(&requests[0]);
+        assert_eq!(request["model"], "qwen2.5-coder:1.5b");
+        let options = &request["options"];
+        assert_eq!(options["temperature"].as_f64().unwrap() as f32, 0.2);
+        assert_eq!(options["num_predict"], 128);
+        assert_eq!(options["num_thread"], 4);
+        assert_eq!(options["num_ctx"], 4096);
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_rejects_grammar() {
+        let mut engine = OllamaInferenceEngine::with_url("http://127.0.0.1:9").unwrap();
+        let options = GenerationOptions::new()
+            .with_constraint(OutputConstraint::Grammar("root ::= \"ls\"".to_string()));
+
+        let result = engine
+            .generate_stream("list files", &options, &mut |_: &str| {})
+            .await;
+        assert!(matches!(result, Err(InferenceError::InvalidParams(_))));
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_maps_errors() {
+        let (url, server) = stub_server(vec![(
+            404,
+            r#"{"error":"model 'llama3.2:3b' not found"}"#.to_string(),
+        )]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+
+        let result = engine
+            .generate_stream("hi", &GenerationOptions::new(), &mut |_: &str| {})
+            .await;
+        assert!(matches!(result, Err(InferenceError::ModelNotLoaded(_))));
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream_times_out() {
+        // Accept the connection but never answer
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+        let options = GenerationOptions::new().with_timeout(Duration::from_millis(50));
+        let result = engine
+            .generate_stream("hi", &options, &mut |_: &str| {})
+            .await;
+
+        assert!(matches!(result, Err(InferenceError::Timeout)));
+        drop(server.join().unwrap());
+    }
+
+    #[test]
+    fn test_model_loaded_queries_tags() {
+        let tags = r#"{"models":[{"name":"llama3.2:3b"},{"name":"mistral:latest"}]}"#;
+        let (url, server) = stub_server(vec![(200, tags.to_string()), (200, tags.to_string())]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+
+        engine.load_model("mistral").unwrap();
+        assert!(engine.is_model_loaded());
+        assert!(matches!(
+            engine.load_model("codellama:7b"),
+            Err(InferenceError::ModelNotLoaded(_))
+        ));
+
+        let requests = server.join().unwrap();
+        assert!(requests.iter().all(|r| r.starts_with("GET /api/tags")));
+    }
+
+    #[tokio::test]
+    async fn test_model_is_rechecked_after_a_failed_generation() {
+        let tags = r#"{"models":[{"name":"llama3.2:3b"}]}"#;
+        let (url, server) = stub_server(vec![
+            (200, tags.to_string()),
+            (200, r#"{"response":"ls","done":true}"#.to_string()),
+            (
+                404,
+                r#"{"error":"model 'llama3.2:3b' not found"}"#.to_string(),
+            ),
+            (200, r#"{"models":[]}"#.to_string()),
+        ]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+
+        engine.load_model("llama3.2:3b").unwrap();
+        assert!(engine.is_model_loaded());
+        assert_eq!(engine.generate("list files").await.unwrap(), "ls");
+        assert!(engine.is_model_loaded());
+        assert!(engine.generate("list files").await.is_err());
+        assert!(!engine.is_model_loaded());
+
+        let requests = server.join().unwrap();
+        let paths: Vec<&str> = requests
+            .iter()
+            .map(|r| r.split_whitespace().nth(1).unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            ["/api/tags", "/api/generate", "/api/generate", "/api/tags"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_errors_are_mapped() {
+        let (url, server) = stub_server(vec![
+            (
+                404,
+                r#"{"error":"model 'llama3.2:3b' not found"}"#.to_string(),
+            ),
+            (200, r#"{"models":[]}"#.to_string()),
+            (500, r#"{"error":"out of memory"}"#.to_string()),
+            (200, r#"{"models":[]}"#.to_string()),
+        ]);
+        let mut engine = OllamaInferenceEngine::with_url(&url).unwrap();
+
+        assert!(matches!(
+            engine.generate("hi").await,
+            Err(InferenceError::ModelNotLoaded(_))
+        ));
+        match engine.generate("hi").await {
+            Err(InferenceError::Http(status, message)) => {
+                assert_eq!(status, 500);
+                assert_eq!(message, "out of memory");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_failure() {
+        // Bind and drop a listener to get a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut engine = OllamaInferenceEngine::with_url(&format!("127.0.0.1:{port}")).unwrap();
+
+        assert!(matches!(
+            engine.generate("hi").await,
+            Err(InferenceError::Connection(_))
+        ));
+        assert!(matches!(
+            engine.initialize(),
+            Err(InferenceError::Initialization(_))
+        ));
+        assert!(!engine.is_model_loaded());
+    }
+
+    #[test]
+    fn test_ollama_host_wildcard_is_reached_via_loopback() {
+        let authority = |host: &str| {
+            OllamaInferenceEngine::with_host(host)
+                .unwrap()
+                .endpoint
+                .authority()
+        };
+
+        assert_eq!(authority("0.0.0.0"), "127.0.0.1:11434");
+        assert_eq!(authority("http://0.0.0.0:8080"), "127.0.0.1:8080");
+        assert_eq!(authority("[::]:11434"), "[::1]:11434");
+        assert_eq!(authority("10.0.0.0:11434"), "10.0.0.0:11434");
+        assert_eq!(authority("100.0.0.01"), "100.0.0.01:11434");
+        assert_eq!(authority("ollama.local"), "ollama.local:11434");
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let mut engine = OllamaInferenceEngine::new();
+        assert!(engine.set_parameters(-1.0, 10).is_err());
+        assert!(engine.set_parameters(0.5, 0).is_err());
+        assert!(engine.set_parameters(0.5, 10).is_ok());
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-request generation options
+//!
+//! This module defines the options that control a single generation call,
+//! such as cancellation, deadlines and constraints on the output.
+
+use super::{InferenceError, InferenceResult};
+use serde_json::Value;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Options for a single generation request
+#[derive(Debug, Clone, Default)]
+pub struct GenerationOptions {
+    /// Token that aborts generation when cancelled
+    pub cancellation: Option<CancellationToken>,
+    /// Point in time after which generation is abandoned
+    pub deadline: Option<Instant>,
+    /// Shape the generated text must have
+    pub constraint: Option<OutputConstraint>,
+    /// Sampling seed, for reproducible or deliberately varied output
+    pub seed: Option<u64>,
+}
+
+/// A constraint enforced on generated text while it is decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputConstraint {
+    /// A GBNF grammar; generation starts at its `root` rule
+    Grammar(String),
+    /// A JSON schema the output must be a valid instance of
+    JsonSchema(Value),
+}
+
+impl GenerationOptions {
+    /// Create options with no cancellation and no deadline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort generation when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Abandon generation at `deadline`
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Abandon generation once `timeout` has elapsed from now
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Constrain the generated text to `constraint`
+    pub fn with_constraint(mut self, constraint: OutputConstraint) -> Self {
+        self.constraint = Some(constraint);
+        self
+    }
+
+    /// Sample with `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Return the error generation should stop with, if any
+    ///
+    /// Engines that generate synchronously call this between tokens.
+    pub fn check(&self) -> InferenceResult<()> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(InferenceError::Cancelled);
+        }
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(InferenceError::Timeout);
+        }
+        Ok(())
+    }
+
+    /// Drive `future` to completion unless cancelled or past the deadline
+    ///
+    /// The future is dropped as soon as either happens, which closes any
+    /// connection it holds to an inference server.
+    pub async fn run<T, F>(&self, future: F) -> InferenceResult<T>
+    where
+        F: Future<Output = InferenceResult<T>>,
+    {
+        self.check()?;
+
+        let cancelled = async {
+            match &self.cancellation {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let expired = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = future => result,
+            _ = cancelled => Err(InferenceError::Cancelled),
+            _ = expired => Err(InferenceError::Timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_completes_without_limits() {
+        let options = GenerationOptions::new();
+        assert_eq!(options.run(async { Ok(42) }).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_run_is_cancelled() {
+        let token = CancellationToken::new();
+        let options = GenerationOptions::new().with_cancellation(token.clone());
+
+        let canceller = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            token.cancel();
+        };
+        let (result, _) = tokio::join!(
+            options.run(std::future::pending::<InferenceResult<()>>()),
+            canceller
+        );
+        assert!(matches!(result, Err(InferenceError::Cancelled)));
+        assert!(matches!(options.check(), Err(InferenceError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_run_times_out() {
+        let options = GenerationOptions::new().with_timeout(Duration::from_millis(10));
+        let result = options
+            .run(std::future::pending::<InferenceResult<()>>())
+            .await;
+        assert!(matches!(result, Err(InferenceError::Timeout)));
+    }
+}
@@ -3,23 +3,34 @@
 //! This crate provides the core functionality for translating natural language
 //! requests into shell commands using local LLM inference.
 
+pub mod context;
 pub mod inference;
 pub mod shell;
 pub mod translation;
 
-pub use inference::{InferenceEngine, MockInferenceEngine};
-pub use shell::ShellExecutor;
-pub use translation::TranslationEngine;
+pub use context::{ContextCollector, ContextOptions, EnvironmentContext};
+pub use inference::{
+    CancellationToken, GenerationOptions, InferenceEngine, MockInferenceEngine,
+    OllamaInferenceEngine,
+};
+pub use shell::{
+    PtyMode, ResourceLimits, RiskAnalyzer, RiskAssessment, RiskLevel, SafetyPolicy, ShellExecutor,
+    ShellKind, ShellSession,
+};
+pub use translation::{ExampleLibrary, PromptTemplate, TranslationEngine};
 
 /// Common types used throughout the cmdr ecosystem
 pub mod types {
+    use crate::context::EnvironmentContext;
     use serde::{Deserialize, Serialize};
+    use std::time::Duration;
 
     /// A natural language request from the user
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct NaturalLanguageRequest {
         pub text: String,
-        pub context: Option<String>,
+        /// Facts about the user's environment included in the prompt
+        pub context: Option<EnvironmentContext>,
     }
 
     /// A translated shell command
@@ -37,23 +48,32 @@ pub mod types {
         pub output: String,
         pub error: Option<String>,
         pub exit_code: i32,
+        /// Whether output past the executor's output limit was dropped from
+        /// `output` and `error`
+        #[serde(default)]
+        pub truncated: bool,
+        /// How long the command ran
+        #[serde(default)]
+        pub duration: Duration,
+        /// The signal that terminated the command, if one did
+        #[serde(default)]
+        pub signal: Option<i32>,
+        /// Whether cmdr killed the command for running past its timeout
+        #[serde(default)]
+        pub killed: bool,
+        /// Whether the command ran on a pseudo-terminal, in which case
+        /// `output` is a transcript of the terminal with stdout and stderr
+        /// interleaved
+        #[serde(default)]
+        pub pty: bool,
+        /// Files the command changed in the sandbox overlay; `None` unless
+        /// it ran sandboxed
+        #[serde(default)]
+        pub changes: Option<Vec<crate::shell::FileChange>>,
+        /// What the command would change; only set for dry runs
+        #[serde(default)]
+        pub preview: Option<crate::shell::Preview>,
     }
 }
 
 pub use types::*;
-
-pub fn hello_world() {
-    println!("Hello from cmdr-core!");
-
-    // TODO: wire up llama.cpp FFI bindings in Phase 2
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_hello_world() {
-        hello_world();
-    }
-}
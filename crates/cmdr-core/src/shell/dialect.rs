@@ -0,0 +1,165 @@
+//! Shell dialects
+//!
+//! The shells cmdr runs commands in differ in syntax: fish has no `export`
+//! or `$(...)`, and plain `sh` lacks bash's arrays and `[[`. A [`ShellKind`]
+//! knows how to quote for its shell, what to tell the model about its
+//! syntax, and how to ask the shell itself whether a command parses.
+
+use super::{ShellError, ShellResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// A family of shells sharing a syntax
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellKind {
+    /// A POSIX shell such as dash, ash or ksh
+    Sh,
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellKind {
+    /// The kind of shell `program` is, from its file name
+    ///
+    /// Accepts paths such as `/usr/bin/zsh` and login shell names such as
+    /// `-bash`.
+    pub fn from_program(program: &str) -> Option<Self> {
+        let name = Path::new(program).file_name()?.to_str()?;
+        match name.trim_start_matches('-') {
+            "sh" | "dash" | "ash" | "ksh" | "mksh" | "posh" => Some(Self::Sh),
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+
+    /// The user's shell, from `$SHELL`
+    pub fn detect() -> Option<Self> {
+        Self::from_program(&std::env::var("SHELL").ok()?)
+    }
+
+    /// The program to run for this kind of shell
+    pub fn program(self) -> &'static str {
+        match self {
+            Self::Sh => "sh",
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+        }
+    }
+
+    /// Quote `text` as a single word
+    pub fn quote(self, text: &str) -> String {
+        match self {
+            Self::Fish => format!("'{}'", text.replace('\\', r"\\").replace('\'', r"\'")),
+            _ => format!("'{}'", text.replace('\'', r"'\''")),
+        }
+    }
+
+    /// How the shell's syntax differs from what a model might assume, for
+    /// the translation prompt
+    pub fn syntax_notes(self) -> &'static str {
+        match self {
+            Self::Sh => {
+                "Use only POSIX sh features: no arrays, `[[ ]]`, `function`, brace \
+                 expansion or `source`; use `[ ]` and `.` instead"
+            }
+            Self::Bash => "Bash features such as arrays, `[[ ]]` and brace expansion are available",
+            Self::Zsh => {
+                "Zsh features are available; quote globs that may match nothing, since \
+                 an unmatched glob is an error"
+            }
+            Self::Fish => {
+                "Use fish syntax: `set -gx NAME value` instead of `export NAME=value`, \
+                 `(command)` instead of `$(command)`, `$status` instead of `$?`, `and`/`or` \
+                 or `&&`/`||` between commands, and `if ...; ...; end` blocks"
+            }
+        }
+    }
+
+    /// Ask `program`, a shell of this kind, whether `command` parses,
+    /// without running it
+    ///
+    /// Returns [`ShellError::InvalidCommand`] with the shell's message if it
+    /// does not.
+    pub(super) fn check_syntax(self, program: &str, command: &str) -> ShellResult<()> {
+        let output = Command::new(program)
+            .args(["-n", "-c", command])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| ShellError::Execution(format!("cannot run {program}: {e}")))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let message = String::from_utf8_lossy(&output.stderr);
+        let message = message.trim();
+        Err(ShellError::InvalidCommand(if message.is_empty() {
+            format!("not valid {self} syntax")
+        } else {
+            format!("not valid {self} syntax: {message}")
+        }))
+    }
+}
+
+impl fmt::Display for ShellKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sh => "POSIX sh",
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+        })
+    }
+}
+
+impl FromStr for ShellKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_program(s).ok_or_else(|| format!("expected sh, bash, zsh or fish, got {s:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_program() {
+        assert_eq!(
+            ShellKind::from_program("/usr/bin/zsh"),
+            Some(ShellKind::Zsh)
+        );
+        assert_eq!(ShellKind::from_program("-bash"), Some(ShellKind::Bash));
+        assert_eq!(ShellKind::from_program("dash"), Some(ShellKind::Sh));
+        assert_eq!(
+            ShellKind::from_program("/opt/homebrew/bin/fish"),
+            Some(ShellKind::Fish)
+        );
+        assert_eq!(ShellKind::from_program("nu"), None);
+        assert!("tcsh".parse::<ShellKind>().is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(ShellKind::Bash.quote("it's"), r"'it'\''s'");
+        assert_eq!(ShellKind::Fish.quote(r"it's a\b"), r"'it\'s a\\b'");
+    }
+
+    #[test]
+    fn test_check_syntax() {
+        let sh = ShellKind::Sh;
+        assert!(sh.check_syntax("sh", "if true; then echo yes; fi").is_ok());
+        let error = sh.check_syntax("sh", "if true; then echo yes").unwrap_err();
+        assert!(matches!(error, ShellError::InvalidCommand(_)), "{error}");
+        // Nothing runs
+        assert!(sh.check_syntax("sh", "exit 1").is_ok());
+    }
+}
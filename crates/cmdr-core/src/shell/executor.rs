@@ -2,53 +2,456 @@
 //!
 //! This module implements the execution of shell commands.
 
-use super::{ShellError, ShellResult};
+use super::output::{self, Capture, Received};
+#[cfg(unix)]
+use super::pty::PtySession;
+use super::pty::{self, PtyMode};
+use super::{
+    DryRun, OutputCallback, OutputChunk, OutputStream, PolicyAction, PolicyDecision,
+    PolicyEnvironment, Preview, ResourceLimits, SafetyPolicy, Sandbox, ShellError, ShellKind,
+    ShellResult, DEFAULT_OUTPUT_LIMIT,
+};
+use crate::inference::CancellationToken;
 use crate::types::{ExecutionResult, ShellCommand};
-use std::process::{Command, Stdio};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running command is checked for completion or cancellation
+pub(super) const POLL_INTERVAL: Duration = Duration::from_millis(20);
 
 /// Shell command executor
+#[derive(Debug, Clone)]
 pub struct ShellExecutor {
     shell: String,
+    /// The shell's dialect, if it is one cmdr knows
+    kind: Option<ShellKind>,
     auto_execute: bool,
+    policy: SafetyPolicy,
+    sandboxed: bool,
+    dry_run: bool,
+    timeout: Option<Duration>,
+    limits: ResourceLimits,
+    output_limit: usize,
+    pty: PtyMode,
+    /// Working directory for commands, instead of the process's own
+    dir: Option<PathBuf>,
+    /// Environment for commands, instead of the process's own
+    env: Option<BTreeMap<String, String>>,
 }
 
 impl ShellExecutor {
     /// Create a new shell executor
+    ///
+    /// `shell` is run with `-c` and the command. Its dialect is recognized
+    /// from its name; see [`ShellKind::from_program`].
     pub fn new(shell: String) -> Self {
         Self {
+            kind: ShellKind::from_program(&shell),
             shell,
             auto_execute: true,
+            policy: SafetyPolicy::default(),
+            sandboxed: false,
+            dry_run: false,
+            timeout: None,
+            limits: ResourceLimits::default(),
+            output_limit: DEFAULT_OUTPUT_LIMIT,
+            pty: PtyMode::default(),
+            dir: None,
+            env: None,
         }
     }
 
+    /// Run commands in a [`Sandbox`] over the current directory
+    ///
+    /// Sandboxed commands cannot change files outside the working
+    /// directory or use the network, and their changes inside it are
+    /// reported in [`ExecutionResult::changes`] instead of applied.
+    /// Commands the policy wants confirmed run without confirmation, since
+    /// they cannot do harm; denied commands are still refused.
+    pub fn with_sandbox(mut self, sandboxed: bool) -> Self {
+        self.sandboxed = sandboxed;
+        self
+    }
+
+    /// Whether commands run in a sandbox
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandboxed
+    }
+
+    /// Preview commands instead of running them
+    ///
+    /// A dry run reports what a command would change in
+    /// [`ExecutionResult::preview`]. Commands [`DryRun`] understands are not
+    /// run at all; others run in a [`Sandbox`], which must be available.
+    /// As with sandboxing, only denied commands are refused.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Whether commands are previewed instead of run
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Kill commands that run longer than `timeout`
+    ///
+    /// A command that times out is killed along with every process it
+    /// started, and its result has [`killed`](ExecutionResult::killed) set.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The wall-clock limit on commands, if any
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Apply `limits` to every command
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The resource limits applied to commands
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    /// Keep at most `bytes` of output, stdout and stderr together
+    ///
+    /// Output past the limit is dropped, both from the result and from
+    /// the chunks passed to streaming callbacks, and the result is marked
+    /// [`truncated`](ExecutionResult::truncated). Defaults to
+    /// [`DEFAULT_OUTPUT_LIMIT`].
+    pub fn with_output_limit(mut self, bytes: usize) -> Self {
+        self.output_limit = bytes;
+        self
+    }
+
+    /// The most output kept from a command, in bytes
+    pub fn output_limit(&self) -> usize {
+        self.output_limit
+    }
+
+    /// Choose when commands run on a pseudo-terminal
+    ///
+    /// A command on a pseudo-terminal can use cmdr's terminal as if it
+    /// ran directly in it. Its stdout and stderr are then one stream, and
+    /// [`ExecutionResult::output`] is a transcript of the terminal.
+    pub fn with_pty(mut self, mode: PtyMode) -> Self {
+        self.pty = mode;
+        self
+    }
+
+    /// When commands run on a pseudo-terminal
+    pub fn pty_mode(&self) -> PtyMode {
+        self.pty
+    }
+
+    /// Use `policy` to decide which commands may run
+    pub fn with_policy(mut self, policy: SafetyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The safety policy commands are checked against
+    pub fn policy(&self) -> &SafetyPolicy {
+        &self.policy
+    }
+
+    /// Evaluate `command` against the safety policy without running it
+    ///
+    /// Relative paths and `~` are resolved against the directory and
+    /// `HOME` the command would run with.
+    pub fn check(&self, command: &str) -> PolicyDecision {
+        let current = PolicyEnvironment::current();
+        let env = PolicyEnvironment {
+            cwd: self.dir.clone().unwrap_or(current.cwd),
+            home: match &self.env {
+                Some(env) => env.get("HOME").map(PathBuf::from),
+                None => current.home,
+            },
+        };
+        self.policy.evaluate_in(command, &env)
+    }
+
     /// Set whether to auto-execute commands
     pub fn set_auto_execute(&mut self, auto_execute: bool) {
         self.auto_execute = auto_execute;
     }
 
+    /// Whether commands are executed
+    pub fn is_auto_execute(&self) -> bool {
+        self.auto_execute
+    }
+
+    /// Create an executor running commands with the usual program for `kind`
+    pub fn for_kind(kind: ShellKind) -> Self {
+        Self::new(kind.program().to_string())
+    }
+
+    /// The shell commands are run with
+    pub fn shell(&self) -> &str {
+        &self.shell
+    }
+
+    /// The shell's dialect, or `None` for a shell cmdr does not know
+    pub fn kind(&self) -> Option<ShellKind> {
+        self.kind
+    }
+
+    /// Check that `command` parses in the shell's dialect, asking the shell
+    ///
+    /// Commands for shells cmdr does not know are not checked.
+    pub(super) fn check_syntax(&self, command: &str) -> ShellResult<()> {
+        match self.kind {
+            Some(kind) => kind.check_syntax(&self.shell, command),
+            None => Ok(()),
+        }
+    }
+
+    /// Run commands in `dir` with exactly the variables in `env`
+    pub(super) fn with_state(mut self, dir: PathBuf, env: BTreeMap<String, String>) -> Self {
+        self.dir = Some(dir);
+        self.env = Some(env);
+        self
+    }
+
+    /// Check `command` against the safety policy, failing unless it may run
+    ///
+    /// Commands the policy wants confirmed may run if `confirmed`, or when
+    /// sandboxed or dry-running since they cannot do harm then.
+    pub(super) fn admit(&self, command: &str, confirmed: bool) -> ShellResult<PolicyDecision> {
+        let decision = self.check(command);
+        match decision.action {
+            PolicyAction::Allow => Ok(decision),
+            PolicyAction::Confirm if confirmed || self.sandboxed || self.dry_run => Ok(decision),
+            PolicyAction::Confirm => Err(ShellError::ConfirmationRequired(Box::new(decision))),
+            PolicyAction::Deny => Err(ShellError::PolicyDenied(Box::new(decision))),
+        }
+    }
+
+    /// Whether a command with this policy decision runs on a pseudo-terminal
+    pub(super) fn wants_pty(&self, decision: &PolicyDecision) -> bool {
+        match self.pty {
+            PtyMode::Never => false,
+            PtyMode::Auto => pty::in_terminal() && decision.risk.interactive.is_some(),
+            PtyMode::Always => true,
+        }
+    }
+
     /// Execute a shell command
     pub fn execute(&self, command: &ShellCommand) -> ShellResult<ExecutionResult> {
+        self.execute_cancellable(command, &CancellationToken::new())
+    }
+
+    /// Execute a shell command, killing it if `cancellation` is cancelled
+    ///
+    /// The command only runs if the safety policy allows it; otherwise this
+    /// returns [`ShellError::PolicyDenied`] or
+    /// [`ShellError::ConfirmationRequired`] with the policy decision.
+    /// Returns [`ShellError::Cancelled`] when the command was killed.
+    pub fn execute_cancellable(
+        &self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+    ) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, cancellation, false, &mut |_| {})
+    }
+
+    /// Execute a command the user has confirmed
+    ///
+    /// Like [`execute_cancellable`](Self::execute_cancellable), but runs
+    /// commands the policy wants confirmed. Denied commands still fail.
+    pub fn execute_confirmed(
+        &self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+    ) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, cancellation, true, &mut |_| {})
+    }
+
+    /// Execute a shell command, passing its output to `on_output` as it
+    /// arrives
+    ///
+    /// Chunks of stdout and stderr are passed in the order they were read,
+    /// and are also collected into the result as with
+    /// [`execute_cancellable`](Self::execute_cancellable).
+    pub fn execute_streaming(
+        &self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+        on_output: &mut OutputCallback<'_>,
+    ) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, cancellation, false, on_output)
+    }
+
+    /// Execute a command the user has confirmed, streaming its output
+    ///
+    /// The streaming counterpart of
+    /// [`execute_confirmed`](Self::execute_confirmed).
+    pub fn execute_confirmed_streaming(
+        &self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+        on_output: &mut OutputCallback<'_>,
+    ) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, cancellation, true, on_output)
+    }
+
+    pub(super) fn execute_checked(
+        &self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+        confirmed: bool,
+        on_output: &mut OutputCallback<'_>,
+    ) -> ShellResult<ExecutionResult> {
         if !self.auto_execute {
             return Ok(ExecutionResult {
                 success: false,
                 output: "Auto-execution disabled".to_string(),
                 error: None,
                 exit_code: 0,
+                truncated: false,
+                duration: Duration::ZERO,
+                signal: None,
+                killed: false,
+                pty: false,
+                changes: None,
+                preview: None,
             });
         }
 
-        let output = Command::new(&self.shell)
-            .arg("-c")
-            .arg(&command.command)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| ShellError::Execution(e.to_string()))?;
+        let decision = self.admit(&command.command, confirmed)?;
+        self.check_syntax(&command.command)?;
+
+        let cwd = || match &self.dir {
+            Some(dir) => Ok(dir.clone()),
+            None => std::env::current_dir()
+                .map_err(|e| ShellError::Execution(format!("no working directory: {e}"))),
+        };
+        if self.dry_run {
+            if let Some(preview) = DryRun::new(cwd()?).preview(&command.command) {
+                return Ok(ExecutionResult {
+                    success: true,
+                    output: String::new(),
+                    error: None,
+                    exit_code: 0,
+                    truncated: false,
+                    duration: Duration::ZERO,
+                    signal: None,
+                    killed: false,
+                    pty: false,
+                    changes: None,
+                    preview: Some(preview),
+                });
+            }
+        }
+
+        let sandbox = if self.sandboxed || self.dry_run {
+            Some(Sandbox::new(cwd()?)?)
+        } else {
+            None
+        };
+        let mut process = match &sandbox {
+            Some(sandbox) => sandbox.command(&self.shell, &command.command)?,
+            None => {
+                let mut process = Command::new(&self.shell);
+                process.arg("-c").arg(&command.command);
+                if let Some(dir) = &self.dir {
+                    process.current_dir(dir);
+                }
+                process
+            }
+        };
+        if let Some(env) = &self.env {
+            process.env_clear().envs(env);
+        }
+
+        #[cfg(unix)]
+        if !self.limits.is_unlimited() {
+            use std::os::unix::process::CommandExt;
+            let limits = self.limits;
+            // SAFETY: `apply` only calls the async-signal-safe `getrlimit`
+            // and `setrlimit`.
+            unsafe {
+                process.pre_exec(move || limits.apply());
+            }
+        }
+
+        let use_pty = self.wants_pty(&decision);
+        let started = Instant::now();
+        let (mut child, mut source) = match use_pty {
+            true => spawn_pty(process, started)?,
+            false => spawn_piped(process, started)?,
+        };
 
-        let success = output.status.success();
-        let output_str = String::from_utf8_lossy(&output.stdout).to_string();
-        let error_str = String::from_utf8_lossy(&output.stderr).to_string();
+        let mut capture = Capture::new(self.output_limit);
+        let mut output_open = true;
+        let mut exit = None;
+        let deadline = self.timeout.map(|timeout| started + timeout);
+        while output_open || exit.is_none() {
+            if output_open {
+                match source.receive(POLL_INTERVAL) {
+                    Received::Chunk(chunk) => {
+                        if let Some(chunk) = capture.push(chunk) {
+                            on_output(&chunk);
+                        }
+                    }
+                    Received::Nothing => {}
+                    Received::Closed => output_open = false,
+                }
+            } else {
+                thread::sleep(POLL_INTERVAL);
+            }
+            let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if let Some((_, killed, duration)) = &mut exit {
+                // The shell has exited, but something it started in the
+                // background still holds the output open
+                if cancellation.is_cancelled() || timed_out {
+                    kill_orphans(&child);
+                    output_open = false;
+                    if cancellation.is_cancelled() {
+                        return Err(ShellError::Cancelled);
+                    }
+                    *killed = true;
+                    *duration = started.elapsed();
+                }
+                continue;
+            }
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|e| ShellError::Execution(e.to_string()))?
+            {
+                exit = Some((status, false, started.elapsed()));
+            } else if cancellation.is_cancelled() {
+                kill_process_group(&mut child);
+                let _ = child.wait();
+                return Err(ShellError::Cancelled);
+            } else if timed_out {
+                kill_process_group(&mut child);
+                let status = child
+                    .wait()
+                    .map_err(|e| ShellError::Execution(e.to_string()))?;
+                exit = Some((status, true, started.elapsed()));
+            }
+        }
+        let (status, killed, duration) = exit.expect("the loop ends after the command exits");
+        // Restores cmdr's terminal if the command ran on a pseudo-terminal,
+        // and stops reading output nothing is left to write
+        drop(source);
 
+        let success = status.success() && !killed;
+        let output_str = String::from_utf8_lossy(&capture.stdout).to_string();
+        let error_str = String::from_utf8_lossy(&capture.stderr).to_string();
+
+        let changes = sandbox.map(|sandbox| sandbox.changes()).transpose()?;
         Ok(ExecutionResult {
             success,
             output: output_str,
@@ -57,18 +460,29 @@ impl ShellExecutor {
             } else {
                 Some(error_str)
             },
-            exit_code: output.status.code().unwrap_or(-1),
+            exit_code: status.code().unwrap_or(-1),
+            truncated: capture.truncated,
+            duration,
+            signal: signal(&status),
+            killed,
+            pty: use_pty,
+            preview: match self.dry_run {
+                true => changes.as_deref().map(Preview::from_sandbox),
+                false => None,
+            },
+            changes,
         })
     }
 
     /// Validate a command without executing it
+    ///
+    /// Fails with [`ShellError::InvalidCommand`] if the command is empty or
+    /// the shell finds a syntax error in it.
     pub fn validate(&self, command: &str) -> ShellResult<()> {
-        // TODO: Implement command validation
-        // For now, just check if the command is not empty
         if command.trim().is_empty() {
             return Err(ShellError::InvalidCommand("Empty command".to_string()));
         }
-        Ok(())
+        self.check_syntax(command)
     }
 }
 
@@ -77,3 +491,417 @@ impl Default for ShellExecutor {
         Self::new("bash".to_string())
     }
 }
+
+/// Where a running command's output is read from
+enum Output {
+    /// Chunks from the threads reading its stdout and stderr pipes
+    Pipes(Receiver<OutputChunk>),
+    #[cfg(unix)]
+    Pty(PtySession),
+}
+
+impl Output {
+    fn receive(&mut self, timeout: Duration) -> Received {
+        match self {
+            Self::Pipes(chunks) => match chunks.recv_timeout(timeout) {
+                Ok(chunk) => Received::Chunk(chunk),
+                Err(RecvTimeoutError::Timeout) => Received::Nothing,
+                Err(RecvTimeoutError::Disconnected) => Received::Closed,
+            },
+            #[cfg(unix)]
+            Self::Pty(session) => session.receive(timeout),
+        }
+    }
+}
+
+/// Spawn `process` with its output on pipes
+fn spawn_piped(mut process: Command, started: Instant) -> ShellResult<(Child, Output)> {
+    // The command leads a new process group so that everything it starts
+    // can be killed together
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        process.process_group(0);
+    }
+    let mut child = process
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ShellError::Execution(e.to_string()))?;
+
+    // Drain both pipes concurrently so a chatty command cannot block on a
+    // full pipe buffer while we wait for it. The readers hang up when every
+    // process holding the pipes has closed them.
+    let (sender, chunks) = mpsc::channel();
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    output::forward(stdout, OutputStream::Stdout, started, sender.clone());
+    output::forward(stderr, OutputStream::Stderr, started, sender);
+    Ok((child, Output::Pipes(chunks)))
+}
+
+/// Spawn `process` on a new pseudo-terminal
+///
+/// The command leads a new session, and so a new process group, so that
+/// everything it starts can be killed together.
+#[cfg(unix)]
+fn spawn_pty(mut process: Command, started: Instant) -> ShellResult<(Child, Output)> {
+    let session = PtySession::attach(&mut process, started)
+        .map_err(|e| ShellError::Execution(format!("cannot open a pseudo-terminal: {e}")))?;
+    let child = process
+        .spawn()
+        .map_err(|e| ShellError::Execution(e.to_string()))?;
+    // Close our copies of the terminal's slave side, so reads from the
+    // master fail once the command and its children have closed theirs
+    drop(process);
+    Ok((child, Output::Pty(session)))
+}
+
+#[cfg(not(unix))]
+fn spawn_pty(_process: Command, _started: Instant) -> ShellResult<(Child, Output)> {
+    Err(ShellError::Execution(
+        "pseudo-terminals are only supported on Unix".to_string(),
+    ))
+}
+
+/// Kill `child` and every process in its process group
+pub(super) fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: the child has not been waited for, so its pid still names
+        // the process group it leads.
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+}
+
+/// Kill the processes left in the process group of `child`, which has
+/// exited and been waited for
+fn kill_orphans(child: &Child) {
+    #[cfg(unix)]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: killing a process group is memory safe. Its id is not
+        // reused while processes the child started keep the group alive.
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = child;
+}
+
+/// The signal that terminated a process
+pub(super) fn signal(status: &std::process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn command(text: &str) -> ShellCommand {
+        ShellCommand {
+            command: text.to_string(),
+            explanation: None,
+            confidence: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_execute_captures_output() {
+        let executor = ShellExecutor::new("sh".to_string());
+        let result = executor
+            .execute(&command("echo out; echo err >&2; exit 3"))
+            .unwrap();
+
+        assert!(!result.success);
+        assert_eq!(result.output, "out\n");
+        assert_eq!(result.error.as_deref(), Some("err\n"));
+        assert_eq!(result.exit_code, 3);
+    }
+
+    #[test]
+    fn test_execute_cancellable_kills_command() {
+        let executor = ShellExecutor::new("sh".to_string());
+        let token = CancellationToken::new();
+
+        let canceller = {
+            let token = token.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                token.cancel();
+            })
+        };
+        let started = Instant::now();
+        let result = executor.execute_cancellable(&command("sleep 10"), &token);
+        canceller.join().unwrap();
+
+        assert!(matches!(result, Err(ShellError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_execute_streaming() {
+        let executor = ShellExecutor::new("sh".to_string());
+        let mut chunks = Vec::new();
+        let result = executor
+            .execute_streaming(
+                &command("echo one; sleep 0.2; echo two >&2"),
+                &CancellationToken::new(),
+                &mut |chunk| chunks.push(chunk.clone()),
+            )
+            .unwrap();
+
+        let streams: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.stream, c.data.as_slice()))
+            .collect();
+        assert_eq!(
+            streams,
+            [
+                (OutputStream::Stdout, &b"one\n"[..]),
+                (OutputStream::Stderr, &b"two\n"[..])
+            ]
+        );
+        assert!(chunks[1].elapsed >= chunks[0].elapsed + Duration::from_millis(150));
+        assert_eq!(result.output, "one\n");
+        assert_eq!(result.error.as_deref(), Some("two\n"));
+        assert!(!result.truncated);
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let executor = ShellExecutor::new("sh".to_string()).with_output_limit(1000);
+        let mut streamed = 0;
+        let result = executor
+            .execute_streaming(
+                &command("yes | head -c 100000"),
+                &CancellationToken::new(),
+                &mut |chunk| streamed += chunk.data.len(),
+            )
+            .unwrap();
+
+        assert!(result.success);
+        assert!(result.truncated);
+        assert_eq!(result.output.len(), 1000);
+        assert_eq!(streamed, 1000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pty_execution() {
+        let executor = ShellExecutor::new("sh".to_string()).with_pty(PtyMode::Always);
+        let result = executor
+            .execute(&command(
+                "tty; test -t 0 && echo in-tty; stty size; echo err >&2",
+            ))
+            .unwrap();
+
+        assert!(result.success, "{result:?}");
+        assert!(result.pty);
+        assert!(result.output.starts_with("/dev/"), "{}", result.output);
+        assert!(result.output.contains("in-tty\r\n"));
+        assert!(result.output.contains("24 80"));
+        // stderr is part of the transcript
+        assert!(result.output.contains("err"));
+        assert_eq!(result.error, None);
+    }
+
+    #[test]
+    fn test_timeout_kills_process_group() {
+        let executor =
+            ShellExecutor::new("sh".to_string()).with_timeout(Duration::from_millis(200));
+        let result = executor
+            .execute(&command("sleep 30 & echo $!; sleep 30"))
+            .unwrap();
+
+        assert!(result.killed);
+        assert!(!result.success);
+        assert_eq!(result.signal, Some(libc::SIGKILL));
+        assert!(result.duration >= Duration::from_millis(200));
+        assert!(result.duration < Duration::from_secs(5));
+
+        // The background sleep went down with the shell
+        let pid = result.output.trim();
+        thread::sleep(Duration::from_millis(100));
+        let state = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap_or_default();
+        let state = state.rsplit(") ").next().unwrap_or_default();
+        assert!(
+            state.is_empty() || state.starts_with('Z'),
+            "{pid} is {state}"
+        );
+    }
+
+    #[test]
+    fn test_timeout_covers_background_children() {
+        let executor = ShellExecutor::new("sh".to_string()).with_timeout(Duration::from_secs(1));
+        let started = Instant::now();
+        let result = executor
+            .execute(&command("sleep 6 & echo started"))
+            .unwrap();
+
+        // The shell exits at once, but the sleep holds its output open
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(result.killed);
+        assert!(!result.success);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.output, "started\n");
+
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                token.cancel();
+            })
+        };
+        let started = Instant::now();
+        let result = ShellExecutor::new("sh".to_string())
+            .execute_cancellable(&command("sleep 6 & echo started"), &token);
+        canceller.join().unwrap();
+        assert!(matches!(result, Err(ShellError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pty_timeout_covers_background_children() {
+        // The child ignores the hangup sent when the shell leaves the
+        // terminal, and keeps the terminal open
+        let executor = ShellExecutor::new("sh".to_string())
+            .with_pty(PtyMode::Always)
+            .with_timeout(Duration::from_secs(1));
+        let started = Instant::now();
+        let result = executor
+            .execute(&command("trap '' HUP; sleep 6 & echo started"))
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(result.killed);
+        assert!(result.output.contains("started"), "{}", result.output);
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("big");
+        let executor = ShellExecutor::new("sh".to_string()).with_limits(
+            ResourceLimits::default()
+                .with_file_size_bytes(1024)
+                .with_cpu_seconds(1),
+        );
+
+        let result = executor
+            .execute(&command(&format!(
+                "head -c 4096 /dev/zero > {}",
+                file.display()
+            )))
+            .unwrap();
+        assert!(!result.success);
+        assert_eq!(std::fs::metadata(&file).unwrap().len(), 1024);
+
+        let result = executor.execute(&command("while :; do :; done")).unwrap();
+        assert!(!result.killed);
+        assert_eq!(result.signal, Some(libc::SIGXCPU));
+    }
+
+    #[test]
+    fn test_sandboxed_execution_reports_changes() {
+        if Sandbox::available_backend().is_none() {
+            eprintln!("skipping: no sandbox backend available");
+            return;
+        }
+        let executor = ShellExecutor::new("sh".to_string()).with_sandbox(true);
+        let result = executor
+            .execute(&command(
+                "echo hi > cmdr-sandbox-test.txt && cat cmdr-sandbox-test.txt",
+            ))
+            .unwrap();
+        assert_eq!(result.output, "hi\n");
+        let changes = result.changes.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].path,
+            std::path::Path::new("cmdr-sandbox-test.txt")
+        );
+        assert!(!std::path::Path::new("cmdr-sandbox-test.txt").exists());
+    }
+
+    #[test]
+    fn test_dry_run_previews_without_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("victim");
+        std::fs::write(&file, "").unwrap();
+        let executor = ShellExecutor::new("sh".to_string()).with_dry_run(true);
+
+        let result = executor
+            .execute(&command(&format!("rm {}", file.display())))
+            .unwrap();
+        let preview = result.preview.unwrap();
+        assert_eq!(preview.source, crate::shell::PreviewSource::Analysis);
+        assert_eq!(preview.changes.len(), 1);
+        assert_eq!(preview.changes[0].path, file);
+        assert!(file.exists());
+
+        if Sandbox::available_backend().is_none() {
+            eprintln!("skipping sandbox fallback: no sandbox backend available");
+            return;
+        }
+        let result = executor
+            .execute(&command("echo hi > cmdr-dry-run-test.txt"))
+            .unwrap();
+        let preview = result.preview.unwrap();
+        assert_eq!(preview.source, crate::shell::PreviewSource::Sandbox);
+        assert_eq!(
+            preview.changes[0].path,
+            std::path::Path::new("cmdr-dry-run-test.txt")
+        );
+        assert!(!std::path::Path::new("cmdr-dry-run-test.txt").exists());
+    }
+
+    #[test]
+    fn test_policy_is_enforced() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("victim");
+        std::fs::write(&file, "").unwrap();
+        let rm = command(&format!("rm {}", file.display()));
+        let executor = ShellExecutor::new("sh".to_string());
+        let token = CancellationToken::new();
+
+        let Err(ShellError::ConfirmationRequired(decision)) =
+            executor.execute_cancellable(&rm, &token)
+        else {
+            panic!("expected a confirmation request");
+        };
+        assert_eq!(decision.rule.as_deref(), Some("destructive"));
+        assert!(file.exists());
+
+        executor.execute_confirmed(&rm, &token).unwrap();
+        assert!(!file.exists());
+
+        let executor = executor.with_policy(
+            SafetyPolicy::new(PolicyAction::Allow).with_rule(
+                crate::shell::PolicyRule::new(PolicyAction::Deny)
+                    .with_name("no-echo")
+                    .with_programs(["echo"]),
+            ),
+        );
+        let result = executor.execute_confirmed(&command("echo hi"), &token);
+        assert!(
+            matches!(result, Err(ShellError::PolicyDenied(d)) if d.rule.as_deref() == Some("no-echo"))
+        );
+    }
+}
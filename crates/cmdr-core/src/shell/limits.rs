@@ -0,0 +1,125 @@
+//! Resource limits for executed commands
+//!
+//! Limits are set with `setrlimit` in the command's process just before it
+//! starts, so they apply to the shell and are inherited by everything it
+//! runs.
+
+use serde::{Deserialize, Serialize};
+
+/// Limits on the resources a command may use
+///
+/// Unset limits are left as cmdr's own. Limits can only be lowered, so a
+/// value above cmdr's hard limit is capped at it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// CPU time per process in seconds; a process past it gets `SIGXCPU`,
+    /// then `SIGKILL` a second later
+    pub cpu_seconds: Option<u64>,
+    /// Address space per process in bytes
+    pub memory_bytes: Option<u64>,
+    /// Largest file a process may write, in bytes
+    pub file_size_bytes: Option<u64>,
+    /// Number of processes, counting all processes of the user, not just
+    /// those of the command
+    pub processes: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Limit CPU time per process
+    pub fn with_cpu_seconds(mut self, seconds: u64) -> Self {
+        self.cpu_seconds = Some(seconds);
+        self
+    }
+
+    /// Limit the address space per process
+    pub fn with_memory_bytes(mut self, bytes: u64) -> Self {
+        self.memory_bytes = Some(bytes);
+        self
+    }
+
+    /// Limit the size of files written
+    pub fn with_file_size_bytes(mut self, bytes: u64) -> Self {
+        self.file_size_bytes = Some(bytes);
+        self
+    }
+
+    /// Limit the number of processes of the user
+    pub fn with_processes(mut self, processes: u64) -> Self {
+        self.processes = Some(processes);
+        self
+    }
+
+    /// Whether no limit is set
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Lower the calling process's limits
+    ///
+    /// Meant to run in a forked child before `exec`, so it neither
+    /// allocates nor takes locks.
+    #[cfg(unix)]
+    pub(super) fn apply(&self) -> std::io::Result<()> {
+        if let Some(seconds) = self.cpu_seconds {
+            lower(libc::RLIMIT_CPU, seconds, seconds.saturating_add(1))?;
+        }
+        if let Some(bytes) = self.memory_bytes {
+            lower(libc::RLIMIT_AS, bytes, bytes)?;
+        }
+        if let Some(bytes) = self.file_size_bytes {
+            lower(libc::RLIMIT_FSIZE, bytes, bytes)?;
+        }
+        if let Some(processes) = self.processes {
+            lower(libc::RLIMIT_NPROC, processes, processes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+/// Lower the soft and hard limits of `resource`, never raising either
+#[cfg(unix)]
+fn lower(resource: Resource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid `rlimit` for the call to fill in.
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let hard = libc::rlim_t::try_from(hard).unwrap_or(libc::RLIM_INFINITY);
+    let soft = libc::rlim_t::try_from(soft).unwrap_or(libc::RLIM_INFINITY);
+    limit.rlim_max = limit.rlim_max.min(hard);
+    limit.rlim_cur = soft.min(limit.rlim_max);
+    // SAFETY: `limit` is a valid `rlimit`.
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_from_toml() {
+        let limits: ResourceLimits =
+            toml::from_str("cpu_seconds = 10\nmemory_bytes = 1073741824").unwrap();
+        assert_eq!(
+            limits,
+            ResourceLimits::default()
+                .with_cpu_seconds(10)
+                .with_memory_bytes(1 << 30)
+        );
+        assert!(!limits.is_unlimited());
+        assert!(ResourceLimits::default().is_unlimited());
+        assert!(toml::from_str::<ResourceLimits>("memory = 1").is_err());
+    }
+}
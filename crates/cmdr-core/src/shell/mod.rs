@@ -2,11 +2,29 @@
 //!
 //! This module handles the execution of shell commands.
 
+mod dialect;
 mod executor;
+mod limits;
+mod output;
 mod parser;
+mod policy;
+mod preview;
+mod pty;
+mod risk;
+mod sandbox;
+mod session;
 
+pub use dialect::ShellKind;
 pub use executor::ShellExecutor;
-pub use parser::CommandParser;
+pub use limits::ResourceLimits;
+pub use output::{OutputCallback, OutputChunk, OutputStream, DEFAULT_OUTPUT_LIMIT};
+pub use parser::{ast, CommandParser, ParseError};
+pub use policy::{PolicyAction, PolicyDecision, PolicyEnvironment, PolicyRule, SafetyPolicy};
+pub use preview::{DryRun, Operation, PlannedChange, Preview, PreviewSource};
+pub use pty::PtyMode;
+pub use risk::{RiskAnalyzer, RiskAssessment, RiskFinding, RiskLevel};
+pub use sandbox::{ChangeKind, FileChange, Sandbox, SandboxBackend};
+pub use session::ShellSession;
 
 /// Error type for shell operations
 #[derive(Debug, thiserror::Error)]
@@ -19,6 +37,18 @@ pub enum ShellError {
     PermissionDenied(String),
     #[error("Invalid command: {0}")]
     InvalidCommand(String),
+    #[error("Syntax error: {0}")]
+    Syntax(#[from] ParseError),
+    #[error("Command was cancelled")]
+    Cancelled,
+    #[error("Blocked by safety policy: {0}")]
+    PolicyDenied(Box<PolicyDecision>),
+    #[error("Safety policy requires confirmation: {0}")]
+    ConfirmationRequired(Box<PolicyDecision>),
+    #[error("Invalid safety policy: {0}")]
+    InvalidPolicy(String),
+    #[error("Sandbox error: {0}")]
+    Sandbox(String),
 }
 
 /// Result type for shell operations
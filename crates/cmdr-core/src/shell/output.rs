@@ -0,0 +1,146 @@
+//! Streamed command output
+//!
+//! A running command's stdout and stderr are read in chunks as they arrive.
+//! Each chunk is timestamped and handed to the caller, and collected up to a
+//! size limit for the [`ExecutionResult`](crate::ExecutionResult).
+
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Receives each chunk of output as the command writes it
+pub type OutputCallback<'a> = dyn FnMut(&OutputChunk) + Send + 'a;
+
+/// Bytes of output kept by default, stdout and stderr together
+pub const DEFAULT_OUTPUT_LIMIT: usize = 1024 * 1024;
+
+/// Size of the reads from a command's pipes or pseudo-terminal
+pub(super) const CHUNK_SIZE: usize = 8192;
+
+/// Which pipe output came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A piece of output as it was read from the command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub stream: OutputStream,
+    pub data: Vec<u8>,
+    /// When the chunk was read, counted from the start of the command
+    pub elapsed: Duration,
+}
+
+/// The result of waiting for output
+#[derive(Debug)]
+pub(super) enum Received {
+    Chunk(OutputChunk),
+    /// Nothing arrived in time
+    Nothing,
+    /// The command closed its output
+    Closed,
+}
+
+/// Read `pipe` to the end on a separate thread, sending chunks to `chunks`
+pub(super) fn forward<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    stream: OutputStream,
+    started: Instant,
+    chunks: Sender<OutputChunk>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let Some(mut pipe) = pipe else {
+            return;
+        };
+        let mut buf = [0; CHUNK_SIZE];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    let chunk = OutputChunk {
+                        stream,
+                        data: buf[..len].to_vec(),
+                        elapsed: started.elapsed(),
+                    };
+                    // Keep draining even if nobody listens, so the command
+                    // never blocks on a full pipe
+                    let _ = chunks.send(chunk);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    })
+}
+
+/// Output collected up to a size limit
+#[derive(Debug)]
+pub(super) struct Capture {
+    pub(super) stdout: Vec<u8>,
+    pub(super) stderr: Vec<u8>,
+    limit: usize,
+    /// Whether output past the limit was dropped
+    pub(super) truncated: bool,
+}
+
+impl Capture {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            limit,
+            truncated: false,
+        }
+    }
+
+    /// Keep as much of `chunk` as fits, returning the part kept, if any
+    pub(super) fn push(&mut self, mut chunk: OutputChunk) -> Option<OutputChunk> {
+        let room = self
+            .limit
+            .saturating_sub(self.stdout.len() + self.stderr.len());
+        if chunk.data.len() > room {
+            chunk.data.truncate(room);
+            self.truncated = true;
+        }
+        if chunk.data.is_empty() {
+            return None;
+        }
+        match chunk.stream {
+            OutputStream::Stdout => self.stdout.extend_from_slice(&chunk.data),
+            OutputStream::Stderr => self.stderr.extend_from_slice(&chunk.data),
+        }
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(stream: OutputStream, data: &str) -> OutputChunk {
+        OutputChunk {
+            stream,
+            data: data.as_bytes().to_vec(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_capture_truncates_at_limit() {
+        let mut capture = Capture::new(8);
+        assert!(capture.push(chunk(OutputStream::Stdout, "abc")).is_some());
+        assert!(capture.push(chunk(OutputStream::Stderr, "de")).is_some());
+        let kept = capture.push(chunk(OutputStream::Stdout, "fghij")).unwrap();
+        assert_eq!(kept.data, b"fgh");
+        assert!(capture.push(chunk(OutputStream::Stderr, "k")).is_none());
+
+        assert_eq!(capture.stdout, b"abcfgh");
+        assert_eq!(capture.stderr, b"de");
+        assert!(capture.truncated);
+    }
+}
@@ -0,0 +1,326 @@
+//! Shell syntax tree
+//!
+//! The tree follows the POSIX shell grammar: a [`List`] of and-or lists,
+//! each made of [`Pipeline`]s of [`Command`]s. Every node records the
+//! [`Span`] of source text it was parsed from.
+
+/// A byte range of the parsed source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    /// Offset of the first byte
+    pub start: usize,
+    /// Offset one past the last byte
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span from `start` up to, but not including, `end`
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// A parsed command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    source: String,
+    /// The commands of the script
+    pub list: List,
+}
+
+impl Script {
+    pub(crate) fn new(source: String, list: List) -> Self {
+        Self { source, list }
+    }
+
+    /// The source the script was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The source text covered by `span`, or `""` if it is out of range
+    pub fn text(&self, span: Span) -> &str {
+        self.source.get(span.start..span.end).unwrap_or_default()
+    }
+}
+
+/// And-or lists separated by `;`, `&` or newlines
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub items: Vec<ListItem>,
+    pub span: Span,
+}
+
+/// One and-or list of a [`List`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    /// Whether the list is run in the background with `&`
+    pub background: bool,
+    pub span: Span,
+}
+
+/// Pipelines joined by `&&` and `||`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOp, Pipeline)>,
+    pub span: Span,
+}
+
+impl AndOr {
+    /// All pipelines in order
+    pub fn pipelines(&self) -> impl Iterator<Item = &Pipeline> {
+        std::iter::once(&self.first).chain(self.rest.iter().map(|(_, pipeline)| pipeline))
+    }
+}
+
+/// Operator joining two pipelines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AndOrOp {
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+}
+
+/// Commands connected by `|`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    /// Whether the pipeline is negated with `!`
+    pub negated: bool,
+    pub commands: Vec<Command>,
+    pub span: Span,
+}
+
+/// A single command of a pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand),
+    FunctionDefinition(FunctionDefinition),
+}
+
+impl Command {
+    /// The source range of the command
+    pub fn span(&self) -> Span {
+        match self {
+            Command::Simple(command) => command.span,
+            Command::Compound(command) => command.span,
+            Command::FunctionDefinition(function) => function.span,
+        }
+    }
+}
+
+/// Assignments, words and redirections, e.g. `LANG=C sort <in >out`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    /// The command name followed by its arguments
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    pub span: Span,
+}
+
+impl SimpleCommand {
+    /// The command name, if any
+    pub fn name(&self) -> Option<&Word> {
+        self.words.first()
+    }
+
+    /// The arguments after the command name
+    pub fn args(&self) -> &[Word] {
+        self.words.get(1..).unwrap_or_default()
+    }
+}
+
+/// A variable assignment, e.g. `PATH=/bin`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+    pub span: Span,
+}
+
+/// A redirection, e.g. `2>&1` or `<<EOF`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// Explicit file descriptor number before the operator
+    pub fd: Option<u32>,
+    pub kind: RedirectKind,
+    /// The file, descriptor or here-document delimiter
+    pub target: Word,
+    /// The here-document body for `<<` and `<<-`
+    pub here_doc: Option<HereDoc>,
+    pub span: Span,
+}
+
+/// Redirection operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `>|`
+    Clobber,
+    /// `<>`
+    ReadWrite,
+    /// `<&`
+    DupInput,
+    /// `>&`
+    DupOutput,
+    /// `<<`
+    HereDoc,
+    /// `<<-`, which strips leading tabs
+    HereDocStrip,
+}
+
+impl RedirectKind {
+    /// Whether the redirection may write to its target
+    pub fn writes(self) -> bool {
+        matches!(
+            self,
+            RedirectKind::Output
+                | RedirectKind::Append
+                | RedirectKind::Clobber
+                | RedirectKind::ReadWrite
+        )
+    }
+}
+
+/// The body of a here-document
+#[derive(Debug, Clone, PartialEq)]
+pub struct HereDoc {
+    pub body: String,
+    /// Whether expansions in the body are performed, i.e. the delimiter
+    /// was not quoted
+    pub expand: bool,
+    pub span: Span,
+}
+
+/// A compound command with its redirections
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundCommand {
+    pub kind: CompoundKind,
+    pub redirects: Vec<Redirect>,
+    pub span: Span,
+}
+
+/// The kinds of compound command
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundKind {
+    /// `{ list; }`
+    BraceGroup(List),
+    /// `( list )`
+    Subshell(List),
+    /// `for name [in words]; do list; done`
+    For {
+        variable: String,
+        /// `None` without `in`, meaning the positional parameters
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    /// `case word in pattern) list;; esac`
+    Case { word: Word, arms: Vec<CaseArm> },
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    If {
+        condition: List,
+        then: List,
+        elifs: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while list; do list; done`
+    While { condition: List, body: List },
+    /// `until list; do list; done`
+    Until { condition: List, body: List },
+}
+
+/// One `pattern) list;;` arm of a `case`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseArm {
+    pub patterns: Vec<Word>,
+    pub body: List,
+    pub span: Span,
+}
+
+/// `name() compound-command`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: CompoundCommand,
+    pub span: Span,
+}
+
+/// A shell word, made of literal text, quotes and expansions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+impl Word {
+    /// The word's value after quote removal, if it involves no expansion
+    /// or pattern matching
+    pub fn literal(&self) -> Option<String> {
+        let mut value = String::new();
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) | WordPart::SingleQuoted(text) => value.push_str(text),
+                WordPart::DoubleQuoted(parts) => {
+                    for part in parts {
+                        match part {
+                            WordPart::Literal(text) => value.push_str(text),
+                            _ => return None,
+                        }
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    /// Whether the word contains an unquoted glob pattern
+    pub fn has_glob(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, WordPart::Glob(_)))
+    }
+}
+
+/// A piece of a [`Word`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Unquoted text with backslash escapes removed
+    Literal(String),
+    /// `'text'`
+    SingleQuoted(String),
+    /// `"..."`, which may contain literals and expansions
+    DoubleQuoted(Vec<WordPart>),
+    /// `$name` or `${...}`
+    Parameter(ParameterExpansion),
+    /// `$(...)` or `` `...` ``
+    CommandSubstitution(Box<List>),
+    /// `$((expression))`
+    Arithmetic(String),
+    /// A leading `~` or `~user`
+    Tilde(String),
+    /// Unquoted `*`, `?` or `[...]`
+    Glob(String),
+}
+
+/// A parameter expansion such as `$HOME`, `${#name}` or `${dir:-.}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterExpansion {
+    /// Variable name, positional number or special parameter
+    pub name: String,
+    /// Whether this is `${#name}`, the value's length
+    pub length: bool,
+    /// Operator such as `:-`, `=` or `%%`
+    pub operator: Option<String>,
+    /// The word after the operator
+    pub argument: Option<Word>,
+}
@@ -0,0 +1,333 @@
+//! Command parsing utilities
+//!
+//! This module provides utilities for parsing and validating shell commands.
+
+pub mod ast;
+mod syntax;
+
+use super::{RiskAnalyzer, ShellError, ShellResult};
+use ast::Script;
+
+/// A syntax error with its position in the source
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at line {line}, column {column}")]
+pub struct ParseError {
+    /// What is wrong
+    pub message: String,
+    /// Byte offset of the error in the source
+    pub offset: usize,
+    /// 1-based line of the error
+    pub line: usize,
+    /// 1-based column of the error, counted in characters
+    pub column: usize,
+}
+
+impl ParseError {
+    fn new(source: &str, offset: usize, message: String) -> Self {
+        let before = &source[..offset.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Self {
+            message,
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// Command parser for shell commands
+pub struct CommandParser;
+
+impl CommandParser {
+    /// Parse a command string into a syntax tree
+    ///
+    /// Fails with [`ShellError::Syntax`] on malformed input and with
+    /// [`ShellError::InvalidCommand`] if there is no command at all.
+    pub fn parse(command: &str) -> ShellResult<Script> {
+        let list = syntax::parse(command)?;
+        if list.items.is_empty() {
+            return Err(ShellError::InvalidCommand("Empty command".to_string()));
+        }
+        Ok(Script::new(command.to_string(), list))
+    }
+
+    /// Check if a command is potentially dangerous
+    ///
+    /// A command is dangerous if [`RiskAnalyzer`] rates it destructive or
+    /// worse.
+    pub fn is_dangerous(command: &str) -> bool {
+        RiskAnalyzer::analyze(command).is_dangerous()
+    }
+
+    /// Validate command safety
+    pub fn validate_safety(command: &str) -> ShellResult<()> {
+        let assessment = RiskAnalyzer::analyze(command);
+        if assessment.is_dangerous() {
+            return Err(ShellError::InvalidCommand(format!(
+                "Command appears to be potentially dangerous: {}",
+                assessment.reasons().join("; ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ast::*;
+    use super::*;
+
+    fn parse(command: &str) -> Script {
+        CommandParser::parse(command).unwrap_or_else(|e| panic!("{command:?}: {e}"))
+    }
+
+    fn syntax_error(command: &str) -> ParseError {
+        match CommandParser::parse(command) {
+            Err(ShellError::Syntax(error)) => error,
+            other => panic!("expected a syntax error for {command:?}, got {other:?}"),
+        }
+    }
+
+    /// The simple commands of a script without compound commands, as
+    /// literal argv vectors
+    fn argvs(script: &Script) -> Vec<Vec<String>> {
+        script
+            .list
+            .items
+            .iter()
+            .flat_map(|item| item.and_or.pipelines())
+            .flat_map(|pipeline| &pipeline.commands)
+            .map(|command| match command {
+                Command::Simple(simple) => simple
+                    .words
+                    .iter()
+                    .map(|word| word.literal().unwrap_or_else(|| "<expansion>".into()))
+                    .collect(),
+                other => panic!("expected a simple command, got {other:?}"),
+            })
+            .collect()
+    }
+
+    fn simple(script: &Script) -> &SimpleCommand {
+        match &script.list.items[0].and_or.first.commands[0] {
+            Command::Simple(simple) => simple,
+            other => panic!("expected a simple command, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_quoting_and_escapes() {
+        let script = parse(r#"echo 'a  b' "c \"d\" \x" e\ f "#);
+        assert_eq!(argvs(&script), [["echo", "a  b", "c \"d\" \\x", "e f"]]);
+        let name = simple(&script).name().unwrap();
+        assert_eq!(script.text(name.span), "echo");
+        assert_eq!(script.text(simple(&script).args()[0].span), "'a  b'");
+    }
+
+    #[test]
+    fn test_lists_pipelines_and_operators() {
+        let script = parse("cd /tmp && ls -l | grep foo || echo none; sleep 1 &");
+        let items = &script.list.items;
+        assert_eq!(items.len(), 2);
+        assert!(!items[0].background);
+        assert!(items[1].background);
+
+        let and_or = &items[0].and_or;
+        assert_eq!(and_or.first.commands.len(), 1);
+        assert_eq!(
+            and_or.rest.iter().map(|(op, _)| *op).collect::<Vec<_>>(),
+            [AndOrOp::And, AndOrOp::Or]
+        );
+        assert_eq!(and_or.rest[0].1.commands.len(), 2);
+        assert_eq!(
+            argvs(&script),
+            [
+                vec!["cd", "/tmp"],
+                vec!["ls", "-l"],
+                vec!["grep", "foo"],
+                vec!["echo", "none"],
+                vec!["sleep", "1"]
+            ]
+        );
+        assert_eq!(script.text(and_or.rest[0].1.span), "ls -l | grep foo");
+
+        let script = parse("! grep -q x file");
+        assert!(script.list.items[0].and_or.first.negated);
+    }
+
+    #[test]
+    fn test_redirections_and_assignments() {
+        let script = parse("LANG=C sort <in.txt 2>&1 >>out.txt -r");
+        let command = simple(&script);
+        assert_eq!(command.assignments[0].name, "LANG");
+        assert_eq!(command.assignments[0].value.literal().unwrap(), "C");
+        assert_eq!(argvs(&script), [["sort", "-r"]]);
+
+        let redirects: Vec<_> = command
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.kind, r.target.literal().unwrap()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (None, RedirectKind::Input, "in.txt".to_string()),
+                (Some(2), RedirectKind::DupOutput, "1".to_string()),
+                (None, RedirectKind::Append, "out.txt".to_string()),
+            ]
+        );
+        assert_eq!(script.text(command.redirects[1].span), "2>&1");
+        assert!(command.redirects[2].kind.writes());
+    }
+
+    #[test]
+    fn test_expansions() {
+        let script =
+            parse(r#"echo $HOME "${name:-default}" $(date +%s) `whoami` $((1 + 2)) ~/x *.rs"#);
+        let args = simple(&script).args();
+
+        assert!(matches!(
+            &args[0].parts[..],
+            [WordPart::Parameter(p)] if p.name == "HOME"
+        ));
+        let [WordPart::DoubleQuoted(quoted)] = &args[1].parts[..] else {
+            panic!("expected a double-quoted word: {:?}", args[1]);
+        };
+        let [WordPart::Parameter(p)] = &quoted[..] else {
+            panic!("expected a parameter: {quoted:?}");
+        };
+        assert_eq!(p.name, "name");
+        assert_eq!(p.operator.as_deref(), Some(":-"));
+        assert_eq!(p.argument.as_ref().unwrap().literal().unwrap(), "default");
+
+        let [WordPart::CommandSubstitution(list)] = &args[2].parts[..] else {
+            panic!("expected a command substitution: {:?}", args[2]);
+        };
+        assert_eq!(list.items.len(), 1);
+        assert!(matches!(
+            &args[3].parts[..],
+            [WordPart::CommandSubstitution(_)]
+        ));
+        assert_eq!(args[4].parts, [WordPart::Arithmetic("1 + 2".to_string())]);
+        assert_eq!(
+            args[5].parts,
+            [
+                WordPart::Tilde(String::new()),
+                WordPart::Literal("/x".to_string())
+            ]
+        );
+        assert!(args[6].has_glob());
+        assert!(args.iter().all(|arg| arg.literal().is_none()));
+        assert_eq!(parse("echo '*'").list.items.len(), 1);
+        assert!(!simple(&parse("echo '*'")).args()[0].has_glob());
+    }
+
+    #[test]
+    fn test_compound_commands() {
+        let script =
+            parse("if test -f a; then echo a; elif test -f b; then echo b; else echo c; fi > log");
+        let Command::Compound(compound) = &script.list.items[0].and_or.first.commands[0] else {
+            panic!("expected a compound command");
+        };
+        assert!(matches!(
+            &compound.kind,
+            CompoundKind::If { elifs, otherwise: Some(_), .. } if elifs.len() == 1
+        ));
+        assert_eq!(compound.redirects[0].kind, RedirectKind::Output);
+
+        for command in [
+            "for f in *.txt; do wc -l \"$f\"; done",
+            "for arg\ndo\n  echo \"$arg\"\ndone",
+            "while read -r line; do echo \"$line\"; done < input",
+            "until false; do break; done",
+            "case \"$1\" in\n  start|run) echo go ;;\n  *) echo stop\nesac",
+            "(cd /tmp && ls)",
+            "{ echo a; echo b; } | sort",
+            "greet() { echo \"hello $1\"; }",
+            ":(){ :|:& };:",
+        ] {
+            parse(command);
+        }
+
+        let script = parse("case x in (a) ;; b|c) echo bc;; esac");
+        let Command::Compound(CompoundCommand {
+            kind: CompoundKind::Case { arms, .. },
+            ..
+        }) = &script.list.items[0].and_or.first.commands[0]
+        else {
+            panic!("expected a case command");
+        };
+        assert_eq!(arms.len(), 2);
+        assert_eq!(arms[1].patterns.len(), 2);
+        assert!(arms[0].body.items.is_empty());
+    }
+
+    #[test]
+    fn test_here_documents() {
+        let script = parse("cat <<EOF | tr a-z A-Z\nhello $USER\nEOF\necho done");
+        let command = simple(&script);
+        let here_doc = command.redirects[0].here_doc.as_ref().unwrap();
+        assert_eq!(here_doc.body, "hello $USER\n");
+        assert!(here_doc.expand);
+        assert_eq!(script.list.items.len(), 2);
+        assert_eq!(argvs(&script)[2], ["echo", "done"]);
+
+        let script = parse("cat <<-'END'\n\tliteral $x\n\tEND\n");
+        let here_doc = simple(&script).redirects[0].here_doc.as_ref().unwrap();
+        assert_eq!(here_doc.body, "literal $x\n");
+        assert!(!here_doc.expand);
+    }
+
+    #[test]
+    fn test_comments_and_line_continuations() {
+        let script = parse("ls \\\n  -la # list everything\n# done");
+        assert_eq!(argvs(&script), [["ls", "-la"]]);
+        assert_eq!(parse("echo a#b").list.items.len(), 1);
+        assert_eq!(argvs(&parse("echo a#b")), [["echo", "a#b"]]);
+    }
+
+    #[test]
+    fn test_syntax_errors_have_positions() {
+        let error = syntax_error("echo 'unterminated");
+        assert_eq!(error.message, "unterminated single quote");
+        assert_eq!((error.offset, error.line, error.column), (5, 1, 6));
+
+        let error = syntax_error("ls |");
+        assert_eq!(error.message, "unexpected end of input");
+        assert_eq!(error.column, 5);
+
+        let error = syntax_error("if true; then\n  echo yes\n");
+        assert_eq!(error.message, "expected `fi`, found end of input");
+        assert_eq!(error.line, 3);
+
+        let error = syntax_error("echo a && && echo b");
+        assert_eq!(error.message, "unexpected `&&`");
+        assert_eq!(error.column, 11);
+
+        assert_eq!(
+            syntax_error("echo \"$(ls\"").message,
+            "unterminated double quote"
+        );
+        assert_eq!(syntax_error("echo )").message, "unexpected `)`");
+        assert_eq!(
+            syntax_error("cat <<EOF\nno end").message,
+            "here-document is not terminated by `EOF`"
+        );
+        assert_eq!(
+            syntax_error("if true; then fi").message,
+            "expected a command, found `fi`"
+        );
+        assert_eq!(
+            syntax_error("echo done; done").to_string(),
+            "unexpected `done` at line 1, column 12"
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_command() {
+        assert!(matches!(
+            CommandParser::parse("  # nothing\n"),
+            Err(ShellError::InvalidCommand(_))
+        ));
+    }
+}
@@ -0,0 +1,1097 @@
+//! Recursive descent parser for POSIX shell syntax
+//!
+//! Tokenizing shell input depends on where the parser is (reserved words are
+//! only recognized in command position, `)` ends a case pattern but not a
+//! word in `$(...)`), so the parser reads characters directly instead of
+//! working on a separate token stream.
+
+use super::ast::*;
+use super::ParseError;
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Reserved words that end the list before them
+const LIST_TERMINATORS: &[&str] = &["then", "else", "elif", "fi", "do", "done", "esac", "}"];
+
+/// All reserved words
+const RESERVED_WORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "do", "done", "case", "esac", "while", "until", "for",
+    "in", "{", "}", "!",
+];
+
+/// Operators, longest first so that `&&` is not read as two `&`
+const OPERATORS: &[(&str, Op)] = &[
+    ("<<-", Op::Redirect(RedirectKind::HereDocStrip)),
+    ("&&", Op::AndIf),
+    ("||", Op::OrIf),
+    (";;", Op::DSemi),
+    ("<<", Op::Redirect(RedirectKind::HereDoc)),
+    (">>", Op::Redirect(RedirectKind::Append)),
+    ("<&", Op::Redirect(RedirectKind::DupInput)),
+    (">&", Op::Redirect(RedirectKind::DupOutput)),
+    ("<>", Op::Redirect(RedirectKind::ReadWrite)),
+    (">|", Op::Redirect(RedirectKind::Clobber)),
+    ("|", Op::Pipe),
+    ("&", Op::Amp),
+    (";", Op::Semi),
+    ("(", Op::LParen),
+    (")", Op::RParen),
+    ("\n", Op::Newline),
+    ("<", Op::Redirect(RedirectKind::Input)),
+    (">", Op::Redirect(RedirectKind::Output)),
+];
+
+/// Parameter expansion operators, longest first
+const PARAMETER_OPERATORS: &[&str] = &[
+    ":-", ":=", ":?", ":+", "%%", "##", "-", "=", "?", "+", "%", "#",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    AndIf,
+    OrIf,
+    DSemi,
+    Pipe,
+    Amp,
+    Semi,
+    LParen,
+    RParen,
+    Newline,
+    Redirect(RedirectKind),
+}
+
+/// What ends a word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WordEnd {
+    /// Blanks and operator characters, as in command arguments
+    Meta,
+    /// An unquoted `}`, as in the argument of `${name:-word}`
+    Brace,
+}
+
+/// Parse a complete command line
+pub(super) fn parse(source: &str) -> ParseResult<List> {
+    let mut parser = Parser::new(source);
+    let list = parser.parse_list()?;
+    parser.skip_blanks();
+    if !parser.at_eof() {
+        return Err(parser.unexpected());
+    }
+    Ok(list)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Where parsing continues after the next newline, past the bodies of
+    /// here-documents started on the current line
+    heredoc_resume: Option<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            pos: 0,
+            heredoc_resume: None,
+        }
+    }
+
+    // Lists and commands
+
+    fn parse_list(&mut self) -> ParseResult<List> {
+        self.skip_linebreaks();
+        let mut items = Vec::new();
+        loop {
+            self.skip_blanks();
+            if self.at_list_end() {
+                break;
+            }
+            let start = self.pos;
+            let and_or = self.parse_and_or()?;
+            let end = and_or.span.end;
+            self.skip_blanks();
+            let (background, separated) = match self.peek_op() {
+                Some((Op::Semi, _)) => {
+                    self.pos += 1;
+                    (false, true)
+                }
+                Some((Op::Amp, _)) => {
+                    self.pos += 1;
+                    (true, true)
+                }
+                Some((Op::Newline, _)) => (false, true),
+                _ => (false, false),
+            };
+            items.push(ListItem {
+                and_or,
+                background,
+                span: Span::new(start, if separated { self.pos } else { end }),
+            });
+            if !separated {
+                break;
+            }
+            self.skip_linebreaks();
+        }
+
+        let span = match (items.first(), items.last()) {
+            (Some(first), Some(last)) => Span::new(first.span.start, last.span.end),
+            _ => Span::new(self.pos, self.pos),
+        };
+        Ok(List { items, span })
+    }
+
+    /// Parse a list that must contain at least one command
+    fn parse_body(&mut self) -> ParseResult<List> {
+        let list = self.parse_list()?;
+        if list.items.is_empty() {
+            return Err(self.error(
+                self.pos,
+                format!("expected a command, found {}", self.describe_next()),
+            ));
+        }
+        Ok(list)
+    }
+
+    fn at_list_end(&self) -> bool {
+        self.at_eof()
+            || self
+                .peek_reserved()
+                .is_some_and(|word| LIST_TERMINATORS.contains(&word))
+            || matches!(self.peek_op(), Some((Op::RParen | Op::DSemi, _)))
+    }
+
+    fn parse_and_or(&mut self) -> ParseResult<AndOr> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            self.skip_blanks();
+            let op = match self.peek_op() {
+                Some((Op::AndIf, _)) => AndOrOp::And,
+                Some((Op::OrIf, _)) => AndOrOp::Or,
+                _ => break,
+            };
+            self.pos += 2;
+            self.skip_linebreaks();
+            rest.push((op, self.parse_pipeline()?));
+        }
+
+        let end = rest
+            .last()
+            .map_or(first.span.end, |(_, pipeline)| pipeline.span.end);
+        Ok(AndOr {
+            span: Span::new(first.span.start, end),
+            first,
+            rest,
+        })
+    }
+
+    fn parse_pipeline(&mut self) -> ParseResult<Pipeline> {
+        self.skip_blanks();
+        let start = self.pos;
+        let negated = self.peek_reserved() == Some("!");
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut commands = vec![self.parse_command()?];
+        loop {
+            self.skip_blanks();
+            if !matches!(self.peek_op(), Some((Op::Pipe, _))) {
+                break;
+            }
+            self.pos += 1;
+            self.skip_linebreaks();
+            commands.push(self.parse_command()?);
+        }
+
+        let end = commands.last().map_or(start, |command| command.span().end);
+        Ok(Pipeline {
+            negated,
+            commands,
+            span: Span::new(start, end),
+        })
+    }
+
+    fn parse_command(&mut self) -> ParseResult<Command> {
+        self.skip_blanks();
+        match self.peek_reserved() {
+            Some("{" | "if" | "while" | "until" | "for" | "case") => {
+                return self.parse_compound().map(Command::Compound);
+            }
+            Some("in") | None => {}
+            Some(_) => return Err(self.unexpected()),
+        }
+        match self.peek_op() {
+            Some((Op::LParen, _)) => return self.parse_compound().map(Command::Compound),
+            Some((Op::Redirect(_), _)) | None => {}
+            Some(_) => return Err(self.unexpected()),
+        }
+        if self.at_eof() {
+            return Err(self.unexpected());
+        }
+        if let Some(function) = self.try_function_definition()? {
+            return Ok(Command::FunctionDefinition(function));
+        }
+        self.parse_simple_command().map(Command::Simple)
+    }
+
+    fn parse_simple_command(&mut self) -> ParseResult<SimpleCommand> {
+        let start = self.pos;
+        let mut end = start;
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+
+        loop {
+            self.skip_blanks();
+            if self.at_redirect() {
+                let redirect = self.parse_redirect()?;
+                end = redirect.span.end;
+                redirects.push(redirect);
+                continue;
+            }
+            if self.at_eof() || self.peek_op().is_some() {
+                break;
+            }
+            if words.is_empty() {
+                if let Some(assignment) = self.try_assignment()? {
+                    end = assignment.span.end;
+                    assignments.push(assignment);
+                    continue;
+                }
+            }
+            match self.read_word()? {
+                Some(word) => {
+                    end = word.span.end;
+                    words.push(word);
+                }
+                None => break,
+            }
+        }
+
+        if assignments.is_empty() && words.is_empty() && redirects.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(SimpleCommand {
+            assignments,
+            words,
+            redirects,
+            span: Span::new(start, end),
+        })
+    }
+
+    fn try_assignment(&mut self) -> ParseResult<Option<Assignment>> {
+        let rest = self.rest();
+        let name_len = identifier_len(rest);
+        if name_len == 0 || !rest[name_len..].starts_with('=') {
+            return Ok(None);
+        }
+
+        let start = self.pos;
+        let name = rest[..name_len].to_string();
+        self.pos += name_len + 1;
+        let value_start = self.pos;
+        let parts = self.parse_word_parts(WordEnd::Meta)?;
+        Ok(Some(Assignment {
+            name,
+            value: Word {
+                parts,
+                span: Span::new(value_start, self.pos),
+            },
+            span: Span::new(start, self.pos),
+        }))
+    }
+
+    /// Parse `name() compound-command` if the input starts with one
+    ///
+    /// As in bash, the name may be any unquoted word without expansions,
+    /// such as `:`.
+    fn try_function_definition(&mut self) -> ParseResult<Option<FunctionDefinition>> {
+        let start = self.pos;
+        let rest = self.rest();
+        let name_len = rest
+            .find(|c: char| is_meta(c) || "'\"`$\\=".contains(c))
+            .unwrap_or(rest.len());
+        if name_len == 0 {
+            return Ok(None);
+        }
+        let after_parens = {
+            let rest = self.src[start + name_len..].trim_start_matches([' ', '\t']);
+            rest.strip_prefix('(')
+                .map(|rest| rest.trim_start_matches([' ', '\t']))
+                .and_then(|rest| rest.strip_prefix(')'))
+                .map(|rest| self.src.len() - rest.len())
+        };
+        let Some(after_parens) = after_parens else {
+            return Ok(None);
+        };
+
+        let name = self.src[start..start + name_len].to_string();
+        self.pos = after_parens;
+        self.skip_linebreaks();
+        let is_compound = matches!(self.peek_op(), Some((Op::LParen, _)))
+            || matches!(
+                self.peek_reserved(),
+                Some("{" | "if" | "while" | "until" | "for" | "case")
+            );
+        if !is_compound {
+            return Err(self.error(
+                self.pos,
+                format!(
+                    "expected the body of function `{name}`, found {}",
+                    self.describe_next()
+                ),
+            ));
+        }
+        let body = self.parse_compound()?;
+        Ok(Some(FunctionDefinition {
+            name,
+            span: Span::new(start, body.span.end),
+            body,
+        }))
+    }
+
+    // Compound commands
+
+    fn parse_compound(&mut self) -> ParseResult<CompoundCommand> {
+        let start = self.pos;
+        let kind = if matches!(self.peek_op(), Some((Op::LParen, _))) {
+            self.pos += 1;
+            let body = self.parse_body()?;
+            self.skip_blanks();
+            if !matches!(self.peek_op(), Some((Op::RParen, _))) {
+                return Err(self.expected("`)`"));
+            }
+            self.pos += 1;
+            CompoundKind::Subshell(body)
+        } else {
+            match self.peek_reserved() {
+                Some("{") => {
+                    self.pos += 1;
+                    let body = self.parse_body()?;
+                    self.expect_reserved("}")?;
+                    CompoundKind::BraceGroup(body)
+                }
+                Some("if") => self.parse_if()?,
+                Some(keyword @ ("while" | "until")) => {
+                    self.pos += keyword.len();
+                    let condition = self.parse_body()?;
+                    self.expect_reserved("do")?;
+                    let body = self.parse_body()?;
+                    self.expect_reserved("done")?;
+                    if keyword == "while" {
+                        CompoundKind::While { condition, body }
+                    } else {
+                        CompoundKind::Until { condition, body }
+                    }
+                }
+                Some("for") => self.parse_for()?,
+                Some("case") => self.parse_case()?,
+                _ => return Err(self.unexpected()),
+            }
+        };
+
+        let mut end = self.pos;
+        let mut redirects = Vec::new();
+        loop {
+            self.skip_blanks();
+            if !self.at_redirect() {
+                break;
+            }
+            let redirect = self.parse_redirect()?;
+            end = redirect.span.end;
+            redirects.push(redirect);
+        }
+        Ok(CompoundCommand {
+            kind,
+            redirects,
+            span: Span::new(start, end),
+        })
+    }
+
+    fn parse_if(&mut self) -> ParseResult<CompoundKind> {
+        self.pos += "if".len();
+        let condition = self.parse_body()?;
+        self.expect_reserved("then")?;
+        let then = self.parse_body()?;
+
+        let mut elifs = Vec::new();
+        let mut otherwise = None;
+        loop {
+            self.skip_blanks();
+            match self.peek_reserved() {
+                Some("elif") => {
+                    self.pos += "elif".len();
+                    let condition = self.parse_body()?;
+                    self.expect_reserved("then")?;
+                    elifs.push((condition, self.parse_body()?));
+                }
+                Some("else") => {
+                    self.pos += "else".len();
+                    otherwise = Some(self.parse_body()?);
+                    self.expect_reserved("fi")?;
+                    break;
+                }
+                _ => {
+                    self.expect_reserved("fi")?;
+                    break;
+                }
+            }
+        }
+        Ok(CompoundKind::If {
+            condition,
+            then,
+            elifs,
+            otherwise,
+        })
+    }
+
+    fn parse_for(&mut self) -> ParseResult<CompoundKind> {
+        self.pos += "for".len();
+        self.skip_blanks();
+        let name_len = identifier_len(self.rest());
+        if name_len == 0 {
+            return Err(self.expected("a variable name after `for`"));
+        }
+        let variable = self.rest()[..name_len].to_string();
+        self.pos += name_len;
+
+        self.skip_linebreaks();
+        let words = if self.peek_reserved() == Some("in") {
+            self.pos += "in".len();
+            let mut words = Vec::new();
+            loop {
+                self.skip_blanks();
+                match self.peek_op() {
+                    Some((Op::Semi | Op::Newline, _)) => break,
+                    Some(_) => return Err(self.unexpected()),
+                    None if self.at_eof() => break,
+                    None => {}
+                }
+                match self.read_word()? {
+                    Some(word) => words.push(word),
+                    None => return Err(self.unexpected()),
+                }
+            }
+            Some(words)
+        } else {
+            None
+        };
+        self.skip_blanks();
+        if matches!(self.peek_op(), Some((Op::Semi, _))) {
+            self.pos += 1;
+        }
+        self.skip_linebreaks();
+
+        self.expect_reserved("do")?;
+        let body = self.parse_body()?;
+        self.expect_reserved("done")?;
+        Ok(CompoundKind::For {
+            variable,
+            words,
+            body,
+        })
+    }
+
+    fn parse_case(&mut self) -> ParseResult<CompoundKind> {
+        self.pos += "case".len();
+        self.skip_blanks();
+        let Some(word) = self.read_word()? else {
+            return Err(self.expected("a word after `case`"));
+        };
+        self.skip_linebreaks();
+        self.expect_reserved("in")?;
+        self.skip_linebreaks();
+
+        let mut arms = Vec::new();
+        loop {
+            self.skip_blanks();
+            if self.peek_reserved() == Some("esac") {
+                self.pos += "esac".len();
+                break;
+            }
+            let start = self.pos;
+            if matches!(self.peek_op(), Some((Op::LParen, _))) {
+                self.pos += 1;
+            }
+
+            let mut patterns = Vec::new();
+            loop {
+                self.skip_blanks();
+                match self.read_word()? {
+                    Some(pattern) => patterns.push(pattern),
+                    None => return Err(self.expected("a case pattern")),
+                }
+                self.skip_blanks();
+                if !matches!(self.peek_op(), Some((Op::Pipe, _))) {
+                    break;
+                }
+                self.pos += 1;
+            }
+            if !matches!(self.peek_op(), Some((Op::RParen, _))) {
+                return Err(self.expected("`)` after the case pattern"));
+            }
+            self.pos += 1;
+
+            let body = self.parse_list()?;
+            self.skip_blanks();
+            let terminated = matches!(self.peek_op(), Some((Op::DSemi, _)));
+            if terminated {
+                self.pos += 2;
+            }
+            arms.push(CaseArm {
+                patterns,
+                body,
+                span: Span::new(start, self.pos),
+            });
+            if !terminated {
+                self.expect_reserved("esac")?;
+                break;
+            }
+            self.skip_linebreaks();
+        }
+        Ok(CompoundKind::Case { word, arms })
+    }
+
+    // Redirections
+
+    fn at_redirect(&self) -> bool {
+        if matches!(self.peek_op(), Some((Op::Redirect(_), _))) {
+            return true;
+        }
+        let digits = self.rest().bytes().take_while(u8::is_ascii_digit).count();
+        digits > 0 && self.rest()[digits..].starts_with(['<', '>'])
+    }
+
+    fn parse_redirect(&mut self) -> ParseResult<Redirect> {
+        let start = self.pos;
+        let digits = self.rest().bytes().take_while(u8::is_ascii_digit).count();
+        let fd = if digits > 0 {
+            let fd = self.rest()[..digits]
+                .parse()
+                .map_err(|_| self.error(start, "file descriptor number is too large"))?;
+            self.pos += digits;
+            Some(fd)
+        } else {
+            None
+        };
+
+        let Some((Op::Redirect(kind), len)) = self.peek_op() else {
+            return Err(self.unexpected());
+        };
+        let operator = &self.src[self.pos..self.pos + len];
+        self.pos += len;
+        self.skip_blanks();
+        let Some(target) = self.read_word()? else {
+            return Err(self.expected(&format!("a file name after `{operator}`")));
+        };
+
+        let here_doc = match kind {
+            RedirectKind::HereDoc => Some(self.read_here_doc(start, &target, false)?),
+            RedirectKind::HereDocStrip => Some(self.read_here_doc(start, &target, true)?),
+            _ => None,
+        };
+        Ok(Redirect {
+            fd,
+            kind,
+            span: Span::new(start, target.span.end),
+            target,
+            here_doc,
+        })
+    }
+
+    /// Read the body of a here-document from the lines after the current one
+    fn read_here_doc(&mut self, start: usize, target: &Word, strip: bool) -> ParseResult<HereDoc> {
+        let raw = &self.src[target.span.start..target.span.end];
+        let expand = !raw.contains(['\'', '"', '\\']);
+        let delimiter = target.literal().unwrap_or_else(|| raw.to_string());
+
+        let body_start = match self.heredoc_resume {
+            Some(resume) => resume,
+            None => match self.rest().find('\n') {
+                Some(newline) => self.pos + newline + 1,
+                None => {
+                    return Err(self.error(
+                        start,
+                        format!("here-document delimited by `{delimiter}` has no body"),
+                    ))
+                }
+            },
+        };
+
+        let mut body = String::new();
+        let mut line_start = body_start;
+        loop {
+            if line_start >= self.src.len() {
+                return Err(self.error(
+                    start,
+                    format!("here-document is not terminated by `{delimiter}`"),
+                ));
+            }
+            let line_end = self.src[line_start..]
+                .find('\n')
+                .map_or(self.src.len(), |newline| line_start + newline);
+            let line = &self.src[line_start..line_end];
+            let line = if strip {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if line == delimiter {
+                self.heredoc_resume = Some((line_end + 1).min(self.src.len()));
+                return Ok(HereDoc {
+                    body,
+                    expand,
+                    span: Span::new(body_start, line_start),
+                });
+            }
+            body.push_str(line);
+            body.push('\n');
+            line_start = line_end + 1;
+        }
+    }
+
+    // Words
+
+    fn read_word(&mut self) -> ParseResult<Option<Word>> {
+        let start = self.pos;
+        let parts = self.parse_word_parts(WordEnd::Meta)?;
+        if self.pos == start {
+            return Ok(None);
+        }
+        Ok(Some(Word {
+            parts,
+            span: Span::new(start, self.pos),
+        }))
+    }
+
+    fn parse_word_parts(&mut self, end: WordEnd) -> ParseResult<Vec<WordPart>> {
+        let start = self.pos;
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            match end {
+                WordEnd::Meta if is_meta(c) => break,
+                WordEnd::Brace if c == '}' => break,
+                _ => {}
+            }
+            let part = match c {
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\n') => self.pos += 1,
+                        Some(escaped) => {
+                            literal.push(escaped);
+                            self.pos += escaped.len_utf8();
+                        }
+                        None => literal.push('\\'),
+                    }
+                    None
+                }
+                '\'' => Some(WordPart::SingleQuoted(self.read_single_quoted()?)),
+                '"' => Some(WordPart::DoubleQuoted(self.parse_double_quoted()?)),
+                '$' => {
+                    let part = self.parse_dollar()?;
+                    if part.is_none() {
+                        literal.push('$');
+                        self.pos += 1;
+                    }
+                    part
+                }
+                '`' => Some(self.parse_backquote()?),
+                '*' | '?' => {
+                    self.pos += 1;
+                    Some(WordPart::Glob(c.to_string()))
+                }
+                '[' => match self.bracket_expression_len() {
+                    Some(len) => {
+                        let pattern = self.rest()[..len].to_string();
+                        self.pos += len;
+                        Some(WordPart::Glob(pattern))
+                    }
+                    None => {
+                        literal.push('[');
+                        self.pos += 1;
+                        None
+                    }
+                },
+                '~' if self.pos == start && end == WordEnd::Meta => {
+                    self.pos += 1;
+                    let user_len = self
+                        .rest()
+                        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+                        .unwrap_or(self.rest().len());
+                    let user = self.rest()[..user_len].to_string();
+                    self.pos += user_len;
+                    Some(WordPart::Tilde(user))
+                }
+                _ => {
+                    literal.push(c);
+                    self.pos += c.len_utf8();
+                    None
+                }
+            };
+            if let Some(part) = part {
+                if !literal.is_empty() {
+                    parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal(literal));
+        }
+        Ok(parts)
+    }
+
+    fn read_single_quoted(&mut self) -> ParseResult<String> {
+        let start = self.pos;
+        let Some(len) = self.src[start + 1..].find('\'') else {
+            return Err(self.error(start, "unterminated single quote"));
+        };
+        self.pos = start + 1 + len + 1;
+        Ok(self.src[start + 1..start + 1 + len].to_string())
+    }
+
+    fn parse_double_quoted(&mut self) -> ParseResult<Vec<WordPart>> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error(start, "unterminated double quote"));
+            };
+            let part = match c {
+                '"' => {
+                    self.pos += 1;
+                    break;
+                }
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(escaped @ ('$' | '`' | '"' | '\\')) => {
+                            literal.push(escaped);
+                            self.pos += 1;
+                        }
+                        Some('\n') => self.pos += 1,
+                        _ => literal.push('\\'),
+                    }
+                    None
+                }
+                '$' => {
+                    let part = self.parse_dollar()?;
+                    if part.is_none() {
+                        literal.push('$');
+                        self.pos += 1;
+                    }
+                    part
+                }
+                '`' => Some(self.parse_backquote()?),
+                _ => {
+                    literal.push(c);
+                    self.pos += c.len_utf8();
+                    None
+                }
+            };
+            if let Some(part) = part {
+                if !literal.is_empty() {
+                    parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal(literal));
+        }
+        Ok(parts)
+    }
+
+    /// Parse an expansion starting with `$`; `None` if the `$` is literal
+    fn parse_dollar(&mut self) -> ParseResult<Option<WordPart>> {
+        let start = self.pos;
+        let rest = &self.src[start + 1..];
+
+        if let Some(arithmetic) = rest.strip_prefix("((") {
+            if let Some(len) = arithmetic_len(arithmetic) {
+                let expression = arithmetic[..len].trim().to_string();
+                self.pos = start + 3 + len + 2;
+                return Ok(Some(WordPart::Arithmetic(expression)));
+            }
+        }
+        if rest.starts_with('(') {
+            self.pos = start + 2;
+            let list = self.parse_list()?;
+            self.skip_blanks();
+            if !matches!(self.peek_op(), Some((Op::RParen, _))) {
+                return Err(self.expected("`)` to close `$(`"));
+            }
+            self.pos += 1;
+            return Ok(Some(WordPart::CommandSubstitution(Box::new(list))));
+        }
+        if rest.starts_with('{') {
+            return self.parse_braced_parameter().map(Some);
+        }
+
+        let name_len = match rest.chars().next() {
+            Some('@' | '*' | '#' | '?' | '-' | '$' | '!') => 1,
+            Some(c) if c.is_ascii_digit() => 1,
+            _ => identifier_len(rest),
+        };
+        if name_len == 0 {
+            return Ok(None);
+        }
+        self.pos = start + 1 + name_len;
+        Ok(Some(WordPart::Parameter(ParameterExpansion {
+            name: rest[..name_len].to_string(),
+            length: false,
+            operator: None,
+            argument: None,
+        })))
+    }
+
+    fn parse_braced_parameter(&mut self) -> ParseResult<WordPart> {
+        let start = self.pos;
+        self.pos += 2;
+
+        let rest = self.rest();
+        let length = rest.starts_with('#') && !rest[1..].starts_with('}');
+        if length {
+            self.pos += 1;
+        }
+        let rest = self.rest();
+        let name_len = match rest.chars().next() {
+            Some('@' | '*' | '#' | '?' | '-' | '$' | '!') => 1,
+            Some(c) if c.is_ascii_digit() => rest.bytes().take_while(u8::is_ascii_digit).count(),
+            _ => identifier_len(rest),
+        };
+        if name_len == 0 {
+            return Err(self.error(start, "invalid parameter expansion"));
+        }
+        let name = rest[..name_len].to_string();
+        self.pos += name_len;
+
+        let mut operator = None;
+        let mut argument = None;
+        if !length {
+            if let Some(op) = PARAMETER_OPERATORS
+                .iter()
+                .find(|op| self.rest().starts_with(**op))
+            {
+                self.pos += op.len();
+                let argument_start = self.pos;
+                let parts = self.parse_word_parts(WordEnd::Brace)?;
+                operator = Some(op.to_string());
+                argument = Some(Word {
+                    parts,
+                    span: Span::new(argument_start, self.pos),
+                });
+            }
+        }
+        if self.peek() != Some('}') {
+            return Err(self.error(start, "unterminated `${`"));
+        }
+        self.pos += 1;
+        Ok(WordPart::Parameter(ParameterExpansion {
+            name,
+            length,
+            operator,
+            argument,
+        }))
+    }
+
+    fn parse_backquote(&mut self) -> ParseResult<WordPart> {
+        let start = self.pos;
+        let mut end = None;
+        let mut chars = self.src[start + 1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '`' => {
+                    end = Some(start + 1 + i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            return Err(self.error(start, "unterminated backquote"));
+        };
+
+        let content = &self.src[start + 1..end];
+        let list = if content.contains('\\') {
+            // Backslashes escape `\`, `$` and `` ` `` inside backquotes, so
+            // the command is parsed from its unescaped text; spans inside it
+            // are relative to that text
+            let mut unescaped = String::new();
+            let mut chars = content.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c == '\\' && matches!(chars.peek(), Some('\\' | '$' | '`')) {
+                    continue;
+                }
+                unescaped.push(c);
+            }
+            parse(&unescaped).map_err(|e| self.error(start, e.message))?
+        } else {
+            let mut inner = Parser {
+                src: &self.src[..end],
+                pos: start + 1,
+                heredoc_resume: None,
+            };
+            let list = inner.parse_list()?;
+            inner.skip_blanks();
+            if !inner.at_eof() {
+                return Err(inner.unexpected());
+            }
+            list
+        };
+        self.pos = end + 1;
+        Ok(WordPart::CommandSubstitution(Box::new(list)))
+    }
+
+    /// Length of a `[...]` bracket expression at the current position
+    fn bracket_expression_len(&self) -> Option<usize> {
+        let rest = self.rest();
+        let mut i = 1;
+        if rest[i..].starts_with(['!', '^']) {
+            i += 1;
+        }
+        if rest[i..].starts_with(']') {
+            i += 1;
+        }
+        let close = rest[i..].find(|c: char| c == ']' || is_meta(c) || "'\"`$\\".contains(c))?;
+        (rest[i + close..].starts_with(']')).then_some(i + close + 1)
+    }
+
+    // Low-level helpers
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn at_eof(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    fn peek_op(&self) -> Option<(Op, usize)> {
+        let rest = self.rest();
+        OPERATORS
+            .iter()
+            .find(|(text, _)| rest.starts_with(text))
+            .map(|(text, op)| (*op, text.len()))
+    }
+
+    /// The reserved word at the current position, if the word there is one
+    fn peek_reserved(&self) -> Option<&'static str> {
+        let rest = self.rest();
+        let word = &rest[..rest.find(is_meta).unwrap_or(rest.len())];
+        RESERVED_WORDS
+            .iter()
+            .find(|reserved| **reserved == word)
+            .copied()
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> ParseResult<()> {
+        self.skip_linebreaks();
+        if self.peek_reserved() == Some(word) {
+            self.pos += word.len();
+            Ok(())
+        } else {
+            Err(self.expected(&format!("`{word}`")))
+        }
+    }
+
+    /// Skip blanks, line continuations and comments, but not newlines
+    fn skip_blanks(&mut self) {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with([' ', '\t']) {
+                self.pos += 1;
+            } else if rest.starts_with("\\\n") {
+                self.pos += 2;
+            } else if rest.starts_with('#') {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Skip blanks and newlines, jumping over pending here-document bodies
+    fn skip_linebreaks(&mut self) {
+        loop {
+            self.skip_blanks();
+            if !self.rest().starts_with('\n') {
+                break;
+            }
+            self.pos = self.heredoc_resume.take().unwrap_or(self.pos + 1);
+        }
+    }
+
+    fn describe_next(&self) -> String {
+        if self.at_eof() {
+            return "end of input".to_string();
+        }
+        let rest = self.rest();
+        match self.peek_op() {
+            Some((Op::Newline, _)) => "newline".to_string(),
+            Some((_, len)) => format!("`{}`", &rest[..len]),
+            None => format!("`{}`", &rest[..rest.find(is_meta).unwrap_or(rest.len())]),
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        self.error(self.pos, format!("unexpected {}", self.describe_next()))
+    }
+
+    fn expected(&self, what: &str) -> ParseError {
+        self.error(
+            self.pos,
+            format!("expected {what}, found {}", self.describe_next()),
+        )
+    }
+
+    fn error(&self, offset: usize, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.src, offset, message.into())
+    }
+}
+
+/// Whether `c` ends an unquoted word
+fn is_meta(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '\n' | '|' | '&' | ';' | '<' | '>' | '(' | ')'
+    )
+}
+
+/// Length of the shell identifier at the start of `s`, or 0
+fn identifier_len(s: &str) -> usize {
+    if !s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return 0;
+    }
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(s.len())
+}
+
+/// Length of an arithmetic expression up to its closing `))`, if it has one
+fn arithmetic_len(s: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return s[i..].starts_with("))").then_some(i),
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,964 @@
+//! Declarative safety policy
+//!
+//! A policy is an ordered list of rules that allow, deny or ask for
+//! confirmation of commands. A rule matches a command when all of its
+//! conditions hold; the first matching rule decides, and commands no rule
+//! matches get the default action. Commands that cannot be parsed, such as
+//! bash process substitution or fish syntax, cannot be checked against the
+//! rules and get the `parse_error` action, which is `confirm` unless set to
+//! `deny`.
+//!
+//! ```toml
+//! default = "allow"
+//! parse_error = "deny"
+//!
+//! [[rules]]
+//! name = "system-files"
+//! action = "deny"
+//! paths = ["/etc", "/boot"]
+//! reason = "system configuration is managed elsewhere"
+//!
+//! [[rules]]
+//! name = "outside-home"
+//! action = "confirm"
+//! outside = ["~", "/tmp"]
+//!
+//! [[rules]]
+//! action = "confirm"
+//! programs = ["rm"]
+//! flags = ["-r"]
+//! ```
+
+use super::ast::*;
+use super::risk::{MAX_DEPTH, SHELLS, WRAPPERS};
+use super::{CommandParser, RiskAnalyzer, RiskAssessment, RiskLevel, ShellError, ShellResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// What to do with a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Run the command
+    Allow,
+    /// Run the command only after the user confirms it
+    Confirm,
+    /// Refuse to run the command
+    Deny,
+}
+
+impl fmt::Display for PolicyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PolicyAction::Allow => "allow",
+            PolicyAction::Confirm => "confirm",
+            PolicyAction::Deny => "deny",
+        })
+    }
+}
+
+/// One rule of a [`SafetyPolicy`]
+///
+/// Unset conditions are ignored, so a rule without conditions matches
+/// every command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Name shown when the rule fires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub action: PolicyAction,
+    /// Program names, also matched through wrappers such as `sudo` and
+    /// `sh -c`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub programs: Vec<String>,
+    /// Flags of which one must be given to a matched program; `-r` also
+    /// matches combined flags such as `-rf`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    /// Path patterns; matches if a path argument or redirection target lies
+    /// under one of them. `*` and `?` match within a path component and
+    /// `**` across components.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    /// Directories; matches if a path argument or redirection target lies
+    /// outside all of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outside: Vec<String>,
+    /// Matches commands rated at least this risky by [`RiskAnalyzer`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<RiskLevel>,
+    /// Path patterns matched against the targets of writing redirections
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<String>,
+    /// Explanation shown when the rule fires instead of the matched
+    /// conditions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl PolicyRule {
+    /// Create a rule without conditions
+    pub fn new(action: PolicyAction) -> Self {
+        Self {
+            name: None,
+            action,
+            programs: Vec::new(),
+            flags: Vec::new(),
+            paths: Vec::new(),
+            outside: Vec::new(),
+            risk: None,
+            redirects: Vec::new(),
+            reason: None,
+        }
+    }
+
+    /// Set the rule name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Match commands at least as risky as `risk`
+    pub fn with_risk(mut self, risk: RiskLevel) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    /// Match commands running one of `programs`
+    pub fn with_programs<S: Into<String>>(mut self, programs: impl IntoIterator<Item = S>) -> Self {
+        self.programs = programs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Match commands passing one of `flags` to a matched program
+    pub fn with_flags<S: Into<String>>(mut self, flags: impl IntoIterator<Item = S>) -> Self {
+        self.flags = flags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Match commands touching paths under one of `patterns`
+    pub fn with_paths<S: Into<String>>(mut self, patterns: impl IntoIterator<Item = S>) -> Self {
+        self.paths = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Match commands touching paths outside all of `directories`
+    pub fn with_outside<S: Into<String>>(
+        mut self,
+        directories: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.outside = directories.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Match commands redirecting output to one of `patterns`
+    pub fn with_redirects<S: Into<String>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.redirects = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the explanation shown when the rule fires
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Describe why the rule matches `command`, or `None` if it does not
+    fn explain(
+        &self,
+        command: &Facts,
+        assessment: &RiskAssessment,
+        env: &PolicyEnvironment,
+    ) -> Option<Vec<String>> {
+        let mut explanation = Vec::new();
+
+        if !self.programs.is_empty() || !self.flags.is_empty() {
+            let (invocation, flag) = command.invocations.iter().find_map(|invocation| {
+                let program_matches =
+                    self.programs.is_empty() || self.programs.contains(&invocation.program);
+                if !program_matches {
+                    return None;
+                }
+                if self.flags.is_empty() {
+                    return Some((invocation, None));
+                }
+                let flag = self.flags.iter().find(|flag| invocation.has_flag(flag))?;
+                Some((invocation, Some(flag)))
+            })?;
+            explanation.push(match flag {
+                Some(flag) => format!("runs `{}` with `{flag}`", invocation.program),
+                None => format!("runs `{}`", invocation.program),
+            });
+        }
+
+        if !self.paths.is_empty() {
+            let (path, pattern) = command
+                .paths
+                .iter()
+                .chain(&command.names)
+                .find_map(|path| {
+                    let resolved = env.resolve(path);
+                    let pattern = self
+                        .paths
+                        .iter()
+                        .find(|pattern| path_matches(&env.resolve(pattern), &resolved))?;
+                    Some((path, pattern))
+                })?;
+            explanation.push(format!("touches {path}, which is under {pattern}"));
+        }
+
+        if !self.outside.is_empty() {
+            let directories: Vec<PathBuf> =
+                self.outside.iter().map(|dir| env.resolve(dir)).collect();
+            let path = command.paths.iter().find(|path| {
+                let resolved = env.resolve(path);
+                !directories.iter().any(|dir| resolved.starts_with(dir))
+            })?;
+            explanation.push(format!(
+                "touches {path}, which is outside {}",
+                self.outside.join(", ")
+            ));
+        }
+
+        if let Some(risk) = self.risk {
+            if assessment.level < risk {
+                return None;
+            }
+            match assessment.reasons().first() {
+                Some(reason) => explanation.push(format!("is {}: {reason}", assessment.level)),
+                None => explanation.push(format!("is {}", assessment.level)),
+            }
+        }
+
+        if !self.redirects.is_empty() {
+            let (target, pattern) = command.redirects.iter().find_map(|target| {
+                let resolved = env.resolve(target);
+                let pattern = self
+                    .redirects
+                    .iter()
+                    .find(|pattern| path_matches(&env.resolve(pattern), &resolved))?;
+                Some((target, pattern))
+            })?;
+            explanation.push(format!("writes to {target}, which matches {pattern}"));
+        }
+
+        Some(explanation)
+    }
+}
+
+/// Where a command runs, for resolving relative paths and `~`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyEnvironment {
+    pub cwd: PathBuf,
+    pub home: Option<PathBuf>,
+}
+
+impl PolicyEnvironment {
+    /// The environment of the current process
+    pub fn current() -> Self {
+        Self {
+            cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            home: std::env::var_os("HOME").map(PathBuf::from),
+        }
+    }
+
+    /// Resolve `path` to an absolute, lexically normalized path
+    fn resolve(&self, path: &str) -> PathBuf {
+        let home_relative = ["~", "$HOME", "${HOME}"].iter().find_map(|prefix| {
+            let rest = path.strip_prefix(prefix)?;
+            (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+        });
+        let path = match (home_relative, &self.home) {
+            (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+            _ => PathBuf::from(path),
+        };
+        normalize(&self.cwd.join(path))
+    }
+}
+
+/// The outcome of evaluating a command against a [`SafetyPolicy`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyDecision {
+    pub action: PolicyAction,
+    /// Name of the rule that fired, or `rule N` for unnamed rules; `None`
+    /// when the default action applied
+    pub rule: Option<String>,
+    /// Why the rule fired
+    pub reason: String,
+    /// Risk analysis of the command
+    pub risk: RiskAssessment,
+}
+
+impl PolicyDecision {
+    /// Whether the command may run without asking
+    pub fn is_allowed(&self) -> bool {
+        self.action == PolicyAction::Allow
+    }
+}
+
+impl fmt::Display for PolicyDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule {
+            Some(rule) => write!(f, "rule `{rule}` ({}): {}", self.action, self.reason),
+            None => f.write_str(&self.reason),
+        }
+    }
+}
+
+/// Ordered allow, confirm and deny rules for commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SafetyPolicy {
+    /// Action for commands no rule matches
+    #[serde(rename = "default", default = "default_action")]
+    pub default_action: PolicyAction,
+    /// Action for commands that cannot be parsed; never `Allow`
+    #[serde(default = "parse_error_action")]
+    pub parse_error: PolicyAction,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+fn default_action() -> PolicyAction {
+    PolicyAction::Allow
+}
+
+fn parse_error_action() -> PolicyAction {
+    PolicyAction::Confirm
+}
+
+impl SafetyPolicy {
+    /// Create a policy without rules
+    pub fn new(default_action: PolicyAction) -> Self {
+        Self {
+            default_action,
+            parse_error: parse_error_action(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Set the action for commands that cannot be parsed; `Allow` is taken
+    /// as `Confirm`
+    pub fn with_parse_error(mut self, action: PolicyAction) -> Self {
+        self.parse_error = match action {
+            PolicyAction::Allow => PolicyAction::Confirm,
+            action => action,
+        };
+        self
+    }
+
+    /// Add a rule after the existing ones
+    pub fn with_rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Parse a policy from TOML
+    pub fn from_toml(text: &str) -> ShellResult<Self> {
+        let policy: Self =
+            toml::from_str(text).map_err(|e| ShellError::InvalidPolicy(e.to_string()))?;
+        if policy.parse_error == PolicyAction::Allow {
+            return Err(ShellError::InvalidPolicy(
+                "parse_error must be \"confirm\" or \"deny\"".to_string(),
+            ));
+        }
+        for (index, rule) in policy.rules.iter().enumerate() {
+            let patterns = rule
+                .paths
+                .iter()
+                .chain(&rule.outside)
+                .chain(&rule.redirects);
+            if rule
+                .programs
+                .iter()
+                .chain(&rule.flags)
+                .chain(patterns)
+                .any(|s| s.is_empty())
+            {
+                return Err(ShellError::InvalidPolicy(format!(
+                    "{} has an empty condition",
+                    rule_label(rule, index)
+                )));
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Load a policy from a TOML file
+    pub fn from_file(path: impl AsRef<Path>) -> ShellResult<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            ShellError::InvalidPolicy(format!("cannot read {}: {e}", path.display()))
+        })?;
+        Self::from_toml(&text).map_err(|e| match e {
+            ShellError::InvalidPolicy(message) => {
+                ShellError::InvalidPolicy(format!("{}: {message}", path.display()))
+            }
+            other => other,
+        })
+    }
+
+    /// Evaluate `command` in the current directory
+    pub fn evaluate(&self, command: &str) -> PolicyDecision {
+        self.evaluate_in(command, &PolicyEnvironment::current())
+    }
+
+    /// Evaluate `command` as if run in `env`
+    ///
+    /// Commands that cannot be parsed get the `parse_error` action without
+    /// consulting the rules, which could not see what they run.
+    pub fn evaluate_in(&self, command: &str, env: &PolicyEnvironment) -> PolicyDecision {
+        let (facts, risk) = match CommandParser::parse(command) {
+            Ok(script) => (
+                Facts::collect(&script),
+                RiskAnalyzer::analyze_script(&script),
+            ),
+            Err(ShellError::Syntax(error)) => {
+                return PolicyDecision {
+                    action: self.parse_error,
+                    rule: None,
+                    reason: format!("cannot be parsed ({error}), so the rules cannot check it"),
+                    risk: RiskAnalyzer::analyze(command),
+                };
+            }
+            Err(_) => (Facts::default(), RiskAnalyzer::analyze(command)),
+        };
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if let Some(explanation) = rule.explain(&facts, &risk, env) {
+                let reason = rule.reason.clone().unwrap_or_else(|| {
+                    if explanation.is_empty() {
+                        "matches every command".to_string()
+                    } else {
+                        explanation.join(" and ")
+                    }
+                });
+                return PolicyDecision {
+                    action: rule.action,
+                    rule: Some(rule_label(rule, index)),
+                    reason,
+                    risk,
+                };
+            }
+        }
+        PolicyDecision {
+            action: self.default_action,
+            rule: None,
+            reason: format!("no rule matched; the default is {}", self.default_action),
+            risk,
+        }
+    }
+}
+
+impl Default for SafetyPolicy {
+    /// Deny irreversible commands and confirm destructive ones
+    fn default() -> Self {
+        Self::new(PolicyAction::Allow)
+            .with_rule(
+                PolicyRule::new(PolicyAction::Deny)
+                    .with_name("irreversible")
+                    .with_risk(RiskLevel::Irreversible),
+            )
+            .with_rule(
+                PolicyRule::new(PolicyAction::Confirm)
+                    .with_name("destructive")
+                    .with_risk(RiskLevel::Destructive),
+            )
+    }
+}
+
+fn rule_label(rule: &PolicyRule, index: usize) -> String {
+    rule.name
+        .clone()
+        .unwrap_or_else(|| format!("rule {}", index + 1))
+}
+
+/// A program run by a command, with its arguments
+#[derive(Debug, Clone)]
+struct Invocation {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Invocation {
+    fn has_flag(&self, flag: &str) -> bool {
+        self.args
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .any(|arg| match flag.strip_prefix("--") {
+                Some(long) => arg
+                    .strip_prefix("--")
+                    .is_some_and(|a| a == long || a.starts_with(&format!("{long}="))),
+                None => {
+                    let short = flag.trim_start_matches('-');
+                    !arg.starts_with("--")
+                        && arg.len() > 1
+                        && arg.starts_with('-')
+                        && short.chars().all(|c| arg[1..].contains(c))
+                }
+            })
+    }
+}
+
+/// What the policy rules look at in a command
+#[derive(Debug, Default)]
+struct Facts {
+    invocations: Vec<Invocation>,
+    /// Path-like arguments and redirection targets
+    paths: Vec<String>,
+    /// Other arguments, which may name files in the working directory; only
+    /// `paths` rules look at them, since they are often not files at all
+    names: Vec<String>,
+    /// Targets of writing redirections
+    redirects: Vec<String>,
+}
+
+impl Facts {
+    fn collect(script: &Script) -> Self {
+        let mut facts = Facts::default();
+        facts.list(script, &script.list, 0);
+        facts
+    }
+
+    fn list(&mut self, script: &Script, list: &List, depth: usize) {
+        for item in &list.items {
+            for pipeline in item.and_or.pipelines() {
+                for command in &pipeline.commands {
+                    self.command(script, command, depth);
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, script: &Script, command: &Command, depth: usize) {
+        let compound = match command {
+            Command::Simple(simple) => {
+                for word in simple
+                    .assignments
+                    .iter()
+                    .map(|a| &a.value)
+                    .chain(&simple.words)
+                {
+                    self.word(script, word, depth);
+                }
+                self.redirects(script, &simple.redirects, depth);
+                let argv: Vec<String> = simple.words.iter().map(|w| text(script, w)).collect();
+                self.argv(&argv, depth);
+                return;
+            }
+            Command::Compound(compound) => compound,
+            Command::FunctionDefinition(function) => &function.body,
+        };
+        self.redirects(script, &compound.redirects, depth);
+        match &compound.kind {
+            CompoundKind::BraceGroup(body) | CompoundKind::Subshell(body) => {
+                self.list(script, body, depth)
+            }
+            CompoundKind::For { words, body, .. } => {
+                for word in words.iter().flatten() {
+                    self.word(script, word, depth);
+                }
+                self.list(script, body, depth);
+            }
+            CompoundKind::Case { word, arms } => {
+                self.word(script, word, depth);
+                for arm in arms {
+                    self.list(script, &arm.body, depth);
+                }
+            }
+            CompoundKind::If {
+                condition,
+                then,
+                elifs,
+                otherwise,
+            } => {
+                let lists = [condition, then]
+                    .into_iter()
+                    .chain(elifs.iter().flat_map(|(c, b)| [c, b]))
+                    .chain(otherwise);
+                for list in lists {
+                    self.list(script, list, depth);
+                }
+            }
+            CompoundKind::While { condition, body } | CompoundKind::Until { condition, body } => {
+                self.list(script, condition, depth);
+                self.list(script, body, depth);
+            }
+        }
+    }
+
+    /// Collect the commands inside command substitutions of `word`
+    fn word(&mut self, script: &Script, word: &Word, depth: usize) {
+        fn walk(facts: &mut Facts, script: &Script, parts: &[WordPart], depth: usize) {
+            for part in parts {
+                match part {
+                    WordPart::CommandSubstitution(list) => facts.list(script, list, depth),
+                    WordPart::DoubleQuoted(inner) => walk(facts, script, inner, depth),
+                    _ => {}
+                }
+            }
+        }
+        walk(self, script, &word.parts, depth);
+    }
+
+    fn redirects(&mut self, script: &Script, redirects: &[Redirect], depth: usize) {
+        for redirect in redirects {
+            self.word(script, &redirect.target, depth);
+            if redirect.kind.writes() {
+                let target = text(script, &redirect.target);
+                self.paths.push(target.clone());
+                self.redirects.push(target);
+            } else if redirect.kind == RedirectKind::Input {
+                self.paths.push(text(script, &redirect.target));
+            }
+        }
+    }
+
+    fn argv(&mut self, argv: &[String], depth: usize) {
+        let Some((program, args)) = argv.split_first() else {
+            return;
+        };
+        let name = program.rsplit('/').next().unwrap_or(program).to_string();
+        for arg in args {
+            // `dd of=/dev/sda` and `--output=file` name paths after `=`
+            let value = arg.split_once('=').map_or(arg.as_str(), |(_, value)| value);
+            if value.starts_with('-') || value.is_empty() {
+                continue;
+            }
+            if looks_like_path(value) {
+                self.paths.push(value.to_string());
+            } else {
+                self.names.push(value.to_string());
+            }
+        }
+        self.invocations.push(Invocation {
+            program: name.clone(),
+            args: args.to_vec(),
+        });
+        if depth >= MAX_DEPTH {
+            return;
+        }
+
+        if WRAPPERS.contains(&name.as_str()) {
+            let mut rest = args
+                .iter()
+                .skip_while(|arg| arg.starts_with('-') || arg.contains('='));
+            if name == "timeout" {
+                rest.next();
+            }
+            let rest: Vec<String> = rest.cloned().collect();
+            self.argv(&rest, depth + 1);
+        } else if SHELLS.contains(&name.as_str()) {
+            let command = args
+                .iter()
+                .position(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains('c'))
+                .and_then(|index| args.get(index + 1));
+            if let Some(script) = command.and_then(|c| CommandParser::parse(c).ok()) {
+                self.list(&script, &script.list, depth + 1);
+            }
+        } else if name == "find" {
+            let mut args = args.iter();
+            while args.any(|arg| matches!(arg.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir")) {
+                let command: Vec<String> = args
+                    .by_ref()
+                    .take_while(|arg| *arg != ";" && *arg != "+")
+                    .cloned()
+                    .collect();
+                self.argv(&command, depth + 1);
+            }
+        }
+    }
+}
+
+/// A word's value, or its source text if it involves expansions
+fn text(script: &Script, word: &Word) -> String {
+    word.literal()
+        .unwrap_or_else(|| script.text(word.span).to_string())
+}
+
+fn looks_like_path(arg: &str) -> bool {
+    arg.contains('/') || arg.starts_with('~') || arg == "." || arg == ".."
+}
+
+/// Whether `path` matches `pattern` or lies beneath a path that does
+fn path_matches(pattern: &Path, path: &Path) -> bool {
+    let pattern = pattern.to_string_lossy();
+    if !pattern.contains(['*', '?']) {
+        return path.starts_with(Path::new(pattern.as_ref()));
+    }
+    path.ancestors()
+        .any(|ancestor| glob_match(&pattern, &ancestor.to_string_lossy()))
+}
+
+/// Match `text` against a glob where `*` and `?` stay within a path
+/// component and `**` crosses components
+fn glob_match(pattern: &str, text: &str) -> bool {
+    if let Some(rest) = pattern.strip_prefix("**") {
+        return text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .any(|i| glob_match(rest, &text[i..]));
+    }
+    let mut chars = pattern.chars();
+    match chars.next() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = chars.as_str();
+            let component_end = text.find('/').unwrap_or(text.len());
+            text[..component_end]
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([component_end])
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+        Some('?') => {
+            let mut text_chars = text.chars();
+            text_chars.next().is_some_and(|c| c != '/')
+                && glob_match(chars.as_str(), text_chars.as_str())
+        }
+        Some(c) => text
+            .strip_prefix(c)
+            .is_some_and(|rest| glob_match(chars.as_str(), rest)),
+    }
+}
+
+/// Remove `.` and resolve `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> PolicyEnvironment {
+        PolicyEnvironment {
+            cwd: PathBuf::from("/home/ada/project"),
+            home: Some(PathBuf::from("/home/ada")),
+        }
+    }
+
+    fn decide(policy: &SafetyPolicy, command: &str) -> PolicyDecision {
+        policy.evaluate_in(command, &env())
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let policy = SafetyPolicy::default();
+        assert!(decide(&policy, "ls -la").is_allowed());
+
+        let decision = decide(&policy, "rm -rf /");
+        assert_eq!(decision.action, PolicyAction::Deny);
+        assert_eq!(decision.rule.as_deref(), Some("irreversible"));
+
+        let decision = decide(&policy, "rm -r build");
+        assert_eq!(decision.action, PolicyAction::Confirm);
+        assert_eq!(
+            decision.to_string(),
+            "rule `destructive` (confirm): is destructive: recursively deletes build"
+        );
+    }
+
+    #[test]
+    fn test_rules_from_toml_in_order() {
+        let policy = SafetyPolicy::from_toml(
+            r#"
+            default = "confirm"
+
+            [[rules]]
+            name = "system-files"
+            action = "deny"
+            paths = ["/etc"]
+
+            [[rules]]
+            action = "confirm"
+            programs = ["rm"]
+            flags = ["-r"]
+
+            [[rules]]
+            name = "devices"
+            action = "deny"
+            redirects = ["/dev/sd*"]
+            reason = "never write to disks"
+
+            [[rules]]
+            name = "outside-home"
+            action = "confirm"
+            outside = ["~", "/tmp"]
+
+            [[rules]]
+            name = "read-only"
+            action = "allow"
+            programs = ["ls", "cat", "grep"]
+            "#,
+        )
+        .unwrap();
+
+        let decision = decide(&policy, "sudo cp hosts /etc/hosts");
+        assert_eq!(decision.action, PolicyAction::Deny);
+        assert_eq!(decision.reason, "touches /etc/hosts, which is under /etc");
+        assert_eq!(
+            decide(&policy, "cat ../../../etc/passwd").action,
+            PolicyAction::Deny
+        );
+
+        let decision = decide(&policy, "find . -exec rm -fr {} +");
+        assert_eq!(decision.rule.as_deref(), Some("rule 2"));
+        assert_eq!(decision.reason, "runs `rm` with `-r`");
+        assert_eq!(decide(&policy, "rm -f notes.txt").rule, None);
+
+        let decision = decide(&policy, "echo x > /dev/sdb");
+        assert_eq!(decision.rule.as_deref(), Some("devices"));
+        assert_eq!(decision.reason, "never write to disks");
+
+        let decision = decide(&policy, "cat /var/log/syslog");
+        assert_eq!(decision.rule.as_deref(), Some("outside-home"));
+        assert_eq!(
+            decide(&policy, "cat ~/notes /tmp/x").rule.as_deref(),
+            Some("read-only")
+        );
+
+        let decision = decide(&policy, "make build");
+        assert_eq!(decision.action, PolicyAction::Confirm);
+        assert_eq!(decision.rule, None);
+    }
+
+    #[test]
+    fn test_programs_through_wrappers_and_shells() {
+        let policy = SafetyPolicy::new(PolicyAction::Allow)
+            .with_rule(PolicyRule::new(PolicyAction::Deny).with_programs(["curl"]));
+        for command in [
+            "curl example.com",
+            "sudo -E env A=1 curl example.com",
+            "bash -c 'cd /tmp && curl example.com'",
+            "echo $(curl example.com)",
+            "for u in a b; do timeout 5 curl $u; done",
+        ] {
+            assert_eq!(
+                decide(&policy, command).action,
+                PolicyAction::Deny,
+                "{command}"
+            );
+        }
+        assert!(decide(&policy, "echo curl").is_allowed());
+    }
+
+    #[test]
+    fn test_unparseable_commands() {
+        let deny_rm = SafetyPolicy::new(PolicyAction::Allow)
+            .with_rule(PolicyRule::new(PolicyAction::Deny).with_programs(["rm"]))
+            .with_rule(PolicyRule::new(PolicyAction::Deny).with_paths(["/tmp/guard"]));
+        let commands = [
+            // Bash process substitution
+            "rm -rf / <(true)",
+            "diff <(ls) <(ls /); rm -rf ~",
+            "rm x <(true)",
+            "cat <(echo hi) > /tmp/guard/c",
+            "tee >(wc -l) < notes",
+            // A bash here-string
+            "rm -rf ~ <<< yes",
+            // Fish command substitution
+            "rm -rf (pwd)",
+            "echo 'unterminated",
+        ];
+        for policy in [SafetyPolicy::default(), deny_rm] {
+            for command in commands {
+                let decision = decide(&policy, command);
+                assert_eq!(decision.action, PolicyAction::Confirm, "{command}");
+                assert_eq!(decision.rule, None, "{command}");
+                assert!(decision.risk.parse_error.is_some(), "{command}");
+                assert!(
+                    decision.reason.starts_with("cannot be parsed ("),
+                    "{}",
+                    decision.reason
+                );
+            }
+        }
+
+        let policy = SafetyPolicy::from_toml("parse_error = \"deny\"").unwrap();
+        for command in commands {
+            assert_eq!(
+                decide(&policy, command).action,
+                PolicyAction::Deny,
+                "{command}"
+            );
+        }
+        let policy = SafetyPolicy::default().with_parse_error(PolicyAction::Allow);
+        assert_eq!(policy.parse_error, PolicyAction::Confirm);
+    }
+
+    #[test]
+    fn test_relative_names() {
+        let policy = SafetyPolicy::new(PolicyAction::Allow)
+            .with_rule(PolicyRule::new(PolicyAction::Deny).with_paths(["~/project/secrets"]))
+            .with_rule(PolicyRule::new(PolicyAction::Confirm).with_outside(["/srv"]));
+        assert_eq!(decide(&policy, "cat secrets").action, PolicyAction::Deny);
+        assert_eq!(
+            decide(&policy, "tar czf out.tgz secrets/keys").action,
+            PolicyAction::Deny
+        );
+        // Bare words are not taken as paths outside a directory
+        assert!(decide(&policy, "git status").is_allowed());
+    }
+
+    #[test]
+    fn test_flags() {
+        let invocation = Invocation {
+            program: "rm".to_string(),
+            args: vec!["-fr".into(), "--force".into(), "--".into(), "-x".into()],
+        };
+        assert!(invocation.has_flag("-r"));
+        assert!(invocation.has_flag("-rf"));
+        assert!(invocation.has_flag("--force"));
+        assert!(!invocation.has_flag("--forc"));
+        assert!(!invocation.has_flag("-x"));
+    }
+
+    #[test]
+    fn test_glob_patterns() {
+        assert!(glob_match("/etc/*.conf", "/etc/ld.so.conf"));
+        assert!(!glob_match("/etc/*.conf", "/etc/nginx/nginx.conf"));
+        assert!(glob_match("/etc/**.conf", "/etc/nginx/nginx.conf"));
+        assert!(glob_match("/dev/sd?", "/dev/sda"));
+        assert!(path_matches(
+            Path::new("/home/*/.ssh"),
+            Path::new("/home/ada/.ssh/id_rsa")
+        ));
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        for text in [
+            "default = \"maybe\"",
+            "parse_error = \"allow\"",
+            "[[rules]]\nprograms = [\"rm\"]",
+            "[[rules]]\naction = \"deny\"\nprogram = \"rm\"",
+            "[[rules]]\naction = \"deny\"\nrisk = \"scary\"",
+            "[[rules]]\naction = \"deny\"\npaths = [\"\"]",
+        ] {
+            assert!(
+                matches!(
+                    SafetyPolicy::from_toml(text),
+                    Err(ShellError::InvalidPolicy(_))
+                ),
+                "{text}"
+            );
+        }
+    }
+}
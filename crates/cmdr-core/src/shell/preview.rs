@@ -0,0 +1,1094 @@
+//! Dry-run previews of what a command would change
+//!
+//! [`DryRun`] works out the paths a command would touch without running it,
+//! for commands whose effects follow from their arguments: `rm`, `mv`, `cp`,
+//! `chmod`, `chown`, `chgrp`, `find -delete`, `sed -i` and `tar` extraction.
+//! Globs and `~` are expanded against the filesystem the way the shell would
+//! expand them. Other commands can only be previewed by running them in a
+//! [`Sandbox`](super::Sandbox), which [`ShellExecutor`](super::ShellExecutor)
+//! does for dry runs when `DryRun` gives up.
+
+use super::ast::*;
+use super::risk::{Arg, Options};
+use super::{ChangeKind, CommandParser, FileChange};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command as Process, Stdio};
+
+/// What a command would do to a path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    Create,
+    /// Change the contents of the file
+    Modify,
+    Delete,
+    /// Rename the path to `to`
+    Move {
+        to: PathBuf,
+    },
+    /// Copy the path to `to`
+    Copy {
+        to: PathBuf,
+    },
+    /// Change permissions, e.g. to `u+x` or `644`
+    ChangeMode {
+        mode: String,
+    },
+    /// Change owner or group, e.g. to `alice:staff` or `:staff`
+    ChangeOwner {
+        owner: String,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Create => f.write_str("create"),
+            Operation::Modify => f.write_str("modify"),
+            Operation::Delete => f.write_str("delete"),
+            Operation::Move { to } => write!(f, "move to {}", to.display()),
+            Operation::Copy { to } => write!(f, "copy to {}", to.display()),
+            Operation::ChangeMode { mode } => write!(f, "chmod {mode}"),
+            Operation::ChangeOwner { owner } => write!(f, "chown {owner}"),
+        }
+    }
+}
+
+/// A path a command would affect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedChange {
+    /// The path as the command names it; relative paths are relative to
+    /// the working directory
+    pub path: PathBuf,
+    pub operation: Operation,
+}
+
+impl PlannedChange {
+    fn new(path: impl Into<PathBuf>, operation: Operation) -> Self {
+        Self {
+            path: path.into(),
+            operation,
+        }
+    }
+}
+
+/// How a preview was worked out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewSource {
+    /// Read off the command's arguments without running it
+    Analysis,
+    /// Observed by running the command in a sandbox
+    Sandbox,
+}
+
+/// The changes a command would make
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preview {
+    pub source: PreviewSource,
+    pub changes: Vec<PlannedChange>,
+}
+
+impl Preview {
+    /// A preview from the changes a command made in a sandbox
+    pub fn from_sandbox(changes: &[FileChange]) -> Self {
+        let changes = changes
+            .iter()
+            .map(|change| {
+                let operation = match change.kind {
+                    ChangeKind::Created => Operation::Create,
+                    ChangeKind::Modified => Operation::Modify,
+                    ChangeKind::Deleted => Operation::Delete,
+                };
+                PlannedChange::new(&change.path, operation)
+            })
+            .collect();
+        Self {
+            source: PreviewSource::Sandbox,
+            changes,
+        }
+    }
+}
+
+/// Works out what a command would change without running it
+#[derive(Debug, Clone)]
+pub struct DryRun {
+    cwd: PathBuf,
+    home: Option<PathBuf>,
+}
+
+impl DryRun {
+    /// Preview commands run in `cwd`, with `~` taken from `$HOME`
+    pub fn new(cwd: impl Into<PathBuf>) -> Self {
+        Self {
+            cwd: cwd.into(),
+            home: std::env::var_os("HOME").map(PathBuf::from),
+        }
+    }
+
+    /// Expand `~` to `home`
+    pub fn with_home(mut self, home: impl Into<PathBuf>) -> Self {
+        self.home = Some(home.into());
+        self
+    }
+
+    /// Preview `command` from its arguments
+    ///
+    /// Returns `None` unless `command` is a single recognised command with
+    /// no writing redirections whose words need no expansion besides globs
+    /// and `~`; such commands have to be run in a sandbox to be previewed.
+    pub fn preview(&self, command: &str) -> Option<Preview> {
+        Some(Preview {
+            source: PreviewSource::Analysis,
+            changes: self.plan(command)?,
+        })
+    }
+
+    fn plan(&self, command: &str) -> Option<Vec<PlannedChange>> {
+        let script = CommandParser::parse(command).ok()?;
+        let [item] = script.list.items.as_slice() else {
+            return None;
+        };
+        if item.background || !item.and_or.rest.is_empty() {
+            return None;
+        }
+        let [Command::Simple(simple)] = item.and_or.first.commands.as_slice() else {
+            return None;
+        };
+        if !simple.assignments.is_empty() || simple.redirects.iter().any(|r| r.kind.writes()) {
+            return None;
+        }
+
+        let mut argv = Vec::new();
+        for word in &simple.words {
+            argv.extend(self.expand(word)?);
+        }
+        let (program, args) = argv.split_first()?;
+        let args: Vec<Arg> = args.iter().map(|arg| Arg::literal(arg.as_str())).collect();
+        match program.rsplit('/').next().unwrap_or(program) {
+            "rm" => Some(self.rm(&args)),
+            "mv" => Some(self.transfer(&args, false)),
+            "cp" => Some(self.transfer(&args, true)),
+            "chmod" => Some(self.chmod(&args)),
+            "chown" => Some(self.chown(&args, false)),
+            "chgrp" => Some(self.chown(&args, true)),
+            "find" => self.find(&args),
+            "sed" => Some(self.sed(&args)),
+            "tar" => self.tar(&args),
+            _ => None,
+        }
+    }
+
+    /// The fields `word` expands to, or `None` if they depend on more than
+    /// the filesystem
+    fn expand(&self, word: &Word) -> Option<Vec<String>> {
+        let mut pattern = Vec::new();
+        for part in &word.parts {
+            match part {
+                WordPart::Literal(text) | WordPart::SingleQuoted(text) => {
+                    pattern.extend(text.chars().map(Token::Char))
+                }
+                WordPart::DoubleQuoted(parts) => {
+                    for part in parts {
+                        let WordPart::Literal(text) = part else {
+                            return None;
+                        };
+                        pattern.extend(text.chars().map(Token::Char));
+                    }
+                }
+                WordPart::Tilde(user) if user.is_empty() => {
+                    pattern.extend(self.home.as_ref()?.to_str()?.chars().map(Token::Char))
+                }
+                WordPart::Glob(glob) => pattern.extend(parse_pattern(glob)),
+                _ => return None,
+            }
+        }
+        if !pattern.iter().any(Token::is_wildcard) {
+            return Some(vec![to_text(&pattern)]);
+        }
+        // Like the shell, leave patterns that match nothing as they are
+        let matches = self.glob(&pattern);
+        Some(if matches.is_empty() {
+            vec![to_text(&pattern)]
+        } else {
+            matches
+        })
+    }
+
+    /// Existing paths matching `pattern`, in the order the shell lists them
+    fn glob(&self, pattern: &[Token]) -> Vec<String> {
+        let components: Vec<&[Token]> = pattern.split(|t| *t == Token::Char('/')).collect();
+        let mut found = vec![String::new()];
+        for (i, component) in components.iter().enumerate() {
+            let last = i + 1 == components.len();
+            let mut next = Vec::new();
+            for prefix in &found {
+                let join = |name: &str| match i {
+                    0 => name.to_string(),
+                    _ => format!("{prefix}/{name}"),
+                };
+                if !component.iter().any(Token::is_wildcard) {
+                    next.push(join(&to_text(component)));
+                    continue;
+                }
+                let dir = match (i, prefix.as_str()) {
+                    (0, _) => ".",
+                    (_, "") => "/",
+                    (_, prefix) => prefix,
+                };
+                // Hidden files only match patterns that start with a dot
+                let hidden = component.first() == Some(&Token::Char('.'));
+                for name in self.children(dir) {
+                    if (hidden || !name.starts_with('.')) && matches(component, &name) {
+                        let path = join(&name);
+                        if last || self.resolve(&path).is_dir() {
+                            next.push(path);
+                        }
+                    }
+                }
+            }
+            found = next;
+        }
+        found.retain(|path| self.metadata(path).is_some());
+        found
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.cwd.join(path)
+    }
+
+    /// Metadata of `path` itself, not what it links to
+    fn metadata(&self, path: &str) -> Option<fs::Metadata> {
+        fs::symlink_metadata(self.resolve(path)).ok()
+    }
+
+    /// Names of the entries of directory `path`, sorted
+    fn children(&self, path: &str) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(self.resolve(path)) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// `path` and everything beneath it, parents before children
+    fn tree(&self, path: &str) -> Vec<String> {
+        let mut paths = vec![path.to_string()];
+        if self.metadata(path).is_some_and(|m| m.is_dir()) {
+            for name in self.children(path) {
+                paths.extend(self.tree(&child(path, &name)));
+            }
+        }
+        paths
+    }
+
+    fn rm(&self, args: &[Arg]) -> Vec<PlannedChange> {
+        let options = Options::parse(args, &[]);
+        let recursive = options.has('r', "recursive") || options.has('R', "recursive");
+        let mut changes = Vec::new();
+        for path in options.operand_texts() {
+            // rm refuses `.`, `..` and `/`
+            if Path::new(path).file_name().is_none() {
+                continue;
+            }
+            let Some(metadata) = self.metadata(path) else {
+                continue;
+            };
+            let paths = if !metadata.is_dir() {
+                vec![path.to_string()]
+            } else if recursive {
+                self.tree(path)
+            } else if options.has('d', "dir") && self.children(path).is_empty() {
+                vec![path.to_string()]
+            } else {
+                continue;
+            };
+            changes.extend(
+                paths
+                    .into_iter()
+                    .map(|path| PlannedChange::new(path, Operation::Delete)),
+            );
+        }
+        changes
+    }
+
+    /// `mv`, or `cp` if `copy` is set
+    fn transfer(&self, args: &[Arg], copy: bool) -> Vec<PlannedChange> {
+        let options = Options::parse(args, &["-t", "--target-directory", "-S", "--suffix"]);
+        let mut sources = options.operand_texts();
+        let (into_directory, destination) = match options.value(&["-t", "--target-directory"]) {
+            Some(directory) => (true, directory),
+            None => {
+                let Some(destination) = sources.pop() else {
+                    return Vec::new();
+                };
+                let is_dir = self.resolve(destination).is_dir();
+                (
+                    is_dir && !options.has('T', "no-target-directory"),
+                    destination,
+                )
+            }
+        };
+        if !into_directory && sources.len() != 1 {
+            return Vec::new();
+        }
+        let recursive = options.has('r', "recursive")
+            || options.has('R', "recursive")
+            || options.has('a', "archive");
+        let no_clobber = options.has('n', "no-clobber");
+
+        let mut changes = Vec::new();
+        for source in sources {
+            let Some(metadata) = self.metadata(source) else {
+                continue;
+            };
+            if copy && metadata.is_dir() && !recursive {
+                continue;
+            }
+            let target = if into_directory {
+                let Some(name) = Path::new(source).file_name() else {
+                    continue;
+                };
+                Path::new(destination).join(name)
+            } else {
+                PathBuf::from(destination)
+            };
+            let overwrites =
+                fs::symlink_metadata(self.cwd.join(&target)).is_ok_and(|m| !m.is_dir());
+            if overwrites && no_clobber {
+                continue;
+            }
+            let operation = if copy {
+                Operation::Copy { to: target.clone() }
+            } else {
+                Operation::Move { to: target.clone() }
+            };
+            changes.push(PlannedChange::new(source, operation));
+            if overwrites {
+                changes.push(PlannedChange::new(target, Operation::Modify));
+            }
+        }
+        changes
+    }
+
+    fn chmod(&self, args: &[Arg]) -> Vec<PlannedChange> {
+        let mut args = args.to_vec();
+        let mode = if args.iter().any(|arg| arg.text.starts_with("--reference")) {
+            None
+        } else {
+            // Symbolic modes such as `-x` look like options
+            let is_mode = |arg: &Arg| {
+                let text = arg.text.as_str();
+                !text.starts_with('-')
+                    || text.len() > 1 && text[1..].chars().all(|c| "rwxXst".contains(c))
+            };
+            let Some(index) = args.iter().position(is_mode) else {
+                return Vec::new();
+            };
+            Some(args.remove(index).text)
+        };
+        let options = Options::parse(&args, &["--reference"]);
+        let mode = match (mode, options.value(&["--reference"])) {
+            (Some(mode), _) => mode,
+            (None, Some(reference)) => format!("like {reference}"),
+            (None, None) => return Vec::new(),
+        };
+        self.change_attributes(
+            &options,
+            &options.operand_texts(),
+            Operation::ChangeMode { mode },
+        )
+    }
+
+    /// `chown`, or `chgrp` if `group` is set
+    fn chown(&self, args: &[Arg], group: bool) -> Vec<PlannedChange> {
+        let options = Options::parse(args, &["--reference", "--from"]);
+        let mut paths = options.operand_texts();
+        let owner = match options.value(&["--reference"]) {
+            Some(reference) => format!("like {reference}"),
+            None if paths.is_empty() => return Vec::new(),
+            None if group => format!(":{}", paths.remove(0)),
+            None => paths.remove(0).to_string(),
+        };
+        self.change_attributes(&options, &paths, Operation::ChangeOwner { owner })
+    }
+
+    /// Apply `operation` to `paths`, and to everything beneath them with `-R`
+    fn change_attributes(
+        &self,
+        options: &Options,
+        paths: &[&str],
+        operation: Operation,
+    ) -> Vec<PlannedChange> {
+        let recursive = options.has('R', "recursive");
+        paths
+            .iter()
+            .filter(|path| self.metadata(path).is_some())
+            .flat_map(|path| match recursive {
+                true => self.tree(path),
+                false => vec![path.to_string()],
+            })
+            .map(|path| PlannedChange::new(path, operation.clone()))
+            .collect()
+    }
+
+    /// `find` is previewed when it combines understood tests with `-delete`
+    fn find(&self, args: &[Arg]) -> Option<Vec<PlannedChange>> {
+        let mut args: Vec<&str> = args.iter().map(|arg| arg.text.as_str()).collect();
+        while let Some(&first) = args.first() {
+            match first {
+                "-P" | "-H" => args.remove(0),
+                "-L" => return None,
+                _ => break,
+            };
+        }
+        let start = args
+            .iter()
+            .position(|arg| arg.starts_with('-') || matches!(*arg, "(" | "!" | ","))
+            .unwrap_or(args.len());
+        let (roots, mut expression) = args.split_at(start);
+
+        let mut search = Search::default();
+        let mut delete = false;
+        while let Some((&primary, rest)) = expression.split_first() {
+            expression = rest;
+            match primary {
+                "-delete" => delete = true,
+                "-empty" if !delete => search.tests.push(Test::Empty),
+                "-print" | "-print0" | "-depth" | "-a" | "-and" | "-true" | "-empty" => {}
+                "-name" | "-iname" | "-path" | "-ipath" | "-wholename" | "-type" | "-maxdepth"
+                | "-mindepth" => {
+                    let (&value, rest) = expression.split_first()?;
+                    expression = rest;
+                    // Global options apply wherever they appear; tests after
+                    // `-delete` do not limit what it deletes
+                    let test = match primary {
+                        "-maxdepth" => {
+                            search.max_depth = Some(value.parse().ok()?);
+                            continue;
+                        }
+                        "-mindepth" => {
+                            search.min_depth = value.parse().ok()?;
+                            continue;
+                        }
+                        _ if delete => continue,
+                        "-name" => Test::Name(parse_pattern(value), false),
+                        "-iname" => Test::Name(parse_pattern(&value.to_lowercase()), true),
+                        "-path" | "-wholename" => Test::Path(parse_pattern(value), false),
+                        "-ipath" => Test::Path(parse_pattern(&value.to_lowercase()), true),
+                        _ => match value {
+                            "f" | "d" | "l" => Test::Type(value.chars().next()?),
+                            _ => return None,
+                        },
+                    };
+                    search.tests.push(test);
+                }
+                _ => return None,
+            }
+        }
+        if !delete {
+            return Some(Vec::new());
+        }
+
+        let mut changes = Vec::new();
+        let roots = if roots.is_empty() { &["."][..] } else { roots };
+        for root in roots {
+            self.search(&search, root, 0, &mut changes);
+        }
+        Some(changes)
+    }
+
+    fn search(&self, search: &Search, path: &str, depth: usize, changes: &mut Vec<PlannedChange>) {
+        let Some(metadata) = self.metadata(path) else {
+            return;
+        };
+        // `find -delete` leaves `.` and `..` alone
+        if depth >= search.min_depth
+            && Path::new(path).file_name().is_some()
+            && search
+                .tests
+                .iter()
+                .all(|test| self.passes(test, path, &metadata))
+        {
+            changes.push(PlannedChange::new(path, Operation::Delete));
+        }
+        if metadata.is_dir() && search.max_depth.is_none_or(|max| depth < max) {
+            for name in self.children(path) {
+                self.search(search, &child(path, &name), depth + 1, changes);
+            }
+        }
+    }
+
+    fn passes(&self, test: &Test, path: &str, metadata: &fs::Metadata) -> bool {
+        let fold = |text: &str, fold: bool| match fold {
+            true => text.to_lowercase(),
+            false => text.to_string(),
+        };
+        match test {
+            Test::Name(pattern, case) => {
+                let name = path
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(path);
+                matches(pattern, &fold(name, *case))
+            }
+            Test::Path(pattern, case) => matches(pattern, &fold(path, *case)),
+            Test::Type('d') => metadata.is_dir(),
+            Test::Type('l') => metadata.is_symlink(),
+            Test::Type(_) => metadata.is_file(),
+            Test::Empty if metadata.is_dir() => self.children(path).is_empty(),
+            Test::Empty => metadata.is_file() && metadata.len() == 0,
+        }
+    }
+
+    fn sed(&self, args: &[Arg]) -> Vec<PlannedChange> {
+        let mut suffix = None;
+        let mut has_script_option = false;
+        let mut operands = Vec::new();
+        let mut args = args.iter().map(|arg| arg.text.as_str());
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                operands.extend(args.by_ref());
+            } else if let Some(long) = arg.strip_prefix("--") {
+                match long.split_once('=') {
+                    Some(("in-place", value)) => suffix = Some(value),
+                    Some(("expression" | "file", _)) => has_script_option = true,
+                    None if long == "in-place" => suffix = Some(""),
+                    None if matches!(long, "expression" | "file" | "line-length") => {
+                        has_script_option |= long != "line-length";
+                        args.next();
+                    }
+                    _ => {}
+                }
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // `-i` takes the rest of the argument as a backup suffix;
+                // `-e`, `-f` and `-l` take it or the next argument as value
+                for (i, c) in arg.char_indices().skip(1) {
+                    let rest = &arg[i + 1..];
+                    match c {
+                        'i' => suffix = Some(rest),
+                        'e' | 'f' | 'l' => {
+                            has_script_option |= c != 'l';
+                            if rest.is_empty() {
+                                args.next();
+                            }
+                        }
+                        _ => continue,
+                    }
+                    break;
+                }
+            } else {
+                operands.push(arg);
+            }
+        }
+        let Some(suffix) = suffix else {
+            return Vec::new();
+        };
+        let files = match has_script_option {
+            true => &operands[..],
+            false => operands.get(1..).unwrap_or_default(),
+        };
+
+        let mut changes = Vec::new();
+        for file in files {
+            if !self.resolve(file).is_file() {
+                continue;
+            }
+            if !suffix.is_empty() {
+                let to = backup_path(file, suffix);
+                changes.push(PlannedChange::new(*file, Operation::Copy { to }));
+            }
+            changes.push(PlannedChange::new(*file, Operation::Modify));
+        }
+        changes
+    }
+
+    /// `tar` is previewed when it extracts from a named archive, by listing
+    /// the archive
+    fn tar(&self, args: &[Arg]) -> Option<Vec<PlannedChange>> {
+        let mut args = args.to_vec();
+        // Old-style `tar xzf archive` bundles options without a dash
+        if let Some(first) = args.first_mut() {
+            if !first.text.starts_with('-') && first.text.chars().all(|c| c.is_ascii_alphabetic()) {
+                first.text.insert(0, '-');
+            }
+        }
+        let options = Options::parse(
+            &args,
+            &[
+                "-f",
+                "--file",
+                "-C",
+                "--directory",
+                "-T",
+                "--files-from",
+                "-X",
+                "--exclude-from",
+                "-b",
+                "--blocking-factor",
+                "-H",
+                "--format",
+                "-I",
+                "--use-compress-program",
+                "-g",
+                "--listed-incremental",
+                "-N",
+                "--newer",
+                "-K",
+                "--starting-file",
+                "-V",
+                "--label",
+            ],
+        );
+        if !options.has('x', "extract") && !options.has_long("get") {
+            let writes = options.has('c', "create")
+                || options.has('r', "append")
+                || options.has('u', "update")
+                || options.has('A', "catenate")
+                || options.has_long("delete");
+            return if writes { None } else { Some(Vec::new()) };
+        }
+        if options.has('O', "to-stdout") {
+            return Some(Vec::new());
+        }
+        // Renaming and filtering options change which paths are written
+        let rewrites = ["strip-components", "transform", "exclude", "wildcards"]
+            .iter()
+            .any(|option| options.has_long(option))
+            || options
+                .value(&["-T", "--files-from", "-X", "--exclude-from"])
+                .is_some();
+        let archive = options.value(&["-f", "--file"])?;
+        if rewrites || archive == "-" {
+            return None;
+        }
+
+        let listing = Process::new("tar")
+            .arg("-tf")
+            .arg(archive)
+            .current_dir(&self.cwd)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !listing.status.success() {
+            return None;
+        }
+        let directory = options.value(&["-C", "--directory"]).unwrap_or(".");
+        let members: Vec<&str> = options
+            .operand_texts()
+            .into_iter()
+            .map(|member| member.trim_end_matches('/'))
+            .collect();
+        let mut changes = Vec::new();
+        for entry in String::from_utf8_lossy(&listing.stdout).lines() {
+            let name = entry.trim_end_matches('/');
+            let selected = members.is_empty()
+                || members.iter().any(|member| {
+                    name == *member
+                        || name
+                            .strip_prefix(member)
+                            .is_some_and(|rest| rest.starts_with('/'))
+                });
+            if !selected {
+                continue;
+            }
+            let path = match directory {
+                "." => name.to_string(),
+                directory => child(directory, name),
+            };
+            match self.metadata(&path) {
+                Some(metadata) if metadata.is_dir() => {}
+                Some(_) => changes.push(PlannedChange::new(path, Operation::Modify)),
+                None => changes.push(PlannedChange::new(path, Operation::Create)),
+            }
+        }
+        Some(changes)
+    }
+}
+
+/// The tests a `find -delete` applies before deleting
+#[derive(Debug, Default)]
+struct Search {
+    tests: Vec<Test>,
+    min_depth: usize,
+    max_depth: Option<usize>,
+}
+
+#[derive(Debug)]
+enum Test {
+    /// `-name`, or `-iname` when case-insensitive
+    Name(Vec<Token>, bool),
+    /// `-path`, or `-ipath` when case-insensitive
+    Path(Vec<Token>, bool),
+    /// `-type` with `f`, `d` or `l`
+    Type(char),
+    Empty,
+}
+
+/// One element of a glob pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `*`
+    Any,
+    /// `?`
+    One,
+    /// A bracket expression such as `[!a-z]`, brackets included
+    Class(String),
+}
+
+impl Token {
+    fn is_wildcard(&self) -> bool {
+        !matches!(self, Token::Char(_))
+    }
+}
+
+/// Parse a glob pattern, where `\` quotes the next character
+fn parse_pattern(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let mut len = c.len_utf8();
+        tokens.push(match c {
+            '*' => Token::Any,
+            '?' => Token::One,
+            '\\' => match rest[1..].chars().next() {
+                Some(quoted) => {
+                    len += quoted.len_utf8();
+                    Token::Char(quoted)
+                }
+                None => Token::Char('\\'),
+            },
+            '[' => match class_len(rest) {
+                Some(class) => {
+                    len = class;
+                    Token::Class(rest[..class].to_string())
+                }
+                None => Token::Char('['),
+            },
+            c => Token::Char(c),
+        });
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+/// Length of the bracket expression at the start of `text`, if it is closed
+fn class_len(text: &str) -> Option<usize> {
+    let body = &text[1..];
+    let negation = usize::from(body.starts_with(['!', '^']));
+    // A `]` right after the opening bracket is a member, not the end
+    let first = body[negation..].chars().next()?.len_utf8();
+    let end = body[negation + first..].find(']')?;
+    Some(1 + negation + first + end + 1)
+}
+
+/// Whether `text` matches `pattern`, with `*` matching `/` too
+fn matches(pattern: &[Token], text: &str) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((Token::Any, rest)) => text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .any(|i| matches(rest, &text[i..])),
+        Some((token, rest)) => {
+            let mut chars = text.chars();
+            let Some(c) = chars.next() else {
+                return false;
+            };
+            let matched = match token {
+                Token::Char(expected) => c == *expected,
+                Token::Class(class) => class_matches(class, c),
+                Token::Any | Token::One => true,
+            };
+            matched && matches(rest, chars.as_str())
+        }
+    }
+}
+
+fn class_matches(class: &str, c: char) -> bool {
+    let body = &class[1..class.len() - 1];
+    let (negated, body) = match body.strip_prefix(['!', '^']) {
+        Some(body) => (true, body),
+        None => (false, body),
+    };
+    let members: Vec<char> = body.chars().collect();
+    let mut found = false;
+    let mut i = 0;
+    while i < members.len() {
+        if i + 2 < members.len() && members[i + 1] == '-' {
+            found |= (members[i]..=members[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= members[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+/// A pattern as text, e.g. to leave it unexpanded
+fn to_text(pattern: &[Token]) -> String {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Char(c) => c.to_string(),
+            Token::Any => "*".to_string(),
+            Token::One => "?".to_string(),
+            Token::Class(class) => class.clone(),
+        })
+        .collect()
+}
+
+fn child(dir: &str, name: &str) -> String {
+    match dir.ends_with('/') {
+        true => format!("{dir}{name}"),
+        false => format!("{dir}/{name}"),
+    }
+}
+
+/// Where `sed -i` backs `file` up, where `*` in `suffix` stands for the
+/// file name
+fn backup_path(file: &str, suffix: &str) -> PathBuf {
+    if !suffix.contains('*') {
+        return PathBuf::from(format!("{file}{suffix}"));
+    }
+    let path = Path::new(file);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let backup = suffix.replace('*', &name);
+    match backup.contains('/') {
+        true => PathBuf::from(backup),
+        false => path.with_file_name(backup),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a.log, b.log, .hidden.log, notes.txt and sub/c.log
+    fn fixture() -> (tempfile::TempDir, DryRun) {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["a.log", "b.log", ".hidden.log", "notes.txt", "sub/c.log"] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "a\n").unwrap();
+        }
+        let dry_run = DryRun::new(dir.path()).with_home(dir.path());
+        (dir, dry_run)
+    }
+
+    fn plan(dry_run: &DryRun, command: &str) -> Vec<(String, Operation)> {
+        let preview = dry_run
+            .preview(command)
+            .unwrap_or_else(|| panic!("no preview of `{command}`"));
+        assert_eq!(preview.source, PreviewSource::Analysis);
+        preview
+            .changes
+            .into_iter()
+            .map(|change| (change.path.display().to_string(), change.operation))
+            .collect()
+    }
+
+    fn deletes(paths: &[&str]) -> Vec<(String, Operation)> {
+        paths
+            .iter()
+            .map(|path| (path.to_string(), Operation::Delete))
+            .collect()
+    }
+
+    #[test]
+    fn test_expands_globs_like_the_shell() {
+        let (dir, dry_run) = fixture();
+        assert_eq!(plan(&dry_run, "rm *.log"), deletes(&["a.log", "b.log"]));
+        assert_eq!(
+            plan(&dry_run, "rm .*.log [ab].log"),
+            deletes(&[".hidden.log", "a.log", "b.log"])
+        );
+        assert_eq!(plan(&dry_run, "rm */*.log"), deletes(&["sub/c.log"]));
+        assert_eq!(plan(&dry_run, "rm '*.log' nothing*"), deletes(&[]));
+        assert_eq!(
+            plan(&dry_run, "rm ~/notes.txt"),
+            deletes(&[&format!("{}/notes.txt", dir.path().display())])
+        );
+    }
+
+    #[test]
+    fn test_rm_mv_cp() {
+        let (_dir, dry_run) = fixture();
+        assert_eq!(plan(&dry_run, "rm -rf sub"), deletes(&["sub", "sub/c.log"]));
+        assert_eq!(plan(&dry_run, "rm sub . /"), deletes(&[]));
+        assert_eq!(
+            plan(&dry_run, "mv *.log sub"),
+            vec![
+                (
+                    "a.log".into(),
+                    Operation::Move {
+                        to: "sub/a.log".into()
+                    }
+                ),
+                (
+                    "b.log".into(),
+                    Operation::Move {
+                        to: "sub/b.log".into()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            plan(&dry_run, "cp a.log b.log"),
+            vec![
+                ("a.log".into(), Operation::Copy { to: "b.log".into() }),
+                ("b.log".into(), Operation::Modify),
+            ]
+        );
+        assert_eq!(plan(&dry_run, "cp -n a.log b.log"), vec![]);
+        assert_eq!(plan(&dry_run, "cp sub copy"), vec![]);
+        assert_eq!(
+            plan(&dry_run, "cp -t sub -r sub"),
+            vec![(
+                "sub".into(),
+                Operation::Copy {
+                    to: "sub/sub".into()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_permissions_and_owners() {
+        let (_dir, dry_run) = fixture();
+        let mode = |mode: &str| Operation::ChangeMode { mode: mode.into() };
+        assert_eq!(
+            plan(&dry_run, "chmod -x a.log"),
+            vec![("a.log".into(), mode("-x"))]
+        );
+        assert_eq!(
+            plan(&dry_run, "chmod -R 755 sub"),
+            vec![
+                ("sub".into(), mode("755")),
+                ("sub/c.log".into(), mode("755"))
+            ]
+        );
+        assert_eq!(
+            plan(&dry_run, "chgrp staff a.log missing"),
+            vec![(
+                "a.log".into(),
+                Operation::ChangeOwner {
+                    owner: ":staff".into()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_find_delete() {
+        let (_dir, dry_run) = fixture();
+        assert_eq!(
+            plan(&dry_run, "find . -name '*.log' -delete"),
+            deletes(&["./.hidden.log", "./a.log", "./b.log", "./sub/c.log"])
+        );
+        assert_eq!(
+            plan(&dry_run, "find . -maxdepth 1 -type f -iname '[A]*' -delete"),
+            deletes(&["./a.log"])
+        );
+        assert_eq!(
+            plan(&dry_run, "find sub -delete"),
+            deletes(&["sub", "sub/c.log"])
+        );
+        assert_eq!(plan(&dry_run, "find . -name '*.log' -print"), deletes(&[]));
+        assert!(dry_run
+            .preview("find . -name a -o -name b -delete")
+            .is_none());
+        assert!(dry_run.preview("find . -mtime +3 -delete").is_none());
+    }
+
+    #[test]
+    fn test_sed_in_place() {
+        let (_dir, dry_run) = fixture();
+        assert_eq!(
+            plan(&dry_run, "sed -i.bak 's/a/b/' a.log"),
+            vec![
+                (
+                    "a.log".into(),
+                    Operation::Copy {
+                        to: "a.log.bak".into()
+                    }
+                ),
+                ("a.log".into(), Operation::Modify),
+            ]
+        );
+        assert_eq!(
+            plan(&dry_run, "sed -E -i -e s/a/b/ sub/c.log missing"),
+            vec![("sub/c.log".into(), Operation::Modify)]
+        );
+        assert_eq!(plan(&dry_run, "sed s/a/b/ a.log"), vec![]);
+    }
+
+    #[test]
+    fn test_tar_extraction() {
+        let (dir, dry_run) = fixture();
+        let status = Process::new("tar")
+            .args(["-cf", "logs.tar", "a.log", "sub"])
+            .current_dir(dir.path())
+            .status();
+        if !status.is_ok_and(|s| s.success()) {
+            eprintln!("skipping: tar is not available");
+            return;
+        }
+        assert_eq!(
+            plan(&dry_run, "tar xf logs.tar"),
+            vec![
+                ("a.log".into(), Operation::Modify),
+                ("sub/c.log".into(), Operation::Modify),
+            ]
+        );
+        assert_eq!(
+            plan(&dry_run, "tar -x -f logs.tar -C out sub"),
+            vec![
+                ("out/sub".into(), Operation::Create),
+                ("out/sub/c.log".into(), Operation::Create),
+            ]
+        );
+        assert_eq!(plan(&dry_run, "tar -tf logs.tar"), vec![]);
+        assert!(dry_run.preview("tar -czf new.tgz a.log").is_none());
+    }
+
+    #[test]
+    fn test_other_commands_need_a_sandbox() {
+        let (_dir, dry_run) = fixture();
+        for command in [
+            "echo hi > a.log",
+            "rm $FILE",
+            "rm a.log && rm b.log",
+            "ls | xargs rm",
+            "touch new",
+            "rm ~other/file",
+            "rm (",
+        ] {
+            assert!(dry_run.preview(command).is_none(), "{command}");
+        }
+    }
+
+    #[test]
+    fn test_from_sandbox() {
+        let preview = Preview::from_sandbox(&[FileChange {
+            path: "gone".into(),
+            kind: ChangeKind::Deleted,
+        }]);
+        assert_eq!(preview.source, PreviewSource::Sandbox);
+        assert_eq!(
+            preview.changes,
+            vec![PlannedChange::new("gone", Operation::Delete)]
+        );
+    }
+}
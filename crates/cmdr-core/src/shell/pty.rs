@@ -0,0 +1,273 @@
+//! Pseudo-terminal execution
+//!
+//! Editors, pagers, full-screen programs and password prompts need a
+//! terminal, and many programs only colour their output on one. A command
+//! run on a pseudo-terminal gets it as its controlling terminal and stdio.
+//! cmdr relays keystrokes to it with its own terminal in raw mode, copies
+//! window size changes across, and records everything the command writes
+//! as a transcript.
+
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+
+/// Whether commands run on a pseudo-terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PtyMode {
+    /// Always use pipes
+    Never,
+    /// Use a pseudo-terminal for programs that need one, as detected by
+    /// [`RiskAnalyzer`](super::RiskAnalyzer), when cmdr runs in a terminal
+    #[default]
+    Auto,
+    /// Always use a pseudo-terminal
+    Always,
+}
+
+impl std::str::FromStr for PtyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            _ => Err(format!("expected never, auto or always, got {s:?}")),
+        }
+    }
+}
+
+/// Whether cmdr's own stdin and stdout are a terminal
+pub(super) fn in_terminal() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+#[cfg(unix)]
+pub(super) use session::PtySession;
+
+#[cfg(unix)]
+mod session {
+    use super::in_terminal;
+    use crate::shell::output::{Received, CHUNK_SIZE};
+    use crate::shell::{OutputChunk, OutputStream};
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    /// Size of a pseudo-terminal when cmdr is not running in one
+    const DEFAULT_SIZE: libc::winsize = libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    /// The cmdr side of a command's pseudo-terminal
+    ///
+    /// Dropping the session restores cmdr's terminal.
+    pub(in crate::shell) struct PtySession {
+        master: File,
+        /// cmdr's terminal, when keystrokes are relayed from it
+        terminal: Option<RawTerminal>,
+        started: Instant,
+    }
+
+    impl PtySession {
+        /// Open a pseudo-terminal and make it `process`'s controlling
+        /// terminal and stdio
+        ///
+        /// The pseudo-terminal is sized like cmdr's terminal. When cmdr runs
+        /// in a terminal, that terminal is put in raw mode so keystrokes,
+        /// including Ctrl-C, reach the command unchanged.
+        pub(in crate::shell) fn attach(
+            process: &mut Command,
+            started: Instant,
+        ) -> io::Result<Self> {
+            let terminal_size = window_size(libc::STDOUT_FILENO);
+            let size = terminal_size.unwrap_or(DEFAULT_SIZE);
+            let (mut master, mut slave) = (-1, -1);
+            // SAFETY: `openpty` stores two new descriptors in `master` and
+            // `slave`; the name may be null and `size` outlives the call.
+            let opened = unsafe {
+                libc::openpty(
+                    &mut master,
+                    &mut slave,
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    &size,
+                )
+            };
+            if opened != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: both descriptors were just opened and nothing else
+            // owns them.
+            let (master, slave) =
+                unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+            close_on_exec(master.as_raw_fd())?;
+            close_on_exec(slave.as_raw_fd())?;
+
+            process
+                .stdin(Stdio::from(slave.try_clone()?))
+                .stdout(Stdio::from(slave.try_clone()?))
+                .stderr(Stdio::from(slave));
+            // SAFETY: `setsid` and `ioctl` are async-signal-safe.
+            unsafe {
+                process.pre_exec(|| {
+                    // A new session, so the pseudo-terminal can become its
+                    // controlling terminal; it also leads a new process group
+                    if libc::setsid() < 0 || libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) < 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+
+            let terminal = match terminal_size {
+                Some(size) if in_terminal() => Some(RawTerminal::enter(size)?),
+                _ => None,
+            };
+            Ok(Self {
+                master: File::from(master),
+                terminal,
+                started,
+            })
+        }
+
+        /// Wait up to `timeout` for output, relaying keystrokes meanwhile
+        pub(in crate::shell) fn receive(&mut self, timeout: Duration) -> Received {
+            if let Some(terminal) = &mut self.terminal {
+                terminal.sync_size(self.master.as_raw_fd());
+            }
+            let input = match self.terminal {
+                Some(_) => libc::STDIN_FILENO,
+                // poll ignores negative descriptors
+                None => -1,
+            };
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.master.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: input,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            let timeout = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+            // SAFETY: `fds` is an array of two initialized `pollfd`s.
+            if unsafe { libc::poll(fds.as_mut_ptr(), 2, timeout) } < 0 {
+                return match io::Error::last_os_error().kind() {
+                    io::ErrorKind::Interrupted => Received::Nothing,
+                    _ => Received::Closed,
+                };
+            }
+
+            if fds[1].revents & libc::POLLIN != 0 {
+                let mut buf = [0u8; 1024];
+                // Read the descriptor directly: data left in the buffer of
+                // `io::stdin()` would be lost to whatever reads it next
+                // SAFETY: `buf` is valid for writes of its length.
+                let len = unsafe { libc::read(input, buf.as_mut_ptr().cast(), buf.len()) };
+                if let Ok(len @ 1..) = usize::try_from(len) {
+                    let _ = self.master.write_all(&buf[..len]);
+                }
+            }
+            if fds[0].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) == 0 {
+                return Received::Nothing;
+            }
+            let mut buf = [0u8; CHUNK_SIZE];
+            match self.master.read(&mut buf) {
+                Ok(0) => Received::Closed,
+                Ok(len) => Received::Chunk(OutputChunk {
+                    stream: OutputStream::Stdout,
+                    data: buf[..len].to_vec(),
+                    elapsed: self.started.elapsed(),
+                }),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => Received::Nothing,
+                // EIO once every process has closed the terminal
+                Err(_) => Received::Closed,
+            }
+        }
+    }
+
+    /// cmdr's terminal in raw mode, restored when dropped
+    struct RawTerminal {
+        original: libc::termios,
+        size: libc::winsize,
+    }
+
+    impl RawTerminal {
+        fn enter(size: libc::winsize) -> io::Result<Self> {
+            // SAFETY: an all-zero `termios` is a valid value to overwrite.
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: `original` is valid for writes.
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            // SAFETY: `raw` is a valid `termios`.
+            unsafe { libc::cfmakeraw(&mut raw) };
+            // SAFETY: `raw` is a valid `termios`.
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { original, size })
+        }
+
+        /// Copy a change of cmdr's window size to the pseudo-terminal, which
+        /// sends `SIGWINCH` to the command
+        fn sync_size(&mut self, master: RawFd) {
+            let Some(size) = window_size(libc::STDOUT_FILENO) else {
+                return;
+            };
+            if (size.ws_row, size.ws_col) != (self.size.ws_row, self.size.ws_col) {
+                // SAFETY: `size` is a valid `winsize`.
+                unsafe { libc::ioctl(master, libc::TIOCSWINSZ, &size) };
+                self.size = size;
+            }
+        }
+    }
+
+    impl Drop for RawTerminal {
+        fn drop(&mut self) {
+            // SAFETY: `original` is the `termios` read in `enter`.
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+        }
+    }
+
+    /// The window size of the terminal `fd`, if it is one
+    fn window_size(fd: RawFd) -> Option<libc::winsize> {
+        let mut size = DEFAULT_SIZE;
+        // SAFETY: `size` is valid for writes.
+        let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+        (result == 0 && size.ws_row > 0 && size.ws_col > 0).then_some(size)
+    }
+
+    fn close_on_exec(fd: RawFd) -> io::Result<()> {
+        // SAFETY: `fd` is an open descriptor.
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pty_mode() {
+        assert_eq!("always".parse(), Ok(PtyMode::Always));
+        assert_eq!("never".parse(), Ok(PtyMode::Never));
+        assert!("sometimes".parse::<PtyMode>().is_err());
+        assert_eq!(PtyMode::default(), PtyMode::Auto);
+    }
+}
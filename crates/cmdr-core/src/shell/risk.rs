@@ -0,0 +1,2442 @@
+//! Risk analysis of shell commands
+//!
+//! The analyzer walks the syntax tree produced by [`CommandParser`] and
+//! reports what each command may do, from harmless reads to wiping disks.
+//! It understands flag permutations (`rm -r -f`, `rm -fr`, `rm --force
+//! --recursive`) and looks through wrappers that run other commands:
+//! `sudo`, `env`, `xargs`, `busybox`, `watch`, `ssh host ...`, `find -exec`,
+//! `sh -c`, `eval` and pipelines feeding a shell or interpreter. Code a
+//! shell reads from a pipe or file is analyzed when it is known up front,
+//! such as the arguments of `echo` or a here-document, and otherwise rated
+//! as destructive, as is a program name produced by a command substitution.
+
+use super::ast::*;
+use super::{CommandParser, ParseError, ShellError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How deeply wrappers such as `sudo sh -c '...'` are followed
+pub(super) const MAX_DEPTH: usize = 8;
+
+/// Shells that run a command string with `-c` or a script from stdin
+pub(super) const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "mksh", "fish", "ash"];
+
+/// Programs that run the command given in their arguments
+pub(super) const WRAPPERS: &[&str] = &[
+    "sudo",
+    "doas",
+    "pkexec",
+    "run0",
+    "env",
+    "nohup",
+    "command",
+    "exec",
+    "setsid",
+    "nice",
+    "ionice",
+    "stdbuf",
+    "time",
+    "caffeinate",
+    "unbuffer",
+    "timeout",
+    "xargs",
+    "busybox",
+];
+
+/// Interpreters that run a script from stdin when given no file
+const INTERPRETERS: &[&str] = &[
+    "python", "python2", "python3", "perl", "ruby", "node", "php", "lua",
+];
+
+/// Options of `ssh` that take a value
+const SSH_OPTIONS_WITH_VALUE: &[&str] = &[
+    "-p", "-i", "-l", "-o", "-F", "-J", "-L", "-R", "-D", "-W", "-b", "-c", "-E", "-m", "-O", "-Q",
+    "-S", "-w",
+];
+
+/// Calls by which interpreter code runs a shell command given as a string,
+/// as in `os.system("...")` or Perl's `system "..."`
+const SHELL_CALLS: &[&str] = &[
+    "system",
+    "popen",
+    "getoutput",
+    "check_output",
+    "execSync",
+    "exec",
+];
+
+/// Names in interpreter code that start programs or delete data, which
+/// make inline code worth confirming when no command can be extracted
+const DANGEROUS_CALLS: &[&str] = &[
+    "system",
+    "popen",
+    "subprocess",
+    "exec",
+    "spawn",
+    "child_process",
+    "qx",
+    "`",
+    "remove",
+    "unlink",
+    "rmtree",
+    "rmdir",
+    "kill",
+    "truncate",
+];
+
+/// How risky a command is, from least to most severe
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    /// Only reads files or prints information
+    #[default]
+    Safe,
+    /// Creates or changes files or other local state that can be restored
+    ModifiesFiles,
+    /// Talks to other machines
+    Network,
+    /// Needs root or changes system-wide configuration
+    Privileged,
+    /// Deletes data or kills processes
+    Destructive,
+    /// Destroys data beyond recovery, e.g. overwrites disks
+    Irreversible,
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RiskLevel::Safe => "safe",
+            RiskLevel::ModifiesFiles => "modifies files",
+            RiskLevel::Network => "network access",
+            RiskLevel::Privileged => "privileged",
+            RiskLevel::Destructive => "destructive",
+            RiskLevel::Irreversible => "irreversible",
+        })
+    }
+}
+
+/// One risky effect of a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskFinding {
+    pub level: RiskLevel,
+    /// Human-readable description, e.g. "recursively deletes /"
+    pub reason: String,
+    /// The part of the command responsible
+    pub span: Span,
+}
+
+/// The result of analyzing a command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RiskAssessment {
+    /// The most severe level among the findings
+    pub level: RiskLevel,
+    pub findings: Vec<RiskFinding>,
+    /// Set if the command could not be parsed, in which case its effects
+    /// are unknown
+    pub parse_error: Option<ParseError>,
+    /// The first program found that needs a terminal, such as an editor,
+    /// a pager or a password prompt
+    pub interactive: Option<String>,
+}
+
+impl RiskAssessment {
+    fn from_findings(findings: Vec<RiskFinding>) -> Self {
+        Self {
+            level: findings
+                .iter()
+                .map(|finding| finding.level)
+                .max()
+                .unwrap_or_default(),
+            findings,
+            parse_error: None,
+            interactive: None,
+        }
+    }
+
+    /// Whether the command deletes or destroys data
+    pub fn is_dangerous(&self) -> bool {
+        self.level >= RiskLevel::Destructive
+    }
+
+    /// The reasons of all findings, most severe first
+    pub fn reasons(&self) -> Vec<&str> {
+        let mut findings: Vec<&RiskFinding> = self.findings.iter().collect();
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.level));
+        findings
+            .into_iter()
+            .map(|finding| finding.reason.as_str())
+            .collect()
+    }
+}
+
+/// Classifies shell commands by what they may do
+pub struct RiskAnalyzer;
+
+impl RiskAnalyzer {
+    /// Analyze a command line
+    ///
+    /// Commands that cannot be parsed, such as those using bash process
+    /// substitution or fish syntax, are rated [`RiskLevel::Destructive`]
+    /// with the parse error attached: nothing is known about them, so they
+    /// must not slip under the thresholds that ask for confirmation.
+    pub fn analyze(command: &str) -> RiskAssessment {
+        match CommandParser::parse(command) {
+            Ok(script) => Self::analyze_script(&script),
+            Err(ShellError::Syntax(error)) => RiskAssessment {
+                level: RiskLevel::Destructive,
+                findings: vec![RiskFinding {
+                    level: RiskLevel::Destructive,
+                    reason: format!("could not be parsed ({error}), so its effects are unknown"),
+                    span: Span::new(0, command.len()),
+                }],
+                parse_error: Some(error),
+                interactive: None,
+            },
+            Err(_) => RiskAssessment::default(),
+        }
+    }
+
+    /// Analyze a parsed command line
+    pub fn analyze_script(script: &Script) -> RiskAssessment {
+        let mut walker = Walker::new(script, 0, None);
+        walker.list(&script.list);
+        RiskAssessment {
+            interactive: walker.interactive,
+            ..RiskAssessment::from_findings(walker.findings)
+        }
+    }
+}
+
+/// A word reduced to what the rules look at
+#[derive(Debug, Clone)]
+pub(super) struct Arg {
+    /// The value after quote removal, or the source text if it has expansions
+    pub(super) text: String,
+    pub(super) literal: bool,
+    /// Whether a command substitution in the word downloads data
+    downloads: bool,
+    /// Whether the word contains a command substitution
+    substitutes: bool,
+}
+
+impl Arg {
+    /// An argument whose value is known, e.g. after expansion
+    pub(super) fn literal(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            literal: true,
+            downloads: false,
+            substitutes: false,
+        }
+    }
+}
+
+/// Where a command reads its standard input from
+#[derive(Debug, Clone, Default)]
+enum Input {
+    /// Whatever the command line itself was given, usually the terminal
+    #[default]
+    Inherited,
+    /// Text known before the command runs, such as a here-document
+    Known(String),
+    /// A pipe or file whose contents are not known
+    Unknown,
+}
+
+/// Options and operands of a command line
+#[derive(Debug, Default)]
+pub(super) struct Options<'a> {
+    short: Vec<char>,
+    long: Vec<String>,
+    values: Vec<(String, String)>,
+    pub(super) operands: Vec<&'a Arg>,
+}
+
+impl<'a> Options<'a> {
+    /// Split `args`; options named in `with_value` (e.g. `-o`, `--output`)
+    /// take the next argument as their value
+    pub(super) fn parse(args: &'a [Arg], with_value: &[&str]) -> Self {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        let mut after_double_dash = false;
+        while let Some(arg) = args.next() {
+            let text = arg.text.as_str();
+            if after_double_dash || !arg.literal || text == "-" || !text.starts_with('-') {
+                options.operands.push(arg);
+            } else if text == "--" {
+                after_double_dash = true;
+            } else if let Some(long) = text.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let option = format!("--{name}");
+                let value = match value {
+                    Some(value) => Some(value),
+                    None if with_value.contains(&option.as_str()) => {
+                        args.next().map(|arg| arg.text.clone())
+                    }
+                    None => None,
+                };
+                if let Some(value) = value {
+                    options.values.push((option, value));
+                }
+                options.long.push(name.to_string());
+            } else {
+                for (i, c) in text.char_indices().skip(1) {
+                    options.short.push(c);
+                    let option = format!("-{c}");
+                    if with_value.contains(&option.as_str()) {
+                        let attached = &text[i + c.len_utf8()..];
+                        let value = if attached.is_empty() {
+                            args.next().map(|arg| arg.text.clone())
+                        } else {
+                            Some(attached.to_string())
+                        };
+                        if let Some(value) = value {
+                            options.values.push((option, value));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        options
+    }
+
+    pub(super) fn has(&self, short: char, long: &str) -> bool {
+        self.short.contains(&short) || self.long.iter().any(|l| l == long)
+    }
+
+    pub(super) fn has_long(&self, long: &str) -> bool {
+        self.long.iter().any(|l| l == long)
+    }
+
+    pub(super) fn value(&self, names: &[&str]) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(super) fn operand_texts(&self) -> Vec<&str> {
+        self.operands.iter().map(|arg| arg.text.as_str()).collect()
+    }
+}
+
+/// Walks a syntax tree collecting findings
+struct Walker<'s> {
+    script: &'s Script,
+    findings: Vec<RiskFinding>,
+    interactive: Option<String>,
+    depth: usize,
+    /// Where findings are reported when analyzing a command string nested
+    /// in another command, e.g. the argument of `sh -c`
+    outer_span: Option<Span>,
+    /// Where the command being analyzed reads its standard input from
+    stdin: Input,
+}
+
+impl<'s> Walker<'s> {
+    fn new(script: &'s Script, depth: usize, outer_span: Option<Span>) -> Self {
+        Self {
+            script,
+            findings: Vec::new(),
+            interactive: None,
+            depth,
+            outer_span,
+            stdin: Input::Inherited,
+        }
+    }
+
+    fn report(&mut self, level: RiskLevel, reason: impl Into<String>, span: Span) {
+        let reason = reason.into();
+        let span = self.outer_span.unwrap_or(span);
+        if !self
+            .findings
+            .iter()
+            .any(|finding| finding.level == level && finding.reason == reason)
+        {
+            self.findings.push(RiskFinding {
+                level,
+                reason,
+                span,
+            });
+        }
+    }
+
+    // Tree traversal
+
+    fn list(&mut self, list: &List) {
+        for item in &list.items {
+            for pipeline in item.and_or.pipelines() {
+                self.pipeline(pipeline);
+            }
+        }
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline) {
+        // Downloaded data keeps flowing through filters such as `tee`
+        let mut network_input = false;
+        let outer = self.stdin.clone();
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            if i > 0 {
+                self.stdin =
+                    known_output(&pipeline.commands[i - 1]).map_or(Input::Unknown, Input::Known);
+            }
+            network_input |= self.command(command, network_input);
+        }
+        self.stdin = outer;
+    }
+
+    /// Analyze a command; returns whether it writes downloaded data to its
+    /// standard output
+    fn command(&mut self, command: &Command, network_input: bool) -> bool {
+        match command {
+            Command::Simple(simple) => self.simple(simple, network_input),
+            Command::Compound(compound) => {
+                self.compound(compound);
+                false
+            }
+            Command::FunctionDefinition(function) => {
+                self.fork_bomb(function);
+                self.compound(&function.body);
+                false
+            }
+        }
+    }
+
+    fn compound(&mut self, compound: &CompoundCommand) {
+        self.redirects(&compound.redirects);
+        let outer = self.stdin.clone();
+        if let Some(input) = redirected_input(&compound.redirects) {
+            self.stdin = input;
+        }
+        self.compound_body(compound);
+        self.stdin = outer;
+    }
+
+    fn compound_body(&mut self, compound: &CompoundCommand) {
+        match &compound.kind {
+            CompoundKind::BraceGroup(body) | CompoundKind::Subshell(body) => self.list(body),
+            CompoundKind::For { words, body, .. } => {
+                for word in words.iter().flatten() {
+                    self.substitutions(&word.parts);
+                }
+                self.list(body);
+            }
+            CompoundKind::Case { word, arms } => {
+                self.substitutions(&word.parts);
+                for arm in arms {
+                    self.list(&arm.body);
+                }
+            }
+            CompoundKind::If {
+                condition,
+                then,
+                elifs,
+                otherwise,
+            } => {
+                self.list(condition);
+                self.list(then);
+                for (condition, body) in elifs {
+                    self.list(condition);
+                    self.list(body);
+                }
+                if let Some(otherwise) = otherwise {
+                    self.list(otherwise);
+                }
+            }
+            CompoundKind::While { condition, body } | CompoundKind::Until { condition, body } => {
+                self.list(condition);
+                self.list(body);
+            }
+        }
+    }
+
+    fn simple(&mut self, command: &SimpleCommand, network_input: bool) -> bool {
+        for assignment in &command.assignments {
+            self.substitutions(&assignment.value.parts);
+        }
+        for word in &command.words {
+            self.substitutions(&word.parts);
+        }
+        self.redirects(&command.redirects);
+
+        let argv: Vec<Arg> = command.words.iter().map(|word| self.arg(word)).collect();
+        let outer = self.stdin.clone();
+        if let Some(input) = redirected_input(&command.redirects) {
+            self.stdin = input;
+        }
+        let downloads = self.argv(&argv, network_input, command.span);
+        self.stdin = outer;
+        downloads
+    }
+
+    /// Analyze the commands run by `$(...)` and `` `...` `` in a word
+    fn substitutions(&mut self, parts: &[WordPart]) {
+        for part in parts {
+            match part {
+                WordPart::CommandSubstitution(list) => self.list(list),
+                WordPart::DoubleQuoted(parts) => self.substitutions(parts),
+                WordPart::Parameter(ParameterExpansion {
+                    argument: Some(argument),
+                    ..
+                }) => self.substitutions(&argument.parts),
+                _ => {}
+            }
+        }
+    }
+
+    fn redirects(&mut self, redirects: &[Redirect]) {
+        for redirect in redirects {
+            self.substitutions(&redirect.target.parts);
+            if redirect.kind.writes() {
+                let target = self.arg(&redirect.target);
+                let verb = if redirect.kind == RedirectKind::Append {
+                    "appends to"
+                } else {
+                    "overwrites"
+                };
+                self.write_target(&target.text, verb, redirect.span);
+            }
+        }
+    }
+
+    fn arg(&self, word: &Word) -> Arg {
+        let downloads = downloads(&word.parts);
+        match word.literal() {
+            Some(text) => Arg {
+                text,
+                literal: true,
+                downloads,
+                substitutes: false,
+            },
+            None => Arg {
+                text: self.script.text(word.span).to_string(),
+                literal: false,
+                downloads,
+                substitutes: substitutes(&word.parts),
+            },
+        }
+    }
+
+    /// Analyze a command string, e.g. the argument of `sh -c`
+    fn shell_string(&mut self, command: &str, span: Span) {
+        let script = match CommandParser::parse(command) {
+            Ok(script) => script,
+            Err(ShellError::Syntax(_)) => {
+                self.report(
+                    RiskLevel::Destructive,
+                    "runs a shell command that could not be parsed",
+                    span,
+                );
+                return;
+            }
+            Err(_) => return,
+        };
+        if self.depth >= MAX_DEPTH {
+            self.report(
+                RiskLevel::ModifiesFiles,
+                "nests commands too deeply to analyze",
+                span,
+            );
+            return;
+        }
+        let mut nested = Walker::new(
+            &script,
+            self.depth + 1,
+            Some(self.outer_span.unwrap_or(span)),
+        );
+        nested.stdin = self.stdin.clone();
+        nested.list(&script.list);
+        for finding in nested.findings {
+            self.report(finding.level, finding.reason, finding.span);
+        }
+        self.interactive = self.interactive.take().or(nested.interactive);
+    }
+
+    /// Analyze a command run by a wrapper such as `sudo` or `xargs`
+    fn nested(&mut self, argv: &[Arg], network_input: bool, span: Span) -> bool {
+        if self.depth >= MAX_DEPTH {
+            self.report(
+                RiskLevel::ModifiesFiles,
+                "nests commands too deeply to analyze",
+                span,
+            );
+            return false;
+        }
+        self.depth += 1;
+        let downloads = self.argv(argv, network_input, span);
+        self.depth -= 1;
+        downloads
+    }
+
+    fn fork_bomb(&mut self, function: &FunctionDefinition) {
+        let mut calls = Vec::new();
+        let mut spawns = false;
+        collect_calls(&function.body, &mut calls, &mut spawns);
+        if spawns && calls.contains(&function.name) {
+            self.report(
+                RiskLevel::Destructive,
+                format!(
+                    "defines `{}`, a fork bomb that spawns copies of itself until the system runs out of processes",
+                    function.name
+                ),
+                function.span,
+            );
+        }
+    }
+
+    // Effects shared by several rules
+
+    fn write_target(&mut self, path: &str, verb: &str, span: Span) {
+        if is_disk_device(path) {
+            self.report(
+                RiskLevel::Irreversible,
+                format!("{verb} the disk device {path}"),
+                span,
+            );
+        } else if is_harmless_device(path) {
+        } else if path.starts_with("/dev/") {
+            self.report(
+                RiskLevel::Privileged,
+                format!("{verb} the device {path}"),
+                span,
+            );
+        } else if is_system_path(path) {
+            self.report(
+                RiskLevel::Privileged,
+                format!("{verb} the system file {path}"),
+                span,
+            );
+        } else {
+            self.report(RiskLevel::ModifiesFiles, format!("{verb} {path}"), span);
+        }
+    }
+
+    /// Report a change to `paths`, escalating for system paths
+    fn modifies(&mut self, verb: &str, paths: &[&str], span: Span) {
+        let (system, other): (Vec<&str>, Vec<&str>) =
+            paths.iter().partition(|path| is_system_path(path));
+        if !system.is_empty() {
+            self.report(
+                RiskLevel::Privileged,
+                format!("{verb} system files: {}", join(&system)),
+                span,
+            );
+        }
+        if !other.is_empty() || paths.is_empty() {
+            self.report(
+                RiskLevel::ModifiesFiles,
+                format!("{verb} {}", describe(&other)),
+                span,
+            );
+        }
+    }
+
+    // Command rules
+
+    /// Analyze one command by name; returns whether it writes downloaded
+    /// data to its standard output
+    fn argv(&mut self, argv: &[Arg], network_input: bool, span: Span) -> bool {
+        let Some(first) = argv.first() else {
+            return false;
+        };
+        let args = &argv[1..];
+        if !first.literal {
+            // The output of a command substitution could be any program
+            let level = if first.downloads || first.substitutes {
+                RiskLevel::Destructive
+            } else {
+                RiskLevel::ModifiesFiles
+            };
+            self.report(
+                level,
+                format!("runs `{}`, a command only known at run time", first.text),
+                span,
+            );
+            return false;
+        }
+        let name = first.text.rsplit('/').next().unwrap_or(&first.text);
+        if self.interactive.is_none() && needs_terminal(name, args) {
+            self.interactive = Some(name.to_string());
+        }
+
+        match name {
+            // Wrappers
+            "sudo" | "doas" | "pkexec" | "run0" => {
+                let rest = skip_options(
+                    args,
+                    &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-T", "-U"],
+                );
+                if rest.is_empty() {
+                    self.report(RiskLevel::Privileged, "opens a root shell", span);
+                    return false;
+                }
+                self.report(
+                    RiskLevel::Privileged,
+                    format!("runs `{}` with root privileges", rest[0].text),
+                    span,
+                );
+                self.nested(rest, network_input, span)
+            }
+            "su" => {
+                self.report(RiskLevel::Privileged, "switches to another user", span);
+                let options = Options::parse(args, &["-c", "--command", "-s", "--shell"]);
+                if let Some(command) = options.value(&["-c", "--command"]) {
+                    self.shell_string(command, span);
+                }
+                false
+            }
+            "env" => {
+                let mut rest = skip_options(args, &["-u", "-C", "-S"]);
+                while rest.first().is_some_and(|arg| arg.text.contains('=')) {
+                    rest = &rest[1..];
+                }
+                self.nested(rest, network_input, span)
+            }
+            "nohup" | "command" | "exec" | "setsid" | "nice" | "ionice" | "stdbuf" | "time"
+            | "caffeinate" | "unbuffer" | "busybox" => {
+                let rest = skip_options(args, &["-n", "-c", "-p", "-i", "-o", "-e", "-f"]);
+                self.nested(rest, network_input, span)
+            }
+            "timeout" => {
+                let rest = skip_options(args, &["-s", "-k", "--signal", "--kill-after"]);
+                self.nested(rest.get(1..).unwrap_or_default(), network_input, span)
+            }
+            "xargs" => {
+                let rest = skip_options(
+                    args,
+                    &[
+                        "-I",
+                        "-n",
+                        "-P",
+                        "-L",
+                        "-d",
+                        "-E",
+                        "-s",
+                        "-a",
+                        "--max-args",
+                        "--max-procs",
+                        "--delimiter",
+                        "--arg-file",
+                    ],
+                );
+                // The command gets its arguments, not the input xargs reads
+                let outer = std::mem::take(&mut self.stdin);
+                let downloads = self.nested(rest, false, span);
+                self.stdin = outer;
+                downloads
+            }
+            "watch" => {
+                // Without `-x`, watch runs its arguments with `sh -c`
+                let rest = skip_options(args, &["-n", "--interval", "-q", "--equexit"]);
+                if rest.iter().all(|arg| arg.literal) {
+                    let command: Vec<&str> = rest.iter().map(|arg| arg.text.as_str()).collect();
+                    self.shell_string(&command.join(" "), span);
+                    false
+                } else {
+                    self.nested(rest, network_input, span)
+                }
+            }
+            "find" => {
+                self.find(args, span);
+                false
+            }
+            "eval" => {
+                if args.iter().any(|arg| arg.downloads) {
+                    self.report(
+                        RiskLevel::Destructive,
+                        "runs code downloaded from the network",
+                        span,
+                    );
+                } else if args.iter().all(|arg| arg.literal) {
+                    let command: Vec<&str> = args.iter().map(|arg| arg.text.as_str()).collect();
+                    self.shell_string(&command.join(" "), span);
+                } else {
+                    self.report(
+                        RiskLevel::ModifiesFiles,
+                        "evaluates a command built at run time",
+                        span,
+                    );
+                }
+                false
+            }
+            _ if SHELLS.contains(&name) => {
+                self.shell(args, network_input, span);
+                false
+            }
+            _ if INTERPRETERS.contains(&name) => {
+                let options = Options::parse(args, &["-e", "-c", "-m", "-E", "-i", "-r"]);
+                let code = options.value(&["-e", "-c", "-E", "-r", "--eval", "--command"]);
+                let reads_stdin = code.is_none()
+                    && !options.has('m', "module")
+                    && options.operands.iter().all(|arg| arg.text == "-");
+                if let Some(code) = code {
+                    self.inline_code(name, code, span);
+                } else if reads_stdin && network_input {
+                    self.report(
+                        RiskLevel::Destructive,
+                        "runs a script downloaded from the network",
+                        span,
+                    );
+                } else if reads_stdin && !matches!(self.stdin, Input::Inherited) {
+                    self.report(
+                        RiskLevel::Destructive,
+                        format!("runs a {name} script read from its input, whose effects are not analyzed"),
+                        span,
+                    );
+                }
+                if name == "perl" && options.short.contains(&'i') {
+                    self.modifies("edits in place", &options.operand_texts(), span);
+                }
+                false
+            }
+
+            // Deleting and destroying data
+            "rm" | "unlink" => {
+                self.rm(args, span);
+                false
+            }
+            "rmdir" => {
+                let options = Options::parse(args, &[]);
+                self.modifies(
+                    "removes the empty directories",
+                    &options.operand_texts(),
+                    span,
+                );
+                false
+            }
+            "shred" => {
+                let options = Options::parse(args, &["-n", "-s", "--iterations", "--size"]);
+                self.report(
+                    RiskLevel::Irreversible,
+                    format!(
+                        "overwrites and destroys {}",
+                        describe(&options.operand_texts())
+                    ),
+                    span,
+                );
+                false
+            }
+            "wipefs" => {
+                let options = Options::parse(args, &["-o", "-t"]);
+                if options.has('a', "all") || !options.values.is_empty() {
+                    self.report(
+                        RiskLevel::Irreversible,
+                        format!(
+                            "erases filesystem signatures on {}",
+                            describe(&options.operand_texts())
+                        ),
+                        span,
+                    );
+                }
+                false
+            }
+            _ if name.starts_with("mkfs") || matches!(name, "mke2fs" | "mkswap" | "mkntfs") => {
+                let options = Options::parse(args, &["-t", "-L", "-b", "-n"]);
+                self.report(
+                    RiskLevel::Irreversible,
+                    format!(
+                        "formats {}, erasing everything on it",
+                        describe(&options.operand_texts())
+                    ),
+                    span,
+                );
+                false
+            }
+            "fdisk" | "sfdisk" | "cfdisk" | "gdisk" | "sgdisk" | "parted" => {
+                let options = Options::parse(args, &[]);
+                if !options.has('l', "list") {
+                    self.report(
+                        RiskLevel::Irreversible,
+                        format!("repartitions {}", describe(&options.operand_texts())),
+                        span,
+                    );
+                }
+                false
+            }
+            "dd" => {
+                if let Some(path) = args.iter().find_map(|arg| arg.text.strip_prefix("of=")) {
+                    self.write_target(path, "overwrites", span);
+                }
+                false
+            }
+            "truncate" => {
+                let options = Options::parse(args, &["-s", "-r", "--size", "--reference"]);
+                self.report(
+                    RiskLevel::Destructive,
+                    format!("truncates {}", describe(&options.operand_texts())),
+                    span,
+                );
+                false
+            }
+
+            // Changing files
+            "chmod" | "chown" | "chgrp" => {
+                self.change_attributes(name, args, span);
+                false
+            }
+            "mv" => {
+                let options = Options::parse(args, &["-t", "-S", "--target-directory", "--suffix"]);
+                let mut paths = options.operand_texts();
+                let destination = options
+                    .value(&["-t", "--target-directory"])
+                    .or_else(|| (paths.len() > 1).then(|| paths.pop()).flatten());
+                if let Some(critical) = paths.iter().find_map(|path| critical_path(path)) {
+                    self.report(RiskLevel::Destructive, format!("moves {critical}"), span);
+                }
+                if let Some(destination) = destination {
+                    self.write_target(destination, "moves files to", span);
+                }
+                false
+            }
+            "cp" | "install" | "rsync" | "scp" => {
+                let options = Options::parse(
+                    args,
+                    &[
+                        "-t",
+                        "-S",
+                        "-m",
+                        "-o",
+                        "-g",
+                        "-e",
+                        "-P",
+                        "-i",
+                        "-F",
+                        "--target-directory",
+                    ],
+                );
+                let paths = options.operand_texts();
+                let remote = paths.iter().any(|path| is_remote(path));
+                if remote || name == "scp" {
+                    self.report(RiskLevel::Network, "copies files over the network", span);
+                }
+                let destination = options
+                    .value(&["-t", "--target-directory"])
+                    .or_else(|| paths.last().copied().filter(|_| paths.len() > 1));
+                if let Some(destination) = destination.filter(|path| !is_remote(path)) {
+                    self.write_target(destination, "copies files to", span);
+                    if name == "rsync" && options.long.iter().any(|l| l.starts_with("delete")) {
+                        self.report(
+                            RiskLevel::Destructive,
+                            format!(
+                                "deletes files in {destination} that are missing from the source"
+                            ),
+                            span,
+                        );
+                    }
+                }
+                false
+            }
+            "tee" => {
+                let options = Options::parse(args, &[]);
+                let verb = if options.has('a', "append") {
+                    "appends to"
+                } else {
+                    "overwrites"
+                };
+                for path in options.operand_texts() {
+                    self.write_target(path, verb, span);
+                }
+                network_input
+            }
+            "touch" | "mkdir" | "ln" | "mkfifo" | "mknod" | "patch" | "split" => {
+                let options = Options::parse(
+                    args,
+                    &[
+                        "-m", "-t", "-r", "-d", "-p", "-i", "-o", "-b", "-l", "-n", "-a",
+                    ],
+                );
+                let verb = match name {
+                    "touch" => "creates or updates",
+                    "mkdir" => "creates the directories",
+                    "ln" => "creates links",
+                    "patch" => "patches files in",
+                    "split" => "splits files into pieces in",
+                    _ => "creates",
+                };
+                self.modifies(verb, &options.operand_texts(), span);
+                false
+            }
+            "sed" => {
+                let options = Options::parse(args, &["-e", "-f", "--expression", "--file", "-l"]);
+                if options.has('i', "in-place")
+                    || options.long.iter().any(|l| l.starts_with("in-place"))
+                {
+                    let mut files = options.operand_texts();
+                    if options
+                        .value(&["-e", "-f", "--expression", "--file"])
+                        .is_none()
+                        && !files.is_empty()
+                    {
+                        files.remove(0);
+                    }
+                    self.modifies("edits in place", &files, span);
+                }
+                false
+            }
+            "tar" | "bsdtar" => {
+                self.tar(args, span);
+                false
+            }
+            "unzip" | "7z" | "unrar" => {
+                let options = Options::parse(args, &["-d", "-x", "-P", "-o"]);
+                let listing = options.has('l', "list")
+                    || options.short.contains(&'t')
+                    || options.operands.first().is_some_and(|arg| arg.text == "l");
+                if !listing {
+                    let destination = options.value(&["-d", "-o"]).unwrap_or(".");
+                    self.modifies(
+                        &format!("extracts {} into", describe(&options.operand_texts())),
+                        &[destination],
+                        span,
+                    );
+                }
+                false
+            }
+            "gzip" | "gunzip" | "bzip2" | "bunzip2" | "xz" | "unxz" | "zstd" | "unzstd" => {
+                let options = Options::parse(args, &["-S", "-o"]);
+                if !options.has('c', "stdout")
+                    && !options.has('l', "list")
+                    && !options.has('t', "test")
+                {
+                    self.modifies(
+                        "replaces with compressed or decompressed copies:",
+                        &options.operand_texts(),
+                        span,
+                    );
+                }
+                false
+            }
+            "git" => {
+                self.git(args, span);
+                false
+            }
+
+            // Network
+            "curl" => self.curl(args, span),
+            "wget" => self.wget(args, span),
+            "ssh" | "mosh" => {
+                let options = Options::parse(args, SSH_OPTIONS_WITH_VALUE);
+                self.report(
+                    RiskLevel::Network,
+                    format!(
+                        "connects to {}",
+                        describe(&options.operand_texts()[..options.operands.len().min(1)])
+                    ),
+                    span,
+                );
+                // A command after the host runs in the remote shell
+                let remote = skip_options(args, SSH_OPTIONS_WITH_VALUE)
+                    .get(1..)
+                    .unwrap_or_default();
+                if remote.iter().all(|arg| arg.literal) && !remote.is_empty() {
+                    let command: Vec<&str> = remote.iter().map(|arg| arg.text.as_str()).collect();
+                    self.shell_string(&command.join(" "), span);
+                } else if !remote.is_empty() {
+                    self.report(
+                        RiskLevel::Destructive,
+                        "runs a remote command built at run time",
+                        span,
+                    );
+                }
+                true
+            }
+            "sftp" | "ftp" | "telnet" | "nc" | "ncat" | "netcat" | "socat" => {
+                self.report(RiskLevel::Network, "opens a network connection", span);
+                true
+            }
+            "http" | "https" | "xh" | "aria2c" | "fetch" => {
+                self.report(RiskLevel::Network, "downloads data from the network", span);
+                true
+            }
+            "ping" | "dig" | "nslookup" | "host" | "traceroute" | "whois" | "nmap" => {
+                self.report(RiskLevel::Network, "sends network requests", span);
+                false
+            }
+
+            // Packages
+            "apt" | "apt-get" | "dnf" | "yum" | "zypper" | "apk" | "snap" | "flatpak" | "port"
+            | "pacman" => {
+                self.system_packages(name, args, span);
+                false
+            }
+            "brew" | "pip" | "pip3" | "pipx" | "npm" | "pnpm" | "yarn" | "gem" | "cargo" | "go"
+            | "composer" => {
+                self.user_packages(name, args, span);
+                false
+            }
+
+            // System administration
+            "shutdown" | "reboot" | "halt" | "poweroff" => {
+                self.report(
+                    RiskLevel::Destructive,
+                    "shuts down or restarts the machine",
+                    span,
+                );
+                false
+            }
+            "systemctl" | "service" | "launchctl" => {
+                let options = Options::parse(args, &["-H", "-M", "-p", "-t", "-s", "-n", "-o"]);
+                let operands = options.operand_texts();
+                let action = if name == "service" {
+                    operands.get(1)
+                } else {
+                    operands.first()
+                };
+                match action.copied() {
+                    Some("poweroff" | "reboot" | "halt" | "kexec" | "suspend" | "hibernate") => {
+                        self.report(
+                            RiskLevel::Destructive,
+                            "shuts down or restarts the machine",
+                            span,
+                        );
+                    }
+                    Some(
+                        "status" | "show" | "cat" | "list-units" | "list-unit-files" | "list"
+                        | "is-active" | "is-enabled" | "is-failed" | "help" | "print",
+                    )
+                    | None => {}
+                    Some(action) => self.report(
+                        RiskLevel::Privileged,
+                        format!("changes system services ({name} {action})"),
+                        span,
+                    ),
+                }
+                false
+            }
+            "mount" | "umount" | "swapon" | "swapoff" | "modprobe" | "insmod" | "rmmod"
+            | "iptables" | "ip6tables" | "nft" | "ufw" | "useradd" | "userdel" | "usermod"
+            | "groupadd" | "groupdel" | "passwd" | "chpasswd" | "visudo" | "chroot"
+            | "setenforce" | "hostnamectl" | "timedatectl" | "update-grub" | "grub-install"
+            | "sysctl" => {
+                let options = Options::parse(args, &[]);
+                let read_only = match name {
+                    "mount" | "iptables" | "ip6tables" => {
+                        args.is_empty() || options.has('L', "list")
+                    }
+                    "sysctl" => {
+                        !options.has('w', "write")
+                            && !options.operand_texts().iter().any(|a| a.contains('='))
+                    }
+                    _ => false,
+                };
+                if !read_only {
+                    self.report(
+                        RiskLevel::Privileged,
+                        format!("changes system configuration ({name})"),
+                        span,
+                    );
+                }
+                false
+            }
+            "crontab" => {
+                let options = Options::parse(args, &["-u"]);
+                if options.short.contains(&'r') {
+                    self.report(RiskLevel::Destructive, "removes all cron jobs", span);
+                } else if options.short.contains(&'e') || !options.operands.is_empty() {
+                    self.report(RiskLevel::ModifiesFiles, "changes cron jobs", span);
+                }
+                false
+            }
+            "kill" | "pkill" | "killall" | "skill" => {
+                let all = name == "kill"
+                    && args.iter().enumerate().any(|(i, arg)| {
+                        arg.text == "-1" && (i > 0 || args.len() == 1) && args.len() > 1
+                    })
+                    || name == "killall" && args.is_empty();
+                if all {
+                    self.report(
+                        RiskLevel::Destructive,
+                        "kills every process you are allowed to signal",
+                        span,
+                    );
+                } else {
+                    let targets: Vec<&str> = args
+                        .iter()
+                        .filter(|arg| !arg.text.starts_with('-'))
+                        .map(|arg| arg.text.as_str())
+                        .collect();
+                    self.report(
+                        RiskLevel::Destructive,
+                        format!("stops the processes {}", describe(&targets)),
+                        span,
+                    );
+                }
+                false
+            }
+            "docker" | "podman" => {
+                self.containers(name, args, span);
+                false
+            }
+            "kubectl" | "helm" | "terraform" => {
+                let options =
+                    Options::parse(args, &["-n", "--namespace", "-f", "-l", "-o", "--context"]);
+                match options.operand_texts().first().copied() {
+                    Some("delete" | "drain" | "uninstall" | "destroy") => self.report(
+                        RiskLevel::Destructive,
+                        format!(
+                            "deletes infrastructure resources ({name} {})",
+                            options.operands[0].text
+                        ),
+                        span,
+                    ),
+                    Some(
+                        sub @ ("apply" | "create" | "edit" | "patch" | "replace" | "scale"
+                        | "rollout" | "set" | "label" | "annotate" | "exec" | "cp"
+                        | "install" | "upgrade" | "rollback" | "cordon" | "import" | "taint"),
+                    ) => self.report(
+                        RiskLevel::ModifiesFiles,
+                        format!("changes infrastructure resources ({name} {sub})"),
+                        span,
+                    ),
+                    _ => {}
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn shell(&mut self, args: &[Arg], network_input: bool, span: Span) {
+        // `-c` may be combined with other flags, as in `bash -lc`
+        let command_flag = args
+            .iter()
+            .take_while(|arg| arg.literal && arg.text.starts_with('-'))
+            .position(|arg| !arg.text.starts_with("--") && arg.text.contains('c'));
+        if let Some(index) = command_flag {
+            match args.get(index + 1) {
+                Some(command) if command.downloads => self.report(
+                    RiskLevel::Destructive,
+                    "runs code downloaded from the network",
+                    span,
+                ),
+                Some(command) if command.literal => self.shell_string(&command.text, span),
+                Some(_) => self.report(
+                    RiskLevel::ModifiesFiles,
+                    "runs a shell command built at run time",
+                    span,
+                ),
+                None => {}
+            }
+            return;
+        }
+
+        let script = args
+            .iter()
+            .find(|arg| !(arg.literal && arg.text.starts_with('-')));
+        match script {
+            None if network_input => self.report(
+                RiskLevel::Destructive,
+                "runs a script downloaded from the network",
+                span,
+            ),
+            None => match std::mem::take(&mut self.stdin) {
+                Input::Inherited => {}
+                // The script consumes the input it is read from
+                Input::Known(commands) => self.shell_string(&commands, span),
+                Input::Unknown => {
+                    self.report(
+                        RiskLevel::Destructive,
+                        "runs commands read from its input, whose effects are not analyzed",
+                        span,
+                    );
+                    self.stdin = Input::Unknown;
+                }
+            },
+            Some(script) if script.downloads => self.report(
+                RiskLevel::Destructive,
+                "runs a script downloaded from the network",
+                span,
+            ),
+            Some(script) => self.report(
+                RiskLevel::ModifiesFiles,
+                format!(
+                    "runs the script {}, whose effects are not analyzed",
+                    script.text
+                ),
+                span,
+            ),
+        }
+    }
+
+    /// Analyze code an interpreter runs from its arguments, as in
+    /// `python -c` or `perl -e`
+    ///
+    /// Shell commands passed to `system` and the like as string literals
+    /// are analyzed; other code is only looked at for calls that start
+    /// programs or delete data.
+    fn inline_code(&mut self, name: &str, code: &str, span: Span) {
+        let commands = embedded_commands(code);
+        for command in &commands {
+            self.shell_string(command, span);
+        }
+        if commands.is_empty() && DANGEROUS_CALLS.iter().any(|call| code.contains(call)) {
+            self.report(
+                RiskLevel::Destructive,
+                format!("runs {name} code that may start programs or delete files"),
+                span,
+            );
+        } else {
+            self.report(
+                RiskLevel::ModifiesFiles,
+                format!("runs {name} code, whose effects are not analyzed"),
+                span,
+            );
+        }
+    }
+
+    fn find(&mut self, args: &[Arg], span: Span) {
+        let mut i = 0;
+        while let Some(arg) = args.get(i) {
+            i += 1;
+            match arg.text.as_str() {
+                "-delete" => self.report(
+                    RiskLevel::Destructive,
+                    "deletes the files find matches",
+                    span,
+                ),
+                "-exec" | "-execdir" | "-ok" | "-okdir" => {
+                    let rest = &args[i..];
+                    let end = rest
+                        .iter()
+                        .position(|arg| arg.text == ";" || arg.text == "+")
+                        .unwrap_or(rest.len());
+                    self.nested(&rest[..end], false, span);
+                    i += end + 1;
+                }
+                "-fprint" | "-fprint0" | "-fprintf" | "-fls" => {
+                    if let Some(path) = args.get(i) {
+                        self.write_target(&path.text, "overwrites", span);
+                    }
+                    i += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn rm(&mut self, args: &[Arg], span: Span) {
+        let options = Options::parse(args, &[]);
+        let recursive = options.has('r', "recursive") || options.short.contains(&'R');
+        if options.has_long("no-preserve-root") {
+            self.report(
+                RiskLevel::Irreversible,
+                "may delete the whole filesystem (--no-preserve-root)",
+                span,
+            );
+        }
+
+        let mut others = Vec::new();
+        for path in options.operand_texts() {
+            match critical_path(path) {
+                Some(critical) if recursive => self.report(
+                    RiskLevel::Irreversible,
+                    format!("recursively deletes {critical}"),
+                    span,
+                ),
+                Some(critical) => {
+                    self.report(RiskLevel::Destructive, format!("deletes {critical}"), span)
+                }
+                None => others.push(path),
+            }
+        }
+        if !others.is_empty() || options.operands.is_empty() {
+            let verb = if recursive {
+                "recursively deletes"
+            } else {
+                "deletes"
+            };
+            self.report(
+                RiskLevel::Destructive,
+                format!("{verb} {}", describe(&others)),
+                span,
+            );
+        }
+    }
+
+    fn change_attributes(&mut self, name: &str, args: &[Arg], span: Span) {
+        let options = Options::parse(args, &["--reference", "--from"]);
+        let recursive = options.short.contains(&'R') || options.has_long("recursive");
+        let mut operands = options.operand_texts();
+        let setting = if options.value(&["--reference"]).is_none() && !operands.is_empty() {
+            Some(operands.remove(0))
+        } else {
+            None
+        };
+        let what = if name == "chmod" {
+            "permissions"
+        } else {
+            "ownership"
+        };
+        let world_writable = name == "chmod"
+            && setting.is_some_and(|mode| {
+                mode.ends_with("777")
+                    || mode.ends_with("666")
+                    || ["o+w", "a+w", "+w", "o=rwx", "a=rwx"]
+                        .iter()
+                        .any(|pattern| mode.contains(pattern) && !mode.starts_with("u"))
+            });
+        let suffix = if world_writable {
+            ", making them writable by everyone"
+        } else {
+            ""
+        };
+
+        let mut others = Vec::new();
+        for path in operands {
+            match critical_path(path) {
+                Some(critical) if recursive => self.report(
+                    RiskLevel::Irreversible,
+                    format!("recursively changes the {what} of {critical}{suffix}"),
+                    span,
+                ),
+                Some(critical) => self.report(
+                    RiskLevel::Privileged,
+                    format!("changes the {what} of {critical}{suffix}"),
+                    span,
+                ),
+                None => others.push(path),
+            }
+        }
+        if !others.is_empty() {
+            let verb = if recursive {
+                format!("recursively changes the {what} of")
+            } else {
+                format!("changes the {what} of")
+            };
+            self.modifies(&verb, &others, span);
+            if world_writable {
+                self.report(
+                    RiskLevel::ModifiesFiles,
+                    format!("makes {} writable by everyone", join(&others)),
+                    span,
+                );
+            }
+        }
+    }
+
+    fn tar(&mut self, args: &[Arg], span: Span) {
+        // Old-style `tar xzf archive` has its flags without a dash
+        let mut args = args.to_vec();
+        if let Some(first) = args.first_mut() {
+            if first.literal && !first.text.starts_with('-') {
+                first.text.insert(0, '-');
+            }
+        }
+        let options = Options::parse(&args, &["-f", "-C", "--file", "--directory", "-T", "-X"]);
+        let archive = options.value(&["-f", "--file"]).unwrap_or("an archive");
+        if options.has('x', "extract") || options.has_long("get") {
+            let directory = options.value(&["-C", "--directory"]).unwrap_or(".");
+            self.modifies(&format!("extracts {archive} into"), &[directory], span);
+        } else if (options.has('c', "create")
+            || options.has('r', "append")
+            || options.has('u', "update")
+            || options.has_long("delete"))
+            && archive != "-"
+        {
+            self.write_target(archive, "writes the archive", span);
+        }
+    }
+
+    fn git(&mut self, args: &[Arg], span: Span) {
+        let global = Options::parse(
+            args,
+            &["-C", "-c", "--git-dir", "--work-tree", "--namespace"],
+        );
+        let Some(subcommand) = global.operands.first() else {
+            return;
+        };
+        let position = args
+            .iter()
+            .position(|arg| std::ptr::eq(arg, *subcommand))
+            .unwrap_or(0);
+        let sub = subcommand.text.as_str();
+        let options = Options::parse(
+            &args[position + 1..],
+            &["-m", "-b", "-B", "-c", "-C", "-o", "-u"],
+        );
+        let operands = options.operand_texts();
+
+        match sub {
+            "clone" => {
+                self.report(
+                    RiskLevel::Network,
+                    format!(
+                        "downloads the repository {}",
+                        describe(&operands[..operands.len().min(1)])
+                    ),
+                    span,
+                );
+                self.report(
+                    RiskLevel::ModifiesFiles,
+                    "creates a new repository directory",
+                    span,
+                );
+            }
+            "fetch" | "pull" | "ls-remote" | "submodule" => {
+                self.report(
+                    RiskLevel::Network,
+                    format!("talks to a remote repository (git {sub})"),
+                    span,
+                );
+                if sub == "pull" {
+                    self.report(
+                        RiskLevel::ModifiesFiles,
+                        "updates the working tree (git pull)",
+                        span,
+                    );
+                }
+            }
+            "push" => {
+                self.report(
+                    RiskLevel::Network,
+                    "uploads commits to a remote repository",
+                    span,
+                );
+                if options.has('f', "force")
+                    || options.long.iter().any(|l| l.starts_with("force"))
+                    || operands
+                        .iter()
+                        .skip(1)
+                        .any(|refspec| refspec.starts_with('+'))
+                {
+                    self.report(
+                        RiskLevel::Destructive,
+                        "force-pushes, which can overwrite remote history",
+                        span,
+                    );
+                }
+                if options.has('d', "delete")
+                    || operands
+                        .iter()
+                        .skip(1)
+                        .any(|refspec| refspec.starts_with(':'))
+                {
+                    self.report(RiskLevel::Destructive, "deletes remote branches", span);
+                }
+            }
+            "reset" if options.has_long("hard") => self.report(
+                RiskLevel::Destructive,
+                "discards uncommitted changes (git reset --hard)",
+                span,
+            ),
+            "clean" if options.has('f', "force") => self.report(
+                RiskLevel::Destructive,
+                "deletes untracked files (git clean)",
+                span,
+            ),
+            "checkout"
+                if options.has('f', "force")
+                    || args.iter().any(|arg| arg.text == "--")
+                    || operands.contains(&".") =>
+            {
+                self.report(
+                    RiskLevel::Destructive,
+                    "discards uncommitted changes (git checkout)",
+                    span,
+                )
+            }
+            "restore" if !options.has('S', "staged") || options.has('W', "worktree") => self
+                .report(
+                    RiskLevel::Destructive,
+                    "discards uncommitted changes (git restore)",
+                    span,
+                ),
+            "branch"
+                if options.short.contains(&'D')
+                    || options.has('d', "delete") && options.has('f', "force") =>
+            {
+                self.report(
+                    RiskLevel::Destructive,
+                    format!("deletes the branches {}", describe(&operands)),
+                    span,
+                )
+            }
+            "stash" if matches!(operands.first(), Some(&"drop" | &"clear")) => {
+                self.report(RiskLevel::Destructive, "deletes stashed changes", span)
+            }
+            "filter-branch" | "filter-repo" => self.report(
+                RiskLevel::Destructive,
+                "rewrites the repository history",
+                span,
+            ),
+            "add" | "commit" | "merge" | "rebase" | "rm" | "mv" | "switch" | "checkout"
+            | "cherry-pick" | "revert" | "am" | "apply" | "tag" | "init" | "config" | "stash"
+            | "reset" | "restore" | "branch" => {
+                let read_only = sub == "branch" && operands.is_empty()
+                    || sub == "tag" && operands.is_empty()
+                    || sub == "config"
+                        && (options.has('l', "list")
+                            || options.has_long("get")
+                            || operands.len() == 1)
+                    || sub == "stash" && matches!(operands.first(), Some(&"list" | &"show"));
+                if !read_only {
+                    self.report(
+                        RiskLevel::ModifiesFiles,
+                        format!("changes the repository (git {sub})"),
+                        span,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns whether the response body goes to standard output
+    fn curl(&mut self, args: &[Arg], span: Span) -> bool {
+        let options = Options::parse(
+            args,
+            &[
+                "-o",
+                "--output",
+                "-d",
+                "--data",
+                "--data-binary",
+                "--data-raw",
+                "--data-urlencode",
+                "-F",
+                "--form",
+                "-T",
+                "--upload-file",
+                "-H",
+                "--header",
+                "-X",
+                "--request",
+                "-u",
+                "--user",
+                "-A",
+                "--user-agent",
+                "-e",
+                "--referer",
+                "-b",
+                "--cookie",
+                "-c",
+                "--cookie-jar",
+                "-x",
+                "--proxy",
+                "-m",
+                "--max-time",
+                "--connect-timeout",
+                "-w",
+                "--write-out",
+                "-r",
+                "--range",
+                "-K",
+                "--config",
+                "--retry",
+                "-E",
+                "--cert",
+            ],
+        );
+        let url = options
+            .operands
+            .iter()
+            .map(|arg| arg.text.as_str())
+            .find(|text| text.contains("://"))
+            .or_else(|| options.operands.first().map(|arg| arg.text.as_str()))
+            .unwrap_or("a URL");
+        let uploads = options.values.iter().any(|(name, _)| {
+            name.starts_with("--data")
+                || matches!(
+                    name.as_str(),
+                    "-d" | "-F" | "--form" | "-T" | "--upload-file"
+                )
+        });
+        if uploads {
+            self.report(RiskLevel::Network, format!("sends data to {url}"), span);
+        } else {
+            self.report(RiskLevel::Network, format!("downloads {url}"), span);
+        }
+
+        let output = options.value(&["-o", "--output"]);
+        if let Some(path) = output.filter(|path| *path != "-") {
+            self.write_target(path, "saves the download to", span);
+            return false;
+        }
+        if output.is_none() && options.has('O', "remote-name") {
+            self.report(
+                RiskLevel::ModifiesFiles,
+                "saves the download to a file",
+                span,
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Returns whether the download goes to standard output
+    fn wget(&mut self, args: &[Arg], span: Span) -> bool {
+        let options = Options::parse(
+            args,
+            &[
+                "-O",
+                "--output-document",
+                "-P",
+                "--directory-prefix",
+                "-o",
+                "--output-file",
+                "-a",
+                "-U",
+                "--header",
+                "-t",
+                "-T",
+                "--post-data",
+                "--post-file",
+            ],
+        );
+        let url = options
+            .operands
+            .first()
+            .map_or("a URL", |arg| arg.text.as_str());
+        self.report(RiskLevel::Network, format!("downloads {url}"), span);
+        match options.value(&["-O", "--output-document"]) {
+            Some("-") => true,
+            Some(path) => {
+                self.write_target(path, "saves the download to", span);
+                false
+            }
+            None => {
+                self.report(
+                    RiskLevel::ModifiesFiles,
+                    "saves the download to a file",
+                    span,
+                );
+                false
+            }
+        }
+    }
+
+    fn system_packages(&mut self, name: &str, args: &[Arg], span: Span) {
+        let options = Options::parse(args, &["-o", "-c", "-t", "--root", "--repo"]);
+        let action = options.operand_texts().first().copied().unwrap_or("");
+        let (installs, removes) = if name == "pacman" {
+            (
+                options.short.contains(&'S')
+                    && !options.short.contains(&'s')
+                    && !options.short.contains(&'i')
+                    || options.short.contains(&'U'),
+                options.short.contains(&'R'),
+            )
+        } else {
+            (
+                matches!(
+                    action,
+                    "install"
+                        | "reinstall"
+                        | "upgrade"
+                        | "update"
+                        | "dist-upgrade"
+                        | "full-upgrade"
+                        | "add"
+                        | "refresh"
+                        | "distro-sync"
+                        | "selfupdate"
+                ),
+                matches!(
+                    action,
+                    "remove" | "purge" | "autoremove" | "erase" | "del" | "uninstall"
+                ),
+            )
+        };
+        if installs {
+            self.report(RiskLevel::Network, "downloads packages", span);
+            self.report(
+                RiskLevel::Privileged,
+                format!("changes system packages ({name})"),
+                span,
+            );
+        }
+        if removes {
+            self.report(
+                RiskLevel::Destructive,
+                format!("removes system packages ({name})"),
+                span,
+            );
+        }
+    }
+
+    fn user_packages(&mut self, name: &str, args: &[Arg], span: Span) {
+        let options = Options::parse(
+            args,
+            &[
+                "-r",
+                "--requirement",
+                "-t",
+                "--target",
+                "--prefix",
+                "--root",
+            ],
+        );
+        let action = options.operand_texts().first().copied().unwrap_or("");
+        let installs = matches!(
+            (name, action),
+            (_, "install" | "upgrade" | "reinstall")
+                | ("npm" | "pnpm", "i" | "ci" | "update" | "add")
+                | ("yarn", "add" | "")
+                | ("go", "get")
+                | ("composer", "require" | "update")
+        ) && !(name == "cargo" && action != "install");
+        let removes = matches!(action, "uninstall" | "remove" | "rm" | "un");
+        if installs {
+            self.report(RiskLevel::Network, "downloads and installs packages", span);
+            let global = options.has('g', "global");
+            let verb = if global {
+                "installs packages globally"
+            } else {
+                "installs packages"
+            };
+            let command = [name, action].join(" ");
+            self.report(
+                RiskLevel::ModifiesFiles,
+                format!("{verb} ({})", command.trim_end()),
+                span,
+            );
+        }
+        if removes {
+            self.report(
+                RiskLevel::ModifiesFiles,
+                format!("uninstalls packages ({name} {action})"),
+                span,
+            );
+        }
+    }
+
+    fn containers(&mut self, name: &str, args: &[Arg], span: Span) {
+        let options = Options::parse(args, &["-H", "--host", "--context", "-c", "--config", "-l"]);
+        let operands = options.operand_texts();
+        let (sub, object) = match operands.as_slice() {
+            [group @ ("system" | "volume" | "image" | "container" | "network" | "builder"), sub, ..] => {
+                (*sub, Some(*group))
+            }
+            [sub, ..] => (*sub, None),
+            [] => return,
+        };
+        match sub {
+            "rm" | "rmi" | "prune" | "kill" => self.report(
+                RiskLevel::Destructive,
+                format!(
+                    "deletes or kills containers, images or volumes ({name} {}{sub})",
+                    object.map(|o| format!("{o} ")).unwrap_or_default()
+                ),
+                span,
+            ),
+            "pull" | "push" | "login" | "search" => self.report(
+                RiskLevel::Network,
+                format!("talks to a container registry ({name} {sub})"),
+                span,
+            ),
+            "run" | "exec" | "build" | "create" | "start" | "stop" | "restart" | "compose"
+            | "cp" => {
+                let privileged = args.iter().any(|arg| {
+                    arg.text == "--privileged"
+                        || arg.text.starts_with("/:")
+                        || arg.text == "--pid=host"
+                });
+                if privileged {
+                    self.report(
+                        RiskLevel::Privileged,
+                        format!("gives a container full access to the host ({name} {sub})"),
+                        span,
+                    );
+                }
+                self.report(
+                    RiskLevel::ModifiesFiles,
+                    format!("changes containers ({name} {sub})"),
+                    span,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Skip leading options of a wrapper, returning the wrapped command
+/// Whether `name` with `args` needs a terminal to interact with the user
+fn needs_terminal(name: &str, args: &[Arg]) -> bool {
+    let options = Options::parse(args, &[]);
+    let operands = options.operand_texts();
+    match name {
+        "vi" | "vim" | "nvim" | "view" | "vimdiff" | "nano" | "pico" | "emacs" | "micro"
+        | "less" | "more" | "most" | "man" | "info" | "htop" | "btop" | "atop" | "iotop" | "mc"
+        | "ranger" | "nnn" | "ncdu" | "tig" | "lazygit" | "fzf" | "tmux" | "screen" | "watch"
+        | "passwd" | "visudo" | "vipw" | "ssh" | "sftp" | "telnet" | "ftp" | "nmtui" | "su" => true,
+        "top" => !options.has('b', "batch"),
+        "sudo" | "doas" => !options.has('n', "non-interactive"),
+        "crontab" => options.has('e', "edit"),
+        "journalctl" => !options.has_long("no-pager") && !options.has('f', "follow"),
+        // Read-eval-print loops without a script or command
+        "python" | "python3" | "ipython" | "node" | "irb" | "ghci" | "lua" | "bc" | "R" => {
+            operands.is_empty() && !options.has('c', "command") && !options.has('e', "eval")
+        }
+        "psql" | "mysql" | "sqlite3" => {
+            !options.has('c', "command") && !options.has('e', "execute") && operands.len() < 2
+        }
+        "git" => {
+            let subcommand = operands.first().copied().unwrap_or_default();
+            match subcommand {
+                "log" | "show" | "diff" | "blame" | "reflog" | "help" => {
+                    !options.has_long("no-pager")
+                }
+                "commit" => !options.has('m', "message") && !options.has('F', "file"),
+                "rebase" => options.has('i', "interactive"),
+                "add" => options.has('p', "patch") || options.has('i', "interactive"),
+                _ => false,
+            }
+        }
+        "docker" | "podman" => {
+            matches!(operands.first(), Some(&("run" | "exec")))
+                && options.has('i', "interactive")
+                && options.has('t', "tty")
+        }
+        _ => false,
+    }
+}
+
+fn skip_options<'a>(args: &'a [Arg], with_value: &[&str]) -> &'a [Arg] {
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        let text = arg.text.as_str();
+        if !arg.literal || !text.starts_with('-') || text == "-" {
+            break;
+        }
+        i += 1;
+        if text == "--" {
+            break;
+        }
+        if with_value.contains(&text) {
+            i += 1;
+        }
+    }
+    args.get(i..).unwrap_or_default()
+}
+
+/// Whether a word contains a command substitution that downloads data
+fn downloads(parts: &[WordPart]) -> bool {
+    parts.iter().any(|part| match part {
+        WordPart::CommandSubstitution(list) => list.items.iter().any(|item| {
+            item.and_or.pipelines().any(|pipeline| {
+                pipeline.commands.iter().any(|command| match command {
+                    Command::Simple(simple) => {
+                        simple.name().and_then(Word::literal).is_some_and(|name| {
+                            let name = name.rsplit('/').next().unwrap_or(&name).to_string();
+                            matches!(
+                                name.as_str(),
+                                "curl" | "wget" | "fetch" | "http" | "https" | "nc" | "xh"
+                            )
+                        })
+                    }
+                    _ => false,
+                })
+            })
+        }),
+        WordPart::DoubleQuoted(parts) => downloads(parts),
+        _ => false,
+    })
+}
+
+/// Whether a word contains a command substitution
+fn substitutes(parts: &[WordPart]) -> bool {
+    parts.iter().any(|part| match part {
+        WordPart::CommandSubstitution(_) => true,
+        WordPart::DoubleQuoted(parts) => substitutes(parts),
+        _ => false,
+    })
+}
+
+/// What a command writes to its standard output, when that is known
+/// before it runs: the arguments of `echo`, or a here-document `cat` prints
+fn known_output(command: &Command) -> Option<String> {
+    let Command::Simple(simple) = command else {
+        return None;
+    };
+    let words = simple
+        .words
+        .iter()
+        .map(Word::literal)
+        .collect::<Option<Vec<String>>>()?;
+    match words.first()?.as_str() {
+        "echo" if !words.get(1).is_some_and(|word| word.starts_with('-')) => {
+            Some(words[1..].join(" "))
+        }
+        "cat" if words.len() == 1 => match redirected_input(&simple.redirects)? {
+            Input::Known(text) => Some(text),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Where `redirects` make a command read its standard input from, if
+/// they redirect it
+fn redirected_input(redirects: &[Redirect]) -> Option<Input> {
+    redirects
+        .iter()
+        .rev()
+        .find(|redirect| {
+            redirect.fd.unwrap_or(0) == 0
+                && matches!(
+                    redirect.kind,
+                    RedirectKind::Input
+                        | RedirectKind::ReadWrite
+                        | RedirectKind::DupInput
+                        | RedirectKind::HereDoc
+                        | RedirectKind::HereDocStrip
+                )
+        })
+        .map(|redirect| match &redirect.here_doc {
+            Some(doc) if !doc.expand || !doc.body.contains(['$', '`']) => {
+                Input::Known(doc.body.clone())
+            }
+            None if redirect.target.literal().as_deref() == Some("/dev/null") => {
+                Input::Known(String::new())
+            }
+            _ => Input::Unknown,
+        })
+}
+
+/// Shell commands interpreter code passes as string literals to calls such
+/// as `os.system("...")`, and Perl or Ruby backticks
+fn embedded_commands(code: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    for call in SHELL_CALLS {
+        for (start, _) in code.match_indices(call) {
+            let rest = code[start + call.len()..].trim_start();
+            let rest = rest.strip_prefix('(').unwrap_or(rest).trim_start();
+            if let Some(command) = quoted(rest) {
+                commands.push(command);
+            }
+        }
+    }
+    if code.matches('`').count() >= 2 {
+        commands.extend(code.split('`').skip(1).step_by(2).map(str::to_string));
+    }
+    commands
+}
+
+/// The string literal `text` starts with, without its quotes
+fn quoted(text: &str) -> Option<String> {
+    let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let mut value = String::new();
+    let mut chars = text[1..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            c if c == quote => return Some(value),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Names of the commands a compound command calls, and whether it runs
+/// any of them in the background or a pipeline
+fn collect_calls(compound: &CompoundCommand, calls: &mut Vec<String>, spawns: &mut bool) {
+    fn list(list: &List, calls: &mut Vec<String>, spawns: &mut bool) {
+        for item in &list.items {
+            *spawns |= item.background;
+            for pipeline in item.and_or.pipelines() {
+                *spawns |= pipeline.commands.len() > 1;
+                for command in &pipeline.commands {
+                    match command {
+                        Command::Simple(simple) => {
+                            if let Some(name) = simple.name().and_then(Word::literal) {
+                                calls.push(name);
+                            }
+                        }
+                        Command::Compound(compound) => collect_calls(compound, calls, spawns),
+                        Command::FunctionDefinition(_) => {}
+                    }
+                }
+            }
+        }
+    }
+    match &compound.kind {
+        CompoundKind::BraceGroup(body) | CompoundKind::Subshell(body) => list(body, calls, spawns),
+        CompoundKind::For { body, .. }
+        | CompoundKind::While { body, .. }
+        | CompoundKind::Until { body, .. } => list(body, calls, spawns),
+        CompoundKind::If {
+            then,
+            elifs,
+            otherwise,
+            ..
+        } => {
+            list(then, calls, spawns);
+            for (_, body) in elifs {
+                list(body, calls, spawns);
+            }
+            if let Some(otherwise) = otherwise {
+                list(otherwise, calls, spawns);
+            }
+        }
+        CompoundKind::Case { arms, .. } => {
+            for arm in arms {
+                list(&arm.body, calls, spawns);
+            }
+        }
+    }
+}
+
+/// Describe `path` if deleting or changing it affects the whole system or
+/// the home directory
+fn critical_path(path: &str) -> Option<String> {
+    let unquoted: String = path.chars().filter(|c| !matches!(c, '"' | '\'')).collect();
+    let mut normalized = unquoted.as_str();
+    while let Some(stripped) = normalized
+        .strip_suffix("/*")
+        .or_else(|| normalized.strip_suffix("/.").filter(|s| !s.is_empty()))
+        .or_else(|| normalized.strip_suffix('/').filter(|s| !s.is_empty()))
+    {
+        normalized = stripped;
+    }
+    if normalized.is_empty() || normalized == "/" {
+        return Some("the root directory /".to_string());
+    }
+    if matches!(normalized, "~" | "$HOME" | "${HOME}") {
+        return Some(format!("your home directory ({path})"));
+    }
+    if matches!(normalized, "/home" | "/Users") {
+        return Some(format!("all home directories ({path})"));
+    }
+    if normalized.starts_with('/') && !normalized[1..].contains('/') && is_system_path(normalized) {
+        return Some(format!("the system directory {normalized}"));
+    }
+    None
+}
+
+/// Whether `path` lies in a directory owned by the operating system
+fn is_system_path(path: &str) -> bool {
+    const SYSTEM_DIRECTORIES: &[&str] = &[
+        "/etc", "/boot", "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/var", "/sys",
+        "/proc", "/opt", "/root", "/srv", "/System", "/Library", "/private",
+    ];
+    SYSTEM_DIRECTORIES.iter().any(|dir| {
+        path.strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+fn is_disk_device(path: &str) -> bool {
+    const DISK_PREFIXES: &[&str] = &[
+        "/dev/sd",
+        "/dev/hd",
+        "/dev/vd",
+        "/dev/xvd",
+        "/dev/nvme",
+        "/dev/mmcblk",
+        "/dev/disk",
+        "/dev/rdisk",
+        "/dev/md",
+        "/dev/dm-",
+        "/dev/mapper/",
+    ];
+    DISK_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+}
+
+fn is_harmless_device(path: &str) -> bool {
+    matches!(
+        path,
+        "/dev/null" | "/dev/stdout" | "/dev/stderr" | "/dev/tty" | "/dev/zero"
+    ) || path.starts_with("/dev/fd/")
+}
+
+/// Whether an rsync/scp operand names another machine, e.g. `host:path`
+fn is_remote(path: &str) -> bool {
+    path.contains("://")
+        || path
+            .split_once(':')
+            .is_some_and(|(host, _)| !host.is_empty() && !host.contains('/'))
+}
+
+fn join(paths: &[&str]) -> String {
+    const SHOWN: usize = 3;
+    let mut text = paths[..paths.len().min(SHOWN)].join(", ");
+    if paths.len() > SHOWN {
+        text.push_str(&format!(" and {} more", paths.len() - SHOWN));
+    }
+    text
+}
+
+/// List the paths a command acts on
+fn describe(paths: &[&str]) -> String {
+    if paths.is_empty() {
+        "files given by its input".to_string()
+    } else {
+        join(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(command: &str) -> RiskLevel {
+        RiskAnalyzer::analyze(command).level
+    }
+
+    #[test]
+    fn test_safe_commands() {
+        for command in [
+            "ls -la",
+            "echo format",
+            "echo 'rm -rf /'",
+            "grep -r mkfs docs/",
+            "cat /etc/passwd | sort > /dev/null",
+            "git status && git log --oneline",
+            "find . -name '*.rs'",
+            "fdisk -l",
+        ] {
+            assert_eq!(level(command), RiskLevel::Safe, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_rm_flag_permutations() {
+        for command in [
+            "rm -rf /",
+            "rm -r -f /",
+            "rm -fr /*",
+            "rm --recursive --force /",
+            "rm -rf ~",
+            "rm -Rf \"$HOME\"",
+            "rm -rf -- /usr",
+        ] {
+            let assessment = RiskAnalyzer::analyze(command);
+            assert_eq!(assessment.level, RiskLevel::Irreversible, "{command}");
+            assert!(assessment.is_dangerous());
+        }
+
+        let assessment = RiskAnalyzer::analyze("rm -rf build/");
+        assert_eq!(assessment.level, RiskLevel::Destructive);
+        assert_eq!(assessment.reasons(), ["recursively deletes build/"]);
+        assert_eq!(level("rm notes.txt"), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn test_disks_and_permissions() {
+        let assessment = RiskAnalyzer::analyze("echo hi > /dev/sda");
+        assert_eq!(assessment.level, RiskLevel::Irreversible);
+        assert_eq!(assessment.findings[0].span, Span::new(8, 18));
+        assert_eq!(
+            level("dd if=/dev/zero of=/dev/nvme0n1 bs=1M"),
+            RiskLevel::Irreversible
+        );
+        assert_eq!(level("mkfs.ext4 /dev/sdb1"), RiskLevel::Irreversible);
+        assert_eq!(level("chmod -R 777 /"), RiskLevel::Irreversible);
+        assert_eq!(level("chmod 644 notes.txt"), RiskLevel::ModifiesFiles);
+        assert_eq!(level("echo x >> /etc/hosts"), RiskLevel::Privileged);
+        assert_eq!(level("sort data > out.txt"), RiskLevel::ModifiesFiles);
+    }
+
+    #[test]
+    fn test_looks_through_wrappers() {
+        let assessment = RiskAnalyzer::analyze("sudo -u root rm -rf /");
+        assert_eq!(assessment.level, RiskLevel::Irreversible);
+        assert!(assessment
+            .findings
+            .iter()
+            .any(|finding| finding.level == RiskLevel::Privileged));
+
+        for command in [
+            r"find . -name '*.tmp' -exec rm {} \;",
+            "find /tmp -mtime +7 -delete",
+            "ls | xargs -n1 rm",
+            "sh -c 'rm -rf ~'",
+            "bash -lc \"sudo rm -rf /var\"",
+            "env FOO=1 nice -n 10 rm -r dir",
+            "eval rm -rf out",
+            "echo $(rm -rf cache)",
+            "if true; then rm -r x; fi",
+        ] {
+            assert!(RiskAnalyzer::analyze(command).is_dangerous(), "{command}");
+        }
+
+        // Findings inside a command string point at the outer command
+        let assessment = RiskAnalyzer::analyze("echo ok; sh -c 'rm -rf ~'");
+        assert_eq!(assessment.findings[0].span, Span::new(9, 25));
+    }
+
+    #[test]
+    fn test_downloaded_code() {
+        for command in [
+            "curl -fsSL https://example.com/install.sh | sh",
+            "wget -qO- https://example.com/x | sudo bash",
+            "curl https://example.com/x | tee log | python3",
+            "sh -c \"$(curl -fsSL https://example.com/install.sh)\"",
+        ] {
+            let assessment = RiskAnalyzer::analyze(command);
+            assert!(assessment.is_dangerous(), "{command}");
+            assert!(assessment.reasons()[0].contains("downloaded"), "{command}");
+        }
+
+        assert_eq!(
+            level("curl -s https://example.com | jq ."),
+            RiskLevel::Network
+        );
+        assert_eq!(
+            level("curl -o page.html https://example.com"),
+            RiskLevel::Network
+        );
+        assert_eq!(level("cat install.sh | sh"), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn test_code_read_from_input() {
+        for command in [
+            "printf 'rm -rf /' | bash",
+            "cat x.sh | sh",
+            "base64 -d x | sh",
+            "sh < script.sh",
+            "sudo bash < script.sh",
+            "cat x.py | python3",
+            "{ sh; } < script.sh",
+        ] {
+            let assessment = RiskAnalyzer::analyze(command);
+            assert_eq!(assessment.level, RiskLevel::Destructive, "{command}");
+            assert!(assessment.reasons()[0].contains("input"), "{command}");
+        }
+
+        // Input known up front is analyzed like `sh -c`
+        for command in [
+            "echo \"rm -rf /\" | sh",
+            "cat <<EOF | sh\nrm -rf /\nEOF\n",
+            "sh <<'EOF'\nrm -rf /\nEOF\n",
+        ] {
+            assert_eq!(level(command), RiskLevel::Irreversible, "{command}");
+        }
+        assert_eq!(level("echo ls | sh"), RiskLevel::Safe);
+        assert_eq!(level("sh < /dev/null"), RiskLevel::Safe);
+        assert_eq!(level("sh"), RiskLevel::Safe);
+        assert_eq!(level("ls | xargs sh -c 'echo $0'"), RiskLevel::Safe);
+    }
+
+    #[test]
+    fn test_substituted_program_names() {
+        let assessment = RiskAnalyzer::analyze("$(echo rm) -rf /");
+        assert_eq!(assessment.level, RiskLevel::Destructive);
+        assert!(assessment.reasons()[0].contains("only known at run time"));
+        assert_eq!(level("\"$EDITOR\" notes.txt"), RiskLevel::ModifiesFiles);
+    }
+
+    #[test]
+    fn test_inline_interpreter_code() {
+        for command in [
+            "python3 -c \"import os; os.system('rm -rf /')\"",
+            "perl -e 'system \"rm -rf /\"'",
+            "ruby -e '`rm -rf /`'",
+            "node -e \"require('child_process').execSync('rm -rf /')\"",
+        ] {
+            assert_eq!(level(command), RiskLevel::Irreversible, "{command}");
+        }
+        assert_eq!(
+            level("python -c 'import shutil; shutil.rmtree(\"build\")'"),
+            RiskLevel::Destructive
+        );
+        assert_eq!(
+            level("python3 -c 'import subprocess; subprocess.run([\"ls\"])'"),
+            RiskLevel::Destructive
+        );
+        assert_eq!(level("python3 -c 'print(1 + 1)'"), RiskLevel::ModifiesFiles);
+        assert_eq!(level("python3 script.py"), RiskLevel::Safe);
+    }
+
+    #[test]
+    fn test_remote_and_repeated_commands() {
+        for command in [
+            "busybox rm -rf /",
+            "watch -n 5 rm -rf /",
+            "watch 'rm -rf /'",
+            "ssh -p 2222 host rm -rf /",
+            "ssh host 'sudo rm -rf /'",
+        ] {
+            assert_eq!(level(command), RiskLevel::Irreversible, "{command}");
+        }
+        assert_eq!(level("watch -n 1 df -h"), RiskLevel::Safe);
+        assert_eq!(level("ssh host uptime"), RiskLevel::Network);
+        assert_eq!(level("ssh host \"$CMD\""), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn test_fork_bomb() {
+        let assessment = RiskAnalyzer::analyze(":(){ :|:& };:");
+        assert_eq!(assessment.level, RiskLevel::Destructive);
+        assert!(assessment.reasons()[0].contains("fork bomb"));
+        assert_eq!(level("greet() { echo hi; }; greet"), RiskLevel::Safe);
+    }
+
+    #[test]
+    fn test_other_commands() {
+        assert_eq!(level("git reset --hard HEAD~1"), RiskLevel::Destructive);
+        assert_eq!(
+            level("git push --force origin main"),
+            RiskLevel::Destructive
+        );
+        assert_eq!(level("git commit -m wip"), RiskLevel::ModifiesFiles);
+        assert_eq!(
+            level("git clone https://example.com/r.git"),
+            RiskLevel::Network
+        );
+        assert_eq!(level("sudo apt install ripgrep"), RiskLevel::Privileged);
+        assert_eq!(level("kill -9 -1"), RiskLevel::Destructive);
+        assert_eq!(level("systemctl status sshd"), RiskLevel::Safe);
+        assert_eq!(level("systemctl restart sshd"), RiskLevel::Privileged);
+        assert_eq!(level("tar -xzf backup.tar.gz"), RiskLevel::ModifiesFiles);
+        assert_eq!(level("tar tzf backup.tar.gz"), RiskLevel::Safe);
+        assert_eq!(level("sed -i 's/a/b/' file"), RiskLevel::ModifiesFiles);
+        assert_eq!(level("docker system prune -af"), RiskLevel::Destructive);
+    }
+
+    #[test]
+    fn test_interactive_programs() {
+        let interactive = |command: &str| RiskAnalyzer::analyze(command).interactive;
+        assert_eq!(interactive("vim notes.txt").as_deref(), Some("vim"));
+        assert_eq!(interactive("git log --oneline").as_deref(), Some("git"));
+        assert_eq!(interactive("git commit -m done").as_deref(), None);
+        assert_eq!(interactive("sudo apt update").as_deref(), Some("sudo"));
+        assert_eq!(interactive("sudo -n ls").as_deref(), None);
+        assert_eq!(interactive("sh -c 'htop'").as_deref(), Some("htop"));
+        assert_eq!(interactive("python3").as_deref(), Some("python3"));
+        assert_eq!(interactive("python3 script.py").as_deref(), None);
+        assert_eq!(
+            interactive("docker run -it ubuntu").as_deref(),
+            Some("docker")
+        );
+        assert_eq!(interactive("ls -la | grep foo").as_deref(), None);
+    }
+
+    #[test]
+    fn test_unparseable_commands() {
+        let assessment = RiskAnalyzer::analyze("echo 'unterminated");
+        assert_eq!(assessment.level, RiskLevel::Destructive);
+        assert!(assessment.parse_error.is_some());
+        assert_eq!(level(""), RiskLevel::Safe);
+
+        // Bash-only and fish syntax is not analyzed, so it is never rated
+        // below the level that asks for confirmation
+        for command in [
+            "bash <(curl -fsSL https://example.com/install.sh)",
+            "diff <(ls) <(ls /)",
+            "rm -rf (pwd)",
+            "sh -c 'cat <(true)'",
+        ] {
+            assert_eq!(level(command), RiskLevel::Destructive, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_wrappers_are_followed() {
+        for wrapper in WRAPPERS {
+            let command = match *wrapper {
+                "timeout" => "timeout 5 shred /dev/sda".to_string(),
+                wrapper => format!("{wrapper} shred /dev/sda"),
+            };
+            assert_eq!(level(&command), RiskLevel::Irreversible, "{command}");
+        }
+    }
+}
@@ -0,0 +1,732 @@
+//! Sandboxed command execution
+//!
+//! A sandbox runs a command with a read-only view of the filesystem, no
+//! network and a writable overlay on top of the working directory. Writes
+//! land in the overlay's upper directory instead of the real files, so after
+//! the command exits the overlay tells which files it would have created,
+//! modified or deleted.
+//!
+//! Two backends exist: bubblewrap (`bwrap`) when it is installed, and
+//! unprivileged user, mount and network namespaces set up by cmdr itself.
+//! Both need Linux 5.11 or newer for unprivileged overlay mounts.
+
+use super::{ShellError, ShellResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+/// How a sandbox is set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxBackend {
+    /// The `bwrap` program
+    Bubblewrap,
+    /// User, mount and network namespaces created directly
+    Namespaces,
+}
+
+/// What a sandboxed command did to a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// A change a sandboxed command made in the working directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Path relative to the working directory
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+}
+
+/// An overlay of a working directory that commands can run in
+///
+/// The overlay's scratch directory is removed when the sandbox is dropped.
+#[derive(Debug)]
+pub struct Sandbox {
+    backend: SandboxBackend,
+    cwd: PathBuf,
+    scratch: PathBuf,
+}
+
+impl Sandbox {
+    /// The first backend that works on this machine, if any
+    ///
+    /// The result of probing is cached for the life of the process.
+    pub fn available_backend() -> Option<SandboxBackend> {
+        static BACKEND: OnceLock<Option<SandboxBackend>> = OnceLock::new();
+        *BACKEND.get_or_init(|| {
+            let candidates = [SandboxBackend::Bubblewrap, SandboxBackend::Namespaces];
+            candidates.into_iter().find(|&backend| probe(backend))
+        })
+    }
+
+    /// Create a sandbox over `cwd` using the first available backend
+    pub fn new(cwd: impl Into<PathBuf>) -> ShellResult<Self> {
+        let backend = Self::available_backend().ok_or_else(|| {
+            ShellError::Sandbox(
+                "no sandbox is available; it needs bubblewrap or unprivileged user namespaces"
+                    .to_string(),
+            )
+        })?;
+        Self::with_backend(cwd, backend)
+    }
+
+    /// Create a sandbox over `cwd` using `backend`
+    pub fn with_backend(cwd: impl Into<PathBuf>, backend: SandboxBackend) -> ShellResult<Self> {
+        let cwd = fs::canonicalize(cwd.into())
+            .map_err(|e| ShellError::Sandbox(format!("cannot open the working directory: {e}")))?;
+        // Overlay mount options separate paths with `,` and `:`
+        if cwd.to_string_lossy().contains([',', ':', '\\']) {
+            return Err(ShellError::Sandbox(format!(
+                "cannot sandbox {}: the path contains `,`, `:` or `\\`",
+                cwd.display()
+            )));
+        }
+
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let scratch = std::env::temp_dir().join(format!(
+            "cmdr-sandbox-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if scratch.starts_with(&cwd) {
+            return Err(ShellError::Sandbox(format!(
+                "cannot sandbox {}: it contains the temporary directory",
+                cwd.display()
+            )));
+        }
+        let sandbox = Self {
+            backend,
+            cwd,
+            scratch,
+        };
+        for dir in [sandbox.upper(), sandbox.work(), sandbox.dev()] {
+            fs::create_dir_all(&dir).map_err(|e| {
+                ShellError::Sandbox(format!("cannot create {}: {e}", dir.display()))
+            })?;
+        }
+        Ok(sandbox)
+    }
+
+    /// The backend this sandbox uses
+    pub fn backend(&self) -> SandboxBackend {
+        self.backend
+    }
+
+    /// The working directory the overlay covers
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Build a command running `command` with `shell -c` inside the sandbox
+    pub fn command(&self, shell: &str, command: &str) -> ShellResult<Command> {
+        match self.backend {
+            SandboxBackend::Bubblewrap => Ok(self.bubblewrap_command(shell, command)),
+            SandboxBackend::Namespaces => self.namespaces_command(shell, command),
+        }
+    }
+
+    /// The files created, modified or deleted in the overlay so far, sorted
+    /// by path
+    pub fn changes(&self) -> ShellResult<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        collect_changes(&self.upper(), &self.cwd, Path::new(""), &mut changes)
+            .map_err(|e| ShellError::Sandbox(format!("cannot read the overlay: {e}")))?;
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    fn upper(&self) -> PathBuf {
+        self.scratch.join("upper")
+    }
+
+    fn work(&self) -> PathBuf {
+        self.scratch.join("work")
+    }
+
+    fn dev(&self) -> PathBuf {
+        self.scratch.join("dev")
+    }
+
+    /// Whether a private `/tmp` can be mounted without hiding the working
+    /// directory
+    fn private_tmp(&self) -> bool {
+        !self.cwd.starts_with("/tmp")
+    }
+
+    fn bubblewrap_command(&self, shell: &str, command: &str) -> Command {
+        let mut process = Command::new("bwrap");
+        process.args([
+            "--die-with-parent",
+            "--unshare-net",
+            "--unshare-pid",
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+        ]);
+        if self.private_tmp() {
+            process.args(["--tmpfs", "/tmp"]);
+        }
+        process
+            .arg("--overlay-src")
+            .arg(&self.cwd)
+            .arg("--overlay")
+            .arg(self.upper())
+            .arg(self.work())
+            .arg(&self.cwd)
+            .arg("--chdir")
+            .arg(&self.cwd)
+            .arg("--")
+            .arg(shell)
+            .arg("-c")
+            .arg(command);
+        process
+    }
+
+    #[cfg(target_os = "linux")]
+    fn namespaces_command(&self, shell: &str, command: &str) -> ShellResult<Command> {
+        use std::os::unix::process::CommandExt;
+
+        let setup = namespaces::Setup::new(self)?;
+        let mut process = Command::new(shell);
+        process.arg("-c").arg(command).current_dir(&self.cwd);
+        // SAFETY: `enter` only makes system calls on data prepared before
+        // the fork and does not allocate.
+        unsafe {
+            process.pre_exec(move || namespaces::enter(&setup));
+        }
+        Ok(process)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn namespaces_command(&self, _shell: &str, _command: &str) -> ShellResult<Command> {
+        Err(ShellError::Sandbox(
+            "namespaces are only available on Linux".to_string(),
+        ))
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.scratch);
+    }
+}
+
+/// Whether `backend` can run a command on this machine
+fn probe(backend: SandboxBackend) -> bool {
+    if backend == SandboxBackend::Bubblewrap && !in_path("bwrap") {
+        return false;
+    }
+    let dir = std::env::temp_dir().join(format!("cmdr-sandbox-probe-{}", std::process::id()));
+    if fs::create_dir_all(&dir).is_err() {
+        return false;
+    }
+    let works = Sandbox::with_backend(&dir, backend)
+        .and_then(|sandbox| {
+            let mut command = sandbox.command("sh", "echo probe > probe")?;
+            let status = command
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .map_err(|e| ShellError::Sandbox(e.to_string()))?;
+            Ok(status.success() && !dir.join("probe").exists() && sandbox.changes()?.len() == 1)
+        })
+        .unwrap_or(false);
+    let _ = fs::remove_dir_all(&dir);
+    works
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Compare the overlay's upper directory under `relative` with the real
+/// working directory
+fn collect_changes(
+    upper: &Path,
+    lower: &Path,
+    relative: &Path,
+    changes: &mut Vec<FileChange>,
+) -> io::Result<()> {
+    let mut push = |path: PathBuf, kind| changes.push(FileChange { path, kind });
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(upper.join(relative))? {
+        let entry = entry?;
+        entries.push((entry.file_name(), entry.metadata()?));
+    }
+
+    let mut recurse = Vec::new();
+    for (name, metadata) in &entries {
+        let path = relative.join(name);
+        let original = fs::symlink_metadata(lower.join(&path)).ok();
+        if is_whiteout(metadata) {
+            if original.is_some() {
+                push(path, ChangeKind::Deleted);
+            }
+            continue;
+        }
+        match original {
+            Some(original) if original.is_dir() && metadata.is_dir() => {}
+            Some(_) => push(path.clone(), ChangeKind::Modified),
+            None => push(path.clone(), ChangeKind::Created),
+        }
+        if metadata.is_dir() {
+            recurse.push(path);
+        }
+    }
+
+    // An opaque directory was deleted and recreated: everything that was in
+    // it and is not in the overlay is gone
+    let here = upper.join(relative);
+    if relative != Path::new("") && is_opaque(&here) {
+        if let Ok(original) = fs::read_dir(lower.join(relative)) {
+            for entry in original.flatten() {
+                if !entries.iter().any(|(name, _)| *name == entry.file_name()) {
+                    push(relative.join(entry.file_name()), ChangeKind::Deleted);
+                }
+            }
+        }
+    }
+
+    for path in recurse {
+        collect_changes(upper, lower, &path, changes)?;
+    }
+    Ok(())
+}
+
+/// Whether an overlay entry marks a deleted file
+fn is_whiteout(metadata: &fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+        metadata.file_type().is_char_device() && metadata.rdev() == 0
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        false
+    }
+}
+
+/// Whether an overlay directory hides the original directory's contents
+fn is_opaque(dir: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+            return false;
+        };
+        [c"user.overlay.opaque", c"trusted.overlay.opaque"]
+            .iter()
+            .any(|name| {
+                let mut value = [0u8; 1];
+                // SAFETY: both strings are NUL-terminated and the buffer
+                // length matches.
+                let len = unsafe {
+                    libc::lgetxattr(
+                        path.as_ptr(),
+                        name.as_ptr(),
+                        value.as_mut_ptr().cast(),
+                        value.len(),
+                    )
+                };
+                len == 1 && value[0] == b'y'
+            })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = dir;
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod namespaces {
+    //! Setting up the sandbox in the child between fork and exec
+
+    use super::Sandbox;
+    use crate::shell::{ShellError, ShellResult};
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    /// Device nodes bound into the sandbox's private `/dev`
+    const DEVICES: &[&str] = &["null", "zero", "full", "random", "urandom", "tty"];
+
+    /// Symbolic links created in the private `/dev`
+    const DEVICE_LINKS: &[(&str, &str)] = &[
+        ("/proc/self/fd", "fd"),
+        ("/proc/self/fd/0", "stdin"),
+        ("/proc/self/fd/1", "stdout"),
+        ("/proc/self/fd/2", "stderr"),
+        ("pts/ptmx", "ptmx"),
+    ];
+
+    /// Everything the child needs, prepared before the fork so setting up
+    /// the sandbox does not allocate
+    pub(super) struct Setup {
+        uid_map: CString,
+        gid_map: CString,
+        cwd: CString,
+        overlay_options: CString,
+        dev: CString,
+        devices: Vec<(CString, CString)>,
+        pts: Option<(CString, CString)>,
+        links: Vec<(CString, CString)>,
+        /// Mount points to make read-only with the flags they must keep
+        mounts: Vec<(CString, libc::c_ulong)>,
+        private_tmp: bool,
+    }
+
+    impl Setup {
+        pub(super) fn new(sandbox: &Sandbox) -> ShellResult<Self> {
+            // SAFETY: getuid and getgid cannot fail.
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let dev = sandbox.dev();
+            let devices = DEVICES
+                .iter()
+                .map(|name| Path::new("/dev").join(name))
+                .filter(|source| source.exists())
+                .map(|source| {
+                    Ok((
+                        c_path(&source)?,
+                        c_path(&dev.join(source.file_name().unwrap_or_default()))?,
+                    ))
+                })
+                .collect::<ShellResult<_>>()?;
+            let pts = if Path::new("/dev/pts").is_dir() {
+                Some((c_path(Path::new("/dev/pts"))?, c_path(&dev.join("pts"))?))
+            } else {
+                None
+            };
+            let links = DEVICE_LINKS
+                .iter()
+                .map(|(target, link)| Ok((c_string(target)?, c_path(&dev.join(link))?)))
+                .collect::<ShellResult<_>>()?;
+            let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")
+                .map_err(|e| ShellError::Sandbox(format!("cannot read mount table: {e}")))?;
+
+            Ok(Self {
+                uid_map: c_string(&format!("{uid} {uid} 1"))?,
+                gid_map: c_string(&format!("{gid} {gid} 1"))?,
+                cwd: c_path(&sandbox.cwd)?,
+                overlay_options: c_string(&format!(
+                    "lowerdir={},upperdir={},workdir={},userxattr",
+                    sandbox.cwd.display(),
+                    sandbox.upper().display(),
+                    sandbox.work().display()
+                ))?,
+                dev: c_path(&dev)?,
+                devices,
+                pts,
+                links,
+                mounts: parse_mountinfo(&mountinfo)?,
+                private_tmp: sandbox.private_tmp(),
+            })
+        }
+    }
+
+    /// Enter new namespaces and arrange the filesystem; runs in the child
+    pub(super) fn enter(setup: &Setup) -> io::Result<()> {
+        // SAFETY: all pointers come from live, NUL-terminated CStrings or
+        // are null where the system calls allow it.
+        unsafe {
+            check(libc::unshare(
+                libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET,
+            ))?;
+            write_file(c"/proc/self/setgroups", b"deny")?;
+            write_file(c"/proc/self/uid_map", setup.uid_map.as_bytes())?;
+            write_file(c"/proc/self/gid_map", setup.gid_map.as_bytes())?;
+            mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE, None)?;
+
+            // A private /dev with only harmless devices
+            mount(
+                Some(c"tmpfs"),
+                &setup.dev,
+                Some(c"tmpfs"),
+                0,
+                Some(c"mode=755"),
+            )?;
+            for (source, target) in &setup.devices {
+                let fd = libc::open(
+                    target.as_ptr(),
+                    libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                    0o666,
+                );
+                check(fd)?;
+                libc::close(fd);
+                mount(Some(source), target, None, libc::MS_BIND, None)?;
+            }
+            if let Some((source, target)) = &setup.pts {
+                check(libc::mkdir(target.as_ptr(), 0o755))?;
+                mount(
+                    Some(source),
+                    target,
+                    None,
+                    libc::MS_BIND | libc::MS_REC,
+                    None,
+                )?;
+            }
+            for (target, link) in &setup.links {
+                check(libc::symlink(target.as_ptr(), link.as_ptr()))?;
+            }
+            mount(Some(&setup.dev), c"/dev", None, libc::MS_MOVE, None)?;
+
+            mount(
+                Some(c"overlay"),
+                &setup.cwd,
+                Some(c"overlay"),
+                0,
+                Some(&setup.overlay_options),
+            )?;
+
+            // Mounts that refuse to become read-only (e.g. /proc entries)
+            // are left alone
+            for (path, flags) in &setup.mounts {
+                if path.as_bytes() != setup.cwd.as_bytes() {
+                    let _ = mount(
+                        None,
+                        path,
+                        None,
+                        libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | flags,
+                        None,
+                    );
+                }
+            }
+            if setup.private_tmp {
+                mount(
+                    Some(c"tmpfs"),
+                    c"/tmp",
+                    Some(c"tmpfs"),
+                    libc::MS_NOSUID | libc::MS_NODEV,
+                    Some(c"mode=1777"),
+                )?;
+            }
+            // Step onto the overlay that now covers the working directory
+            check(libc::chdir(setup.cwd.as_ptr()))?;
+        }
+        Ok(())
+    }
+
+    /// Mount points and their locked flags from `/proc/self/mountinfo`
+    fn parse_mountinfo(mountinfo: &str) -> ShellResult<Vec<(CString, libc::c_ulong)>> {
+        mountinfo
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split(' ').collect();
+                Some((*fields.get(4)?, *fields.get(5)?))
+            })
+            .map(|(mount_point, options)| {
+                let flags = options
+                    .split(',')
+                    .map(|option| match option {
+                        "nosuid" => libc::MS_NOSUID,
+                        "nodev" => libc::MS_NODEV,
+                        "noexec" => libc::MS_NOEXEC,
+                        "noatime" => libc::MS_NOATIME,
+                        "nodiratime" => libc::MS_NODIRATIME,
+                        "relatime" => libc::MS_RELATIME,
+                        "strictatime" => libc::MS_STRICTATIME,
+                        _ => 0,
+                    })
+                    .fold(0, |flags, flag| flags | flag);
+                Ok((c_string(&unescape(mount_point))?, flags))
+            })
+            .collect()
+    }
+
+    /// Undo the octal escapes mountinfo uses for spaces and other bytes
+    fn unescape(field: &str) -> String {
+        let bytes = field.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let escape = bytes.get(i + 1..i + 4).filter(|digits| {
+                bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d))
+            });
+            match escape {
+                Some(digits) => {
+                    let value = digits.iter().fold(0u32, |v, d| v * 8 + u32::from(d - b'0'));
+                    out.push(value as u8);
+                    i += 4;
+                }
+                None => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    fn c_string(text: &str) -> ShellResult<CString> {
+        CString::new(text).map_err(|_| ShellError::Sandbox(format!("invalid path {text:?}")))
+    }
+
+    fn c_path(path: &Path) -> ShellResult<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| ShellError::Sandbox(format!("invalid path {}", path.display())))
+    }
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn mount(
+        source: Option<&CStr>,
+        target: &CStr,
+        fstype: Option<&CStr>,
+        flags: libc::c_ulong,
+        data: Option<&CStr>,
+    ) -> io::Result<()> {
+        let ptr = |s: Option<&CStr>| s.map_or(ptr::null(), CStr::as_ptr);
+        check(libc::mount(
+            ptr(source),
+            target.as_ptr(),
+            ptr(fstype),
+            flags,
+            ptr(data).cast(),
+        ))
+    }
+
+    unsafe fn write_file(path: &CStr, contents: &[u8]) -> io::Result<()> {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        check(fd)?;
+        let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+        libc::close(fd);
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_parse_mountinfo() {
+            let mounts = parse_mountinfo(
+                "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                 30 22 0:5 / /mnt/my\\040disk rw,nosuid,nodev - tmpfs tmpfs rw\n",
+            )
+            .unwrap();
+            assert_eq!(mounts[0], (c"/".to_owned(), libc::MS_RELATIME));
+            assert_eq!(
+                mounts[1],
+                (c"/mnt/my disk".to_owned(), libc::MS_NOSUID | libc::MS_NODEV)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+
+    /// Run `command` in a sandbox over `dir`, or `None` if this machine
+    /// cannot sandbox
+    fn run_sandboxed(dir: &Path, command: &str) -> Option<(Sandbox, std::process::Output)> {
+        if Sandbox::available_backend().is_none() {
+            eprintln!("skipping: no sandbox backend available");
+            return None;
+        }
+        let sandbox = Sandbox::new(dir).unwrap();
+        let output = sandbox
+            .command("sh", command)
+            .unwrap()
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        Some((sandbox, output))
+    }
+
+    #[test]
+    fn test_reports_changes_without_applying_them() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("keep.txt"), "keep").unwrap();
+        fs::write(dir.path().join("edit.txt"), "old\n").unwrap();
+        fs::write(dir.path().join("gone.txt"), "gone").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/inner.txt"), "inner").unwrap();
+
+        let Some((sandbox, output)) = run_sandboxed(
+            dir.path(),
+            "echo new > new.txt && echo new >> edit.txt && rm gone.txt sub/inner.txt \
+             && mkdir made && touch made/file && cat edit.txt",
+        ) else {
+            return;
+        };
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "old\nnew\n");
+
+        let changes: Vec<(String, ChangeKind)> = sandbox
+            .changes()
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.path.display().to_string(), change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("edit.txt".to_string(), ChangeKind::Modified),
+                ("gone.txt".to_string(), ChangeKind::Deleted),
+                ("made".to_string(), ChangeKind::Created),
+                ("made/file".to_string(), ChangeKind::Created),
+                ("new.txt".to_string(), ChangeKind::Created),
+                ("sub/inner.txt".to_string(), ChangeKind::Deleted),
+            ]
+        );
+
+        // The real directory is untouched
+        assert_eq!(
+            fs::read_to_string(dir.path().join("edit.txt")).unwrap(),
+            "old\n"
+        );
+        assert!(dir.path().join("gone.txt").exists());
+        assert!(!dir.path().join("new.txt").exists());
+    }
+
+    #[test]
+    fn test_outside_is_read_only_and_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("x");
+        let Some((_sandbox, output)) = run_sandboxed(
+            dir.path(),
+            &format!(
+                "touch {} 2>/dev/null && echo wrote; \
+                 tail -n +3 /proc/net/dev | grep -vc 'lo:'",
+                target.display()
+            ),
+        ) else {
+            return;
+        };
+        // No network interfaces besides loopback
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+        assert!(!target.exists());
+    }
+}
@@ -0,0 +1,625 @@
+//! Persistent shell sessions
+//!
+//! A [`ShellSession`] keeps one shell running across commands, so `cd`,
+//! `export`, aliases and functions carry over from one command to the next.
+//! Each command is written to the shell's stdin followed by a sentinel: the
+//! shell prints a random marker once the command has finished, then its exit
+//! status, working directory and environment.
+
+use super::executor::{self, POLL_INTERVAL};
+use super::output::{self, Capture};
+use super::{
+    OutputCallback, OutputChunk, OutputStream, PolicyDecision, ShellError, ShellExecutor,
+    ShellKind, ShellResult,
+};
+use crate::inference::CancellationToken;
+use crate::types::{ExecutionResult, ShellCommand};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::BuildHasher;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Instant;
+
+/// Run by bash before the first command, since it only expands aliases in
+/// scripts when asked to
+const BASH_STARTUP: &str = "shopt -s expand_aliases\n";
+
+/// The program fish runs instead of reading scripts from stdin, which it
+/// would read to the end before running any of them
+const FISH_LOOP: &str = "while read --null-terminated __cmdr_script; eval $__cmdr_script; end";
+
+/// A long-lived shell that commands run in one after another
+///
+/// Commands are checked against the executor's safety policy and limited by
+/// its timeout, resource limits and output limit as usual. Commands the
+/// executor would sandbox, dry-run or put on a pseudo-terminal run as
+/// separate processes in the session's working directory and environment,
+/// so their own `cd` and `export` do not carry over.
+///
+/// A command that exits the shell, times out or is cancelled ends the
+/// shell; the next command starts a new one in the last known working
+/// directory and environment, losing aliases and unexported variables.
+#[derive(Debug)]
+pub struct ShellSession {
+    executor: ShellExecutor,
+    shell: Option<Shell>,
+    cwd: PathBuf,
+    env: BTreeMap<String, String>,
+    last_exit_code: Option<i32>,
+    /// Printed by the shell after each command; random so that no command
+    /// prints it by accident
+    marker: String,
+}
+
+impl ShellSession {
+    /// Start a session in the current directory and environment
+    ///
+    /// The shell itself starts with the first command.
+    pub fn new(executor: ShellExecutor) -> ShellResult<Self> {
+        let cwd = std::env::current_dir()
+            .map_err(|e| ShellError::Execution(format!("no working directory: {e}")))?;
+        let env = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        let marker = format!(
+            "__cmdr_{:016x}",
+            RandomState::new().hash_one(std::process::id())
+        );
+        Ok(Self {
+            executor,
+            shell: None,
+            cwd,
+            env,
+            last_exit_code: None,
+            marker,
+        })
+    }
+
+    /// The executor whose settings commands run with
+    pub fn executor(&self) -> &ShellExecutor {
+        &self.executor
+    }
+
+    /// The shell's working directory after the last command
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// The shell's exported variables after the last command
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    /// The exit code of the last command that ran, if any
+    pub fn last_exit_code(&self) -> Option<i32> {
+        self.last_exit_code
+    }
+
+    /// Evaluate `command` against the safety policy as if run next in the
+    /// session, resolving relative paths against its working directory
+    pub fn check(&self, command: &str) -> PolicyDecision {
+        self.executor
+            .clone()
+            .with_state(self.cwd.clone(), self.env.clone())
+            .check(command)
+    }
+
+    /// Execute a shell command in the session
+    pub fn execute(&mut self, command: &ShellCommand) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, &CancellationToken::new(), false, &mut |_| {})
+    }
+
+    /// Execute a shell command in the session, passing its output to
+    /// `on_output` as it arrives
+    ///
+    /// See [`ShellExecutor::execute_streaming`].
+    pub fn execute_streaming(
+        &mut self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+        on_output: &mut OutputCallback<'_>,
+    ) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, cancellation, false, on_output)
+    }
+
+    /// Execute a command the user has confirmed in the session, passing its
+    /// output to `on_output` as it arrives
+    ///
+    /// See [`ShellExecutor::execute_confirmed_streaming`].
+    pub fn execute_confirmed_streaming(
+        &mut self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+        on_output: &mut OutputCallback<'_>,
+    ) -> ShellResult<ExecutionResult> {
+        self.execute_checked(command, cancellation, true, on_output)
+    }
+
+    fn execute_checked(
+        &mut self,
+        command: &ShellCommand,
+        cancellation: &CancellationToken,
+        confirmed: bool,
+        on_output: &mut OutputCallback<'_>,
+    ) -> ShellResult<ExecutionResult> {
+        let executor = self
+            .executor
+            .clone()
+            .with_state(self.cwd.clone(), self.env.clone());
+        let one_off = !executor.is_auto_execute()
+            || executor.is_sandboxed()
+            || executor.is_dry_run()
+            || executor.wants_pty(&executor.admit(&command.command, confirmed)?);
+        if one_off {
+            let result = executor.execute_checked(command, cancellation, confirmed, on_output)?;
+            if executor.is_auto_execute() && !executor.is_dry_run() {
+                self.last_exit_code = Some(result.exit_code);
+            }
+            return Ok(result);
+        }
+        executor.check_syntax(&command.command)?;
+
+        if !self.shell.as_mut().is_some_and(Shell::is_running) {
+            self.shell = Some(self.spawn()?);
+        }
+        let script = sentinel_script(self.kind(), &command.command, &self.marker);
+        let shell = self.shell.as_mut().expect("the shell was just started");
+        let started = Instant::now();
+        shell
+            .stdin
+            .write_all(script.as_bytes())
+            .and_then(|_| shell.stdin.flush())
+            .map_err(|e| ShellError::Execution(format!("cannot write to the shell: {e}")))?;
+
+        let mut stdout = Boundary::new(&self.marker);
+        let mut stderr = Boundary::new(&self.marker);
+        let end = format!("\n{}.\n", self.marker);
+        let mut capture = Capture::new(executor.output_limit());
+        let mut on_data = |stream, data: Vec<u8>| {
+            let chunk = OutputChunk {
+                stream,
+                data,
+                elapsed: started.elapsed(),
+            };
+            if let Some(chunk) = capture.push(chunk) {
+                on_output(&chunk);
+            }
+        };
+        let deadline = executor.timeout().map(|timeout| started + timeout);
+        let mut killed = false;
+        let finished = loop {
+            if stdout.trailer_contains(end.as_bytes()) && stderr.trailer_contains(b"\n") {
+                break true;
+            }
+            match shell.chunks.recv_timeout(POLL_INTERVAL) {
+                Ok(chunk) => {
+                    let boundary = match chunk.stream {
+                        OutputStream::Stdout => &mut stdout,
+                        OutputStream::Stderr => &mut stderr,
+                    };
+                    let data = boundary.push(&chunk.data);
+                    if !data.is_empty() {
+                        on_data(chunk.stream, data);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // The shell exited before finishing the sentinel
+                Err(RecvTimeoutError::Disconnected) => break false,
+            }
+            if cancellation.is_cancelled() {
+                executor::kill_process_group(&mut shell.child);
+                let _ = shell.child.wait();
+                self.shell = None;
+                return Err(ShellError::Cancelled);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                executor::kill_process_group(&mut shell.child);
+                killed = true;
+                break false;
+            }
+        };
+        let duration = started.elapsed();
+
+        let (exit_code, signal) = if finished {
+            let state = State::parse(stdout.trailer(), &end);
+            self.cwd = state.cwd;
+            self.env = state.env;
+            (state.exit_code, None)
+        } else {
+            for (stream, boundary) in [
+                (OutputStream::Stdout, &mut stdout),
+                (OutputStream::Stderr, &mut stderr),
+            ] {
+                let data = boundary.flush();
+                if !data.is_empty() {
+                    on_data(stream, data);
+                }
+            }
+            let status = shell
+                .child
+                .wait()
+                .map_err(|e| ShellError::Execution(e.to_string()))?;
+            self.shell = None;
+            (status.code().unwrap_or(-1), executor::signal(&status))
+        };
+        self.last_exit_code = Some(exit_code);
+
+        let error = String::from_utf8_lossy(&capture.stderr).to_string();
+        Ok(ExecutionResult {
+            success: exit_code == 0 && !killed,
+            output: String::from_utf8_lossy(&capture.stdout).to_string(),
+            error: if error.is_empty() { None } else { Some(error) },
+            exit_code,
+            truncated: capture.truncated,
+            duration,
+            signal,
+            killed,
+            pty: false,
+            changes: None,
+            preview: None,
+        })
+    }
+
+    /// The dialect of the session's shell; unknown shells are taken to be
+    /// POSIX shells
+    fn kind(&self) -> ShellKind {
+        self.executor.kind().unwrap_or(ShellKind::Sh)
+    }
+
+    /// Start a shell in the session's working directory and environment
+    fn spawn(&self) -> ShellResult<Shell> {
+        let mut process = Command::new(self.executor.shell());
+        if self.kind() == ShellKind::Fish {
+            process.arg("-c").arg(FISH_LOOP);
+        }
+        process
+            .current_dir(&self.cwd)
+            .env_clear()
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // The shell leads a new process group so that a command it runs can
+        // be killed along with everything the command started
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            process.process_group(0);
+            let limits = *self.executor.limits();
+            if !limits.is_unlimited() {
+                // SAFETY: `apply` only calls the async-signal-safe
+                // `getrlimit` and `setrlimit`.
+                unsafe {
+                    process.pre_exec(move || limits.apply());
+                }
+            }
+        }
+        let mut child = process
+            .spawn()
+            .map_err(|e| ShellError::Execution(e.to_string()))?;
+
+        let (sender, chunks) = mpsc::channel();
+        let now = Instant::now();
+        output::forward(
+            child.stdout.take(),
+            OutputStream::Stdout,
+            now,
+            sender.clone(),
+        );
+        output::forward(child.stderr.take(), OutputStream::Stderr, now, sender);
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        if self.kind() == ShellKind::Bash {
+            stdin
+                .write_all(BASH_STARTUP.as_bytes())
+                .map_err(|e| ShellError::Execution(format!("cannot write to the shell: {e}")))?;
+        }
+        Ok(Shell {
+            child,
+            stdin,
+            chunks,
+        })
+    }
+}
+
+impl Drop for ShellSession {
+    fn drop(&mut self) {
+        if let Some(shell) = &mut self.shell {
+            executor::kill_process_group(&mut shell.child);
+            let _ = shell.child.wait();
+        }
+    }
+}
+
+/// A running shell
+#[derive(Debug)]
+struct Shell {
+    child: Child,
+    stdin: ChildStdin,
+    /// Everything the shell writes to stdout and stderr
+    chunks: Receiver<OutputChunk>,
+}
+
+impl Shell {
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+/// The script that runs `command` in a `kind` shell and then prints the
+/// sentinel
+///
+/// The command is run with `eval` so that a syntax error in it cannot
+/// swallow the sentinel, and with stdin from `/dev/null` so it cannot read
+/// the scripts that follow. The marker goes to stderr first, then to stdout
+/// followed by the exit status, working directory and environment, and
+/// finally the marker and a full stop.
+fn sentinel_script(kind: ShellKind, command: &str, marker: &str) -> String {
+    let (save_status, status, end) = match kind {
+        ShellKind::Fish => ("set __cmdr_status $status", "$__cmdr_status", "\0"),
+        _ => ("__cmdr_status=$?", "\"$__cmdr_status\"", ""),
+    };
+    format!(
+        "eval {} </dev/null\n\
+         {save_status}\n\
+         command printf '\\n%s\\n' {marker} >&2\n\
+         command printf '\\n%s %d\\n' {marker} {status}\n\
+         command pwd\n\
+         command env\n\
+         command printf '%s.\\n' {marker}\n{end}",
+        kind.quote(command)
+    )
+}
+
+/// What the shell reported after a command
+#[derive(Debug, PartialEq)]
+struct State {
+    exit_code: i32,
+    cwd: PathBuf,
+    env: BTreeMap<String, String>,
+}
+
+impl State {
+    /// Parse what followed the marker on stdout, up to `end`
+    fn parse(trailer: &[u8], end: &str) -> Self {
+        let text = String::from_utf8_lossy(trailer);
+        let text = text.find(end).map_or(&*text, |at| &text[..at]);
+        let (status, rest) = text.trim_start().split_once('\n').unwrap_or((text, ""));
+        let (cwd, env) = rest.split_once('\n').unwrap_or((rest, ""));
+        Self {
+            exit_code: status.trim().parse().unwrap_or(-1),
+            cwd: PathBuf::from(cwd),
+            env: parse_env(env),
+        }
+    }
+}
+
+/// Parse the output of `env`, where values may span several lines
+fn parse_env(text: &str) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    let mut last: Option<String> = None;
+    for line in text.lines() {
+        let variable = line.split_once('=').filter(|(name, _)| {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        match (variable, &last) {
+            (Some((name, value)), _) => {
+                env.insert(name.to_string(), value.to_string());
+                last = Some(name.to_string());
+            }
+            (None, Some(name)) => {
+                let value: &mut String = env.get_mut(name).expect("added above");
+                value.push('\n');
+                value.push_str(line);
+            }
+            (None, None) => {}
+        }
+    }
+    env
+}
+
+/// One of the shell's output streams, split at the sentinel marker
+#[derive(Debug)]
+struct Boundary {
+    /// The marker, after the newline the sentinel prints before it
+    marker: Vec<u8>,
+    /// Output held back because it could be the start of the marker
+    pending: Vec<u8>,
+    /// What followed the marker, once it has been seen
+    trailer: Option<Vec<u8>>,
+}
+
+impl Boundary {
+    fn new(marker: &str) -> Self {
+        Self {
+            marker: format!("\n{marker}").into_bytes(),
+            pending: Vec::new(),
+            trailer: None,
+        }
+    }
+
+    /// Add `data` read from the stream, returning the part that is
+    /// certainly the command's output
+    fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        if let Some(trailer) = &mut self.trailer {
+            trailer.extend_from_slice(data);
+            return Vec::new();
+        }
+        self.pending.extend_from_slice(data);
+        if let Some(at) = find(&self.pending, &self.marker) {
+            self.trailer = Some(self.pending.split_off(at + self.marker.len()));
+            self.pending.truncate(at);
+            return std::mem::take(&mut self.pending);
+        }
+        let held = (1..self.marker.len().min(self.pending.len() + 1))
+            .rev()
+            .find(|&len| self.pending.ends_with(&self.marker[..len]))
+            .unwrap_or(0);
+        let certain = self.pending.len() - held;
+        self.pending.drain(..certain).collect()
+    }
+
+    /// Whether `text` followed the marker
+    fn trailer_contains(&self, text: &[u8]) -> bool {
+        self.trailer
+            .as_deref()
+            .is_some_and(|trailer| find(trailer, text).is_some())
+    }
+
+    fn trailer(&self) -> &[u8] {
+        self.trailer.as_deref().unwrap_or_default()
+    }
+
+    /// Output held back when the stream ended without the marker
+    fn flush(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{PolicyAction, PolicyRule, SafetyPolicy};
+
+    fn command(text: &str) -> ShellCommand {
+        ShellCommand {
+            command: text.to_string(),
+            explanation: None,
+            confidence: 1.0,
+        }
+    }
+
+    fn session() -> ShellSession {
+        ShellSession::new(ShellExecutor::new("sh".to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_state_carries_across_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let mut session = session();
+
+        let result = session
+            .execute(&command(&format!(
+                "cd {} && export CMDR_TEST=42 && alias hello='echo hi'",
+                ShellKind::Sh.quote(&dir.to_string_lossy())
+            )))
+            .unwrap();
+        assert!(result.success, "{result:?}");
+        assert_eq!(session.cwd(), dir);
+        assert_eq!(
+            session.env().get("CMDR_TEST").map(String::as_str),
+            Some("42")
+        );
+
+        let result = session
+            .execute(&command("pwd; echo $CMDR_TEST; hello"))
+            .unwrap();
+        assert_eq!(result.output, format!("{}\n42\nhi\n", dir.display()));
+    }
+
+    #[test]
+    fn test_exit_codes_and_output() {
+        let mut session = session();
+        let result = session
+            .execute(&command("printf out; echo err >&2; false"))
+            .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.exit_code, 1);
+        assert_eq!(result.output, "out");
+        assert_eq!(result.error.as_deref(), Some("err\n"));
+        assert_eq!(session.last_exit_code(), Some(1));
+
+        // A command that cannot be parsed needs confirming, and then the
+        // syntax error is caught before it reaches the shell
+        let unterminated = command("echo 'unterminated");
+        let error = session.execute(&unterminated).unwrap_err();
+        assert!(
+            matches!(error, ShellError::ConfirmationRequired(_)),
+            "{error}"
+        );
+        let error = session
+            .execute_confirmed_streaming(&unterminated, &CancellationToken::new(), &mut |_| {})
+            .unwrap_err();
+        assert!(matches!(error, ShellError::InvalidCommand(_)), "{error}");
+        assert!(session.execute(&command("true")).unwrap().success);
+    }
+
+    #[test]
+    fn test_policy_paths_follow_the_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let guarded = dir.join("b");
+        let policy = SafetyPolicy::new(PolicyAction::Allow)
+            .with_rule(PolicyRule::new(PolicyAction::Deny).with_paths([guarded.to_string_lossy()]));
+        let mut session =
+            ShellSession::new(ShellExecutor::new("sh".to_string()).with_policy(policy)).unwrap();
+        assert!(session.check("touch b").is_allowed());
+
+        let cd = format!("cd {}", ShellKind::Sh.quote(&dir.to_string_lossy()));
+        assert!(session.execute(&command(&cd)).unwrap().success);
+        let decision = session.check("touch b");
+        assert_eq!(decision.action, PolicyAction::Deny);
+        let error = session.execute(&command("touch b")).unwrap_err();
+        assert!(matches!(error, ShellError::PolicyDenied(_)), "{error}");
+        assert!(!guarded.exists());
+        assert!(session.check("touch ../b").is_allowed());
+    }
+
+    #[test]
+    fn test_restarts_after_exit() {
+        let mut session = session();
+        session
+            .execute(&command("cd / && export CMDR_TEST=1"))
+            .unwrap();
+
+        let result = session.execute(&command("exit 7")).unwrap();
+        assert_eq!(result.exit_code, 7);
+
+        let result = session.execute(&command("pwd; echo $CMDR_TEST")).unwrap();
+        assert_eq!(result.output, "/\n1\n");
+    }
+
+    #[test]
+    fn test_timeout_ends_shell() {
+        let executor = ShellExecutor::new("sh".to_string())
+            .with_timeout(std::time::Duration::from_millis(200));
+        let mut session = ShellSession::new(executor).unwrap();
+        session.execute(&command("cd /")).unwrap();
+
+        let result = session.execute(&command("echo started; sleep 30")).unwrap();
+        assert!(result.killed);
+        assert!(!result.success);
+        assert_eq!(result.output, "started\n");
+
+        let result = session.execute(&command("pwd")).unwrap();
+        assert_eq!(result.output, "/\n");
+    }
+
+    #[test]
+    fn test_boundary_holds_back_partial_marker() {
+        let mut boundary = Boundary::new("MARK");
+        assert_eq!(boundary.push(b"abc\n"), b"abc");
+        assert_eq!(boundary.push(b"\nMA"), b"\n");
+        assert_eq!(boundary.push(b"RK 0\n"), b"");
+        assert!(boundary.trailer_contains(b" 0\n"));
+    }
+
+    #[test]
+    fn test_parse_env() {
+        let env = parse_env("A=1\nB=two\nlines\nC=x=y");
+        assert_eq!(env["A"], "1");
+        assert_eq!(env["B"], "two\nlines");
+        assert_eq!(env["C"], "x=y");
+    }
+}
@@ -3,14 +3,26 @@
 //! This module implements the core translation logic for converting
 //! natural language to shell commands.
 
-use super::TranslationResult;
-use crate::inference::InferenceEngine;
+use super::{response, ExampleLibrary, PromptTemplate, TranslationError, TranslationResult};
+use crate::inference::{GenerationOptions, InferenceEngine, OutputConstraint, TokenCallback};
+use crate::shell::ShellKind;
 use crate::types::{NaturalLanguageRequest, ShellCommand};
 
+/// Generations attempted per requested candidate before giving up on
+/// finding more distinct commands
+const ATTEMPTS_PER_CANDIDATE: usize = 2;
+
+/// Few-shot examples included in each prompt by default
+const DEFAULT_EXAMPLE_COUNT: usize = 3;
+
 /// Translation engine for converting natural language to shell commands
 pub struct TranslationEngine<E> {
     inference_engine: E,
-    prompt_template: String,
+    prompt_template: PromptTemplate,
+    examples: ExampleLibrary,
+    example_count: usize,
+    output_constraint: Option<OutputConstraint>,
+    shell: Option<ShellKind>,
 }
 
 impl<E: InferenceEngine> TranslationEngine<E> {
@@ -18,62 +30,469 @@ impl<E: InferenceEngine> TranslationEngine<E> {
     pub fn new(inference_engine: E) -> Self {
         Self {
             inference_engine,
-            prompt_template: Self::default_prompt_template(),
+            prompt_template: PromptTemplate::default(),
+            examples: ExampleLibrary::default(),
+            example_count: DEFAULT_EXAMPLE_COUNT,
+            output_constraint: Some(OutputConstraint::JsonSchema(
+                response::shell_command_schema(),
+            )),
+            shell: None,
         }
     }
 
     /// Set a custom prompt template
-    pub fn with_prompt_template(mut self, template: String) -> Self {
+    ///
+    /// See [`PromptTemplate`] for the variables the engine provides.
+    pub fn with_prompt_template(mut self, template: PromptTemplate) -> Self {
         self.prompt_template = template;
         self
     }
 
+    /// Set the library few-shot examples are chosen from
+    pub fn with_examples(mut self, examples: ExampleLibrary) -> Self {
+        self.examples = examples;
+        self
+    }
+
+    /// Set how many few-shot examples each prompt includes
+    pub fn with_example_count(mut self, count: usize) -> Self {
+        self.example_count = count;
+        self
+    }
+
+    /// Ask for commands in the dialect of `shell`
+    pub fn with_shell(mut self, shell: ShellKind) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    /// Set the constraint applied to model output
+    ///
+    /// Defaults to the JSON schema of the expected response; `None` lets the
+    /// model answer freely.
+    pub fn with_output_constraint(mut self, constraint: Option<OutputConstraint>) -> Self {
+        self.output_constraint = constraint;
+        self
+    }
+
     /// Translate a natural language request to a shell command
     pub async fn translate(
         &mut self,
         request: NaturalLanguageRequest,
     ) -> TranslationResult<ShellCommand> {
-        let prompt = self.build_prompt(&request);
-        let response = self.inference_engine.generate(&prompt).await?;
+        self.translate_stream(request, &GenerationOptions::new(), &mut |_| {})
+            .await
+    }
+
+    /// Translate a natural language request, passing the model output to
+    /// `on_token` as it is generated
+    ///
+    /// The returned command is the same as [`translate`](Self::translate)
+    /// would produce; streaming only lets callers render progress early.
+    /// `options` can cancel the translation or bound how long it may take;
+    /// a constraint in `options` takes precedence over the engine's own.
+    pub async fn translate_stream(
+        &mut self,
+        request: NaturalLanguageRequest,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> TranslationResult<ShellCommand> {
+        let prompt = self.build_prompt(&request)?;
+        let options = self.generation_options(options);
+        let response = self
+            .inference_engine
+            .generate_stream(&prompt, &options, on_token)
+            .await?;
+
+        response::parse_response(&response)
+    }
+
+    /// Translate a natural language request into up to `n` distinct
+    /// candidate commands, ranked by confidence
+    ///
+    /// Each candidate is sampled with its own seed, counting up from the
+    /// seed in `options` (or 0). Candidates whose normalized command text
+    /// matches an earlier one are merged, keeping the higher confidence, so
+    /// fewer than `n` are returned when the model keeps agreeing with itself.
+    /// Responses that cannot be parsed are skipped; if none can be, the last
+    /// parse error is returned.
+    pub async fn translate_n(
+        &mut self,
+        request: NaturalLanguageRequest,
+        n: usize,
+        options: &GenerationOptions,
+    ) -> TranslationResult<Vec<ShellCommand>> {
+        let prompt = self.build_prompt(&request)?;
+        let base_seed = options.seed.unwrap_or(0);
+        let mut candidates: Vec<ShellCommand> = Vec::new();
+        let mut last_error = None;
 
-        // TODO: Parse the response to extract the command and confidence
-        let command = self.parse_response(&response)?;
+        for attempt in 0..n * ATTEMPTS_PER_CANDIDATE {
+            if candidates.len() >= n {
+                break;
+            }
+            let options = self
+                .generation_options(options)
+                .with_seed(base_seed.wrapping_add(attempt as u64));
+            let response = self
+                .inference_engine
+                .generate_stream(&prompt, &options, &mut |_| {})
+                .await?;
 
-        Ok(command)
+            let command = match response::parse_response(&response) {
+                Ok(command) => command,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            let key = normalize_command(&command.command);
+            match candidates
+                .iter_mut()
+                .find(|c| normalize_command(&c.command) == key)
+            {
+                Some(existing) if existing.confidence < command.confidence => *existing = command,
+                Some(_) => {}
+                None => candidates.push(command),
+            }
+        }
+
+        if candidates.is_empty() {
+            return Err(last_error.unwrap_or_else(|| TranslationError::Generation {
+                message: "no candidates were requested".to_string(),
+                raw: None,
+            }));
+        }
+        // Stable, so equally confident candidates keep their sampling order
+        candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        Ok(candidates)
+    }
+
+    /// Fill in the engine's output constraint unless `options` has its own
+    fn generation_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        let mut options = options.clone();
+        if options.constraint.is_none() {
+            options.constraint = self.output_constraint.clone();
+        }
+        options
     }
 
     /// Build the prompt for the inference engine
-    fn build_prompt(&self, request: &NaturalLanguageRequest) -> String {
-        format!(
-            "{}\n\nUser request: {}\n\nShell command:",
-            self.prompt_template, request.text
-        )
-    }
-
-    /// Parse the inference engine response
-    fn parse_response(&self, response: &str) -> TranslationResult<ShellCommand> {
-        // TODO: Implement proper response parsing
-        // For now, just return the response as-is
-        Ok(ShellCommand {
-            command: response.trim().to_string(),
-            explanation: None,
-            confidence: 0.8,
-        })
-    }
-
-    /// Get the default prompt template
-    fn default_prompt_template() -> String {
-        r#"You are a helpful assistant that translates natural language requests into shell commands.
-
-Your task is to:
-1. Understand the user's intent
-2. Generate the appropriate shell command
-3. Provide a brief explanation if needed
-
-Rules:
-- Only output the shell command, no additional text
-- Use standard Unix/Linux commands
-- Be safe and avoid destructive operations
-- Prefer simple, readable commands"#.to_string()
+    ///
+    /// The template sees the request as `{{request}}`, the few-shot examples
+    /// most relevant to it as the list `{{examples}}` and the environment
+    /// context as `{{context}}` and, per source, as `{{os}}`, `{{shell}}`,
+    /// `{{cwd}}`, `{{directory_listing}}`, `{{git_status}}` and `{{tools}}`.
+    /// With a shell set, `{{dialect}}` names it and `{{dialect_notes}}`
+    /// describes its syntax. Context variables are only set when the template
+    /// uses them, so custom templates may leave any of them out.
+    fn build_prompt(&self, request: &NaturalLanguageRequest) -> TranslationResult<String> {
+        let mut template = self.prompt_template.clone();
+        template.set_variable("request", request.text.clone());
+        if template.references("examples") {
+            let examples: Vec<_> = self
+                .examples
+                .select(&request.text, self.example_count)
+                .into_iter()
+                .map(|example| example.to_value())
+                .collect();
+            template.set_variable("examples", examples);
+        }
+        if let Some(shell) = self.shell {
+            if template.references("dialect") {
+                template.set_variable("dialect", shell.to_string());
+            }
+            if template.references("dialect_notes") {
+                template.set_variable("dialect_notes", shell.syntax_notes());
+            }
+        }
+
+        if let Some(context) = &request.context {
+            if template.references("context") {
+                template.set_variable("context", context.render().trim_end());
+            }
+            for (name, value) in context.sources() {
+                if template.references(name) {
+                    template.set_variable(name, value);
+                }
+            }
+        }
+        template.render()
+    }
+}
+
+/// Normalize command text for duplicate detection
+///
+/// Collapses runs of whitespace and drops a trailing `;`, so `ls  -la;` and
+/// `ls -la` count as the same command.
+pub fn normalize_command(command: &str) -> String {
+    command
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(';')
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::EnvironmentContext;
+    use crate::inference::MockInferenceEngine;
+
+    fn request(text: &str) -> NaturalLanguageRequest {
+        NaturalLanguageRequest {
+            text: text.to_string(),
+            context: None,
+        }
+    }
+
+    const RESPONSE: &str =
+        r#"{"command": "ls -la", "explanation": "List all files", "confidence": 0.9}"#;
+
+    fn engine() -> TranslationEngine<MockInferenceEngine> {
+        let mut mock = MockInferenceEngine::new().with_response(RESPONSE);
+        mock.load_model("mock-model").unwrap();
+        TranslationEngine::new(mock)
+    }
+
+    #[tokio::test]
+    async fn test_translate_parses_structured_response() {
+        let command = engine().translate(request("list files")).await.unwrap();
+
+        assert_eq!(command.command, "ls -la");
+        assert_eq!(command.explanation.as_deref(), Some("List all files"));
+        assert_eq!(command.confidence, 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_translate_rejects_unparseable_response() {
+        let mut mock = MockInferenceEngine::new().with_response("I can't help with that.");
+        mock.load_model("mock-model").unwrap();
+        let result = TranslationEngine::new(mock)
+            .translate(request("do something"))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(TranslationError::Generation { raw: Some(raw), .. }) if raw == "I can't help with that."
+        ));
+    }
+
+    /// Records the constraint of each generation request
+    #[derive(Default)]
+    struct RecordingEngine {
+        constraints: Vec<Option<OutputConstraint>>,
+    }
+
+    #[async_trait::async_trait]
+    impl InferenceEngine for RecordingEngine {
+        fn initialize(&mut self) -> crate::inference::InferenceResult<()> {
+            Ok(())
+        }
+
+        fn load_model(&mut self, _model_path: &str) -> crate::inference::InferenceResult<()> {
+            Ok(())
+        }
+
+        async fn generate(&mut self, _prompt: &str) -> crate::inference::InferenceResult<String> {
+            self.constraints.push(None);
+            Ok(RESPONSE.to_string())
+        }
+
+        async fn generate_stream(
+            &mut self,
+            _prompt: &str,
+            options: &GenerationOptions,
+            _on_token: &mut TokenCallback<'_>,
+        ) -> crate::inference::InferenceResult<String> {
+            self.constraints.push(options.constraint.clone());
+            Ok(RESPONSE.to_string())
+        }
+
+        fn set_parameters(
+            &mut self,
+            _temperature: f32,
+            _max_tokens: usize,
+        ) -> crate::inference::InferenceResult<()> {
+            Ok(())
+        }
+
+        fn is_model_loaded(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_translate_constrains_output_by_default() {
+        let mut engine = TranslationEngine::new(RecordingEngine::default());
+        engine.translate(request("list files")).await.unwrap();
+
+        let grammar = OutputConstraint::Grammar("root ::= \"ls\"".to_string());
+        engine
+            .translate_stream(
+                request("list files"),
+                &GenerationOptions::new().with_constraint(grammar.clone()),
+                &mut |_: &str| {},
+            )
+            .await
+            .unwrap();
+
+        let mut engine = engine.with_output_constraint(None);
+        engine.translate(request("list files")).await.unwrap();
+
+        let constraints = &engine.inference_engine.constraints;
+        assert_eq!(
+            constraints[0],
+            Some(OutputConstraint::JsonSchema(
+                response::shell_command_schema()
+            ))
+        );
+        assert_eq!(constraints[1], Some(grammar));
+        assert_eq!(constraints[2], None);
+    }
+
+    #[tokio::test]
+    async fn test_translate_n_ranks_and_deduplicates() {
+        let responses = [
+            r#"{"command": "ls", "explanation": "List files", "confidence": 0.6}"#,
+            r#"{"command": "ls  -la;", "explanation": "List all", "confidence": 0.7}"#,
+            "Sorry, I am not sure.",
+            r#"{"command": "ls -la", "explanation": "List all files", "confidence": 0.9}"#,
+            r#"{"command": "find . -maxdepth 1", "explanation": "Find", "confidence": 0.8}"#,
+        ];
+        let mut mock = MockInferenceEngine::new()
+            .with_responses(responses.iter().map(|r| r.to_string()).collect());
+        mock.load_model("mock-model").unwrap();
+        let mut engine = TranslationEngine::new(mock);
+
+        let candidates = engine
+            .translate_n(request("list files"), 3, &GenerationOptions::new())
+            .await
+            .unwrap();
+
+        let commands: Vec<&str> = candidates.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls -la", "find . -maxdepth 1", "ls"]);
+        assert_eq!(candidates[0].explanation.as_deref(), Some("List all files"));
+    }
+
+    #[tokio::test]
+    async fn test_translate_n_stops_after_bounded_attempts() {
+        let mut engine = engine();
+        let candidates = engine
+            .translate_n(request("list files"), 3, &GenerationOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(candidates.len(), 1);
+
+        let mut mock = MockInferenceEngine::new().with_response("Sorry, I can't.");
+        mock.load_model("mock-model").unwrap();
+        let result = TranslationEngine::new(mock)
+            .translate_n(request("list files"), 2, &GenerationOptions::new())
+            .await;
+        assert!(matches!(result, Err(TranslationError::Generation { .. })));
+    }
+
+    #[test]
+    fn test_build_prompt_includes_context() {
+        let engine = TranslationEngine::new(MockInferenceEngine::new());
+        let mut with_context = request("list files");
+        with_context.context = Some(EnvironmentContext {
+            shell: Some("zsh 5.9".to_string()),
+            ..EnvironmentContext::default()
+        });
+
+        let prompt = engine.build_prompt(&with_context).unwrap();
+        assert!(prompt.ends_with(
+            "\n\nEnvironment:\nShell: zsh 5.9\n\nUser request: list files\n\nJSON response:"
+        ));
+
+        let prompt = engine.build_prompt(&request("list {{shell}}")).unwrap();
+        assert!(prompt.ends_with("\n\nUser request: list {{shell}}\n\nJSON response:"));
+    }
+
+    #[test]
+    fn test_build_prompt_names_dialect() {
+        let engine = TranslationEngine::new(MockInferenceEngine::new());
+        let prompt = engine.build_prompt(&request("list files")).unwrap();
+        assert!(prompt.contains("- Use standard Unix/Linux commands\n"));
+
+        let engine = engine.with_shell(ShellKind::Fish);
+        let prompt = engine.build_prompt(&request("list files")).unwrap();
+        assert!(prompt.contains("- Use standard Unix/Linux commands in fish syntax\n"));
+        assert!(prompt.contains("\n- Use fish syntax: `set -gx NAME value`"));
+    }
+
+    #[test]
+    fn test_build_prompt_sets_only_referenced_context() {
+        let template = PromptTemplate::new("{{request}} in {{shell | default: \"sh\"}}").unwrap();
+        let engine =
+            TranslationEngine::new(MockInferenceEngine::new()).with_prompt_template(template);
+        let mut with_context = request("list files");
+        with_context.context = Some(EnvironmentContext {
+            os: Some("Linux".to_string()),
+            shell: Some("zsh 5.9".to_string()),
+            ..EnvironmentContext::default()
+        });
+
+        assert_eq!(
+            engine.build_prompt(&with_context).unwrap(),
+            "list files in zsh 5.9"
+        );
+        assert_eq!(
+            engine.build_prompt(&request("list files")).unwrap(),
+            "list files in sh"
+        );
+    }
+
+    #[test]
+    fn test_build_prompt_includes_relevant_examples() {
+        let mut examples = ExampleLibrary::empty();
+        for (request, command) in [
+            ("show free disk space", "df -h"),
+            ("list docker containers", "docker ps"),
+            ("extract an archive", "tar -xf archive.tar"),
+        ] {
+            examples.add(crate::translation::Example {
+                request: request.to_string(),
+                command: command.to_string(),
+                explanation: None,
+            });
+        }
+        let engine = TranslationEngine::new(MockInferenceEngine::new())
+            .with_examples(examples)
+            .with_example_count(1);
+
+        let prompt = engine
+            .build_prompt(&request("stop all docker containers"))
+            .unwrap();
+        assert!(prompt.contains(
+            "Example 1:\nUser request: list docker containers\nJSON response: {\"command\":\"docker ps\",\"confidence\":0.9}\n"
+        ));
+        assert!(!prompt.contains("Example 2:"));
+
+        let engine = engine.with_example_count(0);
+        let prompt = engine.build_prompt(&request("list files")).unwrap();
+        assert!(!prompt.contains("Example"));
+    }
+
+    #[tokio::test]
+    async fn test_translate_stream_matches_translate() {
+        let mut engine = engine();
+
+        let mut streamed = String::new();
+        let streamed_command = engine
+            .translate_stream(
+                request("list files"),
+                &GenerationOptions::new(),
+                &mut |t: &str| streamed.push_str(t),
+            )
+            .await
+            .unwrap();
+        let command = engine.translate(request("list files")).await.unwrap();
+
+        assert_eq!(streamed, RESPONSE);
+        assert_eq!(streamed_command.command, command.command);
     }
 }
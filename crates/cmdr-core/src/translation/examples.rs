@@ -0,0 +1,390 @@
+//! Few-shot examples for translation prompts
+//!
+//! Small models follow the expected answer format and pick better commands
+//! when the prompt shows a few worked examples. A curated, versioned set
+//! ships with cmdr; users can add their own from TOML files. For each request
+//! the library picks the examples sharing the most informative keywords with
+//! it, so the selection is deterministic.
+//!
+//! Example files look like the shipped `examples.toml`:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [[examples]]
+//! request = "list all files including hidden ones"
+//! command = "ls -la"
+//! explanation = "Lists every entry in the current directory."
+//! ```
+
+use super::{TranslationError, TranslationResult};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// The example set shipped with cmdr
+const BUILTIN_EXAMPLES: &str = include_str!("examples.toml");
+
+/// Newest example file format this version of cmdr understands
+pub const EXAMPLES_FORMAT_VERSION: u32 = 1;
+
+/// Confidence shown in example responses
+const EXAMPLE_CONFIDENCE: f64 = 0.9;
+
+/// Words too common to say anything about which example fits a request
+const STOP_WORDS: &[&str] = &[
+    "a", "all", "an", "and", "are", "as", "at", "be", "by", "can", "do", "for", "from", "how", "i",
+    "in", "into", "is", "it", "me", "my", "of", "on", "or", "please", "that", "the", "this", "to",
+    "want", "what", "with", "you",
+];
+
+/// A natural language request paired with the command answering it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    /// The request as a user would type it
+    pub request: String,
+    /// The command that fulfils the request
+    pub command: String,
+    /// What the command does
+    #[serde(default)]
+    pub explanation: Option<String>,
+}
+
+impl Example {
+    /// The JSON response the model should give for this example
+    pub fn response(&self) -> String {
+        let mut response = serde_json::Map::new();
+        response.insert("command".to_string(), self.command.clone().into());
+        if let Some(explanation) = &self.explanation {
+            response.insert("explanation".to_string(), explanation.clone().into());
+        }
+        response.insert("confidence".to_string(), EXAMPLE_CONFIDENCE.into());
+        serde_json::Value::Object(response).to_string()
+    }
+
+    /// The example as prompt template variables
+    ///
+    /// Inside `{{#each examples}}` these are `{{request}}`, `{{command}}`,
+    /// `{{explanation}}` and `{{response}}`.
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::json!({
+            "request": self.request,
+            "command": self.command,
+            "explanation": self.explanation,
+            "response": self.response(),
+        })
+    }
+}
+
+/// Contents of an example file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExampleFile {
+    version: u32,
+    #[serde(default)]
+    examples: Vec<Example>,
+}
+
+/// An example and the keywords it is matched on
+#[derive(Debug, Clone)]
+struct Entry {
+    example: Example,
+    terms: BTreeSet<String>,
+    user: bool,
+}
+
+/// A collection of examples to choose from
+#[derive(Debug, Clone)]
+pub struct ExampleLibrary {
+    entries: Vec<Entry>,
+    builtin_version: Option<u32>,
+}
+
+impl ExampleLibrary {
+    /// Create a library without any examples
+    pub fn empty() -> Self {
+        Self {
+            entries: Vec::new(),
+            builtin_version: None,
+        }
+    }
+
+    /// Create a library holding the examples shipped with cmdr
+    pub fn builtin() -> Self {
+        let file = parse_file(BUILTIN_EXAMPLES, "built-in examples")
+            .expect("the built-in examples are valid");
+        let mut library = Self::empty();
+        library.builtin_version = Some(file.version);
+        for example in file.examples {
+            library.push(example, false);
+        }
+        library
+    }
+
+    /// Version of the shipped example set, if the library includes it
+    pub fn builtin_version(&self) -> Option<u32> {
+        self.builtin_version
+    }
+
+    /// Add a user-supplied example
+    ///
+    /// User examples win ties against shipped ones.
+    pub fn add(&mut self, example: Example) {
+        self.push(example, true);
+    }
+
+    /// Add the examples from every `.toml` file in `dir`
+    ///
+    /// Files are read in name order. A missing directory adds nothing.
+    /// Returns the number of examples added.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> TranslationResult<usize> {
+        let dir = dir.as_ref();
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(TranslationError::InvalidExamples(format!(
+                    "cannot read {}: {e}",
+                    dir.display()
+                )))
+            }
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        let mut added = 0;
+        for path in paths {
+            added += self.load_file(&path)?;
+        }
+        Ok(added)
+    }
+
+    /// Add the examples from one file, returning how many were added
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> TranslationResult<usize> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            TranslationError::InvalidExamples(format!("cannot read {}: {e}", path.display()))
+        })?;
+        let file = parse_file(&text, &path.display().to_string())?;
+        let added = file.examples.len();
+        for example in file.examples {
+            self.add(example);
+        }
+        Ok(added)
+    }
+
+    /// Number of examples in the library
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the library has no examples
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over all examples in library order
+    pub fn iter(&self) -> impl Iterator<Item = &Example> {
+        self.entries.iter().map(|entry| &entry.example)
+    }
+
+    /// Pick up to `k` examples most relevant to `request`
+    ///
+    /// Examples are scored by the keywords they share with the request, each
+    /// weighted by how rare it is across the library. Ties, including
+    /// examples sharing nothing with the request, go to user examples and
+    /// then to library order, so the first shipped examples fill up the
+    /// selection when few examples match.
+    pub fn select(&self, request: &str, k: usize) -> Vec<&Example> {
+        let query = terms(request);
+
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        for entry in &self.entries {
+            for term in &entry.terms {
+                *document_frequency.entry(term).or_default() += 1;
+            }
+        }
+        let total = self.entries.len() as f64;
+        let weight = |term: &str| {
+            let frequency = document_frequency.get(term).copied().unwrap_or(0) as f64;
+            ((total + 1.0) / (frequency + 1.0)).ln() + 1.0
+        };
+
+        let mut scored: Vec<(f64, usize, &Entry)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let score = entry.terms.intersection(&query).map(|t| weight(t)).sum();
+                (score, index, entry)
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| Reverse(a.2.user).cmp(&Reverse(b.2.user)))
+                .then_with(|| a.1.cmp(&b.1))
+        });
+        scored
+            .into_iter()
+            .take(k)
+            .map(|(_, _, entry)| &entry.example)
+            .collect()
+    }
+
+    fn push(&mut self, example: Example, user: bool) {
+        let mut example_terms = terms(&example.request);
+        example_terms.extend(terms(&example.command));
+        self.entries.push(Entry {
+            example,
+            terms: example_terms,
+            user,
+        });
+    }
+}
+
+impl Default for ExampleLibrary {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Parse an example file, checking its format version
+fn parse_file(text: &str, origin: &str) -> TranslationResult<ExampleFile> {
+    let file: ExampleFile = toml::from_str(text)
+        .map_err(|e| TranslationError::InvalidExamples(format!("{origin}: {e}")))?;
+    if file.version == 0 || file.version > EXAMPLES_FORMAT_VERSION {
+        return Err(TranslationError::InvalidExamples(format!(
+            "{origin}: unsupported version {} (expected at most {EXAMPLES_FORMAT_VERSION})",
+            file.version
+        )));
+    }
+    if let Some(example) = file
+        .examples
+        .iter()
+        .find(|example| example.request.trim().is_empty() || example.command.trim().is_empty())
+    {
+        return Err(TranslationError::InvalidExamples(format!(
+            "{origin}: example {:?} needs both a request and a command",
+            example.request
+        )));
+    }
+    Ok(file)
+}
+
+/// Normalized keywords of a text
+fn terms(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.len() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| stem(&word))
+        .collect()
+}
+
+/// Strip common English suffixes so `files` matches `file`
+fn stem(word: &str) -> String {
+    for suffix in ["ing", "es", "s"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.len() >= 3 && !stem.ends_with('s') {
+                return stem.to_string();
+            }
+        }
+    }
+    word.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(request: &str, command: &str) -> Example {
+        Example {
+            request: request.to_string(),
+            command: command.to_string(),
+            explanation: None,
+        }
+    }
+
+    #[test]
+    fn test_builtin_examples_parse() {
+        let library = ExampleLibrary::builtin();
+        assert_eq!(library.builtin_version(), Some(EXAMPLES_FORMAT_VERSION));
+        assert!(library.len() >= 20);
+    }
+
+    #[test]
+    fn test_select_prefers_matching_keywords() {
+        let library = ExampleLibrary::builtin();
+
+        let selected = library.select("extract logs.tar.gz", 2);
+        assert_eq!(selected[0].command, "tar -xzf backup.tar.gz");
+        assert_eq!(selected.len(), 2);
+
+        let selected = library.select("show recent git commits", 1);
+        assert_eq!(selected[0].command, "git log --oneline -n 10");
+
+        // Nothing matches: the leading generic examples fill in
+        let selected = library.select("xyzzy", 2);
+        let first: Vec<&Example> = library.iter().take(2).collect();
+        assert_eq!(selected, first);
+
+        assert_eq!(
+            library.select("find large files", 3),
+            library.select("find large files", 3)
+        );
+    }
+
+    #[test]
+    fn test_user_examples_win_ties() {
+        let mut library = ExampleLibrary::empty();
+        library.push(example("deploy the site", "make deploy"), false);
+        library.add(example("deploy the site", "./scripts/deploy.sh"));
+
+        let selected = library.select("deploy", 1);
+        assert_eq!(selected[0].command, "./scripts/deploy.sh");
+    }
+
+    #[test]
+    fn test_load_dir_reads_toml_files_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("b.toml"),
+            "version = 1\n[[examples]]\nrequest = \"second\"\ncommand = \"echo 2\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("a.toml"),
+            "version = 1\n[[examples]]\nrequest = \"first\"\ncommand = \"echo 1\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let mut library = ExampleLibrary::empty();
+        assert_eq!(library.load_dir(dir.path()).unwrap(), 2);
+        let commands: Vec<&str> = library.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["echo 1", "echo 2"]);
+
+        assert_eq!(library.load_dir(dir.path().join("missing")).unwrap(), 0);
+
+        std::fs::write(dir.path().join("c.toml"), "version = 99\n").unwrap();
+        let error = library.load_dir(dir.path()).unwrap_err();
+        assert!(
+            matches!(error, TranslationError::InvalidExamples(msg) if msg.contains("unsupported version 99"))
+        );
+    }
+
+    #[test]
+    fn test_response_is_json() {
+        let response = Example {
+            explanation: Some("Lists files.".to_string()),
+            ..example("list files", "ls")
+        }
+        .response();
+        let value: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(value["command"], "ls");
+        assert_eq!(value["explanation"], "Lists files.");
+    }
+}
@@ -3,18 +3,29 @@
 //! This module handles translating natural language requests into shell commands.
 
 mod engine;
+mod examples;
 mod prompt;
+mod response;
 
-pub use engine::TranslationEngine;
+pub use engine::{normalize_command, TranslationEngine};
+pub use examples::{Example, ExampleLibrary, EXAMPLES_FORMAT_VERSION};
 pub use prompt::PromptTemplate;
+pub use response::{parse_response, shell_command_schema, CommandStream, UNREPORTED_CONFIDENCE};
 
 /// Error type for translation operations
 #[derive(Debug, thiserror::Error)]
 pub enum TranslationError {
-    #[error("Failed to generate translation: {0}")]
-    Generation(String),
+    /// The model output could not be turned into a command; `raw` holds the
+    /// output when there was one
+    #[error("Failed to generate translation: {message}")]
+    Generation {
+        message: String,
+        raw: Option<String>,
+    },
     #[error("Invalid prompt template: {0}")]
     InvalidPrompt(String),
+    #[error("Invalid few-shot examples: {0}")]
+    InvalidExamples(String),
     #[error("Inference engine error: {0}")]
     Inference(#[from] crate::inference::InferenceError),
 }
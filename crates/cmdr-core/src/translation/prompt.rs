@@ -1,45 +1,153 @@
 //! Prompt template management
 //!
 //! This module handles prompt templates for different types of requests.
+//!
+//! Templates use a small Handlebars-like language:
+//!
+//! - `{{name}}` inserts a variable; rendering fails if it is not set
+//! - `{{name | default: "text"}}` falls back to `text` when `name` is unset
+//!   or empty
+//! - `{{#if name}}...{{else}}...{{/if}}` renders a branch depending on
+//!   whether `name` is set and non-empty
+//! - `{{#each name}}...{{/each}}` repeats its body for every item of a list;
+//!   inside, fields of the item are variables, `{{this}}` is the item itself
+//!   and `{{@number}}` its 1-based position
+//! - `{{! comment }}` is dropped
+//!
+//! Block tags that sit alone on a line remove that whole line, so templates
+//! can be laid out readably without leaving blank lines in the prompt.
 
-use std::collections::HashMap;
+use super::{TranslationError, TranslationResult};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
 
 /// A template for generating prompts
+#[derive(Debug, Clone)]
 pub struct PromptTemplate {
     template: String,
-    variables: HashMap<String, String>,
+    nodes: Vec<Node>,
+    references: BTreeSet<String>,
+    variables: HashMap<String, Value>,
+}
+
+/// A parsed piece of a template
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable {
+        path: String,
+        default: Option<String>,
+        line: usize,
+    },
+    If {
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        path: String,
+        body: Vec<Node>,
+    },
+}
+
+/// A lexical piece of a template
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    /// The trimmed contents of a `{{...}}` tag and the line it starts on
+    Tag(String, usize),
 }
 
 impl PromptTemplate {
     /// Create a new prompt template
-    pub fn new(template: String) -> Self {
-        Self {
+    ///
+    /// Returns [`TranslationError::InvalidPrompt`] if the template is
+    /// malformed, e.g. has an unclosed `{{#if}}`.
+    pub fn new(template: impl Into<String>) -> TranslationResult<Self> {
+        let template = template.into();
+        let mut tokens = tokenize(&template)?.into_iter();
+        let (nodes, _) = parse_nodes(&mut tokens, None)?;
+
+        let mut references = BTreeSet::new();
+        collect_references(&nodes, &mut references);
+
+        Ok(Self {
             template,
+            nodes,
+            references,
             variables: HashMap::new(),
-        }
+        })
+    }
+
+    /// Load a template from a file
+    pub fn from_file(path: impl AsRef<Path>) -> TranslationResult<Self> {
+        let path = path.as_ref();
+        let template = std::fs::read_to_string(path).map_err(|e| {
+            TranslationError::InvalidPrompt(format!("cannot read {}: {e}", path.display()))
+        })?;
+        Self::new(template).map_err(|e| match e {
+            TranslationError::InvalidPrompt(msg) => {
+                TranslationError::InvalidPrompt(format!("{}: {msg}", path.display()))
+            }
+            other => other,
+        })
+    }
+
+    /// Get the template source
+    pub fn source(&self) -> &str {
+        &self.template
+    }
+
+    /// Whether the template refers to the variable `name` anywhere
+    pub fn references(&self, name: &str) -> bool {
+        self.references.contains(name)
     }
 
     /// Set a variable in the template
-    pub fn set_variable(&mut self, name: &str, value: String) {
-        self.variables.insert(name.to_string(), value);
+    ///
+    /// Values can be text, booleans, lists (for `{{#each}}`) or objects
+    /// whose fields are reachable as `{{object.field}}`.
+    pub fn set_variable(&mut self, name: &str, value: impl Into<Value>) {
+        self.variables.insert(name.to_string(), value.into());
     }
 
     /// Render the template with the current variables
-    pub fn render(&self) -> String {
-        let mut result = self.template.clone();
-
-        for (name, value) in &self.variables {
-            let placeholder = format!("{{{{{name}}}}}");
-            result = result.replace(&placeholder, value);
+    ///
+    /// Fails with [`TranslationError::InvalidPrompt`] if the template uses a
+    /// variable that is not set (outside `#if`/`#each` and without a
+    /// default) or if a variable is set that the template never uses.
+    pub fn render(&self) -> TranslationResult<String> {
+        let mut unused: Vec<&str> = self
+            .variables
+            .keys()
+            .filter(|name| !self.references.contains(name.as_str()))
+            .map(String::as_str)
+            .collect();
+        if !unused.is_empty() {
+            unused.sort_unstable();
+            return Err(TranslationError::InvalidPrompt(format!(
+                "variables set but not used by the template: {}",
+                unused.join(", ")
+            )));
         }
 
-        result
+        let root = Value::Object(
+            self.variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        );
+        let mut output = String::new();
+        render_nodes(&self.nodes, &mut vec![Scope::root(&root)], &mut output)?;
+        Ok(output)
     }
 }
 
 impl Default for PromptTemplate {
     fn default() -> Self {
-        Self::new(Self::default_template())
+        Self::new(Self::default_template()).expect("the default prompt template is valid")
     }
 }
 
@@ -51,16 +159,572 @@ impl PromptTemplate {
 Your task is to:
 1. Understand the user's intent
 2. Generate the appropriate shell command
-3. Provide a brief explanation if needed
+3. Provide a brief explanation of what it does
+
+Respond with a single JSON object and nothing else:
+{"command": "<shell command>", "explanation": "<one sentence>", "confidence": <number from 0.0 to 1.0>}
 
 Rules:
-- Only output the shell command, no additional text
-- Use standard Unix/Linux commands
+- Do not wrap the JSON in markdown code fences
+- Use standard Unix/Linux commands{{#if dialect}} in {{dialect}} syntax{{/if}}
+{{#if dialect_notes}}
+- {{dialect_notes}}
+{{/if}}
 - Be safe and avoid destructive operations
 - Prefer simple, readable commands
+{{#each examples}}
+
+Example {{@number}}:
+User request: {{request}}
+JSON response: {{response}}
+{{/each}}
+{{#if context}}
+
+Environment:
+{{context}}
+{{/if}}
+
+User request: {{request}}
+
+JSON response:"#
+            .to_string()
+    }
+}
+
+/// Split a template into text and `{{...}}` tags
+fn tokenize(template: &str) -> TranslationResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    let mut line = 1;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+        line += rest[..start].matches('\n').count();
+        let inner = &rest[start + 2..];
+        let end = inner
+            .find("}}")
+            .ok_or_else(|| invalid(line, "unclosed `{{`".to_string()))?;
+        tokens.push(Token::Tag(inner[..end].trim().to_string(), line));
+        line += inner[..end].matches('\n').count();
+        rest = &inner[end + 2..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+
+    strip_standalone_tags(&mut tokens);
+    Ok(tokens)
+}
+
+/// Remove the lines of block tags that are alone on their line
+fn strip_standalone_tags(tokens: &mut [Token]) {
+    let standalone: Vec<usize> = (0..tokens.len())
+        .filter(|&i| is_standalone(tokens, i))
+        .collect();
+
+    for i in standalone {
+        if let Some(Token::Text(text)) = i.checked_sub(1).map(|j| &mut tokens[j]) {
+            let line_start = text.rfind('\n').map_or(0, |n| n + 1);
+            text.truncate(line_start);
+        }
+        if let Some(Token::Text(text)) = tokens.get_mut(i + 1) {
+            let line_end = text.find('\n').map_or(text.len(), |n| n + 1);
+            text.drain(..line_end);
+        }
+    }
+}
+
+/// Whether token `i` is a block tag with only whitespace around it on its line
+fn is_standalone(tokens: &[Token], i: usize) -> bool {
+    let Token::Tag(tag, _) = &tokens[i] else {
+        return false;
+    };
+    if !tag.starts_with(['#', '/', '!']) && tag != "else" {
+        return false;
+    }
+
+    let before_ok = match i.checked_sub(1).map(|j| &tokens[j]) {
+        None => true,
+        Some(Token::Text(text)) => {
+            let line_start = text.rfind('\n').map_or(0, |n| n + 1);
+            let at_line_start = i == 1 || text.contains('\n');
+            at_line_start && text[line_start..].chars().all(|c| c == ' ' || c == '\t')
+        }
+        Some(Token::Tag(..)) => false,
+    };
+    let after_ok = match tokens.get(i + 1) {
+        None => true,
+        Some(Token::Text(text)) => {
+            let line_end = text.find('\n').unwrap_or(text.len());
+            let at_line_end = i + 2 == tokens.len() || text.contains('\n');
+            at_line_end && text[..line_end].trim().is_empty()
+        }
+        Some(Token::Tag(..)) => false,
+    };
+    before_ok && after_ok
+}
+
+/// How a run of nodes ended
+enum End {
+    /// The closing tag of the enclosing block
+    Close,
+    /// An `{{else}}` inside an `{{#if}}`
+    Else,
+    /// The end of the template
+    Eof,
+}
+
+type Tokens = std::vec::IntoIter<Token>;
+
+/// Parse tokens up to the closing tag of `block`, or to the end
+fn parse_nodes(
+    tokens: &mut Tokens,
+    block: Option<(&str, usize)>,
+) -> TranslationResult<(Vec<Node>, End)> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        let (tag, line) = match token {
+            Token::Text(text) => {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(text));
+                }
+                continue;
+            }
+            Token::Tag(tag, line) => (tag, line),
+        };
+
+        if tag.starts_with('!') {
+            continue;
+        }
+        if let Some(closing) = tag.strip_prefix('/') {
+            return match block {
+                Some((name, _)) if closing.trim() == name => Ok((nodes, End::Close)),
+                Some((name, _)) => Err(invalid(
+                    line,
+                    format!("expected `{{{{/{name}}}}}`, found `{{{{{tag}}}}}`"),
+                )),
+                None => Err(invalid(line, format!("unexpected `{{{{{tag}}}}}`"))),
+            };
+        }
+        if tag == "else" {
+            return match block {
+                Some(("if", _)) => Ok((nodes, End::Else)),
+                _ => Err(invalid(line, "`{{else}}` outside of `{{#if}}`".to_string())),
+            };
+        }
+        if let Some(directive) = tag.strip_prefix('#') {
+            let (keyword, argument) = directive
+                .split_once(char::is_whitespace)
+                .map(|(k, a)| (k, a.trim()))
+                .unwrap_or((directive, ""));
+            let path = parse_path(argument, line)?;
+            match keyword {
+                "if" => {
+                    let (then, end) = parse_nodes(tokens, Some(("if", line)))?;
+                    let otherwise = match end {
+                        End::Else => parse_block_end(tokens, "if", line)?,
+                        _ => Vec::new(),
+                    };
+                    nodes.push(Node::If {
+                        path,
+                        then,
+                        otherwise,
+                    });
+                }
+                "each" => {
+                    let body = parse_block_end(tokens, "each", line)?;
+                    nodes.push(Node::Each { path, body });
+                }
+                other => {
+                    return Err(invalid(line, format!("unknown block `#{other}`")));
+                }
+            }
+            continue;
+        }
+        nodes.push(parse_variable(&tag, line)?);
+    }
+
+    match block {
+        Some((name, line)) => Err(invalid(line, format!("`{{{{#{name}}}}}` is never closed"))),
+        None => Ok((nodes, End::Eof)),
+    }
+}
+
+/// Parse the rest of a block that may not contain `{{else}}`
+fn parse_block_end(tokens: &mut Tokens, name: &str, line: usize) -> TranslationResult<Vec<Node>> {
+    match parse_nodes(tokens, Some((name, line)))? {
+        (nodes, End::Close) => Ok(nodes),
+        _ => Err(invalid(
+            line,
+            format!("`{{{{#{name}}}}}` has more than one `{{{{else}}}}`"),
+        )),
+    }
+}
+
+/// Parse a `{{name}}` or `{{name | default: "text"}}` tag
+fn parse_variable(tag: &str, line: usize) -> TranslationResult<Node> {
+    let (path, filter) = match tag.split_once('|') {
+        Some((path, filter)) => (path.trim(), Some(filter.trim())),
+        None => (tag, None),
+    };
+    let path = parse_path(path, line)?;
+
+    let default = match filter {
+        None => None,
+        Some(filter) => {
+            let argument = filter
+                .strip_prefix("default")
+                .map(str::trim_start)
+                .and_then(|rest| rest.strip_prefix(':'))
+                .ok_or_else(|| invalid(line, format!("unknown filter `{filter}`")))?;
+            Some(parse_string_literal(argument.trim(), line)?)
+        }
+    };
+    Ok(Node::Variable {
+        path,
+        default,
+        line,
+    })
+}
+
+/// Validate a variable path such as `name`, `item.field` or `@number`
+fn parse_path(path: &str, line: usize) -> TranslationResult<String> {
+    let valid = !path.is_empty()
+        && path.split('.').all(|segment| {
+            let segment = segment.strip_prefix('@').unwrap_or(segment);
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        });
+    if valid {
+        Ok(path.to_string())
+    } else {
+        Err(invalid(line, format!("invalid variable name `{path}`")))
+    }
+}
+
+/// Parse a double-quoted string with `\"`, `\\`, `\n` and `\t` escapes
+fn parse_string_literal(literal: &str, line: usize) -> TranslationResult<String> {
+    let inner = literal
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| invalid(line, format!("expected a quoted string, found `{literal}`")))?;
+
+    let mut value = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some(c @ ('"' | '\\')) => value.push(c),
+            _ => return Err(invalid(line, format!("invalid escape in {literal}"))),
+        }
+    }
+    Ok(value)
+}
+
+/// Record the top-level variable of every path used in `nodes`
+fn collect_references(nodes: &[Node], references: &mut BTreeSet<String>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Variable { path, .. } => add_reference(path, references),
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                add_reference(path, references);
+                collect_references(then, references);
+                collect_references(otherwise, references);
+            }
+            Node::Each { path, body } => {
+                add_reference(path, references);
+                collect_references(body, references);
+            }
+        }
+    }
+}
+
+fn add_reference(path: &str, references: &mut BTreeSet<String>) {
+    let root = path.split('.').next().unwrap_or(path);
+    if root != "this" && !root.starts_with('@') {
+        references.insert(root.to_string());
+    }
+}
+
+fn invalid(line: usize, message: String) -> TranslationError {
+    TranslationError::InvalidPrompt(format!("line {line}: {message}"))
+}
+
+/// A level of variable lookup: the root variables or one `#each` item
+struct Scope<'a> {
+    value: &'a Value,
+    index: Option<usize>,
+}
+
+impl<'a> Scope<'a> {
+    fn root(value: &'a Value) -> Self {
+        Self { value, index: None }
+    }
+}
+
+fn render_nodes<'a>(
+    nodes: &'a [Node],
+    scopes: &mut Vec<Scope<'a>>,
+    output: &mut String,
+) -> TranslationResult<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable {
+                path,
+                default,
+                line,
+            } => match (lookup(scopes, path).as_deref(), default) {
+                (Some(value), Some(default)) if is_empty(value) => output.push_str(default),
+                (Some(value), _) => display(value, output),
+                (None, Some(default)) => output.push_str(default),
+                (None, None) => {
+                    return Err(invalid(*line, format!("undefined variable `{path}`")));
+                }
+            },
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                let branch = if lookup(scopes, path).is_some_and(|value| is_truthy(&value)) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, scopes, output)?;
+            }
+            Node::Each { path, body } => {
+                let items = match lookup(scopes, path) {
+                    None | Some(Cow::Borrowed(Value::Null)) => continue,
+                    Some(Cow::Borrowed(Value::Array(items))) => items,
+                    Some(_) => {
+                        return Err(TranslationError::InvalidPrompt(format!(
+                            "`{{{{#each {path}}}}}` needs a list"
+                        )));
+                    }
+                };
+                for (index, item) in items.iter().enumerate() {
+                    scopes.push(Scope {
+                        value: item,
+                        index: Some(index),
+                    });
+                    let result = render_nodes(body, scopes, output);
+                    scopes.pop();
+                    result?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a variable path, innermost scope first
+fn lookup<'a>(scopes: &[Scope<'a>], path: &str) -> Option<Cow<'a, Value>> {
+    let mut segments = path.split('.');
+    let first = segments.next()?;
+
+    let mut value = match first {
+        "this" => scopes.last().map(|scope| scope.value)?,
+        "@index" | "@number" => {
+            let index = scopes.iter().rev().find_map(|scope| scope.index)?;
+            let number = if first == "@index" { index } else { index + 1 };
+            return Some(Cow::Owned(Value::from(number)));
+        }
+        name => scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.value.as_object().and_then(|map| map.get(name)))?,
+    };
+    for segment in segments {
+        value = value.as_object().and_then(|map| map.get(segment))?;
+    }
+    Some(Cow::Borrowed(value))
+}
+
+/// Whether a value counts as set for `#if`
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// Whether a value should be replaced by a `default`
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+/// Write a value as prompt text; lists are joined with commas
+fn display(value: &Value, output: &mut String) {
+    match value {
+        Value::Null => {}
+        Value::String(s) => output.push_str(s),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                display(item, output);
+            }
+        }
+        other => output.push_str(&other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, variables: &[(&str, Value)]) -> TranslationResult<String> {
+        let mut template = PromptTemplate::new(template)?;
+        for (name, value) in variables {
+            template.set_variable(name, value.clone());
+        }
+        template.render()
+    }
+
+    #[test]
+    fn test_render_substitutes_in_one_pass() {
+        let output = render(
+            "{{a}} and {{b}}",
+            &[("a", json!("{{b}}")), ("b", json!("x"))],
+        );
+        assert_eq!(output.unwrap(), "{{b}} and x");
+    }
+
+    #[test]
+    fn test_conditionals_and_defaults() {
+        let template =
+            "{{#if shell}}Shell: {{shell}}{{else}}No shell{{/if}}, {{os | default: \"unknown\"}}";
+        assert_eq!(
+            render(template, &[("shell", json!("zsh"))]).unwrap(),
+            "Shell: zsh, unknown"
+        );
+        assert_eq!(
+            render(template, &[("shell", json!("")), ("os", json!("Linux"))]).unwrap(),
+            "No shell, Linux"
+        );
+    }
+
+    #[test]
+    fn test_each_loops_over_items_and_drops_standalone_lines() {
+        let template = "Examples:\n{{#each examples}}\n{{@number}}. {{request}} -> {{command}}\n{{/each}}\n{{! done }}\nRequest: {{request}}";
+        let examples = json!([
+            { "request": "list files", "command": "ls" },
+            { "request": "disk usage", "command": "du -sh ." },
+        ]);
+        let output = render(
+            template,
+            &[("examples", examples), ("request", json!("show date"))],
+        );
+        assert_eq!(
+            output.unwrap(),
+            "Examples:\n1. list files -> ls\n2. disk usage -> du -sh .\nRequest: show date"
+        );
+
+        let output = render(
+            "{{#each tools}}[{{this}}]{{/each}}",
+            &[("tools", json!(["git", "jq"]))],
+        );
+        assert_eq!(output.unwrap(), "[git][jq]");
+    }
+
+    #[test]
+    fn test_undefined_and_unused_variables_are_errors() {
+        let error = render("Hello\n{{name}}", &[]).unwrap_err();
+        assert!(
+            matches!(&error, TranslationError::InvalidPrompt(msg) if msg == "line 2: undefined variable `name`")
+        );
+
+        let error = render("{{name}}", &[("name", json!("x")), ("extra", json!("y"))]).unwrap_err();
+        assert!(matches!(&error, TranslationError::InvalidPrompt(msg) if msg.contains("extra")));
+
+        // Blocks treat undefined variables as empty
+        assert_eq!(
+            render("{{#if a}}x{{/if}}{{#each b}}y{{/each}}", &[]).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_report_lines() {
+        for (template, expected) in [
+            (
+                "{{#if a}}\nnever closed",
+                "line 1: `{{#if}}` is never closed",
+            ),
+            ("ok\n{{/if}}", "line 2: unexpected `{{/if}}`"),
+            (
+                "{{#each a}}{{else}}{{/each}}",
+                "line 1: `{{else}}` outside of `{{#if}}`",
+            ),
+            ("{{name | upper}}", "line 1: unknown filter `upper`"),
+            ("{{a b}}", "line 1: invalid variable name `a b`"),
+            ("{{unclosed", "line 1: unclosed `{{`"),
+        ] {
+            match PromptTemplate::new(template) {
+                Err(TranslationError::InvalidPrompt(msg)) => assert_eq!(msg, expected),
+                other => panic!("expected an error for {template:?}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let mut template = PromptTemplate::default();
+        assert!(template.references("examples"));
+        template.set_variable("request", "list files");
+        template.set_variable("context", "Shell: bash");
+        template.set_variable(
+            "examples",
+            json!([{ "request": "show date", "response": "{\"command\": \"date\"}" }]),
+        );
+        let prompt = template.render().unwrap();
+
+        assert!(prompt.contains(
+            "\n\nExample 1:\nUser request: show date\nJSON response: {\"command\": \"date\"}\n"
+        ));
+        assert!(prompt.ends_with(
+            "\n\nEnvironment:\nShell: bash\n\nUser request: list files\n\nJSON response:"
+        ));
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompt.txt");
+        std::fs::write(&path, "Translate: {{request}}").unwrap();
 
-User request: {request}
+        let mut template = PromptTemplate::from_file(&path).unwrap();
+        template.set_variable("request", "list files");
+        assert_eq!(template.render().unwrap(), "Translate: list files");
 
-Shell command:"#.to_string()
+        assert!(PromptTemplate::from_file(dir.path().join("missing.txt")).is_err());
     }
 }
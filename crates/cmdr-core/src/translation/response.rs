@@ -0,0 +1,694 @@
+//! Model response parsing
+//!
+//! The prompt asks the model for a JSON object such as
+//! `{"command": "ls -la", "explanation": "...", "confidence": 0.9}`, but
+//! models do not always comply. This module extracts a [`ShellCommand`] from
+//! well-formed responses and recovers the command from common deviations:
+//! markdown code fences, leading `$ ` prompts, and prose around the command.
+//! A bare line or inline code span outside fences is only taken for a
+//! command when it starts with a program cmdr knows, and inline code not
+//! at all when the prose around it warns against running it, so that an
+//! answer in prose is reported as a failed generation rather than run.
+
+use super::{TranslationError, TranslationResult};
+use crate::types::ShellCommand;
+use serde_json::{json, Value};
+
+/// Confidence assigned when the model does not report one
+pub const UNREPORTED_CONFIDENCE: f32 = 0.5;
+
+/// Languages of fenced code blocks that may hold a shell command
+const SHELL_FENCE_LANGUAGES: &[&str] = &[
+    "",
+    "sh",
+    "bash",
+    "shell",
+    "zsh",
+    "fish",
+    "console",
+    "shell-session",
+    "terminal",
+];
+
+/// Programs a bare line of a plain-text response may start with
+///
+/// Without fences or a `$ ` prompt, only these tell a command from a line
+/// of prose such as "sure, try ls -la".
+const KNOWN_PROGRAMS: &[&str] = &[
+    "apt",
+    "apt-get",
+    "awk",
+    "base64",
+    "basename",
+    "bash",
+    "brew",
+    "bzip2",
+    "cargo",
+    "cat",
+    "cd",
+    "chmod",
+    "chown",
+    "cmp",
+    "column",
+    "command",
+    "comm",
+    "cp",
+    "crontab",
+    "curl",
+    "cut",
+    "date",
+    "dd",
+    "df",
+    "diff",
+    "dig",
+    "dirname",
+    "dnf",
+    "docker",
+    "doas",
+    "du",
+    "echo",
+    "env",
+    "export",
+    "fd",
+    "file",
+    "find",
+    "fish",
+    "free",
+    "gcc",
+    "git",
+    "go",
+    "grep",
+    "gunzip",
+    "gzip",
+    "head",
+    "host",
+    "hostname",
+    "id",
+    "ip",
+    "jobs",
+    "journalctl",
+    "jq",
+    "kill",
+    "killall",
+    "kubectl",
+    "less",
+    "ln",
+    "locate",
+    "ls",
+    "lsblk",
+    "lsof",
+    "make",
+    "man",
+    "md5sum",
+    "mkdir",
+    "mktemp",
+    "mount",
+    "mv",
+    "nc",
+    "netstat",
+    "nice",
+    "nl",
+    "nohup",
+    "node",
+    "npm",
+    "npx",
+    "nslookup",
+    "open",
+    "pacman",
+    "paste",
+    "pgrep",
+    "ping",
+    "pip",
+    "pip3",
+    "pkill",
+    "printf",
+    "ps",
+    "pwd",
+    "python",
+    "python3",
+    "readlink",
+    "realpath",
+    "rg",
+    "rm",
+    "rmdir",
+    "rsync",
+    "scp",
+    "sed",
+    "seq",
+    "sh",
+    "sha256sum",
+    "sort",
+    "source",
+    "split",
+    "ss",
+    "ssh",
+    "stat",
+    "sudo",
+    "systemctl",
+    "tac",
+    "tail",
+    "tar",
+    "tee",
+    "test",
+    "time",
+    "timeout",
+    "top",
+    "touch",
+    "tr",
+    "tree",
+    "type",
+    "umount",
+    "uname",
+    "uniq",
+    "unzip",
+    "uptime",
+    "wc",
+    "wget",
+    "which",
+    "who",
+    "whoami",
+    "xargs",
+    "xz",
+    "yarn",
+    "yes",
+    "zip",
+    "zsh",
+];
+
+/// Phrases of a response that warns against a command rather than
+/// suggesting it
+const REFUSALS: &[&str] = &[
+    "don't run",
+    "do not run",
+    "shouldn't run",
+    "should not run",
+    "never run",
+    "can't help",
+    "cannot help",
+    "won't help",
+    "i'm sorry",
+    "i am sorry",
+    "i can't",
+    "i cannot",
+    "i won't",
+];
+
+/// JSON schema of the response the prompt asks for
+///
+/// Used as the default output constraint so that backends which support
+/// constrained decoding can only produce responses of this shape.
+pub fn shell_command_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "command": { "type": "string" },
+            "explanation": { "type": "string" },
+            "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+        },
+        "required": ["command", "explanation", "confidence"]
+    })
+}
+
+/// Parse a model response into a shell command
+///
+/// Returns [`TranslationError::Generation`] carrying the raw response when
+/// no command can be recovered from it.
+pub fn parse_response(response: &str) -> TranslationResult<ShellCommand> {
+    // A response that tried to answer in JSON but is malformed (usually
+    // truncated) must not fall through to the plain-text heuristics.
+    let attempted_json = response.contains("\"command\"");
+    let extracted = match extract_from_json(response) {
+        Some(command) => Some(command),
+        None if attempted_json => None,
+        None => extract_from_fences(response)
+            .or_else(|| extract_from_inline_code(response))
+            .or_else(|| extract_from_text(response)),
+    };
+
+    match extracted {
+        Some(command) if !command.command.is_empty() => Ok(command),
+        _ => Err(TranslationError::Generation {
+            message: "no shell command found in the model response".to_string(),
+            raw: Some(response.to_string()),
+        }),
+    }
+}
+
+/// Find the first JSON object with a string `command` field
+fn extract_from_json(response: &str) -> Option<ShellCommand> {
+    response
+        .match_indices('{')
+        .filter_map(|(start, _)| {
+            serde_json::Deserializer::from_str(&response[start..])
+                .into_iter::<Value>()
+                .next()?
+                .ok()
+        })
+        .find_map(|value| command_from_json(&value))
+}
+
+/// Build a command from a parsed JSON object
+fn command_from_json(value: &Value) -> Option<ShellCommand> {
+    let object = value.as_object()?;
+    let command = clean_command(object.get("command")?.as_str()?);
+    let explanation = object
+        .get("explanation")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string);
+    let confidence = object
+        .get("confidence")
+        .and_then(parse_confidence)
+        .unwrap_or(UNREPORTED_CONFIDENCE);
+
+    Some(ShellCommand {
+        command,
+        explanation,
+        confidence,
+    })
+}
+
+/// Interpret a self-reported certainty as a confidence in `0.0..=1.0`
+///
+/// Accepts fractions, percentages and the words `high`, `medium` and `low`.
+fn parse_confidence(value: &Value) -> Option<f32> {
+    let confidence = match value {
+        Value::Number(number) => number.as_f64()? as f32,
+        Value::String(text) => match text.trim().to_ascii_lowercase().as_str() {
+            "high" => 0.9,
+            "medium" => 0.6,
+            "low" => 0.3,
+            other => other.trim_end_matches('%').trim().parse().ok()?,
+        },
+        _ => return None,
+    };
+    let confidence = if confidence > 1.0 {
+        confidence / 100.0
+    } else {
+        confidence
+    };
+    confidence.is_finite().then(|| confidence.clamp(0.0, 1.0))
+}
+
+/// Take the command from the first shell code fence
+fn extract_from_fences(response: &str) -> Option<ShellCommand> {
+    fenced_blocks(response)
+        .into_iter()
+        .filter(|(language, _)| SHELL_FENCE_LANGUAGES.contains(&language.as_str()))
+        .map(|(_, body)| clean_command(&body))
+        .find(|command| !command.is_empty())
+        .map(unreported)
+}
+
+/// Split out the `(language, body)` of every fenced code block
+///
+/// An unterminated fence runs to the end of the response, which is what a
+/// truncated generation looks like.
+fn fenced_blocks(response: &str) -> Vec<(String, String)> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+
+    for line in response.lines() {
+        let trimmed = line.trim();
+        match (&mut current, trimmed.strip_prefix("```")) {
+            (None, Some(language)) => {
+                current = Some((language.trim().to_ascii_lowercase(), Vec::new()));
+            }
+            (Some(_), Some(_)) => {
+                let (language, lines) = current.take().unwrap();
+                blocks.push((language, lines.join("\n")));
+            }
+            (Some((_, lines)), None) => lines.push(line),
+            (None, None) => {}
+        }
+    }
+    if let Some((language, lines)) = current {
+        blocks.push((language, lines.join("\n")));
+    }
+    blocks
+}
+
+/// Take the first `inline code` span that reads as a command, so that a
+/// flag such as `-la` or a command a refusal warns about is not taken
+fn extract_from_inline_code(response: &str) -> Option<ShellCommand> {
+    if looks_like_refusal(response) {
+        return None;
+    }
+    response
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(clean_command)
+        .find(|command| looks_like_command(command))
+        .map(unreported)
+}
+
+/// Whether the prose around inline code advises against running it
+fn looks_like_refusal(response: &str) -> bool {
+    let prose: String = response
+        .split('`')
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .replace('’', "'");
+    REFUSALS.iter().any(|refusal| prose.contains(refusal))
+}
+
+/// Take the command from a plain-text response, skipping prose lines
+fn extract_from_text(response: &str) -> Option<ShellCommand> {
+    let lines: Vec<&str> = response
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let prompted: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("$ "))
+        .collect();
+    if !prompted.is_empty() {
+        return Some(unreported(prompted.join("\n").trim().to_string()));
+    }
+
+    lines
+        .into_iter()
+        .find(|line| looks_like_command(line))
+        .map(|line| unreported(line.to_string()))
+}
+
+/// Whether a bare line reads as a command rather than a sentence
+///
+/// After any environment assignments such as `FOO=bar`, its first word
+/// must be a path or one of [`KNOWN_PROGRAMS`].
+fn looks_like_command(line: &str) -> bool {
+    if line.ends_with(':') {
+        return false;
+    }
+    line.split_whitespace()
+        .find(|word| !is_assignment(word))
+        .is_some_and(|program| program.contains('/') || KNOWN_PROGRAMS.contains(&program))
+}
+
+/// Whether `word` assigns an environment variable, as in `FOO=bar`
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Strip prompts and surrounding whitespace from a command
+///
+/// When some lines of a block carry a `$ ` prompt, the other lines are taken
+/// to be sample output and dropped.
+fn clean_command(text: &str) -> String {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let prompted: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("$ "))
+        .collect();
+    if prompted.is_empty() {
+        lines.join("\n")
+    } else {
+        prompted.join("\n").trim().to_string()
+    }
+}
+
+/// Wrap a recovered command that came without explanation or confidence
+fn unreported(command: String) -> ShellCommand {
+    ShellCommand {
+        command,
+        explanation: None,
+        confidence: UNREPORTED_CONFIDENCE,
+    }
+}
+
+/// Incrementally extracts the `command` value from a streamed JSON response
+///
+/// Feed it model tokens with [`push`](Self::push); it returns the newly
+/// decoded characters of the `command` string so callers can show the
+/// command as it is generated instead of the raw JSON.
+#[derive(Debug, Default)]
+pub struct CommandStream {
+    buffer: String,
+    state: StreamState,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    /// Looking for the `"command"` key
+    #[default]
+    SeekingKey,
+    /// Inside the command string, `usize` bytes into the buffer
+    InValue(usize),
+    /// The command string has been closed
+    Done,
+}
+
+impl CommandStream {
+    /// Create an extractor for a new response
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a token and return any newly available command text
+    pub fn push(&mut self, token: &str) -> String {
+        self.buffer.push_str(token);
+        let mut emitted = String::new();
+
+        loop {
+            match self.state {
+                StreamState::SeekingKey => match value_start(&self.buffer) {
+                    Some(start) => self.state = StreamState::InValue(start),
+                    None => return emitted,
+                },
+                StreamState::InValue(pos) => {
+                    let (text, consumed, closed) = decode_json_string(&self.buffer[pos..]);
+                    emitted.push_str(&text);
+                    self.state = if closed {
+                        StreamState::Done
+                    } else {
+                        StreamState::InValue(pos + consumed)
+                    };
+                    return emitted;
+                }
+                StreamState::Done => return emitted,
+            }
+        }
+    }
+
+    /// Whether the whole command string has been seen
+    pub fn is_done(&self) -> bool {
+        self.state == StreamState::Done
+    }
+}
+
+/// Byte offset just past the opening quote of the `command` value
+fn value_start(buffer: &str) -> Option<usize> {
+    let key = buffer.find("\"command\"")?;
+    let rest = &buffer[key + "\"command\"".len()..];
+    let after_ws = rest.trim_start();
+    let after_colon = after_ws.strip_prefix(':')?.trim_start();
+    after_colon.strip_prefix('"')?;
+    Some(buffer.len() - after_colon.len() + 1)
+}
+
+/// Decode as much of a JSON string body as is complete
+///
+/// Returns the decoded text, the number of bytes consumed and whether the
+/// closing quote was reached. Incomplete escapes are left unconsumed.
+fn decode_json_string(input: &str) -> (String, usize, bool) {
+    let mut text = String::new();
+    let mut consumed = 0;
+    let mut chars = input.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (text, i + 1, true),
+            '\\' => {
+                let Some((_, escape)) = chars.next() else {
+                    break;
+                };
+                let decoded = match escape {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex = input.get(i + 2..i + 6);
+                        let Some(hex) = hex.filter(|h| h.len() == 4) else {
+                            break;
+                        };
+                        for _ in 0..4 {
+                            chars.next();
+                        }
+                        u32::from_str_radix(hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER)
+                    }
+                    other => other,
+                };
+                text.push(decoded);
+                consumed = chars.clone().next().map_or(input.len(), |(j, _)| j);
+            }
+            _ => {
+                text.push(c);
+                consumed = i + c.len_utf8();
+            }
+        }
+    }
+    (text, consumed, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_json_response() {
+        let command = parse_response(
+            r#"{"command": "ls -la", "explanation": "List all files", "confidence": 0.9}"#,
+        )
+        .unwrap();
+
+        assert_eq!(command.command, "ls -la");
+        assert_eq!(command.explanation.as_deref(), Some("List all files"));
+        assert_eq!(command.confidence, 0.9);
+    }
+
+    #[test]
+    fn test_parses_json_in_code_fence_with_prose() {
+        let response = "Sure! Here is the answer:\n```json\n{\"command\": \"df -h\", \"confidence\": \"high\"}\n```\nLet me know if you need more.";
+        let command = parse_response(response).unwrap();
+
+        assert_eq!(command.command, "df -h");
+        assert_eq!(command.explanation, None);
+        assert_eq!(command.confidence, 0.9);
+    }
+
+    #[test]
+    fn test_normalizes_confidence() {
+        assert_eq!(parse_confidence(&Value::from(85)), Some(0.85));
+        assert_eq!(parse_confidence(&Value::from("70%")), Some(0.7));
+        assert_eq!(parse_confidence(&Value::from(-1.0)), Some(0.0));
+        assert_eq!(parse_confidence(&Value::from("unsure")), None);
+    }
+
+    #[test]
+    fn test_recovers_first_shell_code_block() {
+        let response = "Use this:\n\n```python\nprint('no')\n```\n\n```bash\n$ find . -name '*.rs'\n./src/main.rs\n```\n\n```sh\nls\n```";
+        let command = parse_response(response).unwrap();
+
+        assert_eq!(command.command, "find . -name '*.rs'");
+        assert_eq!(command.confidence, UNREPORTED_CONFIDENCE);
+    }
+
+    #[test]
+    fn test_recovers_inline_code() {
+        let command = parse_response("You can run `du -sh *` to see sizes.").unwrap();
+        assert_eq!(command.command, "du -sh *");
+    }
+
+    #[test]
+    fn test_recovers_plain_text_command() {
+        assert_eq!(parse_response("$ pwd\n").unwrap().command, "pwd");
+        assert_eq!(
+            parse_response("Here is the command:\nps aux | grep ssh\nThis lists ssh processes.")
+                .unwrap()
+                .command,
+            "ps aux | grep ssh"
+        );
+        assert_eq!(
+            parse_response("RUST_LOG=debug cargo run").unwrap().command,
+            "RUST_LOG=debug cargo run"
+        );
+    }
+
+    #[test]
+    fn test_unparseable_response_keeps_raw_text() {
+        let response = "I'm sorry, I cannot help with that.";
+        match parse_response(response) {
+            Err(TranslationError::Generation { raw, .. }) => {
+                assert_eq!(raw.as_deref(), Some(response));
+            }
+            other => panic!("expected a generation error, got {other:?}"),
+        }
+        assert!(parse_response("{\"command\": \"  \"}").is_err());
+    }
+
+    #[test]
+    fn test_inline_code_must_be_a_command() {
+        for response in [
+            "You shouldn't run `rm -rf /`.",
+            "I'm sorry, but I can't help with `rm -rf /`; it would wipe the system.",
+            "use the `-la` flag",
+            "Set `HOME` first.",
+        ] {
+            assert!(
+                matches!(
+                    parse_response(response),
+                    Err(TranslationError::Generation { .. })
+                ),
+                "{response:?}"
+            );
+        }
+        assert_eq!(
+            parse_response("Add the `-la` flag: `ls -la`")
+                .unwrap()
+                .command,
+            "ls -la"
+        );
+    }
+
+    #[test]
+    fn test_lowercase_prose_is_not_a_command() {
+        for response in [
+            "sure, try ls -la",
+            "sure, try ls -la\nthat lists everything.",
+            "you could list the files with ls",
+            "here you go:\nthe command lists files",
+            "LS_COLORS=auto",
+        ] {
+            assert!(
+                matches!(
+                    parse_response(response),
+                    Err(TranslationError::Generation { .. })
+                ),
+                "{response:?}"
+            );
+        }
+        assert_eq!(
+            parse_response("sure, here it is:\n./configure --prefix=/usr")
+                .unwrap()
+                .command,
+            "./configure --prefix=/usr"
+        );
+    }
+
+    #[test]
+    fn test_command_stream_emits_command_only() {
+        let mut stream = CommandStream::new();
+        let tokens = [
+            "{\"com",
+            "mand\": \"",
+            "echo \\\"hi",
+            "\\\" \\u00",
+            "e9\"",
+            ", \"explanation\": \"x\"}",
+        ];
+        let emitted: String = tokens.iter().map(|t| stream.push(t)).collect();
+
+        assert_eq!(emitted, "echo \"hi\" é");
+        assert!(stream.is_done());
+    }
+}
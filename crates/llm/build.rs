@@ -1,13 +1,142 @@
+use std::env;
+
+/// The llama.cpp release the bindings in `src/bindings.rs` are written for
+#[cfg(feature = "native-llama")]
+const LLAMA_CPP_TAG: &str = "b5900";
+
 fn main() {
-    // Only link llama.cpp if the feature is explicitly enabled
-    if std::env::var_os("CARGO_FEATURE_NATIVE_LLAMA").is_some() {
-        println!("cargo:rerun-if-changed=build.rs");
-        let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
-        println!("cargo:rustc-link-search=native={}", out_dir.display());
-        println!("cargo:rustc-link-lib=static=llama");
-    } else {
-        // When native-llama feature is not enabled, we don't need to link anything
-        // The crate will work with Ollama-based installation instead
-        println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Only build llama.cpp if the feature is explicitly enabled. Without it
+    // the crate works with the Ollama-based installation instead.
+    if env::var_os("CARGO_FEATURE_NATIVE_LLAMA").is_some() {
+        build_llama_cpp();
     }
 }
+
+#[cfg(feature = "native-llama")]
+fn build_llama_cpp() {
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-env-changed=LLAMA_CPP_DIR");
+    println!("cargo:rerun-if-changed=src/native/shim.c");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let custom_dir = env::var_os("LLAMA_CPP_DIR").map(PathBuf::from);
+    let vendored = custom_dir.is_none();
+    let source_dir = custom_dir.unwrap_or_else(|| manifest_dir.join("vendor").join("llama.cpp"));
+
+    if !source_dir.join("CMakeLists.txt").is_file() {
+        panic!(
+            "llama.cpp sources not found at {}.\n\
+             Run `scripts/vendor-llama-cpp.sh` to check out the llama.cpp submodule \
+             (see crates/llm/vendor/README.md) or set LLAMA_CPP_DIR.",
+            source_dir.display()
+        );
+    }
+
+    // llama.cpp changes its C API often, so the vendored checkout must be
+    // the release the bindings follow. A LLAMA_CPP_DIR is the user's choice
+    // and only warned about.
+    println!(
+        "cargo:rerun-if-changed={}",
+        source_dir.join(".git").join("HEAD").display()
+    );
+    let tag = checked_out_tag(&source_dir);
+    if tag.as_deref() != Some(LLAMA_CPP_TAG) {
+        let found = tag.map_or_else(
+            || "not a tagged release".to_string(),
+            |tag| format!("at {tag}"),
+        );
+        let message = format!(
+            "llama.cpp in {} is {found}, but the bindings are written for {LLAMA_CPP_TAG}",
+            source_dir.display()
+        );
+        if vendored {
+            panic!(
+                "{message}.\n\
+                 Run `scripts/vendor-llama-cpp.sh` to check out {LLAMA_CPP_TAG}."
+            );
+        }
+        println!("cargo:warning={message}");
+    }
+
+    let install_dir = cmake::Config::new(&source_dir)
+        .define("BUILD_SHARED_LIBS", "OFF")
+        .define("LLAMA_BUILD_TESTS", "OFF")
+        .define("LLAMA_BUILD_EXAMPLES", "OFF")
+        .define("LLAMA_BUILD_TOOLS", "OFF")
+        .define("LLAMA_BUILD_SERVER", "OFF")
+        .define("LLAMA_CURL", "OFF")
+        .define("GGML_OPENMP", "OFF")
+        .build();
+
+    cc::Build::new()
+        .file("src/native/shim.c")
+        .include(source_dir.join("include"))
+        .include(source_dir.join("ggml").join("include"))
+        .compile("cmdr_llama_shim");
+
+    // Link every static library the llama.cpp build installed (llama, ggml,
+    // ggml-base, ggml-cpu and any enabled GPU backends).
+    for lib_dir in ["lib", "lib64"] {
+        let lib_dir = install_dir.join(lib_dir);
+        if lib_dir.is_dir() {
+            println!("cargo:rustc-link-search=native={}", lib_dir.display());
+            for name in static_libraries(&lib_dir) {
+                println!("cargo:rustc-link-lib=static={name}");
+            }
+        }
+    }
+
+    let target = env::var("TARGET").unwrap();
+    if target.contains("apple") {
+        println!("cargo:rustc-link-lib=dylib=c++");
+        for framework in ["Foundation", "Accelerate", "Metal", "MetalKit"] {
+            println!("cargo:rustc-link-lib=framework={framework}");
+        }
+    } else if target.contains("linux") {
+        println!("cargo:rustc-link-lib=dylib=stdc++");
+    }
+}
+
+#[cfg(not(feature = "native-llama"))]
+fn build_llama_cpp() {}
+
+/// The release tag checked out in the llama.cpp repository at `dir`, if any
+#[cfg(feature = "native-llama")]
+fn checked_out_tag(dir: &std::path::Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["describe", "--tags", "--exact-match", "HEAD"])
+        .output()
+        .ok()?;
+    let tag = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !tag.trim().is_empty()).then(|| tag.trim().to_string())
+}
+
+/// Names of the `lib<name>.a` archives in `dir`, in link order
+#[cfg(feature = "native-llama")]
+fn static_libraries(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let name = file_name.strip_prefix("lib")?.strip_suffix(".a")?;
+            Some(name.to_string())
+        })
+        .collect();
+
+    // Static archives must come before the archives they depend on:
+    // llama -> ggml -> ggml backends -> ggml-base.
+    names.sort_by_key(|name| match name.as_str() {
+        "llama" => 0,
+        "ggml" => 1,
+        "ggml-base" => 3,
+        _ => 2,
+    });
+    names
+}
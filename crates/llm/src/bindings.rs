@@ -1,78 +1,139 @@
 //! FFI bindings for llama.cpp
 //!
-//! This module contains the raw FFI bindings to the llama.cpp C API.
-
-use std::ffi::{c_char, c_int, c_void};
-
-#[cfg(feature = "native-llama")]
-mod native_bindings {
-    use super::*;
-
-    #[link(name = "llama")]
-    extern "C" {
-        // TODO: Add actual llama.cpp function bindings
-        // For now, these are placeholder bindings
-
-        pub fn llama_backend_init(numa: bool) -> c_int;
-        pub fn llama_backend_free();
-
-        // Model loading
-        pub fn llama_load_model_from_file(
-            path_model: *const c_char,
-            params: *const c_void,
-        ) -> *mut c_void;
-
-        // Context creation
-        pub fn llama_new_context_with_model(
-            model: *const c_void,
-            params: *const c_void,
-        ) -> *mut c_void;
-
-        // Generation
-        pub fn llama_eval(
-            ctx: *mut c_void,
-            tokens: *const i32,
-            n_tokens: c_int,
-            n_past: c_int,
-            n_threads: c_int,
-        ) -> c_int;
-    }
+//! This module contains the raw FFI bindings to the llama.cpp C API
+//! (`include/llama.h`). Only the functions cmdr needs are declared.
+//!
+//! `llama_model_params` and `llama_context_params` are large structs whose
+//! layout changes between llama.cpp releases, so they are never passed across
+//! the boundary. Model and context creation instead go through the small C
+//! shim in `src/native/shim.c`, which is compiled against the same headers.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_void};
+
+pub type llama_token = i32;
+pub type llama_pos = i32;
+pub type llama_seq_id = i32;
+
+/// Opaque `struct llama_model`
+#[repr(C)]
+pub struct llama_model {
+    _private: [u8; 0],
+}
+
+/// Opaque `struct llama_context`
+#[repr(C)]
+pub struct llama_context {
+    _private: [u8; 0],
+}
+
+/// Opaque `struct llama_vocab`
+#[repr(C)]
+pub struct llama_vocab {
+    _private: [u8; 0],
+}
+
+/// Opaque `struct llama_sampler`
+#[repr(C)]
+pub struct llama_sampler {
+    _private: [u8; 0],
+}
+
+/// Opaque handle returned by `llama_get_memory`
+pub type llama_memory_t = *mut c_void;
+
+/// `struct llama_batch`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct llama_batch {
+    pub n_tokens: i32,
+    pub token: *mut llama_token,
+    pub embd: *mut f32,
+    pub pos: *mut llama_pos,
+    pub n_seq_id: *mut i32,
+    pub seq_id: *mut *mut llama_seq_id,
+    pub logits: *mut i8,
+}
+
+/// `struct llama_sampler_chain_params`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct llama_sampler_chain_params {
+    pub no_perf: bool,
+}
+
+extern "C" {
+    // Backend
+    pub fn llama_backend_init();
+
+    // Model
+    pub fn llama_model_free(model: *mut llama_model);
+    pub fn llama_model_get_vocab(model: *const llama_model) -> *const llama_vocab;
+    pub fn llama_model_n_ctx_train(model: *const llama_model) -> i32;
+
+    // Context
+    pub fn llama_free(ctx: *mut llama_context);
+    pub fn llama_n_ctx(ctx: *const llama_context) -> u32;
+    pub fn llama_n_batch(ctx: *const llama_context) -> u32;
+    pub fn llama_get_memory(ctx: *const llama_context) -> llama_memory_t;
+    pub fn llama_memory_clear(mem: llama_memory_t, data: bool);
+    pub fn llama_decode(ctx: *mut llama_context, batch: llama_batch) -> i32;
+
+    // Tokenizer
+    pub fn llama_tokenize(
+        vocab: *const llama_vocab,
+        text: *const c_char,
+        text_len: i32,
+        tokens: *mut llama_token,
+        n_tokens_max: i32,
+        add_special: bool,
+        parse_special: bool,
+    ) -> i32;
+    pub fn llama_token_to_piece(
+        vocab: *const llama_vocab,
+        token: llama_token,
+        buf: *mut c_char,
+        length: i32,
+        lstrip: i32,
+        special: bool,
+    ) -> i32;
+    pub fn llama_vocab_is_eog(vocab: *const llama_vocab, token: llama_token) -> bool;
+
+    // Batch
+    pub fn llama_batch_init(n_tokens: i32, embd: i32, n_seq_max: i32) -> llama_batch;
+    pub fn llama_batch_free(batch: llama_batch);
+
+    // Sampling
+    pub fn llama_sampler_chain_default_params() -> llama_sampler_chain_params;
+    pub fn llama_sampler_chain_init(params: llama_sampler_chain_params) -> *mut llama_sampler;
+    pub fn llama_sampler_chain_add(chain: *mut llama_sampler, smpl: *mut llama_sampler);
+    pub fn llama_sampler_init_greedy() -> *mut llama_sampler;
+    pub fn llama_sampler_init_dist(seed: u32) -> *mut llama_sampler;
+    pub fn llama_sampler_init_top_k(k: i32) -> *mut llama_sampler;
+    pub fn llama_sampler_init_top_p(p: f32, min_keep: usize) -> *mut llama_sampler;
+    pub fn llama_sampler_init_temp(t: f32) -> *mut llama_sampler;
+    pub fn llama_sampler_init_grammar(
+        vocab: *const llama_vocab,
+        grammar_str: *const c_char,
+        grammar_root: *const c_char,
+    ) -> *mut llama_sampler;
+    pub fn llama_sampler_sample(
+        smpl: *mut llama_sampler,
+        ctx: *mut llama_context,
+        idx: i32,
+    ) -> llama_token;
+    pub fn llama_sampler_reset(smpl: *mut llama_sampler);
+    pub fn llama_sampler_free(smpl: *mut llama_sampler);
 }
 
-#[cfg(feature = "native-llama")]
-pub use native_bindings::*;
-
-#[cfg(not(feature = "native-llama"))]
-pub mod dummy {
-    use super::*;
-    #[allow(dead_code, unused_variables)]
-    pub unsafe fn llama_backend_init(_numa: bool) -> c_int {
-        0
-    }
-    #[allow(dead_code)]
-    pub unsafe fn llama_backend_free() {}
-    #[allow(dead_code, unused_variables)]
-    pub unsafe fn llama_load_model_from_file(
-        _path_model: *const c_char,
-        _params: *const c_void,
-    ) -> *mut c_void {
-        std::ptr::null_mut()
-    }
-    #[allow(dead_code, unused_variables)]
-    pub unsafe fn llama_new_context_with_model(
-        _model: *const c_void,
-        _params: *const c_void,
-    ) -> *mut c_void {
-        std::ptr::null_mut()
-    }
-    #[allow(dead_code, unused_variables)]
-    pub unsafe fn llama_eval(
-        _ctx: *mut c_void,
-        _tokens: *const i32,
-        _n_tokens: c_int,
-        _n_past: c_int,
-        _n_threads: c_int,
-    ) -> c_int {
-        0
-    }
+extern "C" {
+    // cmdr shim (src/native/shim.c)
+    pub fn cmdr_llama_model_load(path: *const c_char, n_gpu_layers: i32) -> *mut llama_model;
+    pub fn cmdr_llama_context_new(
+        model: *mut llama_model,
+        n_ctx: u32,
+        n_batch: u32,
+        n_threads: i32,
+    ) -> *mut llama_context;
 }
@@ -0,0 +1,300 @@
+//! High-level text generation context
+//!
+//! [`LlmContext`] ties a model, its tokenizer, an inference context and a
+//! sampler together behind a prompt-in, text-out interface.
+
+use crate::{LlmError, LlmResult};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+#[cfg(feature = "native-llama")]
+use crate::native::{
+    ContextParams, LlamaBatch, LlamaContext, LlamaModel, LlamaSampler, SamplerParams, Tokenizer,
+};
+
+/// Parameters for loading a model and generating text
+#[derive(Debug, Clone)]
+pub struct LlmParams {
+    /// Size of the context window in tokens
+    pub n_ctx: u32,
+    /// Maximum number of prompt tokens decoded at once
+    pub n_batch: u32,
+    /// Number of CPU threads; 0 lets llama.cpp decide
+    pub n_threads: i32,
+    /// Number of layers to offload to the GPU
+    pub n_gpu_layers: i32,
+    /// Sampling temperature; 0 selects greedy decoding
+    pub temperature: f32,
+    /// Top-k sampling cutoff; 0 disables it
+    pub top_k: i32,
+    /// Top-p sampling threshold; 1.0 disables it
+    pub top_p: f32,
+    /// Random seed for sampling
+    pub seed: u32,
+    /// Maximum number of tokens to generate
+    pub max_tokens: usize,
+}
+
+impl Default for LlmParams {
+    fn default() -> Self {
+        Self {
+            n_ctx: 2048,
+            n_batch: 512,
+            n_threads: 0,
+            n_gpu_layers: 0,
+            temperature: 0.7,
+            top_k: 40,
+            top_p: 0.9,
+            seed: 0xFFFF_FFFF,
+            max_tokens: 256,
+        }
+    }
+}
+
+/// Per-request settings for [`LlmContext::generate_stream`]
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// GBNF grammar, with a `root` rule, that the output must match
+    pub grammar: Option<String>,
+    /// Seed overriding [`LlmParams::seed`] for this request
+    pub seed: Option<u32>,
+}
+
+/// LLM context for managing model state
+pub struct LlmContext {
+    params: LlmParams,
+    #[cfg(feature = "native-llama")]
+    tokenizer: Tokenizer,
+    #[cfg(feature = "native-llama")]
+    context: LlamaContext,
+    #[cfg(feature = "native-llama")]
+    sampler: LlamaSampler,
+}
+
+impl LlmContext {
+    /// Load a GGUF model and create a context for it
+    #[cfg(feature = "native-llama")]
+    pub fn new(model_path: impl AsRef<Path>, params: LlmParams) -> LlmResult<Self> {
+        let model = LlamaModel::load(model_path.as_ref(), params.n_gpu_layers)?;
+        let tokenizer = Tokenizer::new(model.clone())?;
+        let context = LlamaContext::new(
+            model,
+            ContextParams {
+                n_ctx: params.n_ctx,
+                n_batch: params.n_batch,
+                n_threads: params.n_threads,
+            },
+        )?;
+        let sampler = LlamaSampler::new(sampler_params(&params))?;
+
+        Ok(Self {
+            params,
+            tokenizer,
+            context,
+            sampler,
+        })
+    }
+
+    /// Load a GGUF model and create a context for it
+    ///
+    /// Always fails: in-process inference requires the `native-llama` feature.
+    #[cfg(not(feature = "native-llama"))]
+    pub fn new(model_path: impl AsRef<Path>, _params: LlmParams) -> LlmResult<Self> {
+        Err(LlmError::ModelLoad(format!(
+            "cannot load {}: cmdr was built without the `native-llama` feature",
+            model_path.as_ref().display()
+        )))
+    }
+
+    /// Get the current parameters
+    pub fn params(&self) -> &LlmParams {
+        &self.params
+    }
+
+    /// Update the sampling parameters used by subsequent generations
+    ///
+    /// Context and model parameters (`n_ctx`, `n_gpu_layers`, ...) only take
+    /// effect when a new context is created.
+    pub fn set_params(&mut self, params: LlmParams) -> LlmResult<()> {
+        #[cfg(feature = "native-llama")]
+        {
+            self.sampler = LlamaSampler::new(sampler_params(&params))?;
+        }
+        self.params = params;
+        Ok(())
+    }
+
+    /// Generate a response for the given prompt
+    pub fn generate(&mut self, prompt: &str) -> LlmResult<String> {
+        self.generate_stream(prompt, &RequestOptions::default(), &mut |_| {
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Generate a response, passing each piece of text to `on_text` as soon
+    /// as it is decoded
+    ///
+    /// `request` can constrain the output to a grammar and override the
+    /// seed. `on_text` is also called with an empty string between prompt
+    /// batches. Returning [`ControlFlow::Break`] stops generation with
+    /// [`LlmError::Interrupted`].
+    #[cfg(feature = "native-llama")]
+    pub fn generate_stream(
+        &mut self,
+        prompt: &str,
+        request: &RequestOptions,
+        on_text: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> LlmResult<String> {
+        self.context.clear();
+
+        // Grammar state and seeds are per generation, so requests that set
+        // either get a fresh chain instead of the shared one
+        let mut request_sampler;
+        let sampler = if request.grammar.is_some() || request.seed.is_some() {
+            let mut params = sampler_params(&self.params);
+            params.seed = request.seed.unwrap_or(params.seed);
+            request_sampler = match &request.grammar {
+                Some(grammar) => LlamaSampler::with_grammar(params, &self.tokenizer, grammar)?,
+                None => LlamaSampler::new(params)?,
+            };
+            &mut request_sampler
+        } else {
+            self.sampler.reset();
+            &mut self.sampler
+        };
+
+        let tokens = self.tokenizer.tokenize(prompt, true)?;
+        let n_ctx = self.context.n_ctx() as usize;
+        if tokens.is_empty() {
+            return Err(LlmError::InvalidParams("prompt is empty".to_string()));
+        }
+        if tokens.len() >= n_ctx {
+            return Err(LlmError::InvalidParams(format!(
+                "prompt is {} tokens but the context only holds {n_ctx}",
+                tokens.len()
+            )));
+        }
+
+        // Feed the prompt in chunks of at most n_batch tokens, requesting
+        // logits only for the final prompt token.
+        let n_batch = (self.context.n_batch() as usize).max(1);
+        let mut batch = LlamaBatch::new(n_batch)?;
+        for (chunk_index, chunk) in tokens.chunks(n_batch).enumerate() {
+            if on_text("").is_break() {
+                return Err(LlmError::Interrupted);
+            }
+            batch.clear();
+            for (i, &token) in chunk.iter().enumerate() {
+                let pos = chunk_index * n_batch + i;
+                batch.add(token, pos as i32, pos == tokens.len() - 1)?;
+            }
+            self.context.decode(&batch)?;
+        }
+
+        let mut output = String::new();
+        let mut pending = Vec::new();
+        let max_pos = n_ctx.min(tokens.len() + self.params.max_tokens);
+        for n_past in tokens.len()..max_pos {
+            let token = sampler.sample(&mut self.context);
+            if self.tokenizer.is_end_of_generation(token) {
+                break;
+            }
+            pending.extend(self.tokenizer.token_to_bytes(token));
+            let text = take_complete_utf8(&mut pending);
+            output.push_str(&text);
+            if on_text(&text).is_break() {
+                return Err(LlmError::Interrupted);
+            }
+
+            batch.clear();
+            batch.add(token, n_past as i32, true)?;
+            self.context.decode(&batch)?;
+        }
+
+        if !pending.is_empty() {
+            let text = String::from_utf8_lossy(&pending);
+            output.push_str(&text);
+            if on_text(&text).is_break() {
+                return Err(LlmError::Interrupted);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Generate a response, passing each piece of text to `on_text` as soon
+    /// as it is decoded
+    #[cfg(not(feature = "native-llama"))]
+    pub fn generate_stream(
+        &mut self,
+        _prompt: &str,
+        _request: &RequestOptions,
+        _on_text: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> LlmResult<String> {
+        Err(LlmError::Generation(
+            "cmdr was built without the `native-llama` feature".to_string(),
+        ))
+    }
+}
+
+#[cfg(feature = "native-llama")]
+fn sampler_params(params: &LlmParams) -> SamplerParams {
+    SamplerParams {
+        temperature: params.temperature,
+        top_k: params.top_k,
+        top_p: params.top_p,
+        seed: params.seed,
+    }
+}
+
+/// Remove and return the longest prefix of `bytes` that is complete UTF-8
+///
+/// Tokens can split multi-byte characters, so an incomplete trailing
+/// sequence is left in `bytes` until the next token completes it. Invalid
+/// sequences are replaced with U+FFFD.
+#[cfg_attr(not(feature = "native-llama"), allow(dead_code))]
+fn take_complete_utf8(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                text.push_str(valid);
+                bytes.clear();
+                return text;
+            }
+            Err(e) => {
+                let valid_up_to = e.valid_up_to();
+                text.push_str(std::str::from_utf8(&bytes[..valid_up_to]).unwrap());
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        bytes.drain(..valid_up_to + len);
+                    }
+                    None => {
+                        bytes.drain(..valid_up_to);
+                        return text;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_complete_utf8_keeps_partial_characters() {
+        let snowman = "☃".as_bytes();
+        let mut pending = vec![b'a', snowman[0], snowman[1]];
+        assert_eq!(take_complete_utf8(&mut pending), "a");
+        assert_eq!(pending.len(), 2);
+
+        pending.push(snowman[2]);
+        assert_eq!(take_complete_utf8(&mut pending), "☃");
+        assert!(pending.is_empty());
+
+        let mut invalid = vec![0xff, b'b'];
+        assert_eq!(take_complete_utf8(&mut invalid), "\u{fffd}b");
+    }
+}
@@ -0,0 +1,133 @@
+//! cmdr-core inference engine backed by llama.cpp
+//!
+//! This module adapts [`LlmContext`] to [`cmdr_core::InferenceEngine`] so the
+//! translation engine can run fully in-process, without an Ollama daemon.
+
+use crate::{json_schema_to_grammar, LlmContext, LlmError, LlmParams, RequestOptions};
+use async_trait::async_trait;
+use cmdr_core::inference::{
+    GenerationOptions, InferenceError, InferenceResult, OutputConstraint, TokenCallback,
+};
+use cmdr_core::InferenceEngine;
+use std::ops::ControlFlow;
+
+/// Inference engine running a GGUF model in-process through llama.cpp
+pub struct LlamaInferenceEngine {
+    params: LlmParams,
+    context: Option<LlmContext>,
+}
+
+impl LlamaInferenceEngine {
+    /// Create an engine with default parameters
+    pub fn new() -> Self {
+        Self::with_params(LlmParams::default())
+    }
+
+    /// Create an engine with the given parameters
+    pub fn with_params(params: LlmParams) -> Self {
+        Self {
+            params,
+            context: None,
+        }
+    }
+}
+
+impl Default for LlamaInferenceEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<LlmError> for InferenceError {
+    fn from(error: LlmError) -> Self {
+        match error {
+            LlmError::ModelLoad(msg) => InferenceError::ModelNotLoaded(msg),
+            LlmError::ContextInit(msg) => InferenceError::Initialization(msg),
+            LlmError::Generation(msg) => InferenceError::Generation(msg),
+            LlmError::InvalidParams(msg) => InferenceError::InvalidParams(msg),
+            LlmError::Interrupted => InferenceError::Cancelled,
+        }
+    }
+}
+
+#[async_trait]
+impl InferenceEngine for LlamaInferenceEngine {
+    fn initialize(&mut self) -> InferenceResult<()> {
+        crate::native::init_backend();
+        Ok(())
+    }
+
+    fn load_model(&mut self, model_path: &str) -> InferenceResult<()> {
+        self.context = None;
+        self.context = Some(LlmContext::new(model_path, self.params.clone())?);
+        Ok(())
+    }
+
+    async fn generate(&mut self, prompt: &str) -> InferenceResult<String> {
+        let context = self
+            .context
+            .as_mut()
+            .ok_or_else(|| InferenceError::ModelNotLoaded("No model loaded".to_string()))?;
+        Ok(context.generate(prompt)?)
+    }
+
+    async fn generate_stream(
+        &mut self,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_token: &mut TokenCallback<'_>,
+    ) -> InferenceResult<String> {
+        let context = self
+            .context
+            .as_mut()
+            .ok_or_else(|| InferenceError::ModelNotLoaded("No model loaded".to_string()))?;
+        let request = RequestOptions {
+            grammar: match &options.constraint {
+                Some(OutputConstraint::Grammar(grammar)) => Some(grammar.clone()),
+                Some(OutputConstraint::JsonSchema(schema)) => Some(json_schema_to_grammar(schema)?),
+                None => None,
+            },
+            // llama.cpp seeds are 32 bits wide
+            seed: options.seed.map(|seed| seed as u32),
+        };
+
+        // Decoding is synchronous, so cancellation and the deadline are
+        // polled between tokens rather than raced against a future.
+        let mut stopped = None;
+        let result = context.generate_stream(prompt, &request, &mut |text| {
+            if let Err(e) = options.check() {
+                stopped = Some(e);
+                return ControlFlow::Break(());
+            }
+            if !text.is_empty() {
+                on_token(text);
+            }
+            ControlFlow::Continue(())
+        });
+        match (result, stopped) {
+            (Err(LlmError::Interrupted), Some(e)) => Err(e),
+            (result, _) => Ok(result?),
+        }
+    }
+
+    fn set_parameters(&mut self, temperature: f32, max_tokens: usize) -> InferenceResult<()> {
+        if max_tokens == 0 {
+            return Err(InferenceError::InvalidParams(
+                "max_tokens must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut params = self.params.clone();
+        params.temperature = temperature;
+        params.max_tokens = max_tokens;
+        if let Some(context) = self.context.as_mut() {
+            context.set_params(params.clone())?;
+        }
+        self.params = params;
+        Ok(())
+    }
+
+    fn is_model_loaded(&self) -> bool {
+        self.context.is_some()
+    }
+}
@@ -0,0 +1,318 @@
+//! JSON schema to GBNF conversion
+//!
+//! llama.cpp constrains sampling with GBNF grammars. This module translates
+//! the subset of JSON schema cmdr uses into an equivalent grammar so that
+//! callers can describe the expected output as a schema.
+//!
+//! Supported keywords: `type` (including type lists), `properties`,
+//! `required`, `items`, `enum`, `const`, `anyOf` and `oneOf`. Object
+//! members are generated required ones first, each group in the order the
+//! schema's map iterates them. Numeric bounds and string formats are not
+//! enforced.
+
+use crate::{LlmError, LlmResult};
+use serde_json::{Map, Value};
+
+/// Whitespace allowed between tokens, bounded so the model cannot pad forever
+const WS_RULE: &str = r#"| " " | "\n" [ \t]{0,20}"#;
+
+/// Primitive rules, emitted only when referenced
+const PRIMITIVES: &[(&str, &str)] = &[
+    (
+        "string",
+        r#""\"" ( [^"\\\x7F\x00-\x1F] | "\\" ( ["\\bfnrt/] | "u" [0-9a-fA-F]{4} ) )* "\"" ws"#,
+    ),
+    (
+        "number",
+        r#"( "-"? ( [0-9] | [1-9] [0-9]{0,15} ) ) ( "." [0-9]+ )? ( [eE] [-+]? [0-9]+ )? ws"#,
+    ),
+    ("integer", r#"( "-"? ( [0-9] | [1-9] [0-9]{0,15} ) ) ws"#),
+    ("boolean", r#"( "true" | "false" ) ws"#),
+    ("null", r#""null" ws"#),
+    (
+        "value",
+        r#"( any-object | any-array | string | number | boolean | null )"#,
+    ),
+    (
+        "any-object",
+        r#""{" ws ( string ":" ws value ( "," ws string ":" ws value )* )? "}" ws"#,
+    ),
+    ("any-array", r#""[" ws ( value ( "," ws value )* )? "]" ws"#),
+];
+
+/// Convert a JSON schema into a GBNF grammar whose start rule is `root`
+pub fn json_schema_to_grammar(schema: &Value) -> LlmResult<String> {
+    let mut builder = GrammarBuilder::default();
+    let root = builder.visit(schema, "root")?;
+    if root != "root" {
+        builder.rules.push(("root".to_string(), root));
+    }
+    Ok(builder.finish())
+}
+
+#[derive(Default)]
+struct GrammarBuilder {
+    rules: Vec<(String, String)>,
+    primitives: Vec<String>,
+}
+
+impl GrammarBuilder {
+    /// Return a grammar expression matching `schema`, adding any rules it needs
+    ///
+    /// Objects and arrays get a rule named `name`; primitives are referenced
+    /// directly.
+    fn visit(&mut self, schema: &Value, name: &str) -> LlmResult<String> {
+        let object = match schema {
+            Value::Bool(true) => return Ok(self.primitive("value")),
+            Value::Object(object) => object,
+            _ => return Err(unsupported(name, "schemas must be objects")),
+        };
+
+        if let Some(value) = object.get("const") {
+            return Ok(literal(value));
+        }
+        if let Some(values) = object.get("enum") {
+            let values = values
+                .as_array()
+                .filter(|values| !values.is_empty())
+                .ok_or_else(|| unsupported(name, "`enum` must be a non-empty array"))?;
+            let alternatives: Vec<String> = values.iter().map(literal).collect();
+            return Ok(format!("( {} )", alternatives.join(" | ")));
+        }
+        if let Some(options) = object.get("anyOf").or_else(|| object.get("oneOf")) {
+            let options = options
+                .as_array()
+                .filter(|options| !options.is_empty())
+                .ok_or_else(|| unsupported(name, "`anyOf` must be a non-empty array"))?;
+            let alternatives = options
+                .iter()
+                .enumerate()
+                .map(|(i, option)| self.visit(option, &format!("{name}-{i}")))
+                .collect::<LlmResult<Vec<_>>>()?;
+            return Ok(format!("( {} )", alternatives.join(" | ")));
+        }
+        if object.contains_key("$ref") {
+            return Err(unsupported(name, "`$ref` is not supported"));
+        }
+
+        match object.get("type") {
+            None => {
+                if object.contains_key("properties") {
+                    self.visit_type("object", object, name)
+                } else {
+                    Ok(self.primitive("value"))
+                }
+            }
+            Some(Value::String(ty)) => self.visit_type(ty, object, name),
+            Some(Value::Array(types)) => {
+                let alternatives = types
+                    .iter()
+                    .map(|ty| {
+                        let ty = ty
+                            .as_str()
+                            .ok_or_else(|| unsupported(name, "`type` entries must be strings"))?;
+                        self.visit_type(ty, object, &format!("{name}-{ty}"))
+                    })
+                    .collect::<LlmResult<Vec<_>>>()?;
+                Ok(format!("( {} )", alternatives.join(" | ")))
+            }
+            Some(_) => Err(unsupported(name, "`type` must be a string or array")),
+        }
+    }
+
+    fn visit_type(
+        &mut self,
+        ty: &str,
+        schema: &Map<String, Value>,
+        name: &str,
+    ) -> LlmResult<String> {
+        match ty {
+            "object" => self.visit_object(schema, name),
+            "array" => {
+                let item = match schema.get("items") {
+                    Some(items) => self.visit(items, &format!("{name}-item"))?,
+                    None => self.primitive("value"),
+                };
+                self.rule(
+                    name,
+                    format!(r#""[" ws ( {item} ( "," ws {item} )* )? "]" ws"#),
+                )
+            }
+            "string" | "number" | "integer" | "boolean" | "null" => Ok(self.primitive(ty)),
+            other => Err(unsupported(name, &format!("unknown type `{other}`"))),
+        }
+    }
+
+    fn visit_object(&mut self, schema: &Map<String, Value>, name: &str) -> LlmResult<String> {
+        let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+            return Ok(self.primitive("any-object"));
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        let mut members = Vec::new();
+        for (key, property) in properties {
+            let value = self.visit(property, &format!("{name}-{}", rule_name(key)))?;
+            let member = format!(
+                r#"{} ws ":" ws {value}"#,
+                literal_str(&format!("\"{key}\""))
+            );
+            members.push((required.contains(&key.as_str()), member));
+        }
+        // Required members come first so that optional ones can always be
+        // introduced by a comma. Without any required member, every member
+        // is emitted.
+        members.sort_by_key(|(required, _)| !required);
+        let any_required = members.first().is_some_and(|(required, _)| *required);
+
+        let mut body = String::new();
+        for (i, (required, member)) in members.iter().enumerate() {
+            let separator = if i == 0 { "" } else { r#""," ws "# };
+            if *required || !any_required {
+                body.push_str(&format!(" {separator}{member}"));
+            } else {
+                body.push_str(&format!(" ( {separator}{member} )?"));
+            }
+        }
+        self.rule(name, format!(r#""{{" ws{body} "}}" ws"#))
+    }
+
+    /// Add a rule and return a reference to it
+    fn rule(&mut self, name: &str, body: String) -> LlmResult<String> {
+        self.rules.push((name.to_string(), body));
+        Ok(name.to_string())
+    }
+
+    /// Reference a primitive rule, pulling in the rules it depends on
+    fn primitive(&mut self, name: &str) -> String {
+        let dependencies: &[&str] = match name {
+            "value" | "any-object" | "any-array" => &[
+                "value",
+                "any-object",
+                "any-array",
+                "string",
+                "number",
+                "boolean",
+                "null",
+            ],
+            _ => &[],
+        };
+        for &primitive in std::iter::once(&name).chain(dependencies) {
+            if !self.primitives.iter().any(|p| p == primitive) {
+                self.primitives.push(primitive.to_string());
+            }
+        }
+        name.to_string()
+    }
+
+    fn finish(self) -> String {
+        let mut grammar = String::new();
+        for (name, body) in &self.rules {
+            grammar.push_str(&format!("{name} ::= {body}\n"));
+        }
+        for (name, body) in PRIMITIVES {
+            if self.primitives.iter().any(|p| p == name) {
+                grammar.push_str(&format!("{name} ::= {body}\n"));
+            }
+        }
+        grammar.push_str(&format!("ws ::= {WS_RULE}\n"));
+        grammar
+    }
+}
+
+/// A grammar expression matching exactly the JSON encoding of `value`
+fn literal(value: &Value) -> String {
+    format!("{} ws", literal_str(&value.to_string()))
+}
+
+/// Quote `text` as a GBNF string literal
+fn literal_str(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Turn a property name into characters allowed in rule names
+fn rule_name(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn unsupported(name: &str, reason: &str) -> LlmError {
+    LlmError::InvalidParams(format!("unsupported JSON schema at `{name}`: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_object_schema_orders_required_members_first() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "note": { "type": "string" },
+                "command": { "type": "string" },
+                "confidence": { "type": "number" }
+            },
+            "required": ["command", "confidence"]
+        });
+        let grammar = json_schema_to_grammar(&schema).unwrap();
+        let root = grammar.lines().next().unwrap();
+
+        assert_eq!(
+            root,
+            r#"root ::= "{" ws "\"command\"" ws ":" ws string "," ws "\"confidence\"" ws ":" ws number ( "," ws "\"note\"" ws ":" ws string )? "}" ws"#
+        );
+        assert!(grammar.contains("\nstring ::= "));
+        assert!(grammar.contains("\nnumber ::= "));
+        assert!(!grammar.contains("\nboolean ::= "));
+        assert!(grammar.ends_with("ws ::= | \" \" | \"\\n\" [ \\t]{0,20}\n"));
+    }
+
+    #[test]
+    fn test_enums_arrays_and_type_lists() {
+        let schema = json!({
+            "type": "array",
+            "items": {
+                "anyOf": [
+                    { "enum": ["ls", 1] },
+                    { "type": ["integer", "null"] }
+                ]
+            }
+        });
+        let grammar = json_schema_to_grammar(&schema).unwrap();
+
+        assert!(grammar.starts_with(
+            r#"root ::= "[" ws ( ( ( "\"ls\"" ws | "1" ws ) | ( integer | null ) ) ( "," ws ( ( "\"ls\"" ws | "1" ws ) | ( integer | null ) ) )* )? "]" ws"#
+        ));
+    }
+
+    #[test]
+    fn test_primitive_root_gets_root_rule() {
+        let grammar = json_schema_to_grammar(&json!({ "type": "boolean" })).unwrap();
+        assert!(grammar.starts_with("root ::= boolean\n"));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_schemas() {
+        assert!(json_schema_to_grammar(&json!({ "$ref": "#/defs/x" })).is_err());
+        assert!(json_schema_to_grammar(&json!({ "type": "date" })).is_err());
+        assert!(json_schema_to_grammar(&json!({ "enum": [] })).is_err());
+    }
+}
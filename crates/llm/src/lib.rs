@@ -2,20 +2,28 @@
 //!
 //! This crate provides safe Rust bindings to the llama.cpp C API
 //! for local LLM inference in cmdr.
+//!
+//! The bindings are only compiled with the `native-llama` feature, which
+//! builds llama.cpp from source. Without it, cmdr talks to Ollama instead and
+//! [`LlmContext::new`] reports that in-process inference is unavailable.
 
+mod context;
+mod grammar;
 pub mod install;
 
 #[cfg(feature = "native-llama")]
 mod bindings;
 
 #[cfg(feature = "native-llama")]
-pub use bindings::*;
+pub mod native;
 
-#[cfg(not(feature = "native-llama"))]
-pub mod llama_stub {
-    // Provide dummy exports if needed
-}
+#[cfg(feature = "native-llama")]
+mod engine;
 
+pub use context::{LlmContext, LlmParams, RequestOptions};
+#[cfg(feature = "native-llama")]
+pub use engine::LlamaInferenceEngine;
+pub use grammar::json_schema_to_grammar;
 pub use install::LlmInstaller;
 
 /// Error type for LLM operations
@@ -29,36 +37,20 @@ pub enum LlmError {
     Generation(String),
     #[error("Invalid parameters: {0}")]
     InvalidParams(String),
+    #[error("Generation was interrupted")]
+    Interrupted,
 }
 
 /// Result type for LLM operations
 pub type LlmResult<T> = Result<T, LlmError>;
 
-/// LLM context for managing model state
-pub struct LlmContext {
-    // TODO: Implement llama.cpp context management
-}
-
-impl LlmContext {
-    /// Create a new LLM context
-    pub fn new() -> LlmResult<Self> {
-        // TODO: Initialize llama.cpp context
-        Ok(Self {})
-    }
-
-    /// Generate a response for the given prompt
-    pub fn generate(&mut self, _prompt: &str) -> LlmResult<String> {
-        // TODO: Implement generation using llama.cpp
-        Ok("Generated response".to_string())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_context_creation() {
-        let _context = LlmContext::new().expect("Failed to create context");
+    fn test_context_requires_existing_model() {
+        let result = LlmContext::new("/nonexistent/model.gguf", LlmParams::default());
+        assert!(matches!(result, Err(LlmError::ModelLoad(_))));
     }
 }
@@ -0,0 +1,75 @@
+//! Token batch wrapper
+
+use crate::bindings::{self, llama_batch, llama_pos, llama_token};
+use crate::{LlmError, LlmResult};
+
+/// A batch of tokens to decode for sequence 0
+pub struct LlamaBatch {
+    raw: llama_batch,
+    capacity: usize,
+}
+
+// SAFETY: the batch owns its buffers exclusively.
+unsafe impl Send for LlamaBatch {}
+unsafe impl Sync for LlamaBatch {}
+
+impl LlamaBatch {
+    /// Allocate a batch that can hold `capacity` tokens
+    pub fn new(capacity: usize) -> LlmResult<Self> {
+        let n_tokens = i32::try_from(capacity.max(1))
+            .map_err(|_| LlmError::InvalidParams("batch is too large".to_string()))?;
+        let raw = unsafe { bindings::llama_batch_init(n_tokens, 0, 1) };
+        Ok(Self {
+            raw,
+            capacity: n_tokens as usize,
+        })
+    }
+
+    /// Append a token at position `pos`, requesting logits if `logits` is set
+    pub fn add(&mut self, token: llama_token, pos: llama_pos, logits: bool) -> LlmResult<()> {
+        let n = self.len();
+        if n >= self.capacity {
+            return Err(LlmError::InvalidParams(format!(
+                "batch capacity of {} tokens exceeded",
+                self.capacity
+            )));
+        }
+
+        // SAFETY: n < capacity, and llama_batch_init allocated every array
+        // with `capacity` entries and one sequence id per entry.
+        unsafe {
+            *self.raw.token.add(n) = token;
+            *self.raw.pos.add(n) = pos;
+            *self.raw.n_seq_id.add(n) = 1;
+            *(*self.raw.seq_id.add(n)) = 0;
+            *self.raw.logits.add(n) = logits as i8;
+        }
+        self.raw.n_tokens += 1;
+        Ok(())
+    }
+
+    /// Remove all tokens from the batch
+    pub fn clear(&mut self) {
+        self.raw.n_tokens = 0;
+    }
+
+    /// Get the number of tokens in the batch
+    pub fn len(&self) -> usize {
+        self.raw.n_tokens as usize
+    }
+
+    /// Check whether the batch is empty
+    pub fn is_empty(&self) -> bool {
+        self.raw.n_tokens == 0
+    }
+
+    pub(crate) fn raw(&self) -> llama_batch {
+        self.raw
+    }
+}
+
+impl Drop for LlamaBatch {
+    fn drop(&mut self) {
+        unsafe { bindings::llama_batch_free(self.raw) }
+    }
+}
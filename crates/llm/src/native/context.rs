@@ -0,0 +1,97 @@
+//! Inference context wrapper
+
+use super::{LlamaBatch, LlamaModel};
+use crate::bindings::{self, llama_context};
+use crate::{LlmError, LlmResult};
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+/// Parameters for creating a [`LlamaContext`]
+#[derive(Debug, Clone, Copy)]
+pub struct ContextParams {
+    /// Size of the context window in tokens
+    pub n_ctx: u32,
+    /// Maximum number of tokens decoded in one batch
+    pub n_batch: u32,
+    /// Number of CPU threads; 0 lets llama.cpp decide
+    pub n_threads: i32,
+}
+
+/// An inference context holding the KV cache for one sequence
+pub struct LlamaContext {
+    raw: NonNull<llama_context>,
+    model: Arc<LlamaModel>,
+}
+
+// SAFETY: a context may move between threads; every call that touches it
+// takes `&mut self`, so it is never used from two threads at once.
+unsafe impl Send for LlamaContext {}
+unsafe impl Sync for LlamaContext {}
+
+impl LlamaContext {
+    /// Create a context for `model`
+    pub fn new(model: Arc<LlamaModel>, params: ContextParams) -> LlmResult<Self> {
+        let raw = unsafe {
+            bindings::cmdr_llama_context_new(
+                model.as_ptr(),
+                params.n_ctx,
+                params.n_batch,
+                params.n_threads,
+            )
+        };
+        NonNull::new(raw)
+            .map(|raw| Self { raw, model })
+            .ok_or_else(|| {
+                LlmError::ContextInit(format!(
+                    "llama.cpp could not create a context with n_ctx={}",
+                    params.n_ctx
+                ))
+            })
+    }
+
+    /// Get the model this context was created from
+    pub fn model(&self) -> &Arc<LlamaModel> {
+        &self.model
+    }
+
+    /// Get the size of the context window in tokens
+    pub fn n_ctx(&self) -> u32 {
+        unsafe { bindings::llama_n_ctx(self.raw.as_ptr()) }
+    }
+
+    /// Get the maximum number of tokens per decode call
+    pub fn n_batch(&self) -> u32 {
+        unsafe { bindings::llama_n_batch(self.raw.as_ptr()) }
+    }
+
+    /// Forget all previously decoded tokens
+    pub fn clear(&mut self) {
+        unsafe {
+            let memory = bindings::llama_get_memory(self.raw.as_ptr());
+            bindings::llama_memory_clear(memory, true);
+        }
+    }
+
+    /// Run the model over a batch of tokens
+    pub fn decode(&mut self, batch: &LlamaBatch) -> LlmResult<()> {
+        match unsafe { bindings::llama_decode(self.raw.as_ptr(), batch.raw()) } {
+            0 => Ok(()),
+            1 => Err(LlmError::Generation(
+                "context is full, try a larger context size".to_string(),
+            )),
+            code => Err(LlmError::Generation(format!(
+                "llama_decode failed with code {code}"
+            ))),
+        }
+    }
+
+    pub(crate) fn as_ptr(&mut self) -> *mut llama_context {
+        self.raw.as_ptr()
+    }
+}
+
+impl Drop for LlamaContext {
+    fn drop(&mut self) {
+        unsafe { bindings::llama_free(self.raw.as_ptr()) }
+    }
+}
@@ -0,0 +1,27 @@
+//! Safe wrappers over the llama.cpp C API
+//!
+//! Every raw llama.cpp object is owned by exactly one RAII wrapper that frees
+//! it on drop. Wrappers that depend on a model keep it alive through an
+//! `Arc<LlamaModel>`, so they can be stored together without lifetimes.
+
+mod batch;
+mod context;
+mod model;
+mod sampler;
+
+pub use batch::LlamaBatch;
+pub use context::{ContextParams, LlamaContext};
+pub use model::{LlamaModel, Tokenizer};
+pub use sampler::{LlamaSampler, SamplerParams};
+
+pub use crate::bindings::llama_token as Token;
+
+use std::sync::Once;
+
+/// Initialize the llama.cpp backend
+///
+/// Safe to call any number of times; only the first call has an effect.
+pub fn init_backend() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| unsafe { crate::bindings::llama_backend_init() });
+}
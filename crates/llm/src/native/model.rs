@@ -0,0 +1,148 @@
+//! Model and tokenizer wrappers
+
+use crate::bindings::{self, llama_model, llama_token, llama_vocab};
+use crate::{LlmError, LlmResult};
+use std::ffi::{c_char, CString};
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+/// A GGUF model loaded into memory
+pub struct LlamaModel {
+    raw: NonNull<llama_model>,
+}
+
+// SAFETY: a loaded model is immutable and llama.cpp allows it to be shared
+// between contexts on different threads.
+unsafe impl Send for LlamaModel {}
+unsafe impl Sync for LlamaModel {}
+
+impl LlamaModel {
+    /// Load a GGUF model file, offloading `n_gpu_layers` layers to the GPU
+    pub fn load(path: &Path, n_gpu_layers: i32) -> LlmResult<Arc<Self>> {
+        if !path.is_file() {
+            return Err(LlmError::ModelLoad(format!(
+                "model file not found: {}",
+                path.display()
+            )));
+        }
+        let c_path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| LlmError::ModelLoad("model path contains a NUL byte".to_string()))?;
+
+        super::init_backend();
+        let raw = unsafe { bindings::cmdr_llama_model_load(c_path.as_ptr(), n_gpu_layers) };
+        NonNull::new(raw)
+            .map(|raw| Arc::new(Self { raw }))
+            .ok_or_else(|| {
+                LlmError::ModelLoad(format!("llama.cpp could not load {}", path.display()))
+            })
+    }
+
+    /// Get the context size the model was trained with
+    pub fn n_ctx_train(&self) -> u32 {
+        unsafe { bindings::llama_model_n_ctx_train(self.raw.as_ptr()) }.max(0) as u32
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut llama_model {
+        self.raw.as_ptr()
+    }
+}
+
+impl Drop for LlamaModel {
+    fn drop(&mut self) {
+        unsafe { bindings::llama_model_free(self.raw.as_ptr()) }
+    }
+}
+
+/// Tokenizer backed by a model's vocabulary
+pub struct Tokenizer {
+    vocab: NonNull<llama_vocab>,
+    // Keeps the vocabulary, which is owned by the model, alive
+    _model: Arc<LlamaModel>,
+}
+
+// SAFETY: the vocabulary is read-only once the model is loaded.
+unsafe impl Send for Tokenizer {}
+unsafe impl Sync for Tokenizer {}
+
+impl Tokenizer {
+    /// Create a tokenizer for `model`
+    pub fn new(model: Arc<LlamaModel>) -> LlmResult<Self> {
+        let vocab = unsafe { bindings::llama_model_get_vocab(model.as_ptr()) };
+        NonNull::new(vocab as *mut llama_vocab)
+            .map(|vocab| Self {
+                vocab,
+                _model: model,
+            })
+            .ok_or_else(|| LlmError::ModelLoad("model has no vocabulary".to_string()))
+    }
+
+    /// Convert text into tokens
+    ///
+    /// `add_special` adds the BOS token where the model expects one; special
+    /// tokens written in the text (e.g. chat template markers) are parsed.
+    pub fn tokenize(&self, text: &str, add_special: bool) -> LlmResult<Vec<llama_token>> {
+        let text_len = i32::try_from(text.len())
+            .map_err(|_| LlmError::InvalidParams("prompt is too long".to_string()))?;
+        let mut tokens = vec![0; text.len() + 2];
+
+        loop {
+            let n = unsafe {
+                bindings::llama_tokenize(
+                    self.vocab.as_ptr(),
+                    text.as_ptr() as *const c_char,
+                    text_len,
+                    tokens.as_mut_ptr(),
+                    tokens.len() as i32,
+                    add_special,
+                    true,
+                )
+            };
+            if n >= 0 {
+                tokens.truncate(n as usize);
+                return Ok(tokens);
+            }
+            // A negative result is the number of tokens that would be produced
+            let needed = n.unsigned_abs() as usize;
+            if needed <= tokens.len() {
+                return Err(LlmError::Generation("tokenization failed".to_string()));
+            }
+            tokens.resize(needed, 0);
+        }
+    }
+
+    /// Get the raw bytes of a token
+    ///
+    /// A single token may hold only part of a multi-byte UTF-8 character, so
+    /// callers should buffer bytes before decoding them.
+    pub fn token_to_bytes(&self, token: llama_token) -> Vec<u8> {
+        let mut buf = vec![0u8; 32];
+        loop {
+            let n = unsafe {
+                bindings::llama_token_to_piece(
+                    self.vocab.as_ptr(),
+                    token,
+                    buf.as_mut_ptr() as *mut c_char,
+                    buf.len() as i32,
+                    0,
+                    false,
+                )
+            };
+            if n >= 0 {
+                buf.truncate(n as usize);
+                return buf;
+            }
+            buf.resize(n.unsigned_abs() as usize, 0);
+        }
+    }
+
+    /// Check whether `token` ends generation (EOS, EOT, ...)
+    pub fn is_end_of_generation(&self, token: llama_token) -> bool {
+        unsafe { bindings::llama_vocab_is_eog(self.vocab.as_ptr(), token) }
+    }
+
+    /// Raw pointer to the vocabulary, valid while the tokenizer is alive
+    pub(crate) fn as_ptr(&self) -> *const llama_vocab {
+        self.vocab.as_ptr()
+    }
+}
@@ -0,0 +1,118 @@
+//! Sampler chain wrapper
+
+use super::{LlamaContext, Tokenizer};
+use crate::bindings::{self, llama_sampler, llama_token};
+use crate::{LlmError, LlmResult};
+use std::ffi::CString;
+use std::ptr::NonNull;
+
+/// Parameters for building a [`LlamaSampler`]
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerParams {
+    /// Sampling temperature; 0 selects greedy decoding
+    pub temperature: f32,
+    /// Keep only the `top_k` most likely tokens; 0 disables the filter
+    pub top_k: i32,
+    /// Nucleus sampling threshold; 1.0 disables the filter
+    pub top_p: f32,
+    /// Seed for the random distribution sampler
+    pub seed: u32,
+}
+
+/// A chain of llama.cpp samplers
+pub struct LlamaSampler {
+    raw: NonNull<llama_sampler>,
+}
+
+// SAFETY: the sampler is only used through `&mut self`.
+unsafe impl Send for LlamaSampler {}
+unsafe impl Sync for LlamaSampler {}
+
+impl LlamaSampler {
+    /// Build a sampler chain from `params`
+    pub fn new(params: SamplerParams) -> LlmResult<Self> {
+        Self::build(params, None)
+    }
+
+    /// Build a sampler chain that only produces text matching `grammar`
+    ///
+    /// `grammar` is GBNF source whose start rule is `root`.
+    pub fn with_grammar(
+        params: SamplerParams,
+        tokenizer: &Tokenizer,
+        grammar: &str,
+    ) -> LlmResult<Self> {
+        Self::build(params, Some((tokenizer, grammar)))
+    }
+
+    fn build(params: SamplerParams, grammar: Option<(&Tokenizer, &str)>) -> LlmResult<Self> {
+        if !params.temperature.is_finite() || params.temperature < 0.0 {
+            return Err(LlmError::InvalidParams(format!(
+                "temperature must be a non-negative number, got {}",
+                params.temperature
+            )));
+        }
+
+        let chain = unsafe {
+            bindings::llama_sampler_chain_init(bindings::llama_sampler_chain_default_params())
+        };
+        let sampler = NonNull::new(chain)
+            .map(|raw| Self { raw })
+            .ok_or_else(|| LlmError::ContextInit("could not create sampler".to_string()))?;
+
+        // The grammar goes first so later samplers only see allowed tokens
+        if let Some((tokenizer, grammar)) = grammar {
+            let grammar = CString::new(grammar)
+                .map_err(|_| LlmError::InvalidParams("grammar contains a NUL byte".to_string()))?;
+            let grammar_sampler = unsafe {
+                bindings::llama_sampler_init_grammar(
+                    tokenizer.as_ptr(),
+                    grammar.as_ptr(),
+                    c"root".as_ptr(),
+                )
+            };
+            if grammar_sampler.is_null() {
+                return Err(LlmError::InvalidParams("invalid GBNF grammar".to_string()));
+            }
+            unsafe { sampler.push(grammar_sampler) };
+        }
+
+        unsafe {
+            if params.temperature == 0.0 {
+                sampler.push(bindings::llama_sampler_init_greedy());
+            } else {
+                if params.top_k > 0 {
+                    sampler.push(bindings::llama_sampler_init_top_k(params.top_k));
+                }
+                if params.top_p < 1.0 {
+                    sampler.push(bindings::llama_sampler_init_top_p(params.top_p, 1));
+                }
+                sampler.push(bindings::llama_sampler_init_temp(params.temperature));
+                sampler.push(bindings::llama_sampler_init_dist(params.seed));
+            }
+        }
+        Ok(sampler)
+    }
+
+    /// Append a sampler to the chain, which takes ownership of it
+    unsafe fn push(&self, sampler: *mut llama_sampler) {
+        bindings::llama_sampler_chain_add(self.raw.as_ptr(), sampler);
+    }
+
+    /// Sample the next token from the logits of the last decoded batch
+    pub fn sample(&mut self, context: &mut LlamaContext) -> llama_token {
+        unsafe { bindings::llama_sampler_sample(self.raw.as_ptr(), context.as_ptr(), -1) }
+    }
+
+    /// Reset the sampler state between generations
+    pub fn reset(&mut self) {
+        unsafe { bindings::llama_sampler_reset(self.raw.as_ptr()) }
+    }
+}
+
+impl Drop for LlamaSampler {
+    fn drop(&mut self) {
+        // Freeing the chain frees every sampler added to it
+        unsafe { bindings::llama_sampler_free(self.raw.as_ptr()) }
+    }
+}
@@ -0,0 +1,29 @@
+// Thin C shim over llama.cpp for cmdr.
+//
+// llama_model_params and llama_context_params change layout between
+// llama.cpp releases, so they are filled in here, against the headers of the
+// llama.cpp tree being built, instead of being mirrored in Rust.
+
+#include "llama.h"
+
+struct llama_model * cmdr_llama_model_load(const char * path, int32_t n_gpu_layers) {
+    struct llama_model_params params = llama_model_default_params();
+    params.n_gpu_layers = n_gpu_layers;
+    return llama_model_load_from_file(path, params);
+}
+
+struct llama_context * cmdr_llama_context_new(
+        struct llama_model * model,
+        uint32_t n_ctx,
+        uint32_t n_batch,
+        int32_t n_threads) {
+    struct llama_context_params params = llama_context_default_params();
+    params.n_ctx = n_ctx;
+    params.n_batch = n_batch;
+    params.n_ubatch = n_batch;
+    if (n_threads > 0) {
+        params.n_threads = n_threads;
+        params.n_threads_batch = n_threads;
+    }
+    return llama_init_from_model(model, params);
+}
@@ -35,7 +35,6 @@ print_usage() {
     echo "  all           Run all tests (default)"
     echo "  build         Build verification tests"
     echo "  cli           CLI interface tests"
-    echo "  repl          REPL behavior tests"
     echo "  fds           File descriptor leak tests"
     echo ""
     echo "Examples:"
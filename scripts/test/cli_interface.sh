@@ -102,13 +102,13 @@ fi
 # Test 7: Config flag (--config)
 echo ""
 echo "📝 Test 7: Config flag (--config)"
-echo "Command: $CMDR_BIN --config test.conf"
-CONFIG_OUTPUT=$("$CMDR_BIN" --config test.conf)
-if [[ $CONFIG_OUTPUT == *"Configuration file specified: test.conf"* ]]; then
+echo "Command: $CMDR_BIN --config test.conf config show"
+CONFIG_OUTPUT=$("$CMDR_BIN" --config test.conf config show 2>&1 || true)
+if [[ $CONFIG_OUTPUT == *"cannot read test.conf"* ]]; then
     echo "✅ Config flag test passed"
 else
     echo "❌ Config flag test failed"
-    echo "Expected output to contain: Configuration file specified: test.conf"
+    echo "Expected output to contain: cannot read test.conf"
     echo "Got: $CONFIG_OUTPUT"
     exit 1
 fi
@@ -128,13 +128,11 @@ fi
 echo ""
 echo "📝 Test 9: Default behavior (no arguments)"
 echo "Command: $CMDR_BIN"
-DEFAULT_OUTPUT=$(echo -e "\x04" | "$CMDR_BIN")
-if [[ $DEFAULT_OUTPUT == *"Interactive REPL mode (not yet implemented)"* ]]; then
+if echo -e "\x04" | "$CMDR_BIN" > /dev/null; then
     echo "✅ Default behavior test passed"
 else
     echo "❌ Default behavior test failed"
-    echo "Expected output to contain: Interactive REPL mode (not yet implemented)"
-    echo "Got: $DEFAULT_OUTPUT"
+    echo "Expected the REPL to exit cleanly on Ctrl-D"
     exit 1
 fi
 
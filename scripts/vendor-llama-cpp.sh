@@ -0,0 +1,33 @@
+#!/bin/bash
+
+# Check out the llama.cpp release the `native-llama` feature is built from
+# as the crates/llm/vendor/llama.cpp submodule, at the tag pinned as
+# LLAMA_CPP_TAG in crates/llm/build.rs.
+#
+# Run it after cloning, or after changing LLAMA_CPP_TAG; when the submodule
+# moves, commit crates/llm/vendor/llama.cpp to record the new pin.
+
+set -euo pipefail
+
+cd "$(git rev-parse --show-toplevel)"
+
+URL="https://github.com/ggml-org/llama.cpp"
+DIR="crates/llm/vendor/llama.cpp"
+TAG="$(sed -n 's/^const LLAMA_CPP_TAG: &str = "\(.*\)";$/\1/p' crates/llm/build.rs)"
+
+if [ -z "$TAG" ]; then
+    echo "LLAMA_CPP_TAG not found in crates/llm/build.rs" >&2
+    exit 1
+fi
+
+if git ls-files --stage -- "$DIR" | grep -q '^160000'; then
+    git submodule update --init --depth 1 -- "$DIR"
+elif [ ! -e "$DIR/.git" ]; then
+    git submodule add --force --depth 1 "$URL" "$DIR"
+fi
+
+git -C "$DIR" fetch --depth 1 origin tag "$TAG"
+git -C "$DIR" checkout --quiet "$TAG"
+git add -- "$DIR"
+
+echo "llama.cpp $TAG checked out in $DIR"